@@ -0,0 +1,259 @@
+use abstutil::Timer;
+use geom::{Duration, LonLat, Pt2D, Time};
+use map_model::raw::{RawBusRoute, RawBusStop, RawMap};
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::fs::File;
+
+// Reads a GTFS feed (https://developers.google.com/transit/gtfs/reference) from a directory with
+// stops.txt, routes.txt, trips.txt, stop_times.txt, and optionally calendar.txt. Every distinct
+// sequence of stops that a route serves within the map becomes one RawBusRoute, with a vehicle
+// departing for every trip following that sequence.
+//
+// Only bus and light rail routes are used. If calendar.txt exists, only trips running on a
+// Wednesday are kept, as a typical weekday; the service's date range is ignored.
+pub fn import(
+    dir: &str,
+    map: &RawMap,
+    timer: &mut Timer,
+) -> Result<Vec<RawBusRoute>, Box<dyn Error>> {
+    timer.start("import GTFS");
+
+    let mut stops: HashMap<String, RawBusStop> = HashMap::new();
+    for rec in read::<Stop>(dir, "stops.txt")? {
+        let gps = LonLat::new(rec.stop_lon, rec.stop_lat);
+        if !map.gps_bounds.contains(gps) {
+            continue;
+        }
+        let pt = Pt2D::from_gps(gps, &map.gps_bounds);
+        if !map.boundary_polygon.contains_pt(pt) {
+            continue;
+        }
+        // The stop is usually placed on the sidewalk, so use it to snap to both the sidewalk and
+        // the driving lane.
+        stops.insert(
+            rec.stop_id,
+            RawBusStop {
+                name: rec.stop_name,
+                vehicle_pos: pt,
+                ped_pos: Some(pt),
+            },
+        );
+    }
+
+    let mut routes: HashMap<String, Route> = HashMap::new();
+    for rec in read::<Route>(dir, "routes.txt")? {
+        if rec.is_bus().is_some() {
+            routes.insert(rec.route_id.clone(), rec);
+        }
+    }
+
+    let calendar = format!("{}/calendar.txt", dir);
+    let weekday_services: Option<BTreeSet<String>> = if abstutil::file_exists(calendar) {
+        Some(
+            read::<Calendar>(dir, "calendar.txt")?
+                .into_iter()
+                .filter(|rec| rec.wednesday == 1)
+                .map(|rec| rec.service_id)
+                .collect(),
+        )
+    } else {
+        None
+    };
+
+    let mut trips: HashMap<String, Trip> = HashMap::new();
+    for rec in read::<Trip>(dir, "trips.txt")? {
+        if !routes.contains_key(&rec.route_id) {
+            continue;
+        }
+        if let Some(ref services) = weekday_services {
+            if !services.contains(&rec.service_id) {
+                continue;
+            }
+        }
+        trips.insert(rec.trip_id.clone(), rec);
+    }
+
+    let mut stop_times: BTreeMap<String, Vec<StopTime>> = BTreeMap::new();
+    for rec in read::<StopTime>(dir, "stop_times.txt")? {
+        if trips.contains_key(&rec.trip_id) {
+            stop_times
+                .entry(rec.trip_id.clone())
+                .or_insert_with(Vec::new)
+                .push(rec);
+        }
+    }
+
+    // Group trips by (route, headsign, the stops they use within the map). For each trip, remember
+    // when it departs and when it's scheduled to depart each stop after that.
+    let mut patterns: BTreeMap<(String, String, Vec<String>), Vec<(Time, Vec<Option<Time>>)>> =
+        BTreeMap::new();
+    timer.start_iter("match trips to stops", stop_times.len());
+    for (trip_id, mut times) in stop_times {
+        timer.next();
+        times.sort_by_key(|st| st.stop_sequence);
+
+        // Like OSM routes, keep the first contiguous run of stops within the map. GTFS only
+        // requires times at some stops, so if the first stop in the map doesn't have one, use the
+        // last time seen before it.
+        let mut keep_stops = Vec::new();
+        let mut keep_times = Vec::new();
+        let mut spawn_time = None;
+        let mut last_time = None;
+        for st in times {
+            let time = if st.departure_time.trim().is_empty() {
+                None
+            } else {
+                Some(Time::parse(st.departure_time.trim())?)
+            };
+            if time.is_some() {
+                last_time = time;
+            }
+            if stops.contains_key(&st.stop_id) {
+                if keep_stops.is_empty() {
+                    spawn_time = last_time;
+                }
+                keep_stops.push(st.stop_id);
+                // Times at stops that aren't timepoints are just estimates.
+                keep_times.push(if st.timepoint == Some(0) { None } else { time });
+            } else if !keep_stops.is_empty() {
+                break;
+            }
+        }
+        if keep_stops.len() < 2 {
+            continue;
+        }
+        let spawn_time = if let Some(t) = spawn_time {
+            t
+        } else {
+            timer.warn(format!("Trip {} has no time at its first stop", trip_id));
+            continue;
+        };
+
+        let trip = &trips[&trip_id];
+        patterns
+            .entry((
+                trip.route_id.clone(),
+                trip.trip_headsign.clone(),
+                keep_stops,
+            ))
+            .or_insert_with(Vec::new)
+            .push((spawn_time, keep_times));
+    }
+
+    let mut results = Vec::new();
+    for ((route_id, headsign, stop_ids), mut schedules) in patterns {
+        schedules.sort_by_key(|(t, _)| *t);
+        let mut spawn_times = Vec::new();
+        let mut timepoints: Vec<Vec<Option<Duration>>> = Vec::new();
+        for (spawn, times) in schedules {
+            spawn_times.push(spawn);
+            timepoints.push(
+                times
+                    .into_iter()
+                    .map(|maybe_t| maybe_t.map(|t| t - spawn))
+                    .collect(),
+            );
+        }
+        let route = &routes[&route_id];
+        let name = if route.route_long_name.is_empty() {
+            route.route_short_name.clone()
+        } else {
+            route.route_long_name.clone()
+        };
+        results.push(RawBusRoute {
+            full_name: if headsign.is_empty() {
+                name
+            } else {
+                format!("{} to {}", name, headsign)
+            },
+            short_name: route.route_short_name.clone(),
+            osm_rel_id: 0,
+            gtfs_route_id: Some(route_id.clone()),
+            is_bus: route.is_bus().unwrap(),
+            stops: stop_ids.iter().map(|id| stops[id].clone()).collect(),
+            border_start: None,
+            border_end: None,
+            all_pts: Vec::new(),
+            spawn_times,
+            timepoints,
+        });
+    }
+    timer.note(format!(
+        "Imported {} GTFS route variations from {}",
+        results.len(),
+        dir
+    ));
+
+    timer.stop("import GTFS");
+    Ok(results)
+}
+
+fn read<T: serde::de::DeserializeOwned>(dir: &str, file: &str) -> Result<Vec<T>, Box<dyn Error>> {
+    let path = format!("{}/{}", dir, file);
+    let mut results = Vec::new();
+    for rec in csv::Reader::from_reader(File::open(&path)?).deserialize() {
+        results.push(rec.map_err(|err| format!("{}: {}", path, err))?);
+    }
+    Ok(results)
+}
+
+#[derive(Deserialize)]
+struct Stop {
+    stop_id: String,
+    #[serde(default)]
+    stop_name: String,
+    stop_lat: f64,
+    stop_lon: f64,
+}
+
+#[derive(Deserialize)]
+struct Route {
+    route_id: String,
+    #[serde(default)]
+    route_short_name: String,
+    #[serde(default)]
+    route_long_name: String,
+    route_type: usize,
+}
+
+impl Route {
+    // None if it's some other kind of transit, like a ferry
+    fn is_bus(&self) -> Option<bool> {
+        match self.route_type {
+            // Including the extended route types
+            3 | 700..=799 => Some(true),
+            0 | 900..=999 => Some(false),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Trip {
+    route_id: String,
+    service_id: String,
+    trip_id: String,
+    #[serde(default)]
+    trip_headsign: String,
+}
+
+#[derive(Deserialize)]
+struct StopTime {
+    trip_id: String,
+    // Might be blank, or past 24:00:00 for trips running after midnight
+    #[serde(default)]
+    departure_time: String,
+    stop_id: String,
+    stop_sequence: usize,
+    // Blank or 1 means the time is exact, 0 means approximate
+    #[serde(default)]
+    timepoint: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct Calendar {
+    service_id: String,
+    wednesday: usize,
+}
@@ -1,13 +1,16 @@
 mod clip;
+mod gtfs;
 mod osm_reader;
+mod pbf;
 mod split_ways;
 mod srtm;
 
 use abstutil::Timer;
-use geom::{Distance, FindClosest, PolyLine, Pt2D};
+use geom::{Distance, FindClosest, LonLat, PolyLine, Pt2D};
 use kml::ExtraShapes;
 use map_model::raw::{OriginalBuilding, OriginalRoad, RawMap};
 use map_model::{osm, MapConfig};
+use std::error::Error;
 
 // Just used for matching hints to different sides of a road.
 const DIRECTED_ROAD_THICKNESS: Distance = Distance::const_meters(2.5);
@@ -27,6 +30,9 @@ pub struct Options {
     // If provided, pull elevation data from this SRTM file. The SRTM parser is incorrect, so the
     // results will be nonsense.
     pub elevation: Option<String>,
+    // If provided, replace the bus and light rail routes from OSM with ones from the GTFS feed in
+    // this directory, which have real schedules.
+    pub gtfs: Option<String>,
 }
 
 // What roads will have on-street parking lanes? Data from
@@ -61,6 +67,17 @@ pub enum PrivateOffstreetParking {
     // TODO Based on the number of residents?
 }
 
+// Clips a large .osm.pbf extract to an osmosis boundary polygon, writing the result as .osm XML.
+pub fn clip_pbf(
+    pbf_path: &str,
+    clip_path: &str,
+    output: &str,
+    timer: &mut Timer,
+) -> Result<(), Box<dyn Error>> {
+    let pts = LonLat::read_osmosis_polygon(clip_path.to_string())?;
+    pbf::clip_to_xml(pbf_path, pts, output, timer)
+}
+
 pub fn convert(opts: Options, timer: &mut abstutil::Timer) -> RawMap {
     let (mut map, amenities) = split_ways::split_up_roads(
         osm_reader::extract_osm(
@@ -117,6 +134,12 @@ pub fn convert(opts: Options, timer: &mut abstutil::Timer) -> RawMap {
     if let Some(ref path) = opts.elevation {
         use_elevation(&mut map, path, timer);
     }
+    if let Some(ref dir) = opts.gtfs {
+        map.bus_routes = match gtfs::import(dir, &map, timer) {
+            Ok(routes) => routes,
+            Err(err) => panic!("Couldn't import GTFS from {}: {}", dir, err),
+        };
+    }
 
     map
 }
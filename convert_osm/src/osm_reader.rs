@@ -30,18 +30,26 @@ pub fn extract_osm(
     // Amenities (location, name, amenity type)
     Vec<(Pt2D, String, String)>,
 ) {
-    let (reader, done) = FileWithProgress::new(osm_path).unwrap();
-    let doc = osm_xml::OSM::parse(reader).expect("OSM parsing failed");
+    let clip_pts = maybe_clip_path
+        .as_ref()
+        .map(|path| LonLat::read_osmosis_polygon(path.to_string()).unwrap());
+    let doc = if osm_path.ends_with(".pbf") {
+        // Clip while reading, rather than producing a giant .osm file first
+        crate::pbf::read(osm_path, &clip_pts, timer).expect("OSM PBF parsing failed")
+    } else {
+        let (reader, done) = FileWithProgress::new(osm_path).unwrap();
+        let doc = osm_xml::OSM::parse(reader).expect("OSM parsing failed");
+        done(timer);
+        doc
+    };
     println!(
         "OSM doc has {} nodes, {} ways, {} relations",
         doc.nodes.len(),
         doc.ways.len(),
         doc.relations.len()
     );
-    done(timer);
 
-    let mut map = if let Some(path) = maybe_clip_path {
-        let pts = LonLat::read_osmosis_polygon(path.to_string()).unwrap();
+    let mut map = if let Some(pts) = clip_pts {
         let mut gps_bounds = GPSBounds::new();
         for pt in &pts {
             gps_bounds.update(*pt);
@@ -817,10 +825,13 @@ fn extract_route(
         short_name,
         is_bus,
         osm_rel_id: rel.id,
+        gtfs_route_id: None,
         stops: keep_stops,
         border_start: None,
         border_end: None,
         all_pts,
+        spawn_times: Vec::new(),
+        timepoints: Vec::new(),
     })
 }
 
@@ -0,0 +1,1054 @@
+use abstutil::{prettyprint_usize, FileWithProgress, Timer};
+use flate2::read::ZlibDecoder;
+use geom::{GPSBounds, LonLat};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, ErrorKind, Read, Write};
+
+// Reads an .osm.pbf file (https://wiki.openstreetmap.org/wiki/PBF_Format) into the same document
+// that parsing .osm XML produces, without needing osmconvert or the disk space for XML.
+//
+// If a clipping polygon is given, only nodes inside of it, ways touching at least one of those
+// nodes (with all of their nodes, even the ones outside), and relations with some member kept
+// (including relations whose kept member is another relation) are returned. The outer and inner
+// ways of kept multipolygons are kept entirely, even when they lie completely outside, so the
+// area can still be closed. Nodes, ways, and relations are read in one streaming pass; if some
+// ways or nodes outside the polygon turn out to be needed, up to two more passes find them. So a
+// huge extract never has to fit in memory. Like every extract from Geofabrik, the file must list
+// nodes before ways before relations.
+pub fn read(
+    path: &str,
+    clip: &Option<Vec<LonLat>>,
+    timer: &mut Timer,
+) -> Result<osm_xml::OSM, Box<dyn Error>> {
+    let clip_bounds = clip.as_ref().map(|pts| GPSBounds::from(pts.clone()));
+    let inside = |pt: LonLat| -> bool {
+        match (clip, &clip_bounds) {
+            (Some(pts), Some(bounds)) => bounds.contains(pt) && polygon_contains(pts, pt),
+            _ => true,
+        }
+    };
+
+    let mut nodes: HashMap<i64, osm_xml::Node> = HashMap::new();
+    let mut ways: HashMap<i64, osm_xml::Way> = HashMap::new();
+    let mut relations: HashMap<i64, osm_xml::Relation> = HashMap::new();
+    // Relations without any node or way members kept, but with some relation members that might
+    // be. Members can appear later in the file, so decide after reading everything.
+    let mut maybe_relations: HashMap<i64, osm_xml::Relation> = HashMap::new();
+    // Nodes outside the clipping polygon that kept ways need
+    let mut missing_nodes: HashSet<i64> = HashSet::new();
+
+    timer.start(format!("read {}", path));
+    for_each_block(path, timer, |block| {
+        for node in block.nodes()? {
+            if inside(LonLat::new(node.lon, node.lat)) {
+                nodes.insert(node.id, block.to_node(node));
+            }
+        }
+        for way in block.ways()? {
+            if !way.refs.iter().any(|id| nodes.contains_key(id)) {
+                continue;
+            }
+            for id in &way.refs {
+                if !nodes.contains_key(id) {
+                    missing_nodes.insert(*id);
+                }
+            }
+            ways.insert(way.id, block.to_way(way));
+        }
+        for rel in block.relations()? {
+            let keep = rel
+                .members
+                .iter()
+                .any(|(member_type, id, _)| match member_type {
+                    MemberType::Node => nodes.contains_key(id),
+                    MemberType::Way => ways.contains_key(id),
+                    MemberType::Relation => false,
+                });
+            if keep {
+                relations.insert(rel.id, block.to_relation(rel));
+            } else if rel
+                .members
+                .iter()
+                .any(|(member_type, _, _)| *member_type == MemberType::Relation)
+            {
+                maybe_relations.insert(rel.id, block.to_relation(rel));
+            }
+        }
+        Ok(())
+    })?;
+
+    // Keep relations containing kept relations, until nothing changes. Route masters contain
+    // routes, which might contain other routes...
+    loop {
+        let newly_kept: Vec<i64> = maybe_relations
+            .values()
+            .filter(|rel| {
+                rel.members.iter().any(|member| match member {
+                    osm_xml::Member::Relation(osm_xml::UnresolvedReference::Relation(id), _) => {
+                        relations.contains_key(id)
+                    }
+                    _ => false,
+                })
+            })
+            .map(|rel| rel.id)
+            .collect();
+        if newly_kept.is_empty() {
+            break;
+        }
+        for id in newly_kept {
+            relations.insert(id, maybe_relations.remove(&id).unwrap());
+        }
+    }
+
+    let mut missing_ways: HashSet<i64> = HashSet::new();
+    for rel in relations.values() {
+        if !rel
+            .tags
+            .iter()
+            .any(|tag| tag.key == "type" && tag.val == "multipolygon")
+        {
+            continue;
+        }
+        for member in &rel.members {
+            if let osm_xml::Member::Way(osm_xml::UnresolvedReference::Way(id), _) = member {
+                if !ways.contains_key(id) {
+                    missing_ways.insert(*id);
+                }
+            }
+        }
+    }
+    if !missing_ways.is_empty() {
+        timer.note(format!(
+            "Reading {} more ways for multipolygons crossing the boundary",
+            prettyprint_usize(missing_ways.len())
+        ));
+        for_each_block(path, timer, |block| {
+            for way in block.ways()? {
+                if missing_ways.contains(&way.id) {
+                    for id in &way.refs {
+                        if !nodes.contains_key(id) {
+                            missing_nodes.insert(*id);
+                        }
+                    }
+                    ways.insert(way.id, block.to_way(way));
+                }
+            }
+            Ok(())
+        })?;
+    }
+
+    if !missing_nodes.is_empty() {
+        timer.note(format!(
+            "Reading {} more nodes for ways crossing the boundary",
+            prettyprint_usize(missing_nodes.len())
+        ));
+        for_each_block(path, timer, |block| {
+            for node in block.nodes()? {
+                if missing_nodes.contains(&node.id) {
+                    nodes.insert(node.id, block.to_node(node));
+                }
+            }
+            Ok(())
+        })?;
+    }
+    timer.stop(format!("read {}", path));
+
+    Ok(osm_xml::OSM {
+        bounds: None,
+        nodes,
+        ways,
+        relations,
+    })
+}
+
+// Clips an .osm.pbf file and writes the result as .osm XML, so importing the same area again
+// doesn't have to read through the entire extract.
+pub fn clip_to_xml(
+    pbf_path: &str,
+    clip: Vec<LonLat>,
+    output: &str,
+    timer: &mut Timer,
+) -> Result<(), Box<dyn Error>> {
+    let doc = read(pbf_path, &Some(clip), timer)?;
+    timer.start(format!("write {}", output));
+    let mut f = BufWriter::new(File::create(output)?);
+    write_xml(&doc, &mut f)?;
+    f.flush()?;
+    timer.stop(format!("write {}", output));
+    Ok(())
+}
+
+fn write_xml<W: Write>(doc: &osm_xml::OSM, f: &mut W) -> Result<(), Box<dyn Error>> {
+    writeln!(f, "<?xml version='1.0' encoding='UTF-8'?>")?;
+    writeln!(f, "<osm version=\"0.6\">")?;
+    // Sort everything, so the output is the same every time
+    for id in doc.nodes.keys().collect::<BTreeSet<_>>() {
+        let node = &doc.nodes[id];
+        writeln!(
+            f,
+            "  <node id=\"{}\" lat=\"{}\" lon=\"{}\">",
+            node.id, node.lat, node.lon
+        )?;
+        write_tags(&node.tags, f)?;
+        writeln!(f, "  </node>")?;
+    }
+    for id in doc.ways.keys().collect::<BTreeSet<_>>() {
+        let way = &doc.ways[id];
+        writeln!(f, "  <way id=\"{}\">", way.id)?;
+        for node in &way.nodes {
+            if let osm_xml::UnresolvedReference::Node(n) = node {
+                writeln!(f, "    <nd ref=\"{}\"/>", n)?;
+            }
+        }
+        write_tags(&way.tags, f)?;
+        writeln!(f, "  </way>")?;
+    }
+    for id in doc.relations.keys().collect::<BTreeSet<_>>() {
+        let rel = &doc.relations[id];
+        writeln!(f, "  <relation id=\"{}\">", rel.id)?;
+        for member in &rel.members {
+            let (member_type, member_id, role) = match member {
+                osm_xml::Member::Node(osm_xml::UnresolvedReference::Node(id), role) => {
+                    ("node", id, role)
+                }
+                osm_xml::Member::Way(osm_xml::UnresolvedReference::Way(id), role) => {
+                    ("way", id, role)
+                }
+                osm_xml::Member::Relation(osm_xml::UnresolvedReference::Relation(id), role) => {
+                    ("relation", id, role)
+                }
+                _ => {
+                    continue;
+                }
+            };
+            writeln!(
+                f,
+                "    <member type=\"{}\" ref=\"{}\" role=\"{}\"/>",
+                member_type,
+                member_id,
+                escape(role)
+            )?;
+        }
+        write_tags(&rel.tags, f)?;
+        writeln!(f, "  </relation>")?;
+    }
+    writeln!(f, "</osm>")?;
+    Ok(())
+}
+
+fn write_tags<W: Write>(tags: &[osm_xml::Tag], f: &mut W) -> Result<(), Box<dyn Error>> {
+    for tag in tags {
+        writeln!(
+            f,
+            "    <tag k=\"{}\" v=\"{}\"/>",
+            escape(&tag.key),
+            escape(&tag.val)
+        )?;
+    }
+    Ok(())
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn for_each_block<F: FnMut(&Block) -> Result<(), Box<dyn Error>>>(
+    path: &str,
+    timer: &mut Timer,
+    mut f: F,
+) -> Result<(), Box<dyn Error>> {
+    let (mut reader, done) = FileWithProgress::new(path)?;
+    while let Some((blob_type, data)) = next_blob(&mut reader)? {
+        // The OSMHeader blob lists required features, but we handle the only ones in common
+        // use: the base schema and dense nodes.
+        if blob_type == "OSMData" {
+            f(&Block::parse(&data)?)?;
+        }
+    }
+    done(timer);
+    Ok(())
+}
+
+// Returns the type and decompressed contents of the next blob in the file.
+fn next_blob<R: Read>(reader: &mut R) -> Result<Option<(String, Vec<u8>)>, Box<dyn Error>> {
+    let mut len_buf = [0; 4];
+    if let Err(err) = reader.read_exact(&mut len_buf) {
+        if err.kind() == ErrorKind::UnexpectedEof {
+            return Ok(None);
+        }
+        return Err(err.into());
+    }
+    let mut header = vec![0; u32::from_be_bytes(len_buf) as usize];
+    reader.read_exact(&mut header)?;
+
+    let mut blob_type = String::new();
+    let mut data_size = 0;
+    for (num, field) in parse_fields(&header)? {
+        match (num, field) {
+            (1, Field::Bytes(b)) => {
+                blob_type = String::from_utf8(b.to_vec())?;
+            }
+            (3, Field::Varint(x)) => {
+                data_size = x as usize;
+            }
+            _ => {}
+        }
+    }
+
+    let mut blob = vec![0; data_size];
+    reader.read_exact(&mut blob)?;
+    for (num, field) in parse_fields(&blob)? {
+        match (num, field) {
+            (1, Field::Bytes(raw)) => {
+                return Ok(Some((blob_type, raw.to_vec())));
+            }
+            (3, Field::Bytes(compressed)) => {
+                let mut data = Vec::new();
+                ZlibDecoder::new(compressed).read_to_end(&mut data)?;
+                return Ok(Some((blob_type, data)));
+            }
+            (4..=7, Field::Bytes(_)) => {
+                return Err(format!("{} blob isn't raw or zlib-compressed", blob_type).into());
+            }
+            _ => {}
+        }
+    }
+    Err(format!("{} blob has no data", blob_type).into())
+}
+
+// A PrimitiveBlock
+struct Block<'a> {
+    strings: Vec<&'a [u8]>,
+    groups: Vec<&'a [u8]>,
+    granularity: i64,
+    lat_offset: i64,
+    lon_offset: i64,
+}
+
+struct Node {
+    id: i64,
+    lon: f64,
+    lat: f64,
+    // Indices into the string table
+    tags: Vec<(usize, usize)>,
+}
+
+struct Way {
+    id: i64,
+    tags: Vec<(usize, usize)>,
+    refs: Vec<i64>,
+}
+
+struct Relation {
+    id: i64,
+    tags: Vec<(usize, usize)>,
+    // The role is an index into the string table
+    members: Vec<(MemberType, i64, usize)>,
+}
+
+#[derive(PartialEq)]
+enum MemberType {
+    Node,
+    Way,
+    Relation,
+}
+
+impl<'a> Block<'a> {
+    fn parse(data: &'a [u8]) -> Result<Block<'a>, Box<dyn Error>> {
+        let mut block = Block {
+            strings: Vec::new(),
+            groups: Vec::new(),
+            granularity: 100,
+            lat_offset: 0,
+            lon_offset: 0,
+        };
+        for (num, field) in parse_fields(data)? {
+            match (num, field) {
+                (1, Field::Bytes(table)) => {
+                    for (num, field) in parse_fields(table)? {
+                        if let (1, Field::Bytes(s)) = (num, field) {
+                            block.strings.push(s);
+                        }
+                    }
+                }
+                (2, Field::Bytes(group)) => {
+                    block.groups.push(group);
+                }
+                (17, Field::Varint(x)) => {
+                    block.granularity = x as i64;
+                }
+                (19, Field::Varint(x)) => {
+                    block.lat_offset = x as i64;
+                }
+                (20, Field::Varint(x)) => {
+                    block.lon_offset = x as i64;
+                }
+                _ => {}
+            }
+        }
+        Ok(block)
+    }
+
+    fn coordinate(&self, offset: i64, value: i64) -> f64 {
+        1e-9 * ((offset + self.granularity * value) as f64)
+    }
+
+    fn nodes(&self) -> Result<Vec<Node>, Box<dyn Error>> {
+        let mut results = Vec::new();
+        for group in &self.groups {
+            for (num, field) in parse_fields(group)? {
+                match (num, field) {
+                    (1, Field::Bytes(node)) => {
+                        let mut id = 0;
+                        let mut lat = 0;
+                        let mut lon = 0;
+                        let mut keys = Vec::new();
+                        let mut vals = Vec::new();
+                        for (num, field) in parse_fields(node)? {
+                            match (num, field) {
+                                (1, Field::Varint(x)) => {
+                                    id = zigzag(x);
+                                }
+                                (2, f) => unpack(&f, &mut keys)?,
+                                (3, f) => unpack(&f, &mut vals)?,
+                                (8, Field::Varint(x)) => {
+                                    lat = zigzag(x);
+                                }
+                                (9, Field::Varint(x)) => {
+                                    lon = zigzag(x);
+                                }
+                                _ => {}
+                            }
+                        }
+                        results.push(Node {
+                            id,
+                            lon: self.coordinate(self.lon_offset, lon),
+                            lat: self.coordinate(self.lat_offset, lat),
+                            tags: zip_tags(keys, vals),
+                        });
+                    }
+                    (2, Field::Bytes(dense)) => {
+                        self.dense_nodes(dense, &mut results)?;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(results)
+    }
+
+    fn dense_nodes(&self, dense: &[u8], results: &mut Vec<Node>) -> Result<(), Box<dyn Error>> {
+        let mut ids = Vec::new();
+        let mut lats = Vec::new();
+        let mut lons = Vec::new();
+        let mut keys_vals = Vec::new();
+        for (num, field) in parse_fields(dense)? {
+            match num {
+                1 => unpack(&field, &mut ids)?,
+                8 => unpack(&field, &mut lats)?,
+                9 => unpack(&field, &mut lons)?,
+                10 => unpack(&field, &mut keys_vals)?,
+                _ => {}
+            }
+        }
+        if ids.len() != lats.len() || ids.len() != lons.len() {
+            return Err("dense nodes have mismatched ids and coordinates".into());
+        }
+
+        // Everything is delta-encoded. Tags for each node are key/value pairs ending with 0, but
+        // if no node in the block has tags, there's nothing at all.
+        let mut id = 0;
+        let mut lat = 0;
+        let mut lon = 0;
+        let mut kv = keys_vals.into_iter();
+        for ((id_delta, lat_delta), lon_delta) in ids.into_iter().zip(lats).zip(lons) {
+            id += zigzag(id_delta);
+            lat += zigzag(lat_delta);
+            lon += zigzag(lon_delta);
+            let mut tags = Vec::new();
+            while let Some(k) = kv.next() {
+                if k == 0 {
+                    break;
+                }
+                let v = kv.next().ok_or("dense node tag is missing a value")?;
+                tags.push((k as usize, v as usize));
+            }
+            results.push(Node {
+                id,
+                lon: self.coordinate(self.lon_offset, lon),
+                lat: self.coordinate(self.lat_offset, lat),
+                tags,
+            });
+        }
+        Ok(())
+    }
+
+    fn ways(&self) -> Result<Vec<Way>, Box<dyn Error>> {
+        let mut results = Vec::new();
+        for group in &self.groups {
+            for (num, field) in parse_fields(group)? {
+                if let (3, Field::Bytes(way)) = (num, field) {
+                    let mut id = 0;
+                    let mut keys = Vec::new();
+                    let mut vals = Vec::new();
+                    let mut deltas = Vec::new();
+                    for (num, field) in parse_fields(way)? {
+                        match (num, field) {
+                            (1, Field::Varint(x)) => {
+                                id = x as i64;
+                            }
+                            (2, f) => unpack(&f, &mut keys)?,
+                            (3, f) => unpack(&f, &mut vals)?,
+                            (8, f) => unpack(&f, &mut deltas)?,
+                            _ => {}
+                        }
+                    }
+                    let mut node = 0;
+                    let refs = deltas
+                        .into_iter()
+                        .map(|x| {
+                            node += zigzag(x);
+                            node
+                        })
+                        .collect();
+                    results.push(Way {
+                        id,
+                        tags: zip_tags(keys, vals),
+                        refs,
+                    });
+                }
+            }
+        }
+        Ok(results)
+    }
+
+    fn relations(&self) -> Result<Vec<Relation>, Box<dyn Error>> {
+        let mut results = Vec::new();
+        for group in &self.groups {
+            for (num, field) in parse_fields(group)? {
+                if let (4, Field::Bytes(rel)) = (num, field) {
+                    let mut id = 0;
+                    let mut keys = Vec::new();
+                    let mut vals = Vec::new();
+                    let mut roles = Vec::new();
+                    let mut deltas = Vec::new();
+                    let mut types = Vec::new();
+                    for (num, field) in parse_fields(rel)? {
+                        match (num, field) {
+                            (1, Field::Varint(x)) => {
+                                id = x as i64;
+                            }
+                            (2, f) => unpack(&f, &mut keys)?,
+                            (3, f) => unpack(&f, &mut vals)?,
+                            (8, f) => unpack(&f, &mut roles)?,
+                            (9, f) => unpack(&f, &mut deltas)?,
+                            (10, f) => unpack(&f, &mut types)?,
+                            _ => {}
+                        }
+                    }
+                    if roles.len() != deltas.len() || roles.len() != types.len() {
+                        return Err(format!("relation {} has mismatched members", id).into());
+                    }
+                    let mut member = 0;
+                    let mut members = Vec::new();
+                    for ((role, delta), member_type) in roles.into_iter().zip(deltas).zip(types) {
+                        member += zigzag(delta);
+                        let member_type = match member_type {
+                            0 => MemberType::Node,
+                            1 => MemberType::Way,
+                            2 => MemberType::Relation,
+                            x => {
+                                return Err(format!("relation {} has member type {}", id, x).into());
+                            }
+                        };
+                        members.push((member_type, member, role as usize));
+                    }
+                    results.push(Relation {
+                        id,
+                        tags: zip_tags(keys, vals),
+                        members,
+                    });
+                }
+            }
+        }
+        Ok(results)
+    }
+
+    fn string(&self, idx: usize) -> String {
+        self.strings
+            .get(idx)
+            .map(|s| String::from_utf8_lossy(s).to_string())
+            .unwrap_or_else(String::new)
+    }
+
+    fn to_tags(&self, tags: &[(usize, usize)]) -> Vec<osm_xml::Tag> {
+        tags.iter()
+            .map(|(k, v)| osm_xml::Tag {
+                key: self.string(*k),
+                val: self.string(*v),
+            })
+            .collect()
+    }
+
+    fn to_node(&self, node: Node) -> osm_xml::Node {
+        osm_xml::Node {
+            id: node.id,
+            lat: node.lat,
+            lon: node.lon,
+            tags: self.to_tags(&node.tags),
+        }
+    }
+
+    fn to_way(&self, way: Way) -> osm_xml::Way {
+        osm_xml::Way {
+            id: way.id,
+            tags: self.to_tags(&way.tags),
+            nodes: way
+                .refs
+                .into_iter()
+                .map(osm_xml::UnresolvedReference::Node)
+                .collect(),
+        }
+    }
+
+    fn to_relation(&self, rel: Relation) -> osm_xml::Relation {
+        osm_xml::Relation {
+            id: rel.id,
+            tags: self.to_tags(&rel.tags),
+            members: rel
+                .members
+                .into_iter()
+                .map(|(member_type, id, role)| {
+                    let role = self.string(role);
+                    match member_type {
+                        MemberType::Node => {
+                            osm_xml::Member::Node(osm_xml::UnresolvedReference::Node(id), role)
+                        }
+                        MemberType::Way => {
+                            osm_xml::Member::Way(osm_xml::UnresolvedReference::Way(id), role)
+                        }
+                        MemberType::Relation => osm_xml::Member::Relation(
+                            osm_xml::UnresolvedReference::Relation(id),
+                            role,
+                        ),
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+fn zip_tags(keys: Vec<u64>, vals: Vec<u64>) -> Vec<(usize, usize)> {
+    keys.into_iter()
+        .zip(vals)
+        .map(|(k, v)| (k as usize, v as usize))
+        .collect()
+}
+
+// Just enough of the protobuf wire format to read OSM PBF. Fixed-width fields are skipped, since
+// the format doesn't use them.
+enum Field<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+}
+
+fn parse_fields(buf: &[u8]) -> Result<Vec<(u64, Field)>, Box<dyn Error>> {
+    let mut fields = Vec::new();
+    let mut pos = 0;
+    while pos < buf.len() {
+        let key = read_varint(buf, &mut pos)?;
+        match key & 0x7 {
+            0 => {
+                fields.push((key >> 3, Field::Varint(read_varint(buf, &mut pos)?)));
+            }
+            1 => {
+                pos += 8;
+            }
+            2 => {
+                let len = read_varint(buf, &mut pos)? as usize;
+                if pos + len > buf.len() {
+                    return Err("truncated protobuf field".into());
+                }
+                fields.push((key >> 3, Field::Bytes(&buf[pos..pos + len])));
+                pos += len;
+            }
+            5 => {
+                pos += 4;
+            }
+            x => {
+                return Err(format!("unsupported protobuf wire type {}", x).into());
+            }
+        }
+    }
+    Ok(fields)
+}
+
+fn read_varint(buf: &[u8], pos: &mut usize) -> Result<u64, Box<dyn Error>> {
+    let mut result = 0;
+    let mut shift = 0;
+    loop {
+        let byte = *buf.get(*pos).ok_or("truncated varint")?;
+        *pos += 1;
+        result |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(result);
+        }
+        shift += 7;
+        if shift >= 64 {
+            return Err("varint is too long".into());
+        }
+    }
+}
+
+// Repeated numbers are usually packed into one field, but they don't have to be.
+fn unpack(field: &Field, results: &mut Vec<u64>) -> Result<(), Box<dyn Error>> {
+    match field {
+        Field::Varint(x) => {
+            results.push(*x);
+        }
+        Field::Bytes(buf) => {
+            let mut pos = 0;
+            while pos < buf.len() {
+                results.push(read_varint(buf, &mut pos)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn zigzag(x: u64) -> i64 {
+    ((x >> 1) as i64) ^ -((x & 1) as i64)
+}
+
+// Ray casting
+fn polygon_contains(polygon: &[LonLat], pt: LonLat) -> bool {
+    let mut inside = false;
+    let mut j = polygon.len() - 1;
+    for i in 0..polygon.len() {
+        let (a, b) = (polygon[i], polygon[j]);
+        if (a.y() > pt.y()) != (b.y() > pt.y())
+            && pt.x() < (b.x() - a.x()) * (pt.y() - a.y()) / (b.y() - a.y()) + a.x()
+        {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+
+    // Encodes just enough protobuf to build a small OSM PBF file.
+    fn varint(buf: &mut Vec<u8>, mut x: u64) {
+        while x >= 0x80 {
+            buf.push((x as u8) | 0x80);
+            x >>= 7;
+        }
+        buf.push(x as u8);
+    }
+
+    fn int_field(buf: &mut Vec<u8>, num: u64, x: u64) {
+        varint(buf, num << 3);
+        varint(buf, x);
+    }
+
+    fn bytes_field(buf: &mut Vec<u8>, num: u64, bytes: &[u8]) {
+        varint(buf, (num << 3) | 2);
+        varint(buf, bytes.len() as u64);
+        buf.extend_from_slice(bytes);
+    }
+
+    fn packed_field(buf: &mut Vec<u8>, num: u64, values: Vec<u64>) {
+        let mut packed = Vec::new();
+        for x in values {
+            varint(&mut packed, x);
+        }
+        bytes_field(buf, num, &packed);
+    }
+
+    fn unzigzag(x: i64) -> u64 {
+        ((x << 1) ^ (x >> 63)) as u64
+    }
+
+    fn deltas(values: &[i64]) -> Vec<u64> {
+        let mut last = 0;
+        values
+            .iter()
+            .map(|x| {
+                let delta = *x - last;
+                last = *x;
+                unzigzag(delta)
+            })
+            .collect()
+    }
+
+    struct Fixture {
+        strings: Vec<&'static str>,
+        blocks: Vec<Vec<u8>>,
+    }
+
+    impl Fixture {
+        fn string(&mut self, s: &'static str) -> u64 {
+            if let Some(idx) = self.strings.iter().position(|x| *x == s) {
+                return idx as u64;
+            }
+            self.strings.push(s);
+            (self.strings.len() - 1) as u64
+        }
+
+        // (id, lon, lat, tags) as dense nodes
+        fn nodes(&mut self, nodes: Vec<(i64, f64, f64, Vec<(&'static str, &'static str)>)>) {
+            let mut keys_vals = Vec::new();
+            for (_, _, _, tags) in &nodes {
+                for (k, v) in tags {
+                    keys_vals.push(self.string(k));
+                    keys_vals.push(self.string(v));
+                }
+                keys_vals.push(0);
+            }
+            let mut dense = Vec::new();
+            let ids: Vec<i64> = nodes.iter().map(|n| n.0).collect();
+            // The default granularity is 100 nanodegrees
+            let lons: Vec<i64> = nodes.iter().map(|n| (n.1 * 1e7) as i64).collect();
+            let lats: Vec<i64> = nodes.iter().map(|n| (n.2 * 1e7) as i64).collect();
+            packed_field(&mut dense, 1, deltas(&ids));
+            packed_field(&mut dense, 8, deltas(&lats));
+            packed_field(&mut dense, 9, deltas(&lons));
+            packed_field(&mut dense, 10, keys_vals);
+            let mut group = Vec::new();
+            bytes_field(&mut group, 2, &dense);
+            self.blocks.push(group);
+        }
+
+        fn way(&mut self, id: i64, refs: Vec<i64>, tags: Vec<(&'static str, &'static str)>) {
+            let mut way = Vec::new();
+            int_field(&mut way, 1, id as u64);
+            let keys = tags.iter().map(|(k, _)| self.string(k)).collect();
+            let vals = tags.iter().map(|(_, v)| self.string(v)).collect();
+            packed_field(&mut way, 2, keys);
+            packed_field(&mut way, 3, vals);
+            packed_field(&mut way, 8, deltas(&refs));
+            let mut group = Vec::new();
+            bytes_field(&mut group, 3, &way);
+            self.blocks.push(group);
+        }
+
+        // Members are (type, id, role), with type 0 for nodes, 1 for ways, 2 for relations
+        fn relation(
+            &mut self,
+            id: i64,
+            members: Vec<(u64, i64, &'static str)>,
+            tags: Vec<(&'static str, &'static str)>,
+        ) {
+            let mut rel = Vec::new();
+            int_field(&mut rel, 1, id as u64);
+            let keys = tags.iter().map(|(k, _)| self.string(k)).collect();
+            let vals = tags.iter().map(|(_, v)| self.string(v)).collect();
+            packed_field(&mut rel, 2, keys);
+            packed_field(&mut rel, 3, vals);
+            let roles = members.iter().map(|(_, _, r)| self.string(r)).collect();
+            let ids: Vec<i64> = members.iter().map(|(_, id, _)| *id).collect();
+            packed_field(&mut rel, 8, roles);
+            packed_field(&mut rel, 9, deltas(&ids));
+            packed_field(&mut rel, 10, members.iter().map(|(t, _, _)| *t).collect());
+            let mut group = Vec::new();
+            bytes_field(&mut group, 4, &rel);
+            self.blocks.push(group);
+        }
+
+        // Every group goes in its own blob, alternating between raw and zlib-compressed. Each
+        // block repeats the whole string table.
+        fn write(&self, path: &str) {
+            let mut table = Vec::new();
+            for s in &self.strings {
+                bytes_field(&mut table, 1, s.as_bytes());
+            }
+
+            let mut file = Vec::new();
+            for (idx, group) in self.blocks.iter().enumerate() {
+                let mut block = Vec::new();
+                bytes_field(&mut block, 1, &table);
+                bytes_field(&mut block, 2, group);
+
+                let mut blob = Vec::new();
+                if idx % 2 == 0 {
+                    bytes_field(&mut blob, 1, &block);
+                } else {
+                    int_field(&mut blob, 2, block.len() as u64);
+                    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                    encoder.write_all(&block).unwrap();
+                    bytes_field(&mut blob, 3, &encoder.finish().unwrap());
+                }
+
+                let mut header = Vec::new();
+                bytes_field(&mut header, 1, b"OSMData");
+                int_field(&mut header, 3, blob.len() as u64);
+
+                file.extend_from_slice(&(header.len() as u32).to_be_bytes());
+                file.extend_from_slice(&header);
+                file.extend_from_slice(&blob);
+            }
+            std::fs::write(path, file).unwrap();
+        }
+    }
+
+    // Clipped to the unit square. Node 3 is just outside, and 4, 5, 6 are far away.
+    fn fixture(path: &str) {
+        let mut f = Fixture {
+            strings: vec![""],
+            blocks: Vec::new(),
+        };
+        f.nodes(vec![
+            (
+                1,
+                0.5,
+                0.5,
+                vec![("amenity", "cafe"), ("name", "Caf\u{e9} & <Co>")],
+            ),
+            (2, 0.6, 0.5, Vec::new()),
+            (3, 2.0, 0.5, Vec::new()),
+        ]);
+        f.nodes(vec![
+            (4, 2.0, 2.0, Vec::new()),
+            (5, 3.0, 3.0, Vec::new()),
+            (6, 3.0, 4.0, vec![("natural", "tree")]),
+        ]);
+        // Crosses the boundary
+        f.way(10, vec![1, 2, 3], vec![("highway", "residential")]);
+        // Entirely outside
+        f.way(11, vec![4, 5], vec![("highway", "primary")]);
+        // Entirely outside, but part of a multipolygon that's kept
+        f.way(12, vec![3, 5, 6, 4, 3], Vec::new());
+        f.relation(
+            20,
+            vec![(1, 10, "outer"), (1, 12, "outer")],
+            vec![("type", "multipolygon"), ("leisure", "park")],
+        );
+        // Only outside members
+        f.relation(21, vec![(1, 11, "")], vec![("type", "route")]);
+        // A route master referring to a route that only appears later
+        f.relation(22, vec![(2, 23, "")], vec![("type", "route_master")]);
+        f.relation(
+            23,
+            vec![(1, 10, ""), (0, 1, "stop")],
+            vec![("type", "route")],
+        );
+        // Contains a relation that isn't kept
+        f.relation(24, vec![(2, 21, "")], vec![("type", "route_master")]);
+        f.write(path);
+    }
+
+    fn unit_square() -> Vec<LonLat> {
+        vec![
+            LonLat::new(0.0, 0.0),
+            LonLat::new(1.0, 0.0),
+            LonLat::new(1.0, 1.0),
+            LonLat::new(0.0, 1.0),
+            LonLat::new(0.0, 0.0),
+        ]
+    }
+
+    fn sorted<'a, I: Iterator<Item = &'a i64>>(ids: I) -> Vec<i64> {
+        let mut ids: Vec<i64> = ids.cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    fn tag<'a>(tags: &'a [osm_xml::Tag], key: &str) -> Option<&'a str> {
+        tags.iter().find(|t| t.key == key).map(|t| t.val.as_str())
+    }
+
+    fn check_clipped(doc: &osm_xml::OSM) {
+        assert_eq!(sorted(doc.nodes.keys()), vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(sorted(doc.ways.keys()), vec![10, 12]);
+        assert_eq!(sorted(doc.relations.keys()), vec![20, 22, 23]);
+
+        let node = &doc.nodes[&1];
+        assert!((node.lon - 0.5).abs() < 1e-7);
+        assert!((node.lat - 0.5).abs() < 1e-7);
+        assert_eq!(tag(&node.tags, "amenity"), Some("cafe"));
+        assert_eq!(tag(&node.tags, "name"), Some("Caf\u{e9} & <Co>"));
+        assert!(doc.nodes[&2].tags.is_empty());
+        assert_eq!(tag(&doc.nodes[&6].tags, "natural"), Some("tree"));
+
+        let refs: Vec<i64> = doc.ways[&10]
+            .nodes
+            .iter()
+            .map(|r| match r {
+                osm_xml::UnresolvedReference::Node(id) => *id,
+                _ => panic!("way refers to a non-node"),
+            })
+            .collect();
+        assert_eq!(refs, vec![1, 2, 3]);
+        assert_eq!(tag(&doc.ways[&10].tags, "highway"), Some("residential"));
+
+        match &doc.relations[&22].members[0] {
+            osm_xml::Member::Relation(osm_xml::UnresolvedReference::Relation(23), role) => {
+                assert_eq!(role, "");
+            }
+            _ => panic!("route master lost its route"),
+        }
+        match &doc.relations[&23].members[1] {
+            osm_xml::Member::Node(osm_xml::UnresolvedReference::Node(1), role) => {
+                assert_eq!(role, "stop");
+            }
+            _ => panic!("route lost its stop"),
+        }
+    }
+
+    #[test]
+    fn test_read_unclipped() {
+        let path = std::env::temp_dir().join("abst_test_read_unclipped.osm.pbf");
+        let path = path.to_str().unwrap();
+        fixture(path);
+        let doc = read(path, &None, &mut Timer::throwaway()).unwrap();
+        assert_eq!(doc.nodes.len(), 6);
+        assert_eq!(doc.ways.len(), 3);
+        assert_eq!(doc.relations.len(), 5);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_read_clipped() {
+        let path = std::env::temp_dir().join("abst_test_read_clipped.osm.pbf");
+        let path = path.to_str().unwrap();
+        fixture(path);
+        let doc = read(path, &Some(unit_square()), &mut Timer::throwaway()).unwrap();
+        check_clipped(&doc);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_clip_to_xml() {
+        let pbf = std::env::temp_dir().join("abst_test_clip_to_xml.osm.pbf");
+        let pbf = pbf.to_str().unwrap();
+        let xml = std::env::temp_dir().join("abst_test_clip_to_xml.osm");
+        let xml = xml.to_str().unwrap();
+        fixture(pbf);
+        clip_to_xml(pbf, unit_square(), xml, &mut Timer::throwaway()).unwrap();
+        let doc = osm_xml::OSM::parse(File::open(xml).unwrap()).unwrap();
+        check_clipped(&doc);
+        std::fs::remove_file(pbf).unwrap();
+        std::fs::remove_file(xml).unwrap();
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut buf = Vec::new();
+        bytes_field(&mut buf, 1, b"hello");
+        buf.pop();
+        assert!(parse_fields(&buf).is_err());
+        assert!(read_varint(&[0x80, 0x80], &mut 0).is_err());
+    }
+}
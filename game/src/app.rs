@@ -121,6 +121,14 @@ impl App {
                     "- passengers_alighting: {} bytes",
                     prettyprint_usize(serialized_size_bytes(&a.passengers_alighting))
                 );
+                println!(
+                    "- bus_schedule_deviations: {} bytes",
+                    prettyprint_usize(serialized_size_bytes(&a.bus_schedule_deviations))
+                );
+                println!(
+                    "- passengers_left_behind: {} bytes",
+                    prettyprint_usize(serialized_size_bytes(&a.passengers_left_behind))
+                );
                 println!(
                     "- started_trips: {} bytes",
                     prettyprint_usize(serialized_size_bytes(&a.started_trips))
@@ -145,6 +153,10 @@ impl App {
                     "- parking_lot_changes: {} bytes",
                     prettyprint_usize(serialized_size_bytes(&a.parking_lot_changes))
                 );
+                println!(
+                    "- car_crossings: {} bytes",
+                    prettyprint_usize(serialized_size_bytes(&a.car_crossings))
+                );
             }
         }
     }
@@ -535,7 +547,13 @@ pub struct PerMap {
 impl PerMap {
     pub fn new(flags: Flags, cs: &ColorScheme, ctx: &mut EventCtx, timer: &mut Timer) -> PerMap {
         let mut mem = MeasureMemory::new();
-        let (map, sim, _) = flags.sim_flags.load(timer);
+        let (map, sim, _) = match flags.sim_flags.load(timer) {
+            Ok(x) => x,
+            Err(err) => {
+                println!("Can't start the simulation: {}", err);
+                std::process::exit(1);
+            }
+        };
         mem.reset("Map and Sim", timer);
 
         timer.start("draw_map");
@@ -28,7 +28,10 @@ use ezgui::{
     Key, Line, Outcome, PersistentSplit, RewriteColor, Text, TextExt, VerticalAlignment, Widget,
 };
 use geom::Speed;
-use map_model::{EditCmd, IntersectionID, LaneID, LaneType, MapEdits, PermanentMapEdits};
+use map_model::{
+    ControlStopSign, ControlTrafficSignal, EditCmd, EditIntersection, IntersectionID, LaneID,
+    LaneType, MapEdits, PermanentMapEdits,
+};
 use sim::DontDrawAgents;
 use std::collections::BTreeSet;
 
@@ -566,6 +569,10 @@ pub fn maybe_edit_intersection(
         )));
     }
 
+    if app.primary.map.get_i(id).is_roundabout() && app.per_obj.left_click(ctx, "edit roundabout") {
+        return Some(edit_roundabout(id, mode.clone()));
+    }
+
     if app.primary.map.get_i(id).is_closed()
         && app.per_obj.left_click(ctx, "re-open closed intersection")
     {
@@ -583,6 +590,75 @@ pub fn maybe_edit_intersection(
     None
 }
 
+fn edit_roundabout(i: IntersectionID, mode: GameplayMode) -> Box<dyn State> {
+    WizardState::new(Box::new(move |wiz, ctx, app| {
+        let stop_sign = "convert to stop signs";
+        let signal = "convert to traffic signal";
+        let close = "close intersection for construction";
+
+        let mut choices = vec![];
+        // TODO Conflating stop signs and construction here
+        if mode.can_edit_stop_signs() {
+            choices.push(stop_sign);
+            choices.push(close);
+        }
+        choices.push(signal);
+
+        let mut wizard = wiz.wrap(ctx);
+        match wizard.choose_string("", move || choices.clone())?.as_str() {
+            x if x == stop_sign => {
+                let mut edits = app.primary.map.get_edits().clone();
+                edits.commands.push(EditCmd::ChangeIntersection {
+                    i,
+                    old: app.primary.map.get_i_edit(i),
+                    new: EditIntersection::StopSign(ControlStopSign::new(&app.primary.map, i)),
+                });
+                apply_map_edits(ctx, app, edits);
+                Some(Transition::Replace(Box::new(StopSignEditor::new(
+                    ctx,
+                    app,
+                    i,
+                    mode.clone(),
+                ))))
+            }
+            x if x == signal => {
+                let mut edits = app.primary.map.get_edits().clone();
+                edits.commands.push(EditCmd::ChangeIntersection {
+                    i,
+                    old: app.primary.map.get_i_edit(i),
+                    new: EditIntersection::TrafficSignal(
+                        ControlTrafficSignal::new(&app.primary.map, i, &mut Timer::throwaway())
+                            .export_with_plans(&app.primary.map),
+                    ),
+                });
+                apply_map_edits(ctx, app, edits);
+                Some(Transition::Replace(Box::new(TrafficSignalEditor::new(
+                    ctx,
+                    app,
+                    i,
+                    mode.clone(),
+                ))))
+            }
+            x if x == close => {
+                let cmd = EditCmd::ChangeIntersection {
+                    i,
+                    old: app.primary.map.get_i_edit(i),
+                    new: EditIntersection::Closed,
+                };
+                if let Some(err) = check_sidewalk_connectivity(ctx, app, cmd.clone()) {
+                    Some(Transition::Replace(err))
+                } else {
+                    let mut edits = app.primary.map.get_edits().clone();
+                    edits.commands.push(cmd);
+                    apply_map_edits(ctx, app, edits);
+                    Some(Transition::Pop)
+                }
+            }
+            _ => unreachable!(),
+        }
+    }))
+}
+
 fn make_changelist(ctx: &mut EventCtx, app: &App) -> Composite {
     // TODO Support redo. Bit harder here to reset the redo_stack when the edits
     // change, because nested other places modify it too.
@@ -58,6 +58,7 @@ impl StopSignEditor {
             },
             Btn::text_fg("close intersection for construction").build_def(ctx, hotkey(Key::C)),
             Btn::text_fg("convert to traffic signal").build_def(ctx, None),
+            Btn::text_fg("convert to a roundabout").build_def(ctx, None),
             Btn::text_fg("Finish").build_def(ctx, hotkey(Key::Escape)),
         ]))
         .aligned(HorizontalAlignment::Center, VerticalAlignment::Top)
@@ -165,7 +166,7 @@ impl State for StopSignEditor {
                                 self.id,
                                 &mut Timer::throwaway(),
                             )
-                            .export(&app.primary.map),
+                            .export_with_plans(&app.primary.map),
                         ),
                     });
                     apply_map_edits(ctx, app, edits);
@@ -176,6 +177,16 @@ impl State for StopSignEditor {
                         self.mode.clone(),
                     )));
                 }
+                "convert to a roundabout" => {
+                    let mut edits = app.primary.map.get_edits().clone();
+                    edits.commands.push(EditCmd::ChangeIntersection {
+                        i: self.id,
+                        old: app.primary.map.get_i_edit(self.id),
+                        new: EditIntersection::Roundabout,
+                    });
+                    apply_map_edits(ctx, app, edits);
+                    return Transition::Pop;
+                }
                 _ => unreachable!(),
             },
             None => {}
@@ -12,10 +12,10 @@ use ezgui::{
     HorizontalAlignment, Key, Line, Outcome, RewriteColor, Text, TextExt, UpdateType,
     VerticalAlignment, Widget,
 };
-use geom::{ArrowCap, Distance, Duration, Polygon};
+use geom::{ArrowCap, Distance, Duration, Polygon, Speed};
 use map_model::{
-    ControlStopSign, ControlTrafficSignal, EditCmd, EditIntersection, IntersectionID, Phase,
-    PhaseType, TurnGroupID, TurnPriority,
+    ActuatedTiming, ControlStopSign, ControlTrafficSignal, EditCmd, EditIntersection,
+    IntersectionID, PedestrianTiming, Phase, PhaseType, TurnGroupID, TurnPriority, TurnType,
 };
 use std::collections::BTreeSet;
 
@@ -61,7 +61,7 @@ impl TrafficSignalEditor {
         TrafficSignalEditor {
             i: id,
             current_phase: 0,
-            composite: make_signal_diagram(ctx, app, id, 0, true),
+            composite: make_signal_diagram(ctx, app, id, 0, 0, true),
             top_panel: make_top_panel(ctx, app, false, false),
             mode,
             groups: DrawTurnGroup::for_i(id, map),
@@ -74,12 +74,12 @@ impl TrafficSignalEditor {
 
     fn change_phase(&mut self, idx: usize, ctx: &mut EventCtx, app: &App) {
         if self.current_phase == idx {
-            let mut new = make_signal_diagram(ctx, app, self.i, self.current_phase, true);
+            let mut new = make_signal_diagram(ctx, app, self.i, 0, self.current_phase, true);
             new.restore(ctx, &self.composite);
             self.composite = new;
         } else {
             self.current_phase = idx;
-            self.composite = make_signal_diagram(ctx, app, self.i, self.current_phase, true);
+            self.composite = make_signal_diagram(ctx, app, self.i, 0, self.current_phase, true);
             // TODO Maybe center of previous member
             self.composite
                 .scroll_to_member(ctx, format!("phase {}", idx + 1));
@@ -143,7 +143,7 @@ impl State for TrafficSignalEditor {
                         // Don't use change_phase; it tries to preserve scroll
                         self.current_phase = if idx == num_phases { idx - 1 } else { idx };
                         self.composite =
-                            make_signal_diagram(ctx, app, self.i, self.current_phase, true);
+                            make_signal_diagram(ctx, app, self.i, 0, self.current_phase, true);
                         return Transition::Keep;
                     }
                     if let Some(x) = x.strip_prefix("move up phase ") {
@@ -496,6 +496,7 @@ fn edit_entire_signal(
         let all_walk = "add an all-walk phase at the end";
         let stop_sign = "convert to stop signs";
         let close = "close intersection for construction";
+        let roundabout = "convert to a roundabout";
         let offset = "edit signal offset";
         let reset = "reset to default";
 
@@ -508,6 +509,7 @@ fn edit_entire_signal(
             choices.push(stop_sign);
             choices.push(close);
         }
+        choices.push(roundabout);
         choices.push(offset);
         choices.push(reset);
 
@@ -592,6 +594,23 @@ fn edit_entire_signal(
                     Some(Transition::PopTwice)
                 }
             }
+            x if x == roundabout => {
+                // First restore the original signal
+                if let Some(ref orig) = orig_signal {
+                    app.primary
+                        .map
+                        .incremental_edit_traffic_signal(orig.clone());
+                }
+
+                let mut edits = app.primary.map.get_edits().clone();
+                edits.commands.push(EditCmd::ChangeIntersection {
+                    i,
+                    old: app.primary.map.get_i_edit(i),
+                    new: EditIntersection::Roundabout,
+                });
+                apply_map_edits(ctx, app, edits);
+                Some(Transition::PopTwice)
+            }
             x if x == offset => {
                 let new_duration = wizard.input_usize_prefilled(
                     "What should the offset of this traffic signal be (seconds)?",
@@ -626,7 +645,7 @@ fn edit_entire_signal(
                     // Don't use change_phase; it tries to preserve scroll
                     editor.current_phase = 0;
                     editor.composite =
-                        make_signal_diagram(ctx, app, editor.i, editor.current_phase, true);
+                        make_signal_diagram(ctx, app, editor.i, 0, editor.current_phase, true);
                 })))
             }
             _ => unreachable!(),
@@ -635,18 +654,29 @@ fn edit_entire_signal(
 }
 
 fn change_duration(app: &App, i: IntersectionID, idx: usize) -> Box<dyn State> {
-    let current_type = app.primary.map.get_traffic_signal(i).phases[idx]
-        .phase_type
-        .clone();
+    let signal = app.primary.map.get_traffic_signal(i);
+    let current_type = signal.phases[idx].phase_type.clone();
+    let current_ped_timing = signal.phases[idx].ped_timing.clone();
+    // Long enough for somebody walking 3.5ft/s to cross the longest crosswalk in this phase
+    let longest_crosswalk = signal.phases[idx]
+        .protected_groups
+        .iter()
+        .filter(|g| signal.turn_groups[*g].turn_type == TurnType::Crosswalk)
+        .map(|g| signal.turn_groups[g].geom.length())
+        .max();
 
     // TODO This UI shouldn't be a wizard
     WizardState::new(Box::new(move |wiz, ctx, _| {
         let mut wizard = wiz.wrap(ctx);
         let new_duration = Duration::seconds(wizard.input_something(
-            "How long should this phase be (seconds)?",
+            "How long should this phase be (seconds)? For actuated phases, this is the minimum.",
             Some(format!(
                 "{}",
-                current_type.simple_duration().inner_seconds() as usize
+                match current_type {
+                    PhaseType::Actuated(ref t) => t.min_green,
+                    ref other => other.simple_duration(),
+                }
+                .inner_seconds() as usize
             )),
             Box::new(|line| {
                 line.parse::<usize>()
@@ -659,20 +689,124 @@ fn change_duration(app: &App, i: IntersectionID, idx: usize) -> Box<dyn State> {
             "Adaptive: some multiple of {}, based on current demand",
             new_duration
         );
+        let actuated = format!(
+            "Actuated: at least {}, extended while vehicles are detected",
+            new_duration
+        );
         let choice = wizard.choose_string("How should this phase be timed?", move || {
-            vec![fixed.clone(), adaptive.clone()]
+            vec![fixed.clone(), adaptive.clone(), actuated.clone()]
         })?;
         let new_type = if choice.starts_with("Fixed") {
             PhaseType::Fixed(new_duration)
-        } else {
+        } else if choice.starts_with("Adaptive") {
             PhaseType::Adaptive(new_duration)
+        } else {
+            let (max_green, gap_out) = match current_type {
+                PhaseType::Actuated(ref t) => (t.max_green, t.gap_out),
+                _ => (new_duration * 2.0, Duration::seconds(3.0)),
+            };
+            let max_green = Duration::seconds(wizard.input_something(
+                "What's the longest this phase can last (seconds)?",
+                Some(format!("{}", max_green.inner_seconds() as usize)),
+                Box::new(move |line| {
+                    line.parse::<usize>().ok().and_then(|n| {
+                        if Duration::seconds(n as f64) >= new_duration {
+                            Some(n)
+                        } else {
+                            None
+                        }
+                    })
+                }),
+            )? as f64);
+            let gap_out = Duration::seconds(wizard.input_something(
+                "How long without detecting a vehicle before the phase ends early (seconds)?",
+                Some(format!("{}", gap_out.inner_seconds() as usize)),
+                Box::new(|line| {
+                    line.parse::<usize>()
+                        .ok()
+                        .and_then(|n| if n != 0 { Some(n) } else { None })
+                }),
+            )? as f64);
+            PhaseType::Actuated(ActuatedTiming {
+                min_green: new_duration,
+                max_green,
+                gap_out,
+            })
+        };
+
+        let new_ped_timing = if let Some(crosswalk) = longest_crosswalk {
+            let anytime = "Pedestrians may start crossing any time";
+            let timed = "WALK, then flashing don't walk, with an optional leading interval";
+            let choice = wizard.choose_string("How should the pedestrian signals work?", || {
+                vec![anytime, timed]
+            })?;
+            if choice == timed {
+                let (walk, clearance, leading_interval) = match current_ped_timing {
+                    Some(ref t) => (t.walk, t.clearance, t.leading_interval),
+                    None => (
+                        Duration::seconds(7.0),
+                        crosswalk / Speed::miles_per_hour(2.4),
+                        Duration::ZERO,
+                    ),
+                };
+                let walk = Duration::seconds(wizard.input_something(
+                    "How long should crosswalks show WALK (seconds)?",
+                    Some(format!("{}", walk.inner_seconds() as usize)),
+                    Box::new(move |line| {
+                        line.parse::<usize>().ok().and_then(|n| {
+                            if n != 0 && Duration::seconds(n as f64) <= new_duration {
+                                Some(n)
+                            } else {
+                                None
+                            }
+                        })
+                    }),
+                )? as f64);
+                let clearance = Duration::seconds(wizard.input_something(
+                    "How long should they flash don't walk afterwards (seconds)?",
+                    Some(format!("{}", clearance.inner_seconds().ceil() as usize)),
+                    Box::new(move |line| {
+                        line.parse::<usize>().ok().and_then(|n| {
+                            if walk + Duration::seconds(n as f64) <= new_duration {
+                                Some(n)
+                            } else {
+                                None
+                            }
+                        })
+                    }),
+                )? as f64);
+                let leading_interval = Duration::seconds(wizard.input_something(
+                    "How long should vehicles wait for pedestrians to get a head start (seconds)?",
+                    Some(format!("{}", leading_interval.inner_seconds() as usize)),
+                    Box::new(move |line| {
+                        line.parse::<usize>().ok().and_then(|n| {
+                            if Duration::seconds(n as f64) <= walk {
+                                Some(n)
+                            } else {
+                                None
+                            }
+                        })
+                    }),
+                )? as f64);
+                Some(PedestrianTiming {
+                    walk,
+                    clearance,
+                    leading_interval,
+                })
+            } else {
+                None
+            }
+        } else {
+            None
         };
+
         Some(Transition::PopWithData(Box::new(move |state, ctx, app| {
             let editor = state.downcast_mut::<TrafficSignalEditor>().unwrap();
             let orig_signal = app.primary.map.get_traffic_signal(editor.i);
 
             let mut new_signal = orig_signal.clone();
             new_signal.phases[idx].phase_type = new_type;
+            new_signal.phases[idx].ped_timing = new_ped_timing;
             editor.command_stack.push(orig_signal.clone());
             editor.redo_stack.clear();
             editor.top_panel = make_top_panel(ctx, app, true, false);
@@ -710,7 +844,9 @@ fn check_for_missing_groups(
                 edits.commands.push(EditCmd::ChangeIntersection {
                     i: new_signal.id,
                     old: app.primary.map.get_i_edit(new_signal.id),
-                    new: EditIntersection::TrafficSignal(new_signal.export(&app.primary.map)),
+                    new: EditIntersection::TrafficSignal(
+                        new_signal.export_with_plans(&app.primary.map),
+                    ),
                 });
                 apply_map_edits(ctx, app, edits);
             }
@@ -735,7 +871,7 @@ fn check_for_missing_groups(
     new_signal.phases.insert(0, phase);
     let id = new_signal.id;
     app.primary.map.incremental_edit_traffic_signal(new_signal);
-    *composite = make_signal_diagram(ctx, app, id, 0, true);
+    *composite = make_signal_diagram(ctx, app, id, 0, 0, true);
 
     Transition::Push(msg(
         "Error: missing turns",
@@ -10,7 +10,7 @@ use ezgui::{
     hotkey, Btn, Color, Composite, Drawable, EventCtx, GfxCtx, HorizontalAlignment, Key, Line,
     Outcome, Text, VerticalAlignment, Widget,
 };
-use map_model::{EditCmd, RoadID};
+use map_model::{EditCmd, PathConstraints, RoadID};
 use maplit::btreeset;
 use sim::TripMode;
 use std::collections::BTreeSet;
@@ -90,11 +90,15 @@ impl State for ZoneEditor {
                         });
                     }
 
-                    let new_allow_through_traffic = self
+                    let mut new_allow_through_traffic = self
                         .allow_through_traffic
                         .iter()
                         .map(|m| m.to_constraints())
                         .collect::<EnumSet<_>>();
+                    // Trucks follow the same rules as cars
+                    if new_allow_through_traffic.contains(PathConstraints::Car) {
+                        new_allow_through_traffic.insert(PathConstraints::Truck);
+                    }
                     for r in &self.selector.roads {
                         let old_allow_through_traffic =
                             app.primary.map.get_r(*r).allow_through_traffic.clone();
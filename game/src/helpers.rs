@@ -126,6 +126,8 @@ pub fn color_for_mode(app: &App, m: TripMode) -> Color {
         TripMode::Bike => app.cs.unzoomed_bike,
         TripMode::Transit => app.cs.unzoomed_bus,
         TripMode::Drive => app.cs.unzoomed_car,
+        TripMode::RideHail => Color::ORANGE,
+        TripMode::ParkAndRide => Color::PURPLE,
     }
 }
 
@@ -147,6 +149,9 @@ pub fn color_for_trip_phase(app: &App, tpt: TripPhaseType) -> Color {
         TripPhaseType::Parking => app.cs.parking_trip,
         TripPhaseType::WaitingForBus(_, _) => app.cs.bus_layer,
         TripPhaseType::RidingBus(_, _, _) => app.cs.bus_lane,
+        TripPhaseType::WaitingForRideHail => Color::ORANGE.alpha(0.5),
+        TripPhaseType::RidingRideHail(_) => Color::ORANGE,
+        TripPhaseType::Delivering => app.cs.parking_trip,
         TripPhaseType::Aborted | TripPhaseType::Finished => unreachable!(),
         TripPhaseType::DelayedStart => Color::YELLOW,
         TripPhaseType::Remote => Color::PINK,
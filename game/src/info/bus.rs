@@ -3,7 +3,7 @@ use crate::helpers::ID;
 use crate::info::{header_btns, make_tabs, Details, Tab};
 use abstutil::{prettyprint_usize, Counter};
 use ezgui::{Btn, Color, EventCtx, Line, RewriteColor, Text, TextExt, Widget};
-use geom::{Circle, Distance, Time};
+use geom::{Circle, Distance, Duration, Time};
 use map_model::{BusRouteID, BusStopID, PathConstraints};
 use sim::{AgentID, CarID};
 
@@ -227,6 +227,51 @@ pub fn route(ctx: &mut EventCtx, app: &App, details: &mut Details, id: BusRouteI
         .draw(ctx),
     );
 
+    let analytics = app.primary.sim.get_analytics();
+    let now = app.primary.sim.time();
+    let (early, on_time, late) = analytics.on_time_performance(id, now);
+    if early + on_time + late > 0 {
+        rows.push(
+            Text::from_all(vec![
+                Line("On time"),
+                Line(format!(
+                    ": {}% ({} early, {} late)",
+                    100 * on_time / (early + on_time + late),
+                    prettyprint_usize(early),
+                    prettyprint_usize(late)
+                ))
+                .secondary(),
+            ])
+            .draw(ctx),
+        );
+    }
+    let headways = analytics.bus_headways(id, now);
+    if !headways.is_empty() {
+        let avg = headways.iter().map(|(_, _, dt)| *dt).sum::<Duration>() / (headways.len() as f64);
+        let mut txt = Text::from_all(vec![
+            Line("Average headway"),
+            Line(format!(": {}", avg)).secondary(),
+        ]);
+        if let Some(dt) = route.scheduled_headway() {
+            txt.append(Line(format!(" (scheduled {})", dt)).secondary());
+        }
+        rows.push(txt.draw(ctx));
+
+        let (bunched, total) = analytics.bus_bunching(id, now, &app.primary.map);
+        rows.push(
+            Text::from_all(vec![
+                Line("Bunched"),
+                Line(format!(
+                    ": {} of {} arrivals",
+                    prettyprint_usize(bunched),
+                    prettyprint_usize(total)
+                ))
+                .secondary(),
+            ])
+            .draw(ctx),
+        );
+    }
+
     rows.push(format!("{} stops", route.stops.len()).draw_text(ctx));
     for bs in &route.stops {
         let bs = app.primary.map.get_bs(*bs);
@@ -296,6 +296,7 @@ fn header(
         IntersectionType::TrafficSignal => format!("{} (Traffic signals)", id),
         IntersectionType::Border => format!("Border #{}", id.0),
         IntersectionType::Construction => format!("{} (under construction)", id),
+        IntersectionType::Roundabout => format!("{} (Roundabout)", id),
     };
     rows.push(Widget::row(vec![
         Line(label).small_heading().draw(ctx),
@@ -139,8 +139,10 @@ pub fn trips(
                         match trip.mode {
                             TripMode::Walk => "system/assets/meters/pedestrian.svg",
                             TripMode::Bike => "system/assets/meters/bike.svg",
-                            TripMode::Drive => "system/assets/meters/car.svg",
-                            TripMode::Transit => "system/assets/meters/bus.svg",
+                            TripMode::Drive | TripMode::RideHail => "system/assets/meters/car.svg",
+                            TripMode::Transit | TripMode::ParkAndRide => {
+                                "system/assets/meters/bus.svg"
+                            }
                         },
                         RewriteColor::ChangeAll(color),
                     ),
@@ -462,12 +464,15 @@ pub fn parked_car(
 
     // TODO how long idle, prev trips, next trips, etc
 
-    let p = app.primary.sim.get_owner_of_car(id).unwrap();
-    rows.push(Btn::text_bg2(format!("Owned by {}", p)).build_def(ctx, None));
-    details.hyperlinks.insert(
-        format!("Owned by {}", p),
-        Tab::PersonTrips(p, BTreeMap::new()),
-    );
+    if let Some(p) = app.primary.sim.get_owner_of_car(id) {
+        rows.push(Btn::text_bg2(format!("Owned by {}", p)).build_def(ctx, None));
+        details.hyperlinks.insert(
+            format!("Owned by {}", p),
+            Tab::PersonTrips(p, BTreeMap::new()),
+        );
+    } else {
+        rows.push("Part of the ride-hail fleet".draw_text(ctx));
+    }
 
     if let Some(p) = app.primary.sim.lookup_parked_car(id) {
         match p.spot {
@@ -522,12 +527,21 @@ fn header(
                         ("walking", Some("system/assets/meters/pedestrian.svg"))
                     }
                     AgentID::Car(c) => match c.1 {
-                        VehicleType::Car => ("driving", Some("system/assets/meters/car.svg")),
+                        VehicleType::Car | VehicleType::Truck => {
+                            ("driving", Some("system/assets/meters/car.svg"))
+                        }
                         VehicleType::Bike => ("biking", Some("system/assets/meters/bike.svg")),
                         VehicleType::Bus | VehicleType::Train => unreachable!(),
                     },
-                    AgentID::BusPassenger(_, _) => {
-                        ("riding a bus", Some("system/assets/meters/bus.svg"))
+                    AgentID::BusPassenger(_, c) => {
+                        if c.1 == VehicleType::Car {
+                            (
+                                "riding in a ride-hail",
+                                Some("system/assets/meters/car.svg"),
+                            )
+                        } else {
+                            ("riding a bus", Some("system/assets/meters/bus.svg"))
+                        }
                     }
                 }
             } else {
@@ -275,6 +275,19 @@ pub fn finished(
                 .force_width_pct(ctx, col_width),
             waiting.to_string().draw_text(ctx),
         ]));
+
+        let analytics = if open_trips[&id].show_after {
+            app.primary.sim.get_analytics()
+        } else {
+            app.prebaked()
+        };
+        if let Some(emissions) = analytics.trip_emissions.get(&id) {
+            col.push(Widget::custom_row(vec![
+                Widget::custom_row(vec![Line("CO2 emitted").secondary().draw(ctx)])
+                    .force_width_pct(ctx, col_width),
+                format!("{:.0} g", emissions.co2).draw_text(ctx),
+            ]));
+        }
     }
 
     col.push(make_timeline(
@@ -505,6 +518,12 @@ fn make_timeline(
                         "system/assets/timeline/waiting_for_bus.svg"
                     }
                     TripPhaseType::RidingBus(_, _, _) => "system/assets/timeline/riding_bus.svg",
+                    // TODO Need icons for ride-hail
+                    TripPhaseType::WaitingForRideHail => {
+                        "system/assets/timeline/waiting_for_bus.svg"
+                    }
+                    TripPhaseType::RidingRideHail(_) => "system/assets/timeline/driving.svg",
+                    TripPhaseType::Delivering => "system/assets/timeline/parking.svg",
                     TripPhaseType::Aborted | TripPhaseType::Finished => unreachable!(),
                     TripPhaseType::DelayedStart => "system/assets/timeline/delayed_start.svg",
                     // TODO What icon should represent this?
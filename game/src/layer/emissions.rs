@@ -0,0 +1,128 @@
+use crate::app::App;
+use crate::common::{ColorLegend, ColorNetwork};
+use crate::layer::{Layer, LayerOutcome};
+use abstutil::Counter;
+use ezgui::{
+    hotkey, Btn, Choice, Composite, Drawable, EventCtx, GfxCtx, HorizontalAlignment, Key, Line,
+    Outcome, Text, TextExt, VerticalAlignment, Widget,
+};
+use geom::Time;
+use sim::Pollutant;
+
+pub struct Emissions {
+    time: Time,
+    pollutant: Pollutant,
+    unzoomed: Drawable,
+    zoomed: Drawable,
+    composite: Composite,
+}
+
+impl Layer for Emissions {
+    fn name(&self) -> Option<&'static str> {
+        Some("emissions")
+    }
+    fn event(
+        &mut self,
+        ctx: &mut EventCtx,
+        app: &mut App,
+        minimap: &Composite,
+    ) -> Option<LayerOutcome> {
+        if app.primary.sim.time() != self.time {
+            *self = Emissions::new(ctx, app, self.pollutant);
+        }
+
+        self.composite.align_above(ctx, minimap);
+        match self.composite.event(ctx) {
+            Some(Outcome::Clicked(x)) => match x.as_ref() {
+                "close" => {
+                    return Some(LayerOutcome::Close);
+                }
+                _ => unreachable!(),
+            },
+            None => {
+                let pollutant = self.composite.dropdown_value("pollutant");
+                if pollutant != self.pollutant {
+                    *self = Emissions::new(ctx, app, pollutant);
+                    self.composite.align_above(ctx, minimap);
+                }
+            }
+        }
+        None
+    }
+    fn draw(&self, g: &mut GfxCtx, app: &App) {
+        self.composite.draw(g);
+        if g.canvas.cam_zoom < app.opts.min_zoom_for_detail {
+            g.redraw(&self.unzoomed);
+        } else {
+            g.redraw(&self.zoomed);
+        }
+    }
+    fn draw_minimap(&self, g: &mut GfxCtx) {
+        g.redraw(&self.unzoomed);
+    }
+}
+
+impl Emissions {
+    pub fn new(ctx: &mut EventCtx, app: &App, pollutant: Pollutant) -> Emissions {
+        let stats = app.primary.sim.get_analytics();
+        // Counters only hold whole numbers, so rank by milligrams
+        let mut per_road = Counter::new();
+        for (r, amount) in &stats.road_emissions {
+            per_road.add(*r, (amount.get(pollutant) * 1000.0) as usize);
+        }
+        let mut per_intersection = Counter::new();
+        for (i, amount) in &stats.intersection_emissions {
+            per_intersection.add(*i, (amount.get(pollutant) * 1000.0) as usize);
+        }
+
+        let mut colorer = ColorNetwork::new(app);
+        colorer.ranked_roads(per_road, &app.cs.good_to_bad_red);
+        colorer.ranked_intersections(per_intersection, &app.cs.good_to_bad_red);
+        let (unzoomed, zoomed) = colorer.build(ctx);
+
+        let total = stats.total_emissions();
+        let composite = Composite::new(Widget::col(vec![
+            Widget::row(vec![
+                Widget::draw_svg(ctx, "system/assets/tools/layers.svg"),
+                "Emissions".draw_text(ctx),
+                Btn::plaintext("X")
+                    .build(ctx, "close", hotkey(Key::Escape))
+                    .align_right(),
+            ]),
+            Text::from(
+                Line("This counts tailpipe emissions from all vehicles since midnight").secondary(),
+            )
+            .wrap_to_pct(ctx, 15)
+            .draw(ctx),
+            Text::from_multiline(vec![
+                Line(format!("{:.1} kg of CO2", total.co2 / 1000.0)),
+                Line(format!("{:.1} g of NOx", total.nox)),
+                Line(format!("{:.1} g of particulate matter", total.pm)),
+            ])
+            .draw(ctx),
+            Widget::row(vec![
+                "Show:".draw_text(ctx),
+                Widget::dropdown(
+                    ctx,
+                    "pollutant",
+                    pollutant,
+                    Pollutant::all()
+                        .into_iter()
+                        .map(|p| Choice::new(p.describe(), p))
+                        .collect(),
+                ),
+            ]),
+            ColorLegend::gradient(ctx, &app.cs.good_to_bad_red, vec!["lowest", "highest"]),
+        ]))
+        .aligned(HorizontalAlignment::Right, VerticalAlignment::Center)
+        .build(ctx);
+
+        Emissions {
+            time: app.primary.sim.time(),
+            pollutant,
+            unzoomed,
+            zoomed,
+            composite,
+        }
+    }
+}
@@ -1,4 +1,5 @@
 mod elevation;
+mod emissions;
 pub mod map;
 mod pandemic;
 mod parking;
@@ -11,6 +12,7 @@ use crate::common::HeatmapOptions;
 use crate::game::{DrawBaselayer, State, Transition};
 use crate::helpers::hotkey_btn;
 use ezgui::{hotkey, Btn, Composite, EventCtx, GfxCtx, Key, Line, Outcome, TextExt, Widget};
+use sim::Pollutant;
 
 // TODO Good ideas in
 // https://towardsdatascience.com/top-10-map-types-in-data-visualization-b3a80898ea70
@@ -105,6 +107,7 @@ impl PickLayer {
                     btn("delay", Key::D),
                     btn("throughput", Key::T),
                     btn("traffic jams", Key::J),
+                    btn("emissions", Key::O),
                 ]),
                 Widget::col(vec![
                     "Map".draw_text(ctx),
@@ -155,6 +158,13 @@ impl State for PickLayer {
                 "traffic jams" => {
                     app.layer = Some(Box::new(traffic::TrafficJams::new(ctx, app)));
                 }
+                "emissions" => {
+                    app.layer = Some(Box::new(emissions::Emissions::new(
+                        ctx,
+                        app,
+                        Pollutant::CO2,
+                    )));
+                }
                 "throughput" => {
                     app.layer = Some(Box::new(traffic::Throughput::new(ctx, app, false)));
                 }
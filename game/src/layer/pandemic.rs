@@ -4,10 +4,11 @@ use crate::layer::{Layer, LayerOutcome};
 use abstutil::prettyprint_usize;
 use ezgui::{
     hotkey, Btn, Checkbox, Choice, Color, Composite, Drawable, EventCtx, GeomBatch, GfxCtx,
-    HorizontalAlignment, Key, Line, Outcome, Text, TextExt, VerticalAlignment, Widget,
+    HorizontalAlignment, Key, Line, LinePlot, Outcome, PlotOptions, Series, Text, TextExt,
+    VerticalAlignment, Widget,
 };
 use geom::{Circle, Distance, Pt2D, Time};
-use sim::{GetDrawAgents, PersonState};
+use sim::{GetDrawAgents, PersonState, SEIRCounts};
 use std::collections::HashSet;
 
 // TODO Disable drawing unzoomed agents... or alternatively, implement this by asking Sim to
@@ -214,6 +215,7 @@ fn make_controls(
             )),
         ])
         .draw(ctx),
+        seir_plot(ctx, app),
         Widget::row(vec![
             "Filter:".draw_text(ctx),
             Widget::dropdown(
@@ -231,6 +233,15 @@ fn make_controls(
         ]),
     ];
 
+    let interventions = model.interventions_started();
+    if !interventions.is_empty() {
+        let mut txt = Text::from(Line("Interventions"));
+        for (t, action) in interventions {
+            txt.add(Line(format!("{}: {}", t.ampm_tostring(), action)).secondary());
+        }
+        col.push(txt.wrap_to_pct(ctx, 15).draw(ctx));
+    }
+
     col.push(Checkbox::text(
         ctx,
         "Show heatmap",
@@ -245,3 +256,25 @@ fn make_controls(
         .aligned(HorizontalAlignment::Right, VerticalAlignment::Center)
         .build(ctx)
 }
+
+fn seir_plot(ctx: &EventCtx, app: &App) -> Widget {
+    let counts = app
+        .primary
+        .sim
+        .get_pandemic_model()
+        .unwrap()
+        .counts_over_time(app.primary.sim.time());
+    let make_series = |label: &str, color: Color, count: fn(&SEIRCounts) -> usize| Series {
+        label: label.to_string(),
+        color,
+        pts: counts.iter().map(|c| (c.time, count(c))).collect(),
+    };
+    let series = vec![
+        make_series("Sane", Color::GREEN, |c| c.sane),
+        make_series("Exposed", Color::YELLOW, |c| c.exposed),
+        make_series("Infected", Color::RED, |c| c.infected),
+        make_series("Recovered", Color::CYAN, |c| c.recovered),
+        make_series("Dead", Color::PURPLE, |c| c.dead),
+    ];
+    LinePlot::new(ctx, series, PlotOptions::fixed())
+}
@@ -69,7 +69,7 @@ impl DrawIntersection {
                         .centered_on(i.polygon.center()),
                 );
             }
-            IntersectionType::TrafficSignal => {}
+            IntersectionType::TrafficSignal | IntersectionType::Roundabout => {}
         }
 
         let zorder = i.get_zorder(map);
@@ -137,10 +137,11 @@ impl Renderable for DrawIntersection {
                 .unwrap_or(true);
             if recalc {
                 let (idx, remaining) = app.primary.sim.current_phase_and_remaining_time(self.id);
+                let plan = app.primary.sim.current_signal_plan(self.id);
                 let mut batch = GeomBatch::new();
                 draw_signal_phase(
                     g.prerender,
-                    &signal.phases[idx],
+                    &signal.plan_phases(plan)[idx],
                     self.id,
                     Some(remaining),
                     &mut batch,
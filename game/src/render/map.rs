@@ -482,7 +482,7 @@ impl UnzoomedAgents {
 
     fn color(&self, agent: &UnzoomedAgent) -> Option<Color> {
         match agent.vehicle_type {
-            Some(VehicleType::Car) => {
+            Some(VehicleType::Car) | Some(VehicleType::Truck) => {
                 if self.cars {
                     if agent.parking {
                         Some(self.parking_color)
@@ -283,6 +283,8 @@ pub fn make_signal_diagram(
     ctx: &mut EventCtx,
     app: &App,
     i: IntersectionID,
+    // Which timing plan to show. The editor only changes the first plan.
+    plan: usize,
     selected: usize,
     edit_mode: bool,
 ) -> Composite {
@@ -294,6 +296,7 @@ pub fn make_signal_diagram(
     let bbox = Polygon::rectangle(zoom * bounds.width(), zoom * bounds.height());
 
     let signal = app.primary.map.get_traffic_signal(i);
+    let phases = signal.plan_phases(plan);
     let txt_widget = {
         let mut txt = Text::from(Line(i.to_string()).big_heading_plain());
 
@@ -307,15 +310,37 @@ pub fn make_signal_diagram(
         }
 
         txt.add(Line(""));
-        txt.add(Line(format!("{} phases", signal.phases.len())).small_heading());
-        txt.add(Line(format!("Signal offset: {}", signal.offset)));
-        {
-            let mut total = Duration::ZERO;
-            for p in &signal.phases {
-                total += p.phase_type.simple_duration();
+        if plan != 0 {
+            txt.add(Line(format!(
+                "Timing plan from {}",
+                signal.plans[plan - 1].start_time.ampm_tostring()
+            )));
+        }
+        txt.add(Line(format!("{} phases", phases.len())).small_heading());
+        txt.add(Line(format!("Signal offset: {}", signal.plan_offset(plan))));
+        // TODO Say "normally" or something?
+        txt.add(Line(format!(
+            "One cycle lasts {}",
+            signal.cycle_length(plan)
+        )));
+        // Summarize the other plans
+        if plan != 0 {
+            txt.add(Line(format!(
+                "Until {}: {} phases, offset {}",
+                signal.plans[0].start_time.ampm_tostring(),
+                signal.phases.len(),
+                signal.offset
+            )));
+        }
+        for (idx, other) in signal.plans.iter().enumerate() {
+            if idx + 1 != plan {
+                txt.add(Line(format!(
+                    "From {}: {} phases, offset {}",
+                    other.start_time.ampm_tostring(),
+                    other.phases.len(),
+                    other.offset
+                )));
             }
-            // TODO Say "normally" or something?
-            txt.add(Line(format!("One cycle lasts {}", total)));
         }
         txt.draw(ctx)
     };
@@ -333,7 +358,7 @@ pub fn make_signal_diagram(
         ])]
     };
 
-    for (idx, phase) in signal.phases.iter().enumerate() {
+    for (idx, phase) in phases.iter().enumerate() {
         // Separator
         col.push(
             Widget::draw_batch(
@@ -385,6 +410,12 @@ pub fn make_signal_diagram(
                         PhaseType::Adaptive(d) => {
                             Line(format!("Phase {}: {} (adaptive)", idx + 1, d))
                         }
+                        PhaseType::Actuated(ref t) => Line(format!(
+                            "Phase {}: {} to {} (actuated)",
+                            idx + 1,
+                            t.min_green,
+                            t.max_green
+                        )),
                     }
                     .small_heading()
                     .draw(ctx),
@@ -434,6 +465,13 @@ pub fn make_signal_diagram(
                     PhaseType::Adaptive(d) => {
                         format!("Phase {}: {} (adaptive)", idx + 1, d).draw_text(ctx)
                     }
+                    PhaseType::Actuated(ref t) => format!(
+                        "Phase {}: {} to {} (actuated)",
+                        idx + 1,
+                        t.min_green,
+                        t.max_green
+                    )
+                    .draw_text(ctx),
                 },
                 phase_btn,
             ])
@@ -211,6 +211,8 @@ impl AgentSpawner {
                         TripMode::Drive,
                         TripMode::all()
                             .into_iter()
+                            // These need a lot and a trip back
+                            .filter(|m| *m != TripMode::ParkAndRide)
                             .map(|m| Choice::new(m.ongoing_verb(), m))
                             .collect(),
                     ),
@@ -438,7 +440,7 @@ fn path_request(
         end: pos(to, mode, false, map)?,
         constraints: match mode {
             TripMode::Walk | TripMode::Transit => PathConstraints::Pedestrian,
-            TripMode::Drive => PathConstraints::Car,
+            TripMode::Drive | TripMode::RideHail | TripMode::ParkAndRide => PathConstraints::Car,
             TripMode::Bike => PathConstraints::Bike,
         },
     })
@@ -449,7 +451,9 @@ fn pos(endpt: TripEndpoint, mode: TripMode, from: bool, map: &Map) -> Option<Pos
         TripEndpoint::Bldg(b) => match mode {
             TripMode::Walk | TripMode::Transit => Some(map.get_b(b).front_path.sidewalk),
             TripMode::Bike => Some(DrivingGoal::ParkNear(b).goal_pos(PathConstraints::Bike, map)),
-            TripMode::Drive => Some(DrivingGoal::ParkNear(b).goal_pos(PathConstraints::Car, map)),
+            TripMode::Drive | TripMode::RideHail | TripMode::ParkAndRide => {
+                Some(DrivingGoal::ParkNear(b).goal_pos(PathConstraints::Car, map))
+            }
         },
         TripEndpoint::Border(i, _) => match mode {
             TripMode::Walk | TripMode::Transit => if from {
@@ -458,23 +462,25 @@ fn pos(endpt: TripEndpoint, mode: TripMode, from: bool, map: &Map) -> Option<Pos
                 SidewalkSpot::end_at_border(i, None, map)
             }
             .map(|spot| spot.sidewalk_pos),
-            TripMode::Bike | TripMode::Drive => (if from {
-                map.get_i(i).some_outgoing_road(map)
-            } else {
-                map.get_i(i).some_incoming_road(map)
-            })
-            .and_then(|dr| {
-                dr.lanes(
-                    if mode == TripMode::Bike {
-                        PathConstraints::Bike
-                    } else {
-                        PathConstraints::Car
-                    },
-                    map,
-                )
-                .get(0)
-                .map(|l| Position::start(*l))
-            }),
+            TripMode::Bike | TripMode::Drive | TripMode::RideHail | TripMode::ParkAndRide => {
+                (if from {
+                    map.get_i(i).some_outgoing_road(map)
+                } else {
+                    map.get_i(i).some_incoming_road(map)
+                })
+                .and_then(|dr| {
+                    dr.lanes(
+                        if mode == TripMode::Bike {
+                            PathConstraints::Bike
+                        } else {
+                            PathConstraints::Car
+                        },
+                        map,
+                    )
+                    .get(0)
+                    .map(|l| Position::start(*l))
+                })
+            }
         },
     }
 }
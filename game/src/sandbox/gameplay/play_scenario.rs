@@ -274,7 +274,11 @@ fn new_modifier(scenario_name: String, modifiers: Vec<ScenarioModifier>) -> Box<
         let mut wizard = wiz.wrap(ctx);
         let new_mod = match wizard
             .choose_string("", || {
-                vec!["repeat days", "cancel all trips for some people"]
+                vec![
+                    "repeat days",
+                    "cancel all trips for some people",
+                    "add delivery tours",
+                ]
             })?
             .as_str()
         {
@@ -284,6 +288,9 @@ fn new_modifier(scenario_name: String, modifiers: Vec<ScenarioModifier>) -> Box<
             x if x == "cancel all trips for some people" => ScenarioModifier::CancelPeople(
                 wizard.input_percent("What percent of people should cancel trips? (0 to 100)")?,
             ),
+            x if x == "add delivery tours" => {
+                ScenarioModifier::AddDeliveryTours(wizard.input_usize("How many delivery trucks?")?)
+            }
             _ => unreachable!(),
         };
         let mut mods = modifiers.clone();
@@ -322,6 +329,8 @@ impl ChangeMode {
                         TripMode::Bike,
                         TripMode::all()
                             .into_iter()
+                            // These need a lot and a trip back
+                            .filter(|m| *m != TripMode::ParkAndRide)
                             .map(|m| Choice::new(m.ongoing_verb(), m))
                             .collect(),
                     ),
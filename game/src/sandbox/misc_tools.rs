@@ -64,6 +64,8 @@ impl RoutePreview {
 
 pub struct ShowTrafficSignal {
     i: IntersectionID,
+    // The timing plan in effect when this was opened
+    plan: usize,
     composite: Composite,
     current_phase: usize,
 }
@@ -71,9 +73,11 @@ pub struct ShowTrafficSignal {
 impl ShowTrafficSignal {
     pub fn new(ctx: &mut EventCtx, app: &App, i: IntersectionID) -> Box<dyn State> {
         let (idx, _) = app.primary.sim.current_phase_and_remaining_time(i);
+        let plan = app.primary.sim.current_signal_plan(i);
         return Box::new(ShowTrafficSignal {
             i,
-            composite: make_signal_diagram(ctx, app, i, idx, false),
+            plan,
+            composite: make_signal_diagram(ctx, app, i, plan, idx, false),
             current_phase: idx,
         });
     }
@@ -81,7 +85,8 @@ impl ShowTrafficSignal {
     fn change_phase(&mut self, idx: usize, ctx: &mut EventCtx, app: &App) {
         if self.current_phase != idx {
             self.current_phase = idx;
-            self.composite = make_signal_diagram(ctx, app, self.i, self.current_phase, false);
+            self.composite =
+                make_signal_diagram(ctx, app, self.i, self.plan, self.current_phase, false);
             self.composite
                 .scroll_to_member(ctx, format!("phase {}", idx + 1));
         }
@@ -97,7 +102,14 @@ impl State for ShowTrafficSignal {
             self.change_phase(self.current_phase - 1, ctx, app);
         }
 
-        if self.current_phase != app.primary.map.get_traffic_signal(self.i).phases.len() - 1
+        if self.current_phase
+            != app
+                .primary
+                .map
+                .get_traffic_signal(self.i)
+                .plan_phases(self.plan)
+                .len()
+                - 1
             && ctx.input.new_was_pressed(&hotkey(Key::DownArrow).unwrap())
         {
             self.change_phase(self.current_phase + 1, ctx, app);
@@ -129,7 +141,10 @@ impl State for ShowTrafficSignal {
         let mut batch = GeomBatch::new();
         draw_signal_phase(
             g.prerender,
-            &app.primary.map.get_traffic_signal(self.i).phases[self.current_phase],
+            &app.primary
+                .map
+                .get_traffic_signal(self.i)
+                .plan_phases(self.plan)[self.current_phase],
             self.i,
             None,
             &mut batch,
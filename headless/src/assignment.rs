@@ -0,0 +1,176 @@
+use abstutil::Timer;
+use geom::{Duration, Time};
+use map_model::{Map, Path, PathConstraints, PathRequest, Traversable};
+use rand::Rng;
+use serde::Serialize;
+use sim::{Analytics, Scenario, Sim, SimFlags, TripID, TripPhaseType};
+use std::collections::BTreeMap;
+
+#[derive(Serialize)]
+pub struct Iteration {
+    pub iteration: usize,
+    // How much longer drivers' routes took than the fastest routes, using the travel times
+    // measured in this iteration, as a fraction of the total. 0 means nobody could do better.
+    pub relative_gap: f64,
+    pub driving_trips: usize,
+    // How many of those will switch to the fastest route in the next iteration
+    pub rerouted: usize,
+}
+
+// Iterative traffic assignment. Initially, every driver takes the fastest route assuming free-flow
+// speeds. Each iteration runs the whole scenario, measures how long cars actually took to cross
+// each lane and turn, and re-weights the driving graph with those times. Then a fraction of
+// drivers switch to the new fastest route, and everybody else keeps their route. This repeats
+// until the relative gap is small enough.
+//
+// Every run uses the same RNG seed, so trips line up between iterations. Afterwards, the map keeps
+// routing cars with the last measured times. Returns every iteration, plus the paths drivers took
+// in the last run and its full Analytics.
+pub fn assign(
+    map: &mut Map,
+    scenario: &Scenario,
+    sim_flags: &SimFlags,
+    end_time: Time,
+    iterations: usize,
+    reroute_fraction: f64,
+    converged_gap: f64,
+) -> (
+    Vec<Iteration>,
+    BTreeMap<TripID, (PathRequest, Path)>,
+    Analytics,
+) {
+    let mut timer = Timer::new("iterative traffic assignment");
+    let mut rng = sim_flags.make_rng();
+    let mut assigned: BTreeMap<TripID, (PathRequest, Path)> = BTreeMap::new();
+    let mut results = Vec::new();
+    let mut last_run = (BTreeMap::new(), Analytics::new());
+
+    timer.start_iter("assign traffic", iterations);
+    for iteration in 0..iterations {
+        timer.next();
+        let (requests, travel_times, analytics) =
+            run(map, scenario, sim_flags, end_time, assigned.clone());
+
+        // Trips without an assigned path (or whose request changed) pathfound when they started,
+        // using the travel times from the previous iteration. The map still has those. Trips that
+        // didn't start this time are dropped.
+        let mut previous = std::mem::replace(&mut assigned, BTreeMap::new());
+        for (trip, req) in requests {
+            let path = match previous.remove(&trip) {
+                Some((r, path)) if r == req => Some(path),
+                _ => map.pathfind(req.clone()),
+            };
+            if let Some(path) = path {
+                assigned.insert(trip, (req, path));
+            }
+        }
+
+        map.set_car_travel_times(travel_times.clone());
+        // Remember the paths driven this time, before some drivers switch.
+        last_run = (assigned.clone(), analytics);
+
+        let mut experienced = Duration::ZERO;
+        let mut best = Duration::ZERO;
+        let mut rerouted = 0;
+        for (req, path) in assigned.values_mut() {
+            let fastest = match map.pathfind(req.clone()) {
+                Some(p) => p,
+                None => {
+                    continue;
+                }
+            };
+            let current_cost = path_cost(path, &travel_times, map);
+            let fastest_cost = path_cost(&fastest, &travel_times, map);
+            experienced += current_cost;
+            best += fastest_cost.min(current_cost);
+            if fastest_cost < current_cost && rng.gen_bool(reroute_fraction) {
+                *path = fastest;
+                rerouted += 1;
+            }
+        }
+        let relative_gap = if experienced == Duration::ZERO {
+            0.0
+        } else {
+            (experienced - best) / experienced
+        };
+        timer.note(format!(
+            "Iteration {}: relative gap {:.4} over {} driving trips, rerouting {}",
+            iteration + 1,
+            relative_gap,
+            assigned.len(),
+            rerouted
+        ));
+        results.push(Iteration {
+            iteration: iteration + 1,
+            relative_gap,
+            driving_trips: assigned.len(),
+            rerouted,
+        });
+        if relative_gap <= converged_gap {
+            timer.note(format!("Converged after {} iterations", iteration + 1));
+            break;
+        }
+    }
+    timer.done();
+    (results, last_run.0, last_run.1)
+}
+
+// Runs the scenario with some paths assigned. Returns the request for every trip's driving
+// portion, the average time cars took to cross each lane and turn, and the full Analytics.
+fn run(
+    map: &Map,
+    scenario: &Scenario,
+    sim_flags: &SimFlags,
+    end_time: Time,
+    assigned: BTreeMap<TripID, (PathRequest, Path)>,
+) -> (
+    BTreeMap<TripID, PathRequest>,
+    BTreeMap<Traversable, Duration>,
+    Analytics,
+) {
+    let mut timer = Timer::throwaway();
+    let mut opts = sim_flags.opts.clone();
+    // Don't clobber the log from every run
+    opts.event_log = None;
+    opts.record_car_crossings = true;
+    let mut sim = Sim::new(map, opts, &mut timer);
+    scenario.instantiate(&mut sim, map, &mut sim_flags.make_rng(), &mut timer);
+    sim.assign_paths(assigned);
+    sim.timed_step(map, end_time - sim.time(), &mut None, &mut timer);
+
+    let analytics = sim.get_analytics().clone();
+    let mut requests = BTreeMap::new();
+    for (_, trip, maybe_req, phase) in &analytics.trip_log {
+        if let (TripPhaseType::Driving, Some(req)) = (phase, maybe_req) {
+            if req.constraints == PathConstraints::Car {
+                requests.insert(*trip, req.clone());
+            }
+        }
+    }
+    let travel_times = analytics.car_travel_times();
+    (requests, travel_times, analytics)
+}
+
+// Uses measured travel times where possible, otherwise assumes free-flow speeds, just like the
+// driving graph. Since every path for a request starts and ends on the same lanes, it doesn't
+// matter that those are counted completely.
+fn path_cost(path: &Path, travel_times: &BTreeMap<Traversable, Duration>, map: &Map) -> Duration {
+    path.get_steps()
+        .iter()
+        .map(|step| {
+            let step = step.as_traversable();
+            if let Some(dt) = travel_times.get(&step) {
+                return *dt;
+            }
+            match step {
+                Traversable::Lane(l) => {
+                    let lane = map.get_l(l);
+                    lane.length() / map.get_r(lane.parent).speed_limit
+                }
+                Traversable::Turn(t) => {
+                    map.get_t(t).geom.length() / map.get_parent(t.dst).speed_limit
+                }
+            }
+        })
+        .sum()
+}
@@ -0,0 +1,305 @@
+use abstutil::{prettyprint_usize, Timer};
+use geom::{Duration, Time};
+use map_model::IntersectionID;
+use serde::Serialize;
+use sim::{Analytics, PersonID, TripID, TripMode};
+use std::collections::BTreeMap;
+
+// How many of the most changed intersections to report
+const NUM_INTERSECTIONS: usize = 20;
+
+// Compares two runs of the same scenario, usually a baseline and one with a MapEdits proposal
+// applied, and writes a report as JSON and CSV to the output directory. Each input is a path to
+// saved Analytics, like the ones written by a normal headless run or prebaked results.
+pub fn compare(before_path: String, after_path: String, output: String) {
+    let mut timer = Timer::new("compare runs");
+    let before: Analytics = abstutil::read_binary(before_path.clone(), &mut timer);
+    let after: Analytics = abstutil::read_binary(after_path.clone(), &mut timer);
+    timer.done();
+
+    let report = Report::new(before_path, after_path, &before, &after);
+    report.print();
+
+    abstutil::write_json(format!("{}/comparison.json", output), &report);
+    write_csv(
+        format!("{}/trips.csv", output),
+        &[
+            "trip",
+            "person",
+            "mode",
+            "before_seconds",
+            "after_seconds",
+            "delta_seconds",
+        ],
+        report.trips.iter().map(|t| {
+            (
+                t.trip.0,
+                t.person.map(|p| p.0),
+                format!("{:?}", t.mode),
+                t.before.inner_seconds(),
+                t.after.inner_seconds(),
+                t.delta.inner_seconds(),
+            )
+        }),
+    );
+    write_csv(
+        format!("{}/people.csv", output),
+        &[
+            "person",
+            "num_trips",
+            "before_seconds",
+            "after_seconds",
+            "delta_seconds",
+        ],
+        report.people.iter().map(|p| {
+            (
+                p.person.0,
+                p.num_trips,
+                p.before.inner_seconds(),
+                p.after.inner_seconds(),
+                p.delta.inner_seconds(),
+            )
+        }),
+    );
+    write_csv(
+        format!("{}/modes.csv", output),
+        &[
+            "mode",
+            "num_trips",
+            "num_faster",
+            "num_slower",
+            "before_seconds",
+            "after_seconds",
+            "delta_seconds",
+        ],
+        report.modes.iter().map(|m| {
+            (
+                format!("{:?}", m.mode),
+                m.num_trips,
+                m.num_faster,
+                m.num_slower,
+                m.before.inner_seconds(),
+                m.after.inner_seconds(),
+                m.delta.inner_seconds(),
+            )
+        }),
+    );
+    write_csv(
+        format!("{}/intersections.csv", output),
+        &["intersection", "delta_delay_seconds"],
+        report
+            .all_intersections
+            .iter()
+            .map(|(i, dt)| (i.0, dt.inner_seconds())),
+    );
+}
+
+// All durations are "after - before", so negative means faster.
+#[derive(Serialize)]
+struct Report {
+    before: String,
+    after: String,
+    // Trips that finished in only one of the runs can't be compared.
+    num_trips_only_finished_before: usize,
+    num_trips_only_finished_after: usize,
+
+    modes: Vec<ModeChange>,
+    // The intersections where the cumulative delay got the most worse and the most better
+    worst_intersections: Vec<(IntersectionID, Duration)>,
+    best_intersections: Vec<(IntersectionID, Duration)>,
+    people: Vec<PersonChange>,
+    trips: Vec<TripChange>,
+
+    #[serde(skip_serializing)]
+    all_intersections: Vec<(IntersectionID, Duration)>,
+}
+
+#[derive(Serialize)]
+struct ModeChange {
+    mode: TripMode,
+    num_trips: usize,
+    num_faster: usize,
+    num_slower: usize,
+    before: Duration,
+    after: Duration,
+    delta: Duration,
+}
+
+#[derive(Serialize)]
+struct PersonChange {
+    person: PersonID,
+    num_trips: usize,
+    before: Duration,
+    after: Duration,
+    delta: Duration,
+}
+
+#[derive(Serialize)]
+struct TripChange {
+    trip: TripID,
+    person: Option<PersonID>,
+    mode: TripMode,
+    before: Duration,
+    after: Duration,
+    delta: Duration,
+}
+
+impl Report {
+    fn new(
+        before_path: String,
+        after_path: String,
+        before: &Analytics,
+        after: &Analytics,
+    ) -> Report {
+        // Both runs should cover the same time period, but just in case, use everything.
+        let now = last_time(before).max(last_time(after));
+
+        let trips: Vec<TripChange> = after
+            .both_finished_trips_by_id(now, before)
+            .into_iter()
+            .map(|(trip, dt1, dt2, mode)| TripChange {
+                trip,
+                person: after
+                    .trip_to_person
+                    .get(&trip)
+                    .or_else(|| before.trip_to_person.get(&trip))
+                    .cloned(),
+                mode,
+                before: dt1,
+                after: dt2,
+                delta: dt2 - dt1,
+            })
+            .collect();
+
+        let mut modes: BTreeMap<TripMode, ModeChange> = BTreeMap::new();
+        let mut people: BTreeMap<PersonID, PersonChange> = BTreeMap::new();
+        for t in &trips {
+            let m = modes.entry(t.mode).or_insert_with(|| ModeChange {
+                mode: t.mode,
+                num_trips: 0,
+                num_faster: 0,
+                num_slower: 0,
+                before: Duration::ZERO,
+                after: Duration::ZERO,
+                delta: Duration::ZERO,
+            });
+            m.num_trips += 1;
+            if t.delta < Duration::ZERO {
+                m.num_faster += 1;
+            } else if t.delta > Duration::ZERO {
+                m.num_slower += 1;
+            }
+            m.before += t.before;
+            m.after += t.after;
+            m.delta += t.delta;
+
+            if let Some(person) = t.person {
+                let p = people.entry(person).or_insert_with(|| PersonChange {
+                    person,
+                    num_trips: 0,
+                    before: Duration::ZERO,
+                    after: Duration::ZERO,
+                    delta: Duration::ZERO,
+                });
+                p.num_trips += 1;
+                p.before += t.before;
+                p.after += t.after;
+                p.delta += t.delta;
+            }
+        }
+
+        let num_matched = trips.len();
+        let num_finished = |a: &Analytics| {
+            a.finished_trips
+                .iter()
+                .filter(|(_, _, mode, _)| mode.is_some())
+                .count()
+        };
+
+        let mut all_intersections = after.compare_delay(now, before);
+        all_intersections.sort_by_key(|(_, dt)| Duration::ZERO - *dt);
+        let worst_intersections = all_intersections
+            .iter()
+            .take(NUM_INTERSECTIONS)
+            .filter(|(_, dt)| *dt > Duration::ZERO)
+            .cloned()
+            .collect();
+        let best_intersections = all_intersections
+            .iter()
+            .rev()
+            .take(NUM_INTERSECTIONS)
+            .filter(|(_, dt)| *dt < Duration::ZERO)
+            .cloned()
+            .collect();
+
+        let mut people: Vec<PersonChange> = people.into_iter().map(|(_, p)| p).collect();
+        // People who lost the most time first
+        people.sort_by_key(|p| Duration::ZERO - p.delta);
+
+        Report {
+            before: before_path,
+            after: after_path,
+            num_trips_only_finished_before: num_finished(before) - num_matched,
+            num_trips_only_finished_after: num_finished(after) - num_matched,
+            modes: modes.into_iter().map(|(_, m)| m).collect(),
+            worst_intersections,
+            best_intersections,
+            people,
+            trips,
+            all_intersections,
+        }
+    }
+
+    fn print(&self) {
+        println!(
+            "{} trips finished in both runs ({} only before, {} only after)",
+            prettyprint_usize(self.trips.len()),
+            prettyprint_usize(self.num_trips_only_finished_before),
+            prettyprint_usize(self.num_trips_only_finished_after)
+        );
+        for m in &self.modes {
+            println!(
+                "- {}: {} trips, {} faster, {} slower, {} total change",
+                m.mode.ongoing_verb(),
+                prettyprint_usize(m.num_trips),
+                prettyprint_usize(m.num_faster),
+                prettyprint_usize(m.num_slower),
+                m.delta
+            );
+        }
+        for (i, dt) in &self.worst_intersections {
+            println!("- {} has {} more delay", i, dt);
+        }
+        for (i, dt) in &self.best_intersections {
+            println!("- {} has {} less delay", i, Duration::ZERO - *dt);
+        }
+    }
+}
+
+// The last time anything was recorded
+fn last_time(a: &Analytics) -> Time {
+    let mut t = Time::START_OF_DAY;
+    if let Some((t1, _, _, _)) = a.finished_trips.last() {
+        t = t.max(*t1);
+    }
+    for list in a.intersection_delays.values() {
+        if let Some((t1, _, _)) = list.last() {
+            t = t.max(*t1);
+        }
+    }
+    t
+}
+
+// Each row is usually a tuple with one value per column in the header. The csv crate takes care
+// of quoting.
+pub fn write_csv<R: Serialize, I: Iterator<Item = R>>(path: String, header: &[&str], rows: I) {
+    std::fs::create_dir_all(std::path::Path::new(&path).parent().unwrap())
+        .expect("Creating parent dir failed");
+    let mut w = csv::Writer::from_path(&path).unwrap();
+    w.write_record(header).unwrap();
+    for row in rows {
+        w.serialize(row).unwrap();
+    }
+    w.flush().unwrap();
+    println!("Wrote {}", path);
+}
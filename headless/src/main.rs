@@ -1,58 +1,582 @@
+mod assignment;
+mod compare;
+mod optimize;
+
 use abstutil::{prettyprint_usize, CmdArgs, Timer};
-use map_model::Map;
-use rand::SeedableRng;
-use rand_xorshift::XorShiftRng;
-use sim::{AlertHandler, Scenario, ScenarioModifier, Sim, SimFlags};
+use geom::{Duration, Histogram, Speed, Statistic, Time};
+use map_model::{GreenWave, IntersectionID, Map, MapEdits, PermanentMapEdits, WaveDirection};
+use serde::Serialize;
+use sim::{
+    Analytics, Emissions, EventLog, ExposureLocation, Scenario, ScenarioModifier, Sim, SimFlags,
+    TripMode,
+};
+use std::collections::BTreeMap;
+use std::num::ParseIntError;
 
-// This is specialized to experiment with running the pandemic model over long time periods.
-// Original functionality for profiling and debugging gridlock have been removed.
+// Runs a scenario from start to finish without the GUI, optionally with map edits and scenario
+// modifiers applied, then writes the full Analytics and a summary of trip times to disk. Useful
+// for batch experiments and parameter sweeps.
+//
+// Example:
+//   headless data/system/maps/montlake.bin --scenario=weekday --edits=my_proposal
+//     --modifiers=mods.json --rng_seed=7 --end_time=12:00:00 --output=data/player/runs/exp1
+//
+// With --pandemic, --pandemic_params=params.json configures the disease and any interventions,
+// like closing schools or wearing masks. The number of people in each state over time and every
+// exposure are written as CSV, along with a contact graph in Graphviz's DOT format.
+//
+// Or to compare the results of two runs:
+//   headless --compare_before=baseline/analytics.bin --compare_after=proposal/analytics.bin
+//     --output=data/player/runs/comparison
+//
+// Or to search for better timing at some traffic signals, writing the result as edits that can be
+// passed back in with --edits. The traffic signal survivor challenge is a good benchmark:
+//   headless data/system/maps/downtown.bin --optimize_signals=12,34,56 --iterations=50
+//     --end_time=08:00:00
+//
+// Or to rebuild Analytics from an event log written by an earlier run with --event_log, using the
+// same map and edits:
+//   headless data/system/maps/montlake.bin --edits=my_proposal --replay_events=events.bin
+//     --output=data/player/runs/exp1
+//
+// Or to set up a green wave along a corridor of traffic signals, writing edits and a time-space
+// diagram:
+//   headless data/system/maps/montlake.bin --green_wave=12,34,56 --design_speed_mph=25
+//     --wave_direction=both
+//
+// Or to find congestion-aware routes for drivers by repeatedly running the scenario and rerouting
+// some of them, until few could do better:
+//   headless data/system/maps/montlake.bin --traffic_assignment --iterations=10
+//     --reroute_fraction=0.2 --converged_gap=0.01
+// This writes the final routes and the Analytics from the last run. Later runs can follow those
+// routes with --assigned_paths=assigned_paths.bin.
 
 fn main() {
     let mut args = CmdArgs::new();
-    let num_days = args
-        .optional_parse("--days", |s| s.parse::<usize>())
-        .unwrap_or(1);
+    // Ignore other arguments and just compare two saved Analytics.
+    if let Some(before) = args.optional("--compare_before") {
+        let after = args.required("--compare_after");
+        let output = args
+            .optional("--output")
+            .unwrap_or_else(|| abstutil::path("player/headless/comparison"));
+        args.done();
+        compare::compare(before, after, output);
+        return;
+    }
+
+    // Handles the map (as the free argument), --rng_seed, --run_name, --pandemic, --alerts, etc.
+    let mut sim_flags = SimFlags::from_args(&mut args);
+    let job = Job {
+        scenario: args
+            .optional("--scenario")
+            .unwrap_or_else(|| "weekday".to_string()),
+        // Either the name of edits saved for this map, or a path to any .json file with
+        // PermanentMapEdits, like a proposal.
+        edits: args.optional("--edits"),
+        // A path to a .json file with a list of ScenarioModifiers, applied in order.
+        modifiers: args
+            .optional("--modifiers")
+            .map(|path| abstutil::read_json(path, &mut Timer::throwaway()))
+            .unwrap_or_else(Vec::new),
+        // If not specified, run until the end of the day (or longer, if the scenario has trips
+        // starting later).
+        end_time: args.optional_parse("--end_time", Time::parse),
+        output: args.optional("--output"),
+        // Instead of writing results, save at this time, then check that resuming from the
+        // savestate reaches the same state by the end time.
+        verify_savestate_at: args.optional_parse("--verify_savestate_at", Time::parse),
+        // Instead of writing results, hill-climb on the timing of these traffic signals.
+        optimize_signals: args.optional_parse("--optimize_signals", parse_intersections),
+        iterations: args.optional_parse("--iterations", |s| s.parse::<usize>()),
+        // Instead of writing results, iteratively assign drivers to routes based on congestion.
+        traffic_assignment: args.enabled("--traffic_assignment"),
+        reroute_fraction: args
+            .optional_parse("--reroute_fraction", |s| match s.parse::<f64>() {
+                Ok(x) if (0.0..=1.0).contains(&x) => Ok(x),
+                _ => Err(()),
+            })
+            .unwrap_or(0.2),
+        converged_gap: args
+            .optional_parse("--converged_gap", |s| s.parse::<f64>())
+            .unwrap_or(0.01),
+        // Drivers follow the routes written by an earlier --traffic_assignment run, instead of
+        // pathfinding when they start. Use the same scenario, edits, and --rng_seed.
+        assigned_paths: args.optional("--assigned_paths"),
+        // Instead of running anything, coordinate the offsets of these traffic signals, in order
+        // along a corridor.
+        green_wave: args.optional_parse("--green_wave", parse_intersections),
+        design_speed: args
+            .optional_parse("--design_speed_mph", |s| s.parse::<f64>())
+            .map(Speed::miles_per_hour)
+            .unwrap_or_else(|| Speed::miles_per_hour(25.0)),
+        wave_direction: args
+            .optional_parse("--wave_direction", |s| match s {
+                "forwards" => Ok(WaveDirection::Forwards),
+                "backwards" => Ok(WaveDirection::Backwards),
+                "both" => Ok(WaveDirection::Both),
+                _ => Err(()),
+            })
+            .unwrap_or(WaveDirection::Forwards),
+        // Instead of running anything, rebuild Analytics from this event log.
+        replay_events: args.optional("--replay_events"),
+    };
     args.done();
 
-    let mut sim_flags = SimFlags::synthetic_test("montlake", "pandemic");
-    sim_flags.opts.enable_pandemic_model = Some(XorShiftRng::from_seed([sim_flags.rng_seed; 16]));
-    // Less spam
-    sim_flags.opts.alerts = AlertHandler::Silence;
+    if !sim_flags.load.starts_with(&abstutil::path_all_maps()) {
+        panic!(
+            "headless needs a map (in {}), not {}",
+            abstutil::path_all_maps(),
+            sim_flags.load
+        );
+    }
+    if sim_flags.opts.run_name == "unnamed" {
+        sim_flags.opts.run_name = "headless".to_string();
+    }
+
     let mut timer = Timer::new("setup headless");
-    let (mut map, _, mut rng) = sim_flags.load(&mut timer);
-    map.hack_override_offstreet_spots(num_days);
-    // After the map changes, have to re-create the (empty) Sim, because things like
-    // ParkingSimState are out of sync.
-    let mut sim = Sim::new(&map, sim_flags.opts.clone(), &mut timer);
+    let mut map = Map::new(sim_flags.load.clone(), &mut timer);
+    if let Err(err) = sim_flags.check_incidents(&map) {
+        panic!("Bad --incidents: {}", err);
+    }
+    let mut rng = sim_flags.make_rng();
+
+    let edits_name = if let Some(ref edits) = job.edits {
+        let edits = load_edits(&map, edits, &mut timer);
+        let name = edits.edits_name.clone();
+        map.must_apply_edits(edits, &mut timer);
+        map.recalculate_pathfinding_after_edits(&mut timer);
+        name
+    } else {
+        "untitled edits".to_string()
+    };
+
+    if let Some(corridor) = job.green_wave {
+        timer.done();
+        let output = job.output.clone().unwrap_or_else(|| {
+            abstutil::path(format!("player/headless/{}/green_wave", map.get_name()))
+        });
+        green_wave(
+            &mut map,
+            corridor,
+            job.design_speed,
+            job.wave_direction,
+            output,
+        );
+        return;
+    }
+
+    if let Some(path) = job.replay_events {
+        timer.done();
+        let analytics = match EventLog::replay(&path, &map) {
+            Ok(a) => a,
+            Err(err) => panic!("Can't replay {}: {}", path, err),
+        };
+        let output = job.output.clone().unwrap_or_else(|| {
+            abstutil::path(format!("player/headless/{}/replay", map.get_name()))
+        });
+        println!(
+            "Replayed {} finished trips from {}",
+            prettyprint_usize(analytics.finished_trips.len()),
+            path
+        );
+        abstutil::write_binary(format!("{}/analytics.bin", output), &analytics);
+        return;
+    }
 
-    let base_scenario: Scenario = abstutil::read_binary(
-        abstutil::path_scenario(map.get_name(), "weekday"),
+    let mut scenario: Scenario = abstutil::read_binary(
+        abstutil::path_scenario(map.get_name(), &job.scenario),
         &mut timer,
     );
-    ScenarioModifier::RepeatDays(num_days)
-        .apply(&map, base_scenario, &mut rng)
-        .instantiate(&mut sim, &map, &mut rng, &mut timer);
+    for m in &job.modifiers {
+        timer.note(format!("Applying modifier: {}", m.describe()));
+        if let ScenarioModifier::RepeatDays(n) = m {
+            // Each person blindly repeats their trips, so they'll need more cars. Make sure
+            // there's room to seed them.
+            map.hack_override_offstreet_spots(*n);
+        }
+        scenario = m.apply(&map, scenario, &mut rng);
+    }
+
+    // Create the Sim only after the map is in its final state, since things like
+    // ParkingSimState depend on it.
+    let mut sim = Sim::new(&map, sim_flags.opts.clone(), &mut timer);
+    scenario.instantiate(&mut sim, &map, &mut rng, &mut timer);
+    if let Some(ref path) = job.assigned_paths {
+        sim.assign_paths(abstutil::read_binary(path.clone(), &mut timer));
+    }
     timer.done();
 
-    run_experiment(&map, &mut sim);
-}
-
-fn run_experiment(map: &Map, sim: &mut Sim) {
-    let timer = Timer::new("run sim until done");
-    sim.run_until_done(
-        &map,
-        |sim, _map| {
-            // This'll run every 30 sim seconds
-            if false {
-                println!(
-                    "At {}, {} infected",
-                    sim.time(),
-                    prettyprint_usize(sim.get_pandemic_model().unwrap().count_infected())
-                );
-            }
-        },
-        None,
-    );
+    let end_time = job.end_time.unwrap_or_else(|| sim.get_end_of_day());
+    if let Some(savestate_at) = job.verify_savestate_at {
+        let mut timer = Timer::new("verify savestate determinism");
+        let result = sim.verify_savestate_determinism(&map, savestate_at, end_time, &mut timer);
+        timer.done();
+        if let Some(diff) = result {
+            println!(
+                "Resuming from the savestate at {} diverged by {}: {}",
+                savestate_at, end_time, diff
+            );
+            std::process::exit(1);
+        }
+        println!(
+            "Resuming from the savestate at {} matches the original run at {}",
+            savestate_at, end_time
+        );
+        return;
+    }
+
+    let output = job.output.clone().unwrap_or_else(|| {
+        abstutil::path(format!(
+            "player/headless/{}/{}/{}",
+            map.get_name(),
+            job.scenario,
+            sim_flags.opts.run_name
+        ))
+    });
+
+    if let Some(ref intersections) = job.optimize_signals {
+        let edits = optimize::optimize_signals(
+            &mut map,
+            &scenario,
+            &sim_flags,
+            intersections,
+            end_time,
+            job.iterations.unwrap_or(20),
+        );
+        let path = format!("{}/optimized_signals.json", output);
+        abstutil::write_json(path.clone(), &PermanentMapEdits::to_permanent(&edits, &map));
+        println!("Try these edits with --edits={}", path);
+        return;
+    }
+
+    if job.traffic_assignment {
+        let (iterations, paths, analytics) = assignment::assign(
+            &mut map,
+            &scenario,
+            &sim_flags,
+            end_time,
+            job.iterations.unwrap_or(10),
+            job.reroute_fraction,
+            job.converged_gap,
+        );
+        for i in &iterations {
+            println!(
+                "Iteration {}: relative gap {:.4}, rerouting {} of {} driving trips",
+                i.iteration, i.relative_gap, i.rerouted, i.driving_trips
+            );
+        }
+        abstutil::write_json(format!("{}/traffic_assignment.json", output), &iterations);
+        abstutil::write_binary(format!("{}/assigned_paths.bin", output), &paths);
+        abstutil::write_binary(format!("{}/analytics.bin", output), &analytics);
+        println!(
+            "Run with these routes using --assigned_paths={}/assigned_paths.bin",
+            output
+        );
+        return;
+    }
+
+    let mut timer = Timer::new("run sim until done");
+    sim.timed_step(&map, end_time - sim.time(), &mut None, &mut timer);
     timer.done();
     println!("Done at {}", sim.time());
+
+    let summary = Summary::new(
+        &sim,
+        map.get_name().to_string(),
+        job.scenario,
+        edits_name,
+        job.modifiers.iter().map(|m| m.describe()).collect(),
+        sim_flags.rng_seed,
+    );
+    summary.print();
+    abstutil::write_binary(format!("{}/analytics.bin", output), sim.get_analytics());
+    if sim.get_pandemic_model().is_some() {
+        write_pandemic_results(&sim, &output);
+    }
+    abstutil::write_json(format!("{}/summary.json", output), &summary);
+}
+
+struct Job {
+    scenario: String,
+    edits: Option<String>,
+    modifiers: Vec<ScenarioModifier>,
+    end_time: Option<Time>,
+    output: Option<String>,
+    verify_savestate_at: Option<Time>,
+    optimize_signals: Option<Vec<IntersectionID>>,
+    iterations: Option<usize>,
+    traffic_assignment: bool,
+    reroute_fraction: f64,
+    converged_gap: f64,
+    assigned_paths: Option<String>,
+    green_wave: Option<Vec<IntersectionID>>,
+    design_speed: Speed,
+    wave_direction: WaveDirection,
+    replay_events: Option<String>,
+}
+
+fn parse_intersections(list: &str) -> Result<Vec<IntersectionID>, ParseIntError> {
+    list.split(',')
+        .map(|i| i.parse::<usize>().map(IntersectionID))
+        .collect()
+}
+
+fn green_wave(
+    map: &mut Map,
+    corridor: Vec<IntersectionID>,
+    design_speed: Speed,
+    direction: WaveDirection,
+    output: String,
+) {
+    let orig_signals = corridor
+        .iter()
+        .map(|i| (*i, map.get_traffic_signal(*i).clone()))
+        .collect();
+    let wave = match GreenWave::coordinate(map, corridor, design_speed, direction) {
+        Ok(wave) => wave,
+        Err(err) => panic!("Can't set up a green wave: {}", err),
+    };
+    println!(
+        "Cycle length {}, forwards bandwidth {}, backwards bandwidth {}",
+        wave.cycle_length, wave.forwards_bandwidth, wave.backwards_bandwidth
+    );
+
+    let svg_path = format!("{}/time_space.svg", output);
+    std::fs::create_dir_all(&output).unwrap();
+    std::fs::write(&svg_path, wave.time_space_diagram_svg(map, 3)).unwrap();
+    println!("Wrote {}", svg_path);
+    abstutil::write_json(format!("{}/green_wave.json", output), &wave);
+
+    let edits = optimize::signal_edits(map, orig_signals, "green_wave");
+    let path = format!("{}/green_wave_edits.json", output);
+    abstutil::write_json(path.clone(), &PermanentMapEdits::to_permanent(&edits, map));
+    println!("Try these edits with --edits={}", path);
+}
+
+fn write_pandemic_results(sim: &Sim, output: &str) {
+    let model = sim.get_pandemic_model().unwrap();
+    compare::write_csv(
+        format!("{}/pandemic_counts.csv", output),
+        &[
+            "time_seconds",
+            "sane",
+            "exposed",
+            "infected",
+            "recovered",
+            "dead",
+        ],
+        model.counts_over_time(sim.time()).into_iter().map(|c| {
+            (
+                (c.time - Time::START_OF_DAY).inner_seconds(),
+                c.sane,
+                c.exposed,
+                c.infected,
+                c.recovered,
+                c.dead,
+            )
+        }),
+    );
+
+    let locations: Vec<(&str, String)> = model
+        .get_exposures()
+        .iter()
+        .map(|e| match e.location {
+            ExposureLocation::Building(b) => ("building", b.0.to_string()),
+            ExposureLocation::RemoteBuilding(ref loc) => {
+                ("remote_building", loc.parcel_id.to_string())
+            }
+            ExposureLocation::BusStop(bs) => ("bus_stop", bs.to_string()),
+            ExposureLocation::Bus(car) => ("bus", car.0.to_string()),
+        })
+        .collect();
+    compare::write_csv(
+        format!("{}/pandemic_exposures.csv", output),
+        &[
+            "time_seconds",
+            "source_person",
+            "target_person",
+            "location_type",
+            "location_id",
+        ],
+        model
+            .get_exposures()
+            .iter()
+            .zip(locations.iter())
+            .map(|(e, (kind, id))| {
+                (
+                    (e.time - Time::START_OF_DAY).inner_seconds(),
+                    e.source.0,
+                    e.target.0,
+                    kind,
+                    id,
+                )
+            }),
+    );
+
+    // Each person is a node, and each exposure is a directed edge
+    let mut dot = String::from("digraph contacts {\n");
+    for (e, (kind, id)) in model.get_exposures().iter().zip(locations.iter()) {
+        dot.push_str(&format!(
+            "  {} -> {} [time=\"{}\", location=\"{} {}\"];\n",
+            e.source.0, e.target.0, e.time, kind, id
+        ));
+    }
+    dot.push_str("}\n");
+    let path = format!("{}/contact_graph.dot", output);
+    std::fs::write(&path, dot).unwrap();
+    println!("Wrote {}", path);
+}
+
+fn load_edits(map: &Map, edits: &str, timer: &mut Timer) -> MapEdits {
+    let result = if edits.ends_with(".json") {
+        PermanentMapEdits::from_permanent(abstutil::read_json(edits.to_string(), timer), map)
+    } else {
+        MapEdits::load(map, edits, timer)
+    };
+    match result {
+        Ok(edits) => edits,
+        Err(err) => panic!("Can't load edits {}: {}", edits, err),
+    }
+}
+
+#[derive(Serialize)]
+struct Summary {
+    map: String,
+    scenario: String,
+    edits: String,
+    modifiers: Vec<String>,
+    rng_seed: u8,
+    end_time: Time,
+
+    finished_trips: usize,
+    aborted_trips: usize,
+    // Started, but not finished by end_time
+    unfinished_trips: usize,
+    per_mode: Vec<ModeSummary>,
+    // Tailpipe emissions from all vehicles, in grams
+    emissions: Emissions,
+    // Only with --pandemic
+    pandemic: Option<PandemicSummary>,
+}
+
+#[derive(Serialize)]
+struct PandemicSummary {
+    sane: usize,
+    exposed: usize,
+    infected: usize,
+    recovered: usize,
+    dead: usize,
+    interventions: Vec<(Time, String)>,
+}
+
+#[derive(Serialize)]
+struct ModeSummary {
+    mode: TripMode,
+    count: usize,
+    total_time: Duration,
+    // Keyed by the name of the Statistic
+    trip_time: BTreeMap<String, Duration>,
+}
+
+impl Summary {
+    fn new(
+        sim: &Sim,
+        map: String,
+        scenario: String,
+        edits: String,
+        modifiers: Vec<String>,
+        rng_seed: u8,
+    ) -> Summary {
+        let analytics: &Analytics = sim.get_analytics();
+
+        let mut finished_trips = 0;
+        let mut aborted_trips = 0;
+        let mut per_mode: BTreeMap<TripMode, (Duration, Histogram<Duration>)> = BTreeMap::new();
+        for (_, _, maybe_mode, dt) in &analytics.finished_trips {
+            if let Some(mode) = maybe_mode {
+                finished_trips += 1;
+                let entry = per_mode
+                    .entry(*mode)
+                    .or_insert_with(|| (Duration::ZERO, Histogram::new()));
+                entry.0 += *dt;
+                entry.1.add(*dt);
+            } else {
+                aborted_trips += 1;
+            }
+        }
+
+        Summary {
+            map,
+            scenario,
+            edits,
+            modifiers,
+            rng_seed,
+            end_time: sim.time(),
+
+            finished_trips,
+            aborted_trips,
+            unfinished_trips: analytics.started_trips.len() - finished_trips - aborted_trips,
+            per_mode: per_mode
+                .into_iter()
+                .map(|(mode, (total_time, hgram))| ModeSummary {
+                    mode,
+                    count: hgram.count(),
+                    total_time,
+                    trip_time: Statistic::all()
+                        .into_iter()
+                        .map(|stat| (stat.to_string(), hgram.select(stat).unwrap()))
+                        .collect(),
+                })
+                .collect(),
+            emissions: analytics.total_emissions(),
+            pandemic: sim.get_pandemic_model().map(|m| PandemicSummary {
+                sane: m.count_sane(),
+                exposed: m.count_exposed(),
+                infected: m.count_infected(),
+                recovered: m.count_recovered(),
+                dead: m.count_dead(),
+                interventions: m.interventions_started(),
+            }),
+        }
+    }
+
+    fn print(&self) {
+        println!(
+            "{} finished trips, {} aborted, {} unfinished at {}",
+            prettyprint_usize(self.finished_trips),
+            prettyprint_usize(self.aborted_trips),
+            prettyprint_usize(self.unfinished_trips),
+            self.end_time
+        );
+        for m in &self.per_mode {
+            println!(
+                "- {}: {} trips, {} total, {} mean, {} 50%ile, {} 90%ile",
+                m.mode.ongoing_verb(),
+                prettyprint_usize(m.count),
+                m.total_time,
+                m.trip_time[&Statistic::Mean.to_string()],
+                m.trip_time[&Statistic::P50.to_string()],
+                m.trip_time[&Statistic::P90.to_string()],
+            );
+        }
+        println!(
+            "Emitted {:.1} kg of CO2, {:.1} g of NOx, {:.1} g of particulate matter",
+            self.emissions.co2 / 1000.0,
+            self.emissions.nox,
+            self.emissions.pm
+        );
+        if let Some(ref p) = self.pandemic {
+            println!(
+                "Pandemic: {} sane, {} exposed, {} infected, {} recovered, {} dead",
+                prettyprint_usize(p.sane),
+                prettyprint_usize(p.exposed),
+                prettyprint_usize(p.infected),
+                prettyprint_usize(p.recovered),
+                prettyprint_usize(p.dead)
+            );
+            for (t, action) in &p.interventions {
+                println!("- at {}, {}", t, action);
+            }
+        }
+    }
 }
@@ -0,0 +1,200 @@
+use abstutil::Timer;
+use geom::{Duration, Time};
+use map_model::{
+    ControlTrafficSignal, EditCmd, EditIntersection, IntersectionID, Map, MapEdits, PhaseType,
+};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rand_xorshift::XorShiftRng;
+use sim::{Scenario, Sim, SimFlags};
+use std::collections::BTreeMap;
+
+// Phases can't get shorter than this
+const MIN_PHASE_DURATION: Duration = Duration::const_seconds(5.0);
+
+// Hill-climbs towards better timing for some traffic signals. Each iteration tweaks one timing
+// plan of one of the signals at random -- reordering its phases, changing a duration or its
+// offset, or switching to a different canned policy -- runs the whole scenario, and keeps the
+// change only if the score improves. Returns edits (on top of whatever the map already has) with
+// the best signals found.
+//
+// Every run uses the same RNG seed, so the only difference between candidates is the signal
+// timing. This costs one full simulation per iteration, so keep the end_time short. Time-of-day
+// plans that don't start before end_time can't affect the score, so they're left alone.
+pub fn optimize_signals(
+    map: &mut Map,
+    scenario: &Scenario,
+    sim_flags: &SimFlags,
+    intersections: &[IntersectionID],
+    end_time: Time,
+    iterations: usize,
+) -> MapEdits {
+    for i in intersections {
+        if !map.get_i(*i).is_traffic_signal() {
+            panic!("Can't optimize {}; it's not a traffic signal", i);
+        }
+    }
+
+    let mut timer = Timer::new("optimize traffic signals");
+    let mut rng = sim_flags.make_rng();
+    let orig_signals: BTreeMap<IntersectionID, ControlTrafficSignal> = intersections
+        .iter()
+        .map(|i| (*i, map.get_traffic_signal(*i).clone()))
+        .collect();
+
+    let mut best_score = score(map, scenario, sim_flags, end_time);
+    timer.note(format!("Baseline score: {}", best_score));
+
+    timer.start_iter("try signal changes", iterations);
+    for _ in 0..iterations {
+        timer.next();
+        let i = *intersections.choose(&mut rng).unwrap();
+        let current = map.get_traffic_signal(i).clone();
+        let (change, candidate) = mutate(map, current.clone(), end_time, &mut rng);
+        let candidate = match candidate.validate() {
+            Ok(ts) => ts,
+            Err(_) => {
+                continue;
+            }
+        };
+        if candidate == current {
+            continue;
+        }
+
+        map.incremental_edit_traffic_signal(candidate);
+        let candidate_score = score(map, scenario, sim_flags, end_time);
+        if candidate_score < best_score {
+            timer.note(format!(
+                "{} at {}: score {} -> {}",
+                change, i, best_score, candidate_score
+            ));
+            best_score = candidate_score;
+        } else {
+            map.incremental_edit_traffic_signal(current);
+        }
+    }
+    timer.note(format!("Final score: {}", best_score));
+    timer.done();
+
+    signal_edits(map, orig_signals, "optimized_signals")
+}
+
+// Turns traffic signals changed directly in the map into edits (on top of whatever the map
+// already has), then restores the original signals. The caller can apply the edits.
+pub fn signal_edits(
+    map: &mut Map,
+    orig_signals: BTreeMap<IntersectionID, ControlTrafficSignal>,
+    edits_name: &str,
+) -> MapEdits {
+    let mut edits = map.get_edits().clone();
+    edits.edits_name = edits_name.to_string();
+    for (i, orig) in orig_signals {
+        let ts = map.get_traffic_signal(i);
+        if ts != &orig {
+            edits.commands.push(EditCmd::ChangeIntersection {
+                i,
+                old: EditIntersection::TrafficSignal(orig.export_with_plans(map)),
+                new: EditIntersection::TrafficSignal(ts.export_with_plans(map)),
+            });
+        }
+        map.incremental_edit_traffic_signal(orig);
+    }
+    edits
+}
+
+// Lower is better. This is the total delay at all intersections, plus how long every unfinished
+// trip has been going. Otherwise, gridlock would look like an improvement, since delay is only
+// measured once an agent finally makes it through.
+fn score(map: &Map, scenario: &Scenario, sim_flags: &SimFlags, end_time: Time) -> Duration {
+    let mut timer = Timer::throwaway();
+    let mut opts = sim_flags.opts.clone();
+    // Don't clobber the log from every run
+    opts.event_log = None;
+    let mut sim = Sim::new(map, opts, &mut timer);
+    scenario.instantiate(&mut sim, map, &mut sim_flags.make_rng(), &mut timer);
+    sim.timed_step(map, end_time - sim.time(), &mut None, &mut timer);
+
+    let analytics = sim.get_analytics();
+    let mut total = Duration::ZERO;
+    for list in analytics.intersection_delays.values() {
+        for (_, dt, _) in list {
+            total += *dt;
+        }
+    }
+    let mut unfinished = analytics.started_trips.clone();
+    for (_, id, _, _) in &analytics.finished_trips {
+        unfinished.remove(id);
+    }
+    for start in unfinished.values() {
+        total += sim.time() - *start;
+    }
+    total
+}
+
+fn mutate(
+    map: &Map,
+    mut ts: ControlTrafficSignal,
+    end_time: Time,
+    rng: &mut XorShiftRng,
+) -> (String, ControlTrafficSignal) {
+    // Plan 0 is the base timing; plan i is plans[i - 1]
+    let num_plans = 1 + ts
+        .plans
+        .iter()
+        .take_while(|p| p.start_time < end_time)
+        .count();
+    let plan = rng.gen_range(0, num_plans);
+    let cycle_length = ts.cycle_length(plan);
+    let mut policies =
+        ControlTrafficSignal::get_possible_policies(map, ts.id, &mut Timer::throwaway());
+    let describe_plan = if plan == 0 {
+        String::new()
+    } else {
+        format!(
+            " of the plan from {}",
+            ts.plans[plan - 1].start_time.ampm_tostring()
+        )
+    };
+    let (phases, offset) = if plan == 0 {
+        (&mut ts.phases, &mut ts.offset)
+    } else {
+        let p = &mut ts.plans[plan - 1];
+        (&mut p.phases, &mut p.offset)
+    };
+
+    let change = match rng.gen_range(0, 4) {
+        0 if phases.len() > 1 => {
+            let idx1 = rng.gen_range(0, phases.len());
+            let idx2 = rng.gen_range(0, phases.len());
+            phases.swap(idx1, idx2);
+            format!("Swap phases {} and {}", idx1 + 1, idx2 + 1)
+        }
+        1 => {
+            let idx = rng.gen_range(0, phases.len());
+            let dt = Duration::seconds(*[-10.0, -5.0, 5.0, 10.0].choose(rng).unwrap());
+            let change = |d: Duration| (d + dt).max(MIN_PHASE_DURATION);
+            let phase_type = &mut phases[idx].phase_type;
+            *phase_type = match phase_type.clone() {
+                PhaseType::Fixed(d) => PhaseType::Fixed(change(d)),
+                PhaseType::Adaptive(d) => PhaseType::Adaptive(change(d)),
+                PhaseType::Actuated(mut timing) => {
+                    timing.min_green = change(timing.min_green);
+                    timing.max_green = change(timing.max_green).max(timing.min_green);
+                    PhaseType::Actuated(timing)
+                }
+            };
+            format!("Change phase {} duration by {}", idx + 1, dt)
+        }
+        2 => {
+            let seconds = rng.gen_range(0, (cycle_length.inner_seconds() as usize).max(1));
+            *offset = Duration::seconds(seconds as f64);
+            format!("Change offset to {}", offset)
+        }
+        _ => {
+            let (name, policy) = policies.remove(rng.gen_range(0, policies.len()));
+            *phases = policy.phases;
+            format!("Use the {} policy", name)
+        }
+    };
+    (format!("{}{}", change, describe_plan), ts)
+}
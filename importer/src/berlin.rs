@@ -1,4 +1,4 @@
-use crate::utils::{download, download_kml, osmconvert};
+use crate::utils::{clip_osm, download, download_kml};
 use abstutil::{prettyprint_usize, Timer};
 use geom::Polygon;
 use kml::ExtraShapes;
@@ -44,7 +44,7 @@ fn input() {
 
 pub fn osm_to_raw(name: &str) {
     input();
-    osmconvert(
+    clip_osm(
         "input/berlin/osm/berlin-latest.osm.pbf",
         format!("input/berlin/polygons/{}.poly", name),
         format!("input/berlin/osm/{}.osm", name),
@@ -70,6 +70,7 @@ pub fn osm_to_raw(name: &str) {
             public_offstreet_parking: convert_osm::PublicOffstreetParking::None,
             private_offstreet_parking: convert_osm::PrivateOffstreetParking::FixedPerBldg(3),
             elevation: None,
+            gtfs: None,
         },
         &mut abstutil::Timer::throwaway(),
     );
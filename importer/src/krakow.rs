@@ -1,4 +1,4 @@
-use crate::utils::{download, osmconvert};
+use crate::utils::{clip_osm, download};
 
 fn input() {
     download(
@@ -9,7 +9,7 @@ fn input() {
 
 pub fn osm_to_raw(name: &str) {
     input();
-    osmconvert(
+    clip_osm(
         "input/krakow/osm/malopolskie-latest.osm.pbf",
         format!("input/krakow/polygons/{}.poly", name),
         format!("input/krakow/osm/{}.osm", name),
@@ -36,6 +36,7 @@ pub fn osm_to_raw(name: &str) {
             private_offstreet_parking: convert_osm::PrivateOffstreetParking::FixedPerBldg(3), /* TODO: support amenity=parking_entrance */
             // TODO: investigate why some many buildings drop their private parkings
             elevation: None,
+            gtfs: None,
         },
         &mut abstutil::Timer::throwaway(),
     );
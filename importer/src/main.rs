@@ -21,6 +21,7 @@ struct Job {
     oneshot: Option<String>,
     oneshot_clip: Option<String>,
     oneshot_drive_on_left: bool,
+    oneshot_gtfs: Option<String>,
 }
 
 fn main() {
@@ -43,10 +44,12 @@ fn main() {
         // data/input/$city/polygons/.
         only_map: args.optional_free(),
 
-        // Ignore other arguments and just convert the given .osm file to a Map.
+        // Ignore other arguments and just convert the given .osm or .osm.pbf file to a Map.
         oneshot: args.optional("--oneshot"),
         oneshot_clip: args.optional("--oneshot_clip"),
         oneshot_drive_on_left: args.enabled("--oneshot_drive_on_left"),
+        // A directory with a GTFS feed, to use instead of transit routes from OSM.
+        oneshot_gtfs: args.optional("--oneshot_gtfs"),
     };
     args.done();
     if !job.osm_to_raw
@@ -63,7 +66,12 @@ fn main() {
     }
 
     if let Some(path) = job.oneshot {
-        oneshot(path, job.oneshot_clip, !job.oneshot_drive_on_left);
+        oneshot(
+            path,
+            job.oneshot_clip,
+            !job.oneshot_drive_on_left,
+            job.oneshot_gtfs,
+        );
         return;
     }
 
@@ -166,10 +174,13 @@ fn main() {
     }
 }
 
-fn oneshot(osm_path: String, clip: Option<String>, drive_on_right: bool) {
+fn oneshot(osm_path: String, clip: Option<String>, drive_on_right: bool, gtfs: Option<String>) {
     let mut timer = abstutil::Timer::new("oneshot");
     println!("- Running convert_osm on {}", osm_path);
-    let name = abstutil::basename(&osm_path);
+    // For .osm.pbf, the file stem still ends with .osm
+    let name = abstutil::basename(&osm_path)
+        .trim_end_matches(".osm")
+        .to_string();
     let raw = convert_osm::convert(
         convert_osm::Options {
             osm_input: osm_path,
@@ -190,6 +201,7 @@ fn oneshot(osm_path: String, clip: Option<String>, drive_on_right: bool) {
             public_offstreet_parking: convert_osm::PublicOffstreetParking::None,
             private_offstreet_parking: convert_osm::PrivateOffstreetParking::FixedPerBldg(1),
             elevation: None,
+            gtfs,
         },
         &mut timer,
     );
@@ -1,4 +1,4 @@
-use crate::utils::{download, download_kml, osmconvert};
+use crate::utils::{clip_osm, download, download_kml};
 use map_model::Map;
 use sim::Scenario;
 
@@ -41,7 +41,7 @@ fn input() {
 
 pub fn osm_to_raw(name: &str) {
     input();
-    osmconvert(
+    clip_osm(
         "input/seattle/osm/washington-latest.osm.pbf",
         format!("input/seattle/polygons/{}.poly", name),
         format!("input/seattle/osm/{}.osm", name),
@@ -80,6 +80,13 @@ pub fn osm_to_raw(name: &str) {
                 },
             ),
             elevation: Some(abstutil::path("input/seattle/N47W122.hgt")),
+            // King County Metro's GTFS feed isn't downloaded automatically. If it's been unzipped
+            // here, use its schedules instead of the OSM routes.
+            gtfs: if abstutil::file_exists(abstutil::path("input/seattle/gtfs/stops.txt")) {
+                Some(abstutil::path("input/seattle/gtfs"))
+            } else {
+                None
+            },
         },
         &mut abstutil::Timer::throwaway(),
     );
@@ -1,7 +1,9 @@
-use crate::soundcast::popdat::{Endpoint, OrigTrip, PopDat};
+use crate::soundcast::popdat::{Endpoint, OrigTrip, PopDat, Purpose};
 use abstutil::{prettyprint_usize, MultiMap, Timer};
-use geom::LonLat;
-use map_model::{BuildingID, IntersectionID, Map, PathConstraints, PathRequest, PathStep};
+use geom::{Distance, LonLat, Pt2D, Time};
+use map_model::{
+    BuildingID, IntersectionID, Map, ParkingLotID, PathConstraints, PathRequest, PathStep,
+};
 use sim::{
     IndividTrip, OffMapLocation, OrigPersonID, PersonID, PersonSpec, Scenario, SpawnTrip,
     TripEndpoint, TripMode,
@@ -215,12 +217,16 @@ fn clip_trips(map: &Map, popdat: &PopDat, huge_map: &Map, timer: &mut Timer) ->
                     TripMode::Walk | TripMode::Transit => {
                         (&incoming_borders_walking, &outgoing_borders_walking)
                     }
-                    TripMode::Drive => (&incoming_borders_driving, &outgoing_borders_driving),
+                    TripMode::Drive | TripMode::RideHail | TripMode::ParkAndRide => {
+                        (&incoming_borders_driving, &outgoing_borders_driving)
+                    }
                     TripMode::Bike => (&incoming_borders_biking, &outgoing_borders_biking),
                 },
                 match orig.mode {
                     TripMode::Walk | TripMode::Transit => PathConstraints::Pedestrian,
-                    TripMode::Drive => PathConstraints::Car,
+                    TripMode::Drive | TripMode::RideHail | TripMode::ParkAndRide => {
+                        PathConstraints::Car
+                    }
                     TripMode::Bike => PathConstraints::Bike,
                 },
                 maybe_huge_map.as_ref(),
@@ -242,6 +248,86 @@ fn clip_trips(map: &Map, popdat: &PopDat, huge_map: &Map, timer: &mut Timer) ->
     trips
 }
 
+// Soundcast only knows the parcel where somebody switches from driving to transit. Don't send
+// anybody to a lot farther away than this.
+const MAX_DIST_TO_LOT: Distance = Distance::const_meters(500.0);
+
+// Soundcast splits each park-and-ride trip in two, with the transfer as the purpose in between:
+// driving to the lot and then riding transit, or the reverse on the way back. Merge these halves
+// into one trip, so the car waits at a lot in the map. When there's no lot nearby or transit isn't
+// useful from it, the halves are left alone.
+fn merge_park_and_ride(
+    mut trips: Vec<Trip>,
+    map: &Map,
+    timer: &mut Timer,
+) -> (
+    Vec<Trip>,
+    Vec<(Option<SpawnTrip>, Time, OrigPersonID, (usize, bool, usize))>,
+) {
+    trips.sort_by_key(|t| (t.orig.person, t.orig.seq));
+
+    let mut remaining = Vec::new();
+    let mut merged = Vec::new();
+    let mut iter = trips.into_iter().peekable();
+    timer.start("merge park-and-ride trips");
+    while let Some(first) = iter.next() {
+        if let Some(second) = iter.peek() {
+            if let Some(trip) = merge_pair(&first, second, map) {
+                merged.push((
+                    Some(trip),
+                    first.orig.depart_at,
+                    first.orig.person,
+                    first.orig.seq,
+                ));
+                iter.next();
+                continue;
+            }
+        }
+        remaining.push(first);
+    }
+    timer.stop("merge park-and-ride trips");
+    timer.note(format!(
+        "{} park-and-ride trips",
+        prettyprint_usize(merged.len())
+    ));
+
+    (remaining, merged)
+}
+
+fn merge_pair(first: &Trip, second: &Trip, map: &Map) -> Option<SpawnTrip> {
+    let (person, (tour, half, idx)) = (first.orig.person, first.orig.seq);
+    if second.orig.person != person || second.orig.seq != (tour, half, idx + 1) {
+        return None;
+    }
+    match (first.orig.purpose.1, second.orig.purpose.0) {
+        (Purpose::ParkAndRideTransfer, Purpose::ParkAndRideTransfer) => {}
+        _ => {
+            return None;
+        }
+    }
+
+    let lot = nearest_lot(first.orig.to.pos, map)?;
+    match (first.orig.mode, second.orig.mode, &first.from, &second.to) {
+        (TripMode::Drive, TripMode::Transit, TripEndpoint::Bldg(home), _) => {
+            SpawnTrip::park_and_ride(*home, Some(lot), second.to.clone(), map)
+        }
+        (TripMode::Transit, TripMode::Drive, _, TripEndpoint::Bldg(home)) => {
+            SpawnTrip::return_from_park_and_ride(first.from.clone(), Some(lot), *home, map)
+        }
+        _ => None,
+    }
+}
+
+fn nearest_lot(pos: LonLat, map: &Map) -> Option<ParkingLotID> {
+    let pt = Pt2D::from_gps(pos, map.get_gps_bounds());
+    map.all_parking_lots()
+        .iter()
+        .map(|pl| (pl.id, pl.polygon.center().dist_to(pt)))
+        .filter(|(_, dist)| *dist <= MAX_DIST_TO_LOT)
+        .min_by_key(|(_, dist)| *dist)
+        .map(|(id, _)| id)
+}
+
 pub fn make_weekday_scenario(
     map: &Map,
     popdat: &PopDat,
@@ -250,13 +336,14 @@ pub fn make_weekday_scenario(
 ) -> Scenario {
     let trips = clip_trips(map, popdat, huge_map, timer);
     let orig_trips = trips.len();
+    let (trips, park_and_rides) = merge_park_and_ride(trips, map, timer);
 
     let mut individ_trips: Vec<Option<IndividTrip>> = Vec::new();
     // person -> (trip seq, index into individ_trips)
     let mut trips_per_person: MultiMap<OrigPersonID, ((usize, bool, usize), usize)> =
         MultiMap::new();
-    for (trip, depart, person, seq) in
-        timer.parallelize("turn Soundcast trips into SpawnTrips", trips, |trip| {
+    for (trip, depart, person, seq) in timer
+        .parallelize("turn Soundcast trips into SpawnTrips", trips, |trip| {
             (
                 SpawnTrip::new(trip.from, trip.to, trip.orig.mode, map),
                 trip.orig.depart_at,
@@ -264,6 +351,8 @@ pub fn make_weekday_scenario(
                 trip.orig.seq,
             )
         })
+        .into_iter()
+        .chain(park_and_rides)
     {
         if let Some(trip) = trip {
             let idx = individ_trips.len();
@@ -92,9 +92,9 @@ pub fn download_kml(
         .arg(output.replace(".bin", ".kml")));
 }
 
-// Uses osmconvert to clip the input .osm (or .pbf) against a polygon and produce some output.
-// Skips if the output exists.
-pub fn osmconvert(input: &str, clipping_polygon: String, output: String) {
+// Clips the input .osm.pbf against a polygon and produces a smaller .osm file. Skips if the output
+// exists.
+pub fn clip_osm(input: &str, clipping_polygon: String, output: String) {
     let input = abstutil::path(input);
     let clipping_polygon = abstutil::path(clipping_polygon);
     let output = abstutil::path(output);
@@ -104,12 +104,13 @@ pub fn osmconvert(input: &str, clipping_polygon: String, output: String) {
         return;
     }
     println!("- Clipping {} to {}", input, clipping_polygon);
-
-    run(Command::new("osmconvert")
-        .arg(input)
-        .arg(format!("-B={}", clipping_polygon))
-        .arg("--complete-ways")
-        .arg(format!("-o={}", output)));
+    convert_osm::clip_pbf(
+        &input,
+        &clipping_polygon,
+        &output,
+        &mut Timer::new(format!("clip {}", input)),
+    )
+    .unwrap();
 }
 
 // Removes files. Be careful!
@@ -250,6 +250,7 @@ impl Model {
             IntersectionType::StopSign => Color::RED,
             IntersectionType::Border => Color::BLUE,
             IntersectionType::Construction => Color::ORANGE,
+            IntersectionType::Roundabout => Color::PURPLE,
         };
 
         let poly = if self.intersection_geom && !self.map.roads_per_intersection(id).is_empty() {
@@ -302,7 +303,7 @@ impl Model {
             }
             IntersectionType::Border => IntersectionType::StopSign,
             // These shouldn't exist in a basemap!
-            IntersectionType::Construction => unreachable!(),
+            IntersectionType::Construction | IntersectionType::Roundabout => unreachable!(),
         };
         self.map
             .intersections
@@ -1,7 +1,7 @@
 use crate::raw::{OriginalIntersection, OriginalRoad};
 use crate::{
-    connectivity, ControlStopSign, ControlTrafficSignal, IntersectionID, IntersectionType, LaneID,
-    LaneType, Map, PathConstraints, RoadID, TurnID, Zone,
+    connectivity, ControlStopSign, ControlTrafficSignal, ExportedTrafficSignal, IntersectionID,
+    IntersectionType, LaneID, LaneType, Map, PathConstraints, RoadID, Roundabout, TurnID, Zone,
 };
 use abstutil::{deserialize_btreemap, retain_btreemap, retain_btreeset, serialize_btreemap, Timer};
 use enumset::EnumSet;
@@ -32,8 +32,11 @@ pub enum EditIntersection {
     StopSign(ControlStopSign),
     // Don't keep ControlTrafficSignal here, because it contains turn groups that should be
     // generated after all lane edits are applied.
-    TrafficSignal(seattle_traffic_signals::TrafficSignal),
+    TrafficSignal(ExportedTrafficSignal),
     Closed,
+    // Intersections along a junction=roundabout ring use that ring; anything else becomes a
+    // mini-roundabout.
+    Roundabout,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -244,8 +247,9 @@ enum PermanentEditIntersection {
         )]
         must_stop: BTreeMap<OriginalRoad, bool>,
     },
-    TrafficSignal(seattle_traffic_signals::TrafficSignal),
+    TrafficSignal(ExportedTrafficSignal),
     Closed,
+    Roundabout,
 }
 
 // Enough data to notice when lanes along a road have changed
@@ -426,6 +430,7 @@ impl EditIntersection {
                 PermanentEditIntersection::TrafficSignal(raw_ts.clone())
             }
             EditIntersection::Closed => PermanentEditIntersection::Closed,
+            EditIntersection::Roundabout => PermanentEditIntersection::Roundabout,
         }
     }
 }
@@ -458,6 +463,7 @@ impl PermanentEditIntersection {
                 Some(EditIntersection::TrafficSignal(ts))
             }
             PermanentEditIntersection::Closed => Some(EditIntersection::Closed),
+            PermanentEditIntersection::Roundabout => Some(EditIntersection::Roundabout),
         }
     }
 }
@@ -503,6 +509,7 @@ impl EditCmd {
                 EditIntersection::StopSign(_) => format!("stop sign #{}", i.0),
                 EditIntersection::TrafficSignal(_) => format!("traffic signal #{}", i.0),
                 EditIntersection::Closed => format!("close {}", i),
+                EditIntersection::Roundabout => format!("roundabout {}", i),
             },
             // TODO "allow/ban X on Y"
             EditCmd::ChangeAccessRestrictions { id, .. } => {
@@ -591,6 +598,8 @@ impl EditCmd {
 
                 map.stop_signs.remove(i);
                 map.traffic_signals.remove(i);
+                map.roundabouts
+                    .retain(|r| !(r.is_mini() && r.members.contains(i)));
                 effects.changed_intersections.insert(*i);
                 match new {
                     EditIntersection::StopSign(ref ss) => {
@@ -604,12 +613,19 @@ impl EditCmd {
                         }
                         map.traffic_signals.insert(
                             *i,
-                            ControlTrafficSignal::import(raw_ts.clone(), *i, map).unwrap(),
+                            ControlTrafficSignal::import_with_plans(raw_ts.clone(), *i, map)
+                                .unwrap(),
                         );
                     }
                     EditIntersection::Closed => {
                         map.intersections[i.0].intersection_type = IntersectionType::Construction;
                     }
+                    EditIntersection::Roundabout => {
+                        map.intersections[i.0].intersection_type = IntersectionType::Roundabout;
+                        if !map.roundabouts.iter().any(|r| r.members.contains(i)) {
+                            map.roundabouts.push(Roundabout::mini(*i));
+                        }
+                    }
                 }
 
                 if old == &EditIntersection::Closed || new == &EditIntersection::Closed {
@@ -744,6 +760,8 @@ fn recalculate_turns(
             map.traffic_signals
                 .insert(id, ControlTrafficSignal::new(map, id, timer));
         }
+        // The priority of each turn comes from the Roundabout, nothing to regenerate
+        IntersectionType::Roundabout => {}
         IntersectionType::Border | IntersectionType::Construction => unreachable!(),
     }
 }
@@ -758,9 +776,10 @@ impl Map {
         match self.get_i(i).intersection_type {
             IntersectionType::StopSign => EditIntersection::StopSign(self.get_stop_sign(i).clone()),
             IntersectionType::TrafficSignal => {
-                EditIntersection::TrafficSignal(self.get_traffic_signal(i).export(self))
+                EditIntersection::TrafficSignal(self.get_traffic_signal(i).export_with_plans(self))
             }
             IntersectionType::Construction => EditIntersection::Closed,
+            IntersectionType::Roundabout => EditIntersection::Roundabout,
             IntersectionType::Border => unreachable!(),
         }
     }
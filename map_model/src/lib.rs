@@ -13,6 +13,7 @@ pub use crate::city::City;
 pub use crate::edits::{
     EditCmd, EditEffects, EditIntersection, MapEdits, OriginalLane, PermanentMapEdits,
 };
+pub use crate::make::green_wave::{GreenWave, WaveDirection};
 pub use crate::make::initial::lane_specs::RoadSpec;
 pub use crate::map::MapConfig;
 pub use crate::objects::area::{Area, AreaID, AreaType};
@@ -26,8 +27,12 @@ pub use crate::objects::lane::{
 };
 pub use crate::objects::parking_lot::{ParkingLot, ParkingLotID};
 pub use crate::objects::road::{DirectedRoadID, Road, RoadID};
+pub use crate::objects::roundabout::Roundabout;
 pub use crate::objects::stop_signs::{ControlStopSign, RoadWithStopSign};
-pub use crate::objects::traffic_signals::{ControlTrafficSignal, Phase, PhaseType};
+pub use crate::objects::traffic_signals::{
+    ActuatedTiming, ControlTrafficSignal, ExportedTimingPlan, ExportedTrafficSignal,
+    PedestrianTiming, Phase, PhaseType, TimingPlan,
+};
 pub use crate::objects::turn::{Turn, TurnGroup, TurnGroupID, TurnID, TurnPriority, TurnType};
 pub use crate::objects::zone::Zone;
 pub use crate::pathfind::uber_turns::{IntersectionCluster, UberTurn, UberTurnGroup};
@@ -75,6 +80,7 @@ pub struct Map {
     // Note that border nodes belong in neither!
     stop_signs: BTreeMap<IntersectionID, ControlStopSign>,
     traffic_signals: BTreeMap<IntersectionID, ControlTrafficSignal>,
+    roundabouts: Vec<Roundabout>,
 
     gps_bounds: GPSBounds,
     bounds: Bounds,
@@ -0,0 +1,488 @@
+use crate::{ControlTrafficSignal, IntersectionID, Map, PhaseType, RoadID, TurnType};
+use geom::{Distance, Duration, Speed};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+// How finely to sample departure times when measuring the band
+const BAND_RESOLUTION: Duration = Duration::const_seconds(0.5);
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum WaveDirection {
+    // From the first intersection to the last
+    Forwards,
+    Backwards,
+    Both,
+}
+
+// The result of coordinating a corridor of traffic signals so platoons moving at the design speed
+// hit green after green.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GreenWave {
+    pub corridor: Vec<IntersectionID>,
+    pub design_speed: Speed,
+    pub direction: WaveDirection,
+    // Every signal along the corridor runs this cycle length.
+    pub cycle_length: Duration,
+    // Distance along the corridor to each intersection, starting with 0
+    pub distances: Vec<Distance>,
+    // How much of each cycle a platoon can depart and get through the whole corridor without
+    // stopping, in each direction. Only exact for fixed phases.
+    pub forwards_bandwidth: Duration,
+    pub backwards_bandwidth: Duration,
+}
+
+// Relative to the start of the cycle
+#[derive(Clone, Copy)]
+struct Green {
+    start: Duration,
+    duration: Duration,
+}
+
+impl GreenWave {
+    // The corridor is an ordered list of traffic signals, each directly connected to the next by
+    // one road. Every signal is stretched to the longest cycle length among them, then offsets
+    // are chosen so the phase serving through traffic starts just as a platoon arrives. When
+    // coordinating both directions, each offset is the compromise halfway between the ideal for
+    // each direction. This modifies the signals in the map.
+    //
+    // Only the base timing plan is coordinated, so signals with time-of-day plans are refused;
+    // otherwise the coordination would silently stop applying whenever another plan starts.
+    pub fn coordinate(
+        map: &mut Map,
+        corridor: Vec<IntersectionID>,
+        design_speed: Speed,
+        direction: WaveDirection,
+    ) -> Result<GreenWave, String> {
+        if corridor.len() < 2 {
+            return Err("A corridor needs at least two intersections".to_string());
+        }
+        for i in &corridor {
+            if !map.get_i(*i).is_traffic_signal() {
+                return Err(format!("{} isn't a traffic signal", i));
+            }
+            if !map.get_traffic_signal(*i).plans.is_empty() {
+                return Err(format!(
+                    "{} has time-of-day timing plans; remove them before coordinating",
+                    i
+                ));
+            }
+        }
+        let roads = corridor_roads(map, &corridor)?;
+        let mut distances = vec![Distance::ZERO];
+        for r in &roads {
+            let dist = *distances.last().unwrap() + map.get_r(*r).center_pts.length();
+            distances.push(dist);
+        }
+
+        let cycle_length = corridor
+            .iter()
+            .map(|i| map.get_traffic_signal(*i).cycle_length(0))
+            .max()
+            .unwrap();
+        let mut signals = Vec::new();
+        for i in &corridor {
+            signals.push(stretch_cycle(
+                map.get_traffic_signal(*i).clone(),
+                cycle_length,
+            ));
+        }
+
+        let fwd_greens = through_greens(&signals, &roads, true)?;
+        let back_greens = through_greens(&signals, &roads, false)?;
+        for (idx, ts) in signals.iter_mut().enumerate() {
+            // When does a platoon leaving either end of the corridor arrive here?
+            let fwd_arrival = distances[idx] / design_speed;
+            let back_arrival = (*distances.last().unwrap() - distances[idx]) / design_speed;
+            let fwd_offset = wrap(fwd_arrival - fwd_greens[idx].start, cycle_length);
+            let back_offset = wrap(back_arrival - back_greens[idx].start, cycle_length);
+            ts.offset = match direction {
+                WaveDirection::Forwards => fwd_offset,
+                WaveDirection::Backwards => back_offset,
+                WaveDirection::Both => circular_midpoint(fwd_offset, back_offset, cycle_length),
+            };
+        }
+
+        let offsets: Vec<Duration> = signals.iter().map(|ts| ts.offset).collect();
+        let back_distances: Vec<Distance> = distances
+            .iter()
+            .map(|d| *distances.last().unwrap() - *d)
+            .collect();
+        let forwards_bandwidth = bandwidth(
+            &fwd_greens,
+            &offsets,
+            &distances,
+            design_speed,
+            cycle_length,
+        );
+        let backwards_bandwidth = bandwidth(
+            &back_greens,
+            &offsets,
+            &back_distances,
+            design_speed,
+            cycle_length,
+        );
+
+        for ts in signals {
+            map.incremental_edit_traffic_signal(ts);
+        }
+
+        Ok(GreenWave {
+            corridor,
+            design_speed,
+            direction,
+            cycle_length,
+            distances,
+            forwards_bandwidth,
+            backwards_bandwidth,
+        })
+    }
+
+    // Draws when each signal is green for through traffic (in each direction) against distance
+    // along the corridor, for a few cycles, plus the edges of the forwards band. Time goes left to
+    // right.
+    pub fn time_space_diagram_svg(&self, map: &Map, num_cycles: usize) -> String {
+        let px_per_second = 2.0;
+        let px_per_meter = 0.5;
+        let margin = 20.0;
+        let total_time = self.cycle_length * (num_cycles as f64);
+        let width = total_time.inner_seconds() * px_per_second + 2.0 * margin;
+        let height = self.distances.last().unwrap().inner_meters() * px_per_meter + 2.0 * margin;
+        let x = |t: Duration| margin + t.inner_seconds() * px_per_second;
+        // Put the start of the corridor at the bottom
+        let y = |d: Distance| height - margin - d.inner_meters() * px_per_meter;
+
+        let mut svg = String::new();
+        writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}">"#,
+            width, height
+        )
+        .unwrap();
+        writeln!(
+            svg,
+            r#"<rect width="{}" height="{}" fill="white"/>"#,
+            width, height
+        )
+        .unwrap();
+
+        let signals: Vec<&ControlTrafficSignal> = self
+            .corridor
+            .iter()
+            .map(|i| map.get_traffic_signal(*i))
+            .collect();
+        let roads = corridor_roads(map, &self.corridor).unwrap();
+        let lists = vec![
+            (
+                through_greens_refs(&signals, &roads, true).unwrap(),
+                -3.0,
+                "green",
+            ),
+            (
+                through_greens_refs(&signals, &roads, false).unwrap(),
+                3.0,
+                "blue",
+            ),
+        ];
+        for (greens, shift, color) in lists {
+            for (idx, green) in greens.iter().enumerate() {
+                let y1 = y(self.distances[idx]) + shift;
+                // Start one cycle early to catch greens wrapping around
+                for cycle in 0..=num_cycles {
+                    let start = self.cycle_length * ((cycle as f64) - 1.0)
+                        + signals[idx].offset
+                        + green.start;
+                    let end = start + green.duration;
+                    if end <= Duration::ZERO || start >= total_time {
+                        continue;
+                    }
+                    writeln!(
+                        svg,
+                        r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{}" stroke-width="4"/>"#,
+                        x(start.max(Duration::ZERO)),
+                        y1,
+                        x(end.min(total_time)),
+                        y1,
+                        color
+                    )
+                    .unwrap();
+                }
+            }
+        }
+
+        // Trajectories at the design speed, departing the first intersection at the start of its
+        // through green and bandwidth later. For a forwards wave, these are the edges of the band.
+        let travel_time = *self.distances.last().unwrap() / self.design_speed;
+        let first = through_greens_refs(&signals, &roads, true).unwrap()[0];
+        for cycle in 0..num_cycles {
+            let depart = wrap(signals[0].offset + first.start, self.cycle_length)
+                + self.cycle_length * (cycle as f64);
+            for dt in &[Duration::ZERO, self.forwards_bandwidth] {
+                writeln!(
+                    svg,
+                    r#"<path d="M{} {} L{} {}" stroke="black" stroke-dasharray="4"/>"#,
+                    x(depart + *dt),
+                    y(Distance::ZERO),
+                    x(depart + *dt + travel_time),
+                    y(*self.distances.last().unwrap())
+                )
+                .unwrap();
+            }
+        }
+
+        writeln!(svg, "</svg>").unwrap();
+        svg
+    }
+}
+
+// The road connecting each pair of intersections
+fn corridor_roads(map: &Map, corridor: &[IntersectionID]) -> Result<Vec<RoadID>, String> {
+    let mut roads = Vec::new();
+    for pair in corridor.windows(2) {
+        let i1 = map.get_i(pair[0]);
+        let i2 = map.get_i(pair[1]);
+        if let Some(r) = i1.roads.intersection(&i2.roads).next() {
+            roads.push(*r);
+        } else {
+            return Err(format!(
+                "{} and {} aren't directly connected",
+                pair[0], pair[1]
+            ));
+        }
+    }
+    Ok(roads)
+}
+
+// Scale up each phase proportionally, rounding to whole seconds, with the last phase absorbing the
+// rounding error.
+fn stretch_cycle(mut signal: ControlTrafficSignal, cycle: Duration) -> ControlTrafficSignal {
+    let factor = cycle / signal.cycle_length(0);
+    let mut total = Duration::ZERO;
+    let num_phases = signal.phases.len();
+    for (idx, phase) in signal.phases.iter_mut().enumerate() {
+        let scale = |d: Duration| {
+            if idx == num_phases - 1 {
+                cycle - total
+            } else {
+                Duration::seconds((d * factor).inner_seconds().round())
+            }
+        };
+        phase.phase_type = match phase.phase_type.clone() {
+            PhaseType::Fixed(d) => PhaseType::Fixed(scale(d)),
+            PhaseType::Adaptive(d) => PhaseType::Adaptive(scale(d)),
+            PhaseType::Actuated(mut timing) => {
+                let max_green = scale(timing.max_green);
+                timing.min_green = timing.min_green.min(max_green);
+                timing.max_green = max_green;
+                PhaseType::Actuated(timing)
+            }
+        };
+        total += phase.phase_type.simple_duration();
+    }
+    signal
+}
+
+fn through_greens(
+    signals: &[ControlTrafficSignal],
+    roads: &[RoadID],
+    forwards: bool,
+) -> Result<Vec<Green>, String> {
+    through_greens_refs(&signals.iter().collect::<Vec<_>>(), roads, forwards)
+}
+
+// For each signal, the phase serving through traffic along the corridor in one direction
+fn through_greens_refs(
+    signals: &[&ControlTrafficSignal],
+    roads: &[RoadID],
+    forwards: bool,
+) -> Result<Vec<Green>, String> {
+    let mut greens = Vec::new();
+    for (idx, ts) in signals.iter().enumerate() {
+        // The road leading to the previous and next intersections
+        let prev = if idx == 0 { None } else { Some(roads[idx - 1]) };
+        let next = roads.get(idx).cloned();
+        let (from, to) = if forwards { (prev, next) } else { (next, prev) };
+
+        let mut groups: Vec<_> = ts
+            .turn_groups
+            .values()
+            .filter(|g| {
+                !g.id.crosswalk
+                    && from.map(|r| g.id.from.id == r).unwrap_or(true)
+                    && to.map(|r| g.id.to.id == r).unwrap_or(true)
+            })
+            .collect();
+        // At the ends of the corridor, only one road is fixed, so prefer going straight.
+        groups.sort_by_key(|g| g.turn_type != TurnType::Straight);
+
+        let mut start = Duration::ZERO;
+        let mut found = None;
+        'phases: for phase in &ts.phases {
+            for g in &groups {
+                if phase.protected_groups.contains(&g.id) {
+                    found = Some(Green {
+                        start,
+                        duration: phase.phase_type.simple_duration(),
+                    });
+                    break 'phases;
+                }
+            }
+            start += phase.phase_type.simple_duration();
+        }
+        if let Some(green) = found {
+            greens.push(green);
+        } else {
+            return Err(format!(
+                "{} has no phase protecting through traffic along the corridor",
+                ts.id
+            ));
+        }
+    }
+    Ok(greens)
+}
+
+// How much of one cycle's worth of departure times from the start of the corridor make it through
+// every signal on green
+fn bandwidth(
+    greens: &[Green],
+    offsets: &[Duration],
+    distances: &[Distance],
+    speed: Speed,
+    cycle: Duration,
+) -> Duration {
+    let mut count = 0;
+    let mut depart = Duration::ZERO;
+    while depart < cycle {
+        if greens.iter().enumerate().all(|(idx, green)| {
+            let arrival = depart + distances[idx] / speed;
+            wrap(arrival - offsets[idx] - green.start, cycle) < green.duration
+        }) {
+            count += 1;
+        }
+        depart += BAND_RESOLUTION;
+    }
+    BAND_RESOLUTION * (count as f64)
+}
+
+// Into [0, cycle)
+fn wrap(dt: Duration, cycle: Duration) -> Duration {
+    Duration::seconds(dt.inner_seconds().rem_euclid(cycle.inner_seconds()))
+}
+
+fn circular_midpoint(a: Duration, b: Duration, cycle: Duration) -> Duration {
+    let diff = wrap(b - a, cycle);
+    if diff <= cycle / 2.0 {
+        wrap(a + diff / 2.0, cycle)
+    } else {
+        wrap(a - (cycle - diff) / 2.0, cycle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ActuatedTiming, Phase};
+    use std::collections::BTreeMap;
+
+    fn signal(phases: Vec<PhaseType>) -> ControlTrafficSignal {
+        ControlTrafficSignal {
+            id: IntersectionID(0),
+            phases: phases
+                .into_iter()
+                .map(|phase_type| {
+                    let mut phase = Phase::new();
+                    phase.phase_type = phase_type;
+                    phase
+                })
+                .collect(),
+            offset: Duration::ZERO,
+            plans: Vec::new(),
+            turn_groups: BTreeMap::new(),
+        }
+    }
+
+    fn secs(x: f64) -> Duration {
+        Duration::seconds(x)
+    }
+
+    #[test]
+    fn test_stretch_cycle() {
+        let ts = stretch_cycle(
+            signal(vec![
+                PhaseType::Fixed(secs(30.0)),
+                PhaseType::Fixed(secs(20.0)),
+                PhaseType::Actuated(ActuatedTiming {
+                    min_green: secs(5.0),
+                    max_green: secs(10.0),
+                    gap_out: secs(2.0),
+                }),
+            ]),
+            secs(90.0),
+        );
+        assert_eq!(ts.cycle_length(0), secs(90.0));
+        assert_eq!(ts.phases[0].phase_type, PhaseType::Fixed(secs(45.0)));
+        assert_eq!(ts.phases[1].phase_type, PhaseType::Fixed(secs(30.0)));
+        // The last phase absorbs any rounding, and min_green is untouched
+        assert_eq!(
+            ts.phases[2].phase_type,
+            PhaseType::Actuated(ActuatedTiming {
+                min_green: secs(5.0),
+                max_green: secs(15.0),
+                gap_out: secs(2.0),
+            })
+        );
+
+        // Rounding to whole seconds still adds up to the cycle
+        let ts = stretch_cycle(
+            signal(vec![
+                PhaseType::Fixed(secs(10.0)),
+                PhaseType::Fixed(secs(10.0)),
+                PhaseType::Fixed(secs(10.0)),
+            ]),
+            secs(50.0),
+        );
+        assert_eq!(ts.cycle_length(0), secs(50.0));
+        assert_eq!(ts.phases[0].phase_type, PhaseType::Fixed(secs(17.0)));
+        assert_eq!(ts.phases[2].phase_type, PhaseType::Fixed(secs(16.0)));
+    }
+
+    #[test]
+    fn test_bandwidth() {
+        let cycle = secs(60.0);
+        let speed = Speed::meters_per_second(10.0);
+        let greens = vec![
+            Green {
+                start: Duration::ZERO,
+                duration: secs(30.0),
+            },
+            Green {
+                start: Duration::ZERO,
+                duration: secs(30.0),
+            },
+        ];
+        // 20s of travel between the two signals
+        let distances = vec![Distance::ZERO, Distance::meters(200.0)];
+
+        // Perfectly offset, so every departure on green makes it through
+        let perfect = bandwidth(&greens, &[secs(0.0), secs(20.0)], &distances, speed, cycle);
+        assert_eq!(perfect, secs(30.0));
+
+        // Without an offset, only departures in the first 10s of green reach the next signal before
+        // it turns red
+        let none = bandwidth(&greens, &[secs(0.0), secs(0.0)], &distances, speed, cycle);
+        assert_eq!(none, secs(10.0));
+
+        // Completely out of phase
+        let worst = bandwidth(&greens, &[secs(0.0), secs(50.0)], &distances, speed, cycle);
+        assert_eq!(worst, Duration::ZERO);
+    }
+
+    #[test]
+    fn test_wrap_and_midpoint() {
+        let cycle = secs(60.0);
+        assert_eq!(wrap(secs(-10.0), cycle), secs(50.0));
+        assert_eq!(wrap(secs(130.0), cycle), secs(10.0));
+        assert_eq!(circular_midpoint(secs(10.0), secs(30.0), cycle), secs(20.0));
+        // The short way around passes 0
+        assert_eq!(circular_midpoint(secs(50.0), secs(10.0), cycle), secs(0.0));
+    }
+}
@@ -1,5 +1,6 @@
 mod bridges;
 mod buildings;
+pub mod green_wave;
 pub mod initial;
 mod remove_disconnected;
 pub mod traffic_signals;
@@ -12,7 +13,7 @@ use crate::raw::{OriginalIntersection, OriginalRoad, RawMap};
 use crate::{
     connectivity, osm, Area, AreaID, ControlStopSign, ControlTrafficSignal, Intersection,
     IntersectionID, IntersectionType, Lane, LaneID, Map, MapEdits, PathConstraints, Position, Road,
-    RoadID, Zone,
+    RoadID, Roundabout, Zone,
 };
 use abstutil::Timer;
 use enumset::EnumSet;
@@ -44,6 +45,7 @@ impl Map {
             boundary_polygon: raw.boundary_polygon.clone(),
             stop_signs: BTreeMap::new(),
             traffic_signals: BTreeMap::new(),
+            roundabouts: Vec::new(),
             gps_bounds,
             bounds,
             config: raw.config.clone(),
@@ -213,6 +215,13 @@ impl Map {
             map.roads.push(road);
         }
 
+        map.roundabouts = Roundabout::find_all(&map.roads);
+        let roundabout_members: BTreeSet<IntersectionID> = map
+            .roundabouts
+            .iter()
+            .flat_map(|r| r.members.clone())
+            .collect();
+
         for i in map.intersections.iter_mut() {
             if is_border(i, &map.lanes) {
                 i.intersection_type = IntersectionType::Border;
@@ -246,6 +255,12 @@ impl Map {
                     i.intersection_type = IntersectionType::StopSign;
                 }
             }
+            // Signalized roundabouts keep their signals
+            if i.intersection_type == IntersectionType::StopSign
+                && roundabout_members.contains(&i.id)
+            {
+                i.intersection_type = IntersectionType::Roundabout;
+            }
 
             if i.incoming_lanes.is_empty() || i.outgoing_lanes.is_empty() {
                 timer.warn(format!("{} is orphaned!", i.orig_id));
@@ -308,7 +323,9 @@ impl Map {
                 IntersectionType::TrafficSignal => {
                     traffic_signals.insert(i.id, ControlTrafficSignal::new(&map, i.id, timer));
                 }
-                IntersectionType::Border | IntersectionType::Construction => {}
+                IntersectionType::Border
+                | IntersectionType::Construction
+                | IntersectionType::Roundabout => {}
             };
         }
         map.stop_signs = stop_signs;
@@ -97,6 +97,7 @@ fn greedy_assignment(map: &Map, intersection: IntersectionID) -> ControlTrafficS
         id: intersection,
         phases,
         offset: Duration::ZERO,
+        plans: Vec::new(),
         turn_groups,
     };
     // This must succeed
@@ -119,6 +120,7 @@ fn degenerate(map: &Map, i: IntersectionID) -> Option<ControlTrafficSignal> {
         id: i,
         phases,
         offset: Duration::ZERO,
+        plans: Vec::new(),
         turn_groups: TurnGroup::for_i(i, map),
     };
     ts.validate().ok()
@@ -164,6 +166,7 @@ fn three_way(map: &Map, i: IntersectionID) -> Option<ControlTrafficSignal> {
         id: i,
         phases,
         offset: Duration::ZERO,
+        plans: Vec::new(),
         turn_groups,
     };
     ts.validate().ok()
@@ -205,6 +208,7 @@ fn four_way_four_phase(map: &Map, i: IntersectionID) -> Option<ControlTrafficSig
         id: i,
         phases,
         offset: Duration::ZERO,
+        plans: Vec::new(),
         turn_groups: TurnGroup::for_i(i, map),
     };
     ts.validate().ok()
@@ -245,6 +249,7 @@ fn four_way_two_phase(map: &Map, i: IntersectionID) -> Option<ControlTrafficSign
         id: i,
         phases,
         offset: Duration::ZERO,
+        plans: Vec::new(),
         turn_groups: TurnGroup::for_i(i, map),
     };
     ts.validate().ok()
@@ -296,6 +301,7 @@ fn four_oneways(map: &Map, i: IntersectionID) -> Option<ControlTrafficSignal> {
         id: i,
         phases,
         offset: Duration::ZERO,
+        plans: Vec::new(),
         turn_groups: TurnGroup::for_i(i, map),
     };
     ts.validate().ok()
@@ -322,6 +328,7 @@ fn all_walk_all_yield(map: &Map, i: IntersectionID) -> ControlTrafficSignal {
         id: i,
         phases: vec![all_walk, all_yield],
         offset: Duration::ZERO,
+        plans: Vec::new(),
         turn_groups,
     };
     // This must succeed
@@ -359,6 +366,7 @@ fn phase_per_road(map: &Map, i: IntersectionID) -> Option<ControlTrafficSignal>
         id: i,
         phases,
         offset: Duration::ZERO,
+        plans: Vec::new(),
         turn_groups,
     };
     ts.validate().ok()
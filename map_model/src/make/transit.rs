@@ -17,7 +17,7 @@ pub fn make_stops_and_routes(map: &mut Map, raw_routes: &Vec<RawBusRoute>, timer
             timer.warn(format!(
                 "Skipping route {} ({}): {}",
                 r.full_name,
-                rel_url(r.osm_rel_id),
+                source_url(r),
                 err
             ));
         }
@@ -98,7 +98,7 @@ fn make_route(
             // TODO Should panic
             println!(
                 "Route {} starts at {} ({}), but no starting lane for a {:?}?",
-                rel_url(r.osm_rel_id),
+                source_url(r),
                 i.id,
                 i.orig_id,
                 route_type
@@ -116,7 +116,7 @@ fn make_route(
             // TODO Should panic
             println!(
                 "Route {} ends at {} ({}), but no ending lane for a {:?}?",
-                rel_url(r.osm_rel_id),
+                source_url(r),
                 i.id,
                 i.orig_id,
                 route_type
@@ -132,6 +132,8 @@ fn make_route(
         route_type,
         start_border,
         end_border,
+        spawn_times: r.spawn_times.clone(),
+        timepoints: r.timepoints.clone(),
     };
 
     // Make sure the route is connected
@@ -278,6 +280,10 @@ impl Matcher {
     }
 }
 
-fn rel_url(id: i64) -> String {
-    format!("https://www.openstreetmap.org/relation/{}", id)
+fn source_url(r: &RawBusRoute) -> String {
+    if let Some(ref id) = r.gtfs_route_id {
+        format!("GTFS route {}", id)
+    } else {
+        format!("https://www.openstreetmap.org/relation/{}", r.osm_rel_id)
+    }
 }
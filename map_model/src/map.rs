@@ -3,10 +3,10 @@ use crate::{
     Area, AreaID, Building, BuildingID, BuildingType, BusRoute, BusRouteID, BusStop, BusStopID,
     ControlStopSign, ControlTrafficSignal, Intersection, IntersectionID, Lane, LaneID, LaneType,
     Map, MapEdits, ParkingLot, ParkingLotID, Path, PathConstraints, PathRequest, Position, Road,
-    RoadID, Turn, TurnGroupID, TurnID, TurnType,
+    RoadID, Roundabout, Traversable, Turn, TurnGroupID, TurnID, TurnType,
 };
 use abstutil::Timer;
-use geom::{Angle, Bounds, Distance, GPSBounds, Line, PolyLine, Polygon, Pt2D};
+use geom::{Angle, Bounds, Distance, Duration, GPSBounds, Line, PolyLine, Polygon, Pt2D};
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
 
@@ -133,6 +133,7 @@ impl Map {
             ]),
             stop_signs: BTreeMap::new(),
             traffic_signals: BTreeMap::new(),
+            roundabouts: Vec::new(),
             gps_bounds: GPSBounds::new(),
             bounds: Bounds::new(),
             config: MapConfig {
@@ -159,6 +160,10 @@ impl Map {
         &self.intersections
     }
 
+    pub fn all_roundabouts(&self) -> &Vec<Roundabout> {
+        &self.roundabouts
+    }
+
     pub fn all_turns(&self) -> &BTreeMap<TurnID, Turn> {
         &self.turns
     }
@@ -215,6 +220,13 @@ impl Map {
         self.traffic_signals.get(&id)
     }
 
+    pub fn maybe_get_roundabout(&self, id: IntersectionID) -> Option<&Roundabout> {
+        if !self.get_i(id).is_roundabout() {
+            return None;
+        }
+        self.roundabouts.iter().find(|r| r.members.contains(&id))
+    }
+
     pub fn get_r(&self, id: RoadID) -> &Road {
         &self.roads[id.0]
     }
@@ -564,11 +576,33 @@ impl Map {
         self.pathfinder.as_ref().unwrap().pathfind(req, self)
     }
 
+    // Like pathfind, but with extra delay on some lanes. Also returns the cost of the path.
+    pub(crate) fn pathfind_with_delays(
+        &self,
+        req: &PathRequest,
+        delays: &BTreeMap<LaneID, Duration>,
+    ) -> Option<(Path, usize)> {
+        assert!(!self.pathfinder_dirty);
+        self.pathfinder
+            .as_ref()
+            .unwrap()
+            .pathfind_with_delays(req, delays, self)
+    }
+
+    // Route cars using these travel times (like ones measured from a simulation) instead of
+    // free-flow speeds, for the lanes and turns listed. Pass an empty map to go back to free-flow.
+    pub fn set_car_travel_times(&mut self, travel_times: BTreeMap<Traversable, Duration>) {
+        assert!(!self.pathfinder_dirty);
+        let mut pathfinder = self.pathfinder.take().unwrap();
+        pathfinder.set_car_travel_times(self, travel_times);
+        self.pathfinder = Some(pathfinder);
+    }
+
     pub fn should_use_transit(
         &self,
         start: Position,
         end: Position,
-    ) -> Option<(BusStopID, BusStopID, BusRouteID)> {
+    ) -> Option<Vec<(BusStopID, BusStopID, BusRouteID)>> {
         self.pathfinder
             .as_ref()
             .unwrap()
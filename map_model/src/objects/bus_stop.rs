@@ -1,5 +1,6 @@
 use crate::{LaneID, Map, PathConstraints, PathRequest, Position};
 use abstutil::{deserialize_usize, serialize_usize};
+use geom::{Duration, Time};
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
@@ -52,6 +53,13 @@ pub struct BusRoute {
     pub start_border: Option<LaneID>,
     pub end_border: Option<LaneID>,
     pub route_type: PathConstraints,
+    // When vehicles depart from the start of the route, sorted. If empty, there's no schedule, and
+    // one vehicle is spawned every hour.
+    pub spawn_times: Vec<Time>,
+    // Parallel to spawn_times, since each trip may be scheduled differently. For each departure,
+    // parallel to stops: at timepoints, how long after departing the first stop should the vehicle
+    // depart this one?
+    pub timepoints: Vec<Vec<Option<Duration>>>,
 }
 
 impl BusRoute {
@@ -80,4 +88,15 @@ impl BusRoute {
         }
         steps
     }
+
+    // The average time between vehicles, if the route has a schedule
+    pub fn scheduled_headway(&self) -> Option<Duration> {
+        if self.spawn_times.len() < 2 {
+            return None;
+        }
+        Some(
+            (*self.spawn_times.last().unwrap() - self.spawn_times[0])
+                / ((self.spawn_times.len() - 1) as f64),
+        )
+    }
 }
@@ -27,6 +27,8 @@ pub enum IntersectionType {
     TrafficSignal,
     Border,
     Construction,
+    // Part of a Roundabout
+    Roundabout,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -69,6 +71,10 @@ impl Intersection {
         self.intersection_type == IntersectionType::TrafficSignal
     }
 
+    pub fn is_roundabout(&self) -> bool {
+        self.intersection_type == IntersectionType::Roundabout
+    }
+
     pub fn is_light_rail(&self, map: &Map) -> bool {
         self.roads.iter().all(|r| map.get_r(*r).is_light_rail())
     }
@@ -5,6 +5,7 @@ pub mod intersection;
 pub mod lane;
 pub mod parking_lot;
 pub mod road;
+pub mod roundabout;
 pub mod stop_signs;
 pub mod traffic_signals;
 pub mod turn;
@@ -411,6 +411,10 @@ impl Road {
         }
     }
 
+    pub fn is_roundabout(&self) -> bool {
+        self.osm_tags.get("junction") == Some(&"roundabout".to_string())
+    }
+
     pub fn is_private(&self) -> bool {
         self.allow_through_traffic != EnumSet::all()
     }
@@ -0,0 +1,186 @@
+use crate::raw::DrivingSide;
+use crate::{IntersectionID, LaneID, LaneType, Map, Road, RoadID, TurnID, TurnPriority};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+// Vehicles entering a roundabout yield to traffic already circulating; everything else has
+// priority. Usually this is a closed ring of roads tagged junction=roundabout and the
+// intersections along it, derived from the roads' OSM tags when the map is built. Any other
+// intersection can be edited into a mini-roundabout, where the intersection itself is the ring:
+// the ring of roads is empty, every vehicle enters, and they yield to vehicles that entered from
+// an upstream road and will pass in front of them.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Roundabout {
+    pub ring: BTreeSet<RoadID>,
+    pub members: BTreeSet<IntersectionID>,
+}
+
+impl Roundabout {
+    pub(crate) fn find_all(roads: &Vec<Road>) -> Vec<Roundabout> {
+        find_rings(
+            roads
+                .iter()
+                .filter(|r| r.is_roundabout())
+                .map(|r| (r.id, r.src_i, r.dst_i)),
+        )
+    }
+
+    pub(crate) fn mini(i: IntersectionID) -> Roundabout {
+        let mut members = BTreeSet::new();
+        members.insert(i);
+        Roundabout {
+            ring: BTreeSet::new(),
+            members,
+        }
+    }
+
+    pub fn is_mini(&self) -> bool {
+        self.ring.is_empty()
+    }
+
+    pub fn is_circulating(&self, turn: TurnID, map: &Map) -> bool {
+        self.ring.contains(&map.get_l(turn.src).parent)
+    }
+
+    pub fn is_entry(&self, turn: TurnID, map: &Map) -> bool {
+        if self.is_mini() {
+            return map.get_l(turn.src).lane_type != LaneType::Sidewalk;
+        }
+        !self.is_circulating(turn, map) && self.ring.contains(&map.get_l(turn.dst).parent)
+    }
+
+    // Does a vehicle making the turn `ours` have to give way to one approaching to make `theirs`?
+    pub fn gives_way_to(&self, ours: TurnID, theirs: TurnID, map: &Map) -> bool {
+        if !self.is_entry(ours, map) {
+            return false;
+        }
+        if !self.is_mini() {
+            return self.is_circulating(theirs, map);
+        }
+
+        // Vehicles circulate counter-clockwise when driving on the right.
+        let mut order = map
+            .get_i(ours.parent)
+            .get_roads_sorted_by_incoming_angle(map.all_roads());
+        if map.get_driving_side() == DrivingSide::Right {
+            order.reverse();
+        }
+        let idx = |l: LaneID| {
+            let r = map.get_l(l).parent;
+            order.iter().position(|x| *x == r).unwrap()
+        };
+        let steps = |from: usize, to: usize| (to + order.len() - from) % order.len();
+        let (their_entry, their_exit) = (idx(theirs.src), idx(theirs.dst));
+        let to_us = steps(their_entry, idx(ours.src));
+        // A U-turn goes all the way around
+        to_us != 0 && (their_exit == their_entry || to_us < steps(their_entry, their_exit))
+    }
+
+    pub fn get_priority(&self, turn: TurnID, map: &Map) -> TurnPriority {
+        if self.is_entry(turn, map) {
+            TurnPriority::Yield
+        } else {
+            TurnPriority::Protected
+        }
+    }
+}
+
+// Groups the roundabout roads into connected components, keeping only the ones forming a closed
+// cycle. A junction=roundabout way that doesn't loop back on itself (a partial tag, or a ring cut
+// by the map boundary) isn't treated as a roundabout.
+fn find_rings(
+    edges: impl Iterator<Item = (RoadID, IntersectionID, IntersectionID)>,
+) -> Vec<Roundabout> {
+    let mut roads_per_i: BTreeMap<IntersectionID, Vec<(RoadID, IntersectionID)>> = BTreeMap::new();
+    for (r, i1, i2) in edges {
+        roads_per_i.entry(i1).or_insert_with(Vec::new).push((r, i2));
+        roads_per_i.entry(i2).or_insert_with(Vec::new).push((r, i1));
+    }
+
+    let mut roundabouts = Vec::new();
+    let mut visited: BTreeSet<IntersectionID> = BTreeSet::new();
+    for start in roads_per_i.keys() {
+        if visited.contains(start) {
+            continue;
+        }
+        let mut members = BTreeSet::new();
+        let mut ring = BTreeSet::new();
+        let mut queue = vec![*start];
+        while let Some(i) = queue.pop() {
+            if !members.insert(i) {
+                continue;
+            }
+            for (r, next) in &roads_per_i[&i] {
+                ring.insert(*r);
+                queue.push(*next);
+            }
+        }
+        visited.extend(members.iter().cloned());
+
+        // In a simple cycle, every intersection touches exactly two ring roads, and there are as
+        // many roads as intersections.
+        if ring.len() == members.len() && members.iter().all(|i| roads_per_i[i].len() == 2) {
+            roundabouts.push(Roundabout { ring, members });
+        }
+    }
+    roundabouts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ring(edges: Vec<(usize, usize, usize)>) -> Vec<Roundabout> {
+        find_rings(
+            edges
+                .into_iter()
+                .map(|(r, i1, i2)| (RoadID(r), IntersectionID(i1), IntersectionID(i2))),
+        )
+    }
+
+    #[test]
+    fn test_closed_ring() {
+        let found = ring(vec![(0, 0, 1), (1, 1, 2), (2, 2, 0)]);
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            found[0].ring,
+            vec![RoadID(0), RoadID(1), RoadID(2)].into_iter().collect()
+        );
+        assert_eq!(
+            found[0].members,
+            vec![IntersectionID(0), IntersectionID(1), IntersectionID(2)]
+                .into_iter()
+                .collect()
+        );
+    }
+
+    #[test]
+    fn test_two_roads_between_two_intersections() {
+        assert_eq!(ring(vec![(0, 0, 1), (1, 1, 0)]).len(), 1);
+    }
+
+    #[test]
+    fn test_open_chain() {
+        assert!(ring(vec![(0, 0, 1), (1, 1, 2), (2, 2, 3)]).is_empty());
+    }
+
+    #[test]
+    fn test_ring_with_a_tail() {
+        // The tail leaves intersection 2 with three roundabout roads, so this isn't a simple ring
+        assert!(ring(vec![(0, 0, 1), (1, 1, 2), (2, 2, 0), (3, 2, 3)]).is_empty());
+    }
+
+    #[test]
+    fn test_separate_rings() {
+        let found = ring(vec![
+            (0, 0, 1),
+            (1, 1, 2),
+            (2, 2, 0),
+            (3, 3, 4),
+            (4, 4, 5),
+            (5, 5, 6),
+        ]);
+        assert_eq!(found.len(), 1);
+        assert!(found[0].members.contains(&IntersectionID(0)));
+    }
+}
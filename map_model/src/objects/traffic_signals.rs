@@ -3,7 +3,7 @@ use crate::{
     DirectedRoadID, IntersectionID, Map, TurnGroup, TurnGroupID, TurnID, TurnPriority, TurnType,
 };
 use abstutil::{deserialize_btreemap, retain_btreeset, serialize_btreemap, Timer};
-use geom::Duration;
+use geom::{Duration, Time};
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet};
 
@@ -12,6 +12,9 @@ pub struct ControlTrafficSignal {
     pub id: IntersectionID,
     pub phases: Vec<Phase>,
     pub offset: Duration,
+    // Other timing plans that take over at some time of day, sorted by start_time. Before the
+    // first one starts (and if there are none), phases and offset apply.
+    pub plans: Vec<TimingPlan>,
 
     #[serde(
         serialize_with = "serialize_btreemap",
@@ -20,11 +23,22 @@ pub struct ControlTrafficSignal {
     pub turn_groups: BTreeMap<TurnGroupID, TurnGroup>,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct TimingPlan {
+    // Time of day, so the plan applies every day of a multi-day simulation
+    pub start_time: Time,
+    pub phases: Vec<Phase>,
+    pub offset: Duration,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct Phase {
     pub protected_groups: BTreeSet<TurnGroupID>,
     pub yield_groups: BTreeSet<TurnGroupID>,
     pub phase_type: PhaseType,
+    // If None, pedestrians may start crossing any time during the phase, as long as they'll
+    // finish before it ends.
+    pub ped_timing: Option<PedestrianTiming>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
@@ -34,13 +48,41 @@ pub enum PhaseType {
     // repeat the phase entirely.
     // TODO This is a silly policy, but a start towards variable timers.
     Adaptive(Duration),
+    // Driven by detectors on the incoming lanes of the protected groups.
+    Actuated(ActuatedTiming),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ActuatedTiming {
+    // The phase always lasts at least this long.
+    pub min_green: Duration,
+    // And never longer than this, even if vehicles keep coming.
+    pub max_green: Duration,
+    // After min_green, the phase is extended by this much every time a vehicle is detected
+    // approaching. If nothing shows up in that time, the phase ends early.
+    pub gap_out: Duration,
+}
+
+// The pedestrian signal for the crosswalks in a phase, relative to when the phase starts.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct PedestrianTiming {
+    // Crosswalks show WALK for this long.
+    pub walk: Duration,
+    // Then flashing don't walk for this long. Nobody new starts crossing, but anybody already in
+    // the crosswalk has time to finish.
+    pub clearance: Duration,
+    // Vehicles in this phase wait this long before going, giving pedestrians a head start. Zero
+    // means no leading pedestrian interval.
+    pub leading_interval: Duration,
 }
 
 impl PhaseType {
     // TODO Maybe don't have this; force callers to acknowledge different policies
+    // For actuated phases, this is the longest the phase could last.
     pub fn simple_duration(&self) -> Duration {
         match self {
             PhaseType::Fixed(d) | PhaseType::Adaptive(d) => *d,
+            PhaseType::Actuated(timing) => timing.max_green,
         }
     }
 }
@@ -67,10 +109,34 @@ impl ControlTrafficSignal {
     }
 
     pub fn validate(self) -> Result<ControlTrafficSignal, String> {
+        for pair in self.plans.windows(2) {
+            if pair[0].start_time >= pair[1].start_time {
+                return Err(format!(
+                    "Timing plans for {} aren't sorted by start time",
+                    self.id
+                ));
+            }
+        }
+        if let Some(plan) = self.plans.last() {
+            if plan.start_time >= Time::START_OF_DAY + Duration::hours(24) {
+                return Err(format!(
+                    "Timing plan for {} starts at {}, which isn't a time of day",
+                    self.id, plan.start_time
+                ));
+            }
+        }
+
+        for plan in 0..=self.plans.len() {
+            self.validate_phases(self.plan_phases(plan))?;
+        }
+        Ok(self)
+    }
+
+    fn validate_phases(&self, phases: &[Phase]) -> Result<(), String> {
         // Does the assignment cover the correct set of groups?
         let expected_groups: BTreeSet<TurnGroupID> = self.turn_groups.keys().cloned().collect();
         let mut actual_groups: BTreeSet<TurnGroupID> = BTreeSet::new();
-        for phase in &self.phases {
+        for phase in phases {
             actual_groups.extend(phase.protected_groups.iter());
             actual_groups.extend(phase.yield_groups.iter());
         }
@@ -89,7 +155,7 @@ impl ControlTrafficSignal {
             ));
         }
 
-        for phase in &self.phases {
+        for phase in phases {
             // Do any of the priority groups in one phase conflict?
             for g1 in phase.protected_groups.iter().map(|g| &self.turn_groups[g]) {
                 for g2 in phase.protected_groups.iter().map(|g| &self.turn_groups[g]) {
@@ -107,9 +173,73 @@ impl ControlTrafficSignal {
             for g in phase.yield_groups.iter().map(|g| &self.turn_groups[g]) {
                 assert!(g.turn_type != TurnType::Crosswalk);
             }
+
+            if let PhaseType::Actuated(ref timing) = phase.phase_type {
+                if timing.min_green == Duration::ZERO
+                    || timing.min_green > timing.max_green
+                    || timing.gap_out == Duration::ZERO
+                {
+                    return Err(format!(
+                        "Traffic signal {} has a bad actuated phase: {:?}",
+                        self.id, timing
+                    ));
+                }
+            }
+
+            if let Some(ref ped) = phase.ped_timing {
+                let min_duration = match phase.phase_type {
+                    PhaseType::Fixed(d) | PhaseType::Adaptive(d) => d,
+                    PhaseType::Actuated(ref timing) => timing.min_green,
+                };
+                if ped.walk == Duration::ZERO
+                    || ped.leading_interval > ped.walk
+                    || ped.walk + ped.clearance > min_duration
+                {
+                    return Err(format!(
+                        "Traffic signal {} has bad pedestrian timing: {:?}",
+                        self.id, ped
+                    ));
+                }
+            }
         }
 
-        Ok(self)
+        Ok(())
+    }
+
+    // Plan 0 is phases and offset; plan i is plans[i - 1].
+    pub fn plan_at(&self, now: Time) -> usize {
+        let time_of_day = Time::START_OF_DAY
+            + Duration::seconds(now.inner_seconds() % Duration::hours(24).inner_seconds());
+        self.plans
+            .iter()
+            .rposition(|p| p.start_time <= time_of_day)
+            .map(|idx| idx + 1)
+            .unwrap_or(0)
+    }
+
+    pub fn plan_phases(&self, plan: usize) -> &Vec<Phase> {
+        if plan == 0 {
+            &self.phases
+        } else {
+            &self.plans[plan - 1].phases
+        }
+    }
+
+    pub fn plan_offset(&self, plan: usize) -> Duration {
+        if plan == 0 {
+            self.offset
+        } else {
+            self.plans[plan - 1].offset
+        }
+    }
+
+    // How long one cycle of a plan lasts. Actuated phases count as their max_green, since that's
+    // what coordination has to leave room for.
+    pub fn cycle_length(&self, plan: usize) -> Duration {
+        self.plan_phases(plan)
+            .iter()
+            .map(|p| p.phase_type.simple_duration())
+            .sum()
     }
 
     // Returns true if this did anything
@@ -123,38 +253,47 @@ impl ControlTrafficSignal {
             }
         }
 
-        // Remove Crosswalk groups from existing phases.
-        let mut replaced = std::mem::replace(&mut self.phases, Vec::new());
-        let mut has_all_walk = false;
-        for phase in replaced.iter_mut() {
-            if !has_all_walk && phase == &all_walk_phase {
-                has_all_walk = true;
-                continue;
-            }
+        convert_phases_to_ped_scramble(&mut self.phases, &all_walk_phase, &self.turn_groups);
+        for plan in self.plans.iter_mut() {
+            convert_phases_to_ped_scramble(&mut plan.phases, &all_walk_phase, &self.turn_groups);
+        }
+        self != &orig
+    }
+}
 
-            // Crosswalks are only in protected_groups.
-            retain_btreeset(&mut phase.protected_groups, |g| {
-                self.turn_groups[g].turn_type != TurnType::Crosswalk
-            });
+fn convert_phases_to_ped_scramble(
+    phases: &mut Vec<Phase>,
+    all_walk_phase: &Phase,
+    turn_groups: &BTreeMap<TurnGroupID, TurnGroup>,
+) {
+    // Remove Crosswalk groups from existing phases.
+    let mut has_all_walk = false;
+    for phase in phases.iter_mut() {
+        if !has_all_walk && phase == all_walk_phase {
+            has_all_walk = true;
+            continue;
+        }
 
-            // Blindly try to promote yield groups to protected, now that crosswalks are gone.
-            let mut promoted = Vec::new();
-            for g in &phase.yield_groups {
-                if phase.could_be_protected(*g, &self.turn_groups) {
-                    phase.protected_groups.insert(*g);
-                    promoted.push(*g);
-                }
-            }
-            for g in promoted {
-                phase.yield_groups.remove(&g);
+        // Crosswalks are only in protected_groups.
+        retain_btreeset(&mut phase.protected_groups, |g| {
+            turn_groups[g].turn_type != TurnType::Crosswalk
+        });
+
+        // Blindly try to promote yield groups to protected, now that crosswalks are gone.
+        let mut promoted = Vec::new();
+        for g in &phase.yield_groups {
+            if phase.could_be_protected(*g, turn_groups) {
+                phase.protected_groups.insert(*g);
+                promoted.push(*g);
             }
         }
-        self.phases = replaced;
-
-        if !has_all_walk {
-            self.phases.push(all_walk_phase);
+        for g in promoted {
+            phase.yield_groups.remove(&g);
         }
-        self != &orig
+    }
+
+    if !has_all_walk {
+        phases.push(all_walk_phase.clone());
     }
 }
 
@@ -164,6 +303,7 @@ impl Phase {
             protected_groups: BTreeSet::new(),
             yield_groups: BTreeSet::new(),
             phase_type: PhaseType::Fixed(Duration::seconds(30.0)),
+            ped_timing: None,
         }
     }
 
@@ -227,34 +367,66 @@ impl Phase {
     }
 }
 
+// The seattle_traffic_signals format only describes one set of phases, so this keeps the offset,
+// any time-of-day plans, the timing of actuated phases, and pedestrian timing alongside it. This
+// is what map edits store.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportedTrafficSignal {
+    #[serde(flatten)]
+    pub base: seattle_traffic_signals::TrafficSignal,
+    // Edits saved before these existed won't have them.
+    #[serde(default = "zero_offset")]
+    pub offset: Duration,
+    #[serde(default)]
+    pub plans: Vec<ExportedTimingPlan>,
+    // Keyed by phase index
+    #[serde(default)]
+    pub actuated: BTreeMap<usize, ActuatedTiming>,
+    #[serde(default)]
+    pub ped_timing: BTreeMap<usize, PedestrianTiming>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportedTimingPlan {
+    pub start_time: Time,
+    pub offset: Duration,
+    pub phases: Vec<seattle_traffic_signals::Phase>,
+    #[serde(default)]
+    pub actuated: BTreeMap<usize, ActuatedTiming>,
+    #[serde(default)]
+    pub ped_timing: BTreeMap<usize, PedestrianTiming>,
+}
+
+fn zero_offset() -> Duration {
+    Duration::ZERO
+}
+
 impl ControlTrafficSignal {
+    // Only the base plan
     pub fn export(&self, map: &Map) -> seattle_traffic_signals::TrafficSignal {
         seattle_traffic_signals::TrafficSignal {
             intersection_osm_node_id: map.get_i(self.id).orig_id.osm_node_id,
-            phases: self
-                .phases
+            phases: export_phases(&self.phases, map),
+        }
+    }
+
+    pub fn export_with_plans(&self, map: &Map) -> ExportedTrafficSignal {
+        ExportedTrafficSignal {
+            base: self.export(map),
+            offset: self.offset,
+            plans: self
+                .plans
                 .iter()
-                .map(|p| seattle_traffic_signals::Phase {
-                    protected_turns: p
-                        .protected_groups
-                        .iter()
-                        .map(|t| export_turn_group(t, map))
-                        .collect(),
-                    permitted_turns: p
-                        .yield_groups
-                        .iter()
-                        .map(|t| export_turn_group(t, map))
-                        .collect(),
-                    phase_type: match p.phase_type {
-                        PhaseType::Fixed(d) => {
-                            seattle_traffic_signals::PhaseType::Fixed(d.inner_seconds() as usize)
-                        }
-                        PhaseType::Adaptive(d) => {
-                            seattle_traffic_signals::PhaseType::Adaptive(d.inner_seconds() as usize)
-                        }
-                    },
+                .map(|p| ExportedTimingPlan {
+                    start_time: p.start_time,
+                    offset: p.offset,
+                    phases: export_phases(&p.phases, map),
+                    actuated: export_actuated(&p.phases),
+                    ped_timing: export_ped_timing(&p.phases),
                 })
                 .collect(),
+            actuated: export_actuated(&self.phases),
+            ped_timing: export_ped_timing(&self.phases),
         }
     }
 
@@ -263,50 +435,177 @@ impl ControlTrafficSignal {
         id: IntersectionID,
         map: &Map,
     ) -> Result<ControlTrafficSignal, String> {
-        let mut phases = Vec::new();
-        for p in raw.phases {
-            let num_protected = p.protected_turns.len();
-            let num_permitted = p.permitted_turns.len();
-            let protected_groups = p
-                .protected_turns
-                .into_iter()
-                .filter_map(|t| import_turn_group(t, map))
-                .collect::<BTreeSet<_>>();
-            let yield_groups = p
-                .permitted_turns
-                .into_iter()
-                .filter_map(|t| import_turn_group(t, map))
-                .collect::<BTreeSet<_>>();
-            if protected_groups.len() == num_protected && yield_groups.len() == num_permitted {
-                phases.push(Phase {
-                    protected_groups,
-                    yield_groups,
-                    phase_type: match p.phase_type {
-                        seattle_traffic_signals::PhaseType::Fixed(d) => {
-                            PhaseType::Fixed(Duration::seconds(d as f64))
-                        }
-                        seattle_traffic_signals::PhaseType::Adaptive(d) => {
-                            PhaseType::Adaptive(Duration::seconds(d as f64))
-                        }
-                    },
-                });
-            } else {
-                return Err(format!(
-                    "Failed to import some of the turn groups for {}",
-                    raw.intersection_osm_node_id
-                ));
-            }
+        ControlTrafficSignal {
+            id,
+            phases: import_phases(raw.phases, raw.intersection_osm_node_id, map)?,
+            offset: Duration::ZERO,
+            plans: Vec::new(),
+            turn_groups: TurnGroup::for_i(id, map),
         }
+        .validate()
+    }
+
+    pub fn import_with_plans(
+        raw: ExportedTrafficSignal,
+        id: IntersectionID,
+        map: &Map,
+    ) -> Result<ControlTrafficSignal, String> {
+        let osm_node_id = raw.base.intersection_osm_node_id;
+        let mut plans = Vec::new();
+        for p in raw.plans {
+            let mut phases = import_phases(p.phases, osm_node_id, map)?;
+            import_actuated(&mut phases, p.actuated, osm_node_id)?;
+            import_ped_timing(&mut phases, p.ped_timing, osm_node_id)?;
+            plans.push(TimingPlan {
+                start_time: p.start_time,
+                offset: p.offset,
+                phases,
+            });
+        }
+        let mut phases = import_phases(raw.base.phases, osm_node_id, map)?;
+        import_actuated(&mut phases, raw.actuated, osm_node_id)?;
+        import_ped_timing(&mut phases, raw.ped_timing, osm_node_id)?;
         ControlTrafficSignal {
             id,
             phases,
-            offset: Duration::ZERO,
+            offset: raw.offset,
+            plans,
             turn_groups: TurnGroup::for_i(id, map),
         }
         .validate()
     }
 }
 
+fn export_phases(phases: &[Phase], map: &Map) -> Vec<seattle_traffic_signals::Phase> {
+    phases
+        .iter()
+        .map(|p| seattle_traffic_signals::Phase {
+            protected_turns: p
+                .protected_groups
+                .iter()
+                .map(|t| export_turn_group(t, map))
+                .collect(),
+            permitted_turns: p
+                .yield_groups
+                .iter()
+                .map(|t| export_turn_group(t, map))
+                .collect(),
+            phase_type: match p.phase_type {
+                PhaseType::Fixed(d) => {
+                    seattle_traffic_signals::PhaseType::Fixed(d.inner_seconds() as usize)
+                }
+                PhaseType::Adaptive(d) => {
+                    seattle_traffic_signals::PhaseType::Adaptive(d.inner_seconds() as usize)
+                }
+                // The format has nothing for this; the real timing is kept separately by
+                // export_actuated.
+                PhaseType::Actuated(ref timing) => seattle_traffic_signals::PhaseType::Adaptive(
+                    timing.min_green.inner_seconds() as usize,
+                ),
+            },
+        })
+        .collect()
+}
+
+fn export_actuated(phases: &[Phase]) -> BTreeMap<usize, ActuatedTiming> {
+    let mut actuated = BTreeMap::new();
+    for (idx, p) in phases.iter().enumerate() {
+        if let PhaseType::Actuated(ref timing) = p.phase_type {
+            actuated.insert(idx, timing.clone());
+        }
+    }
+    actuated
+}
+
+fn import_actuated(
+    phases: &mut Vec<Phase>,
+    actuated: BTreeMap<usize, ActuatedTiming>,
+    osm_node_id: i64,
+) -> Result<(), String> {
+    for (idx, timing) in actuated {
+        if let Some(p) = phases.get_mut(idx) {
+            p.phase_type = PhaseType::Actuated(timing);
+        } else {
+            return Err(format!(
+                "Actuated timing for {} refers to missing phase {}",
+                osm_node_id, idx
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn export_ped_timing(phases: &[Phase]) -> BTreeMap<usize, PedestrianTiming> {
+    let mut ped_timing = BTreeMap::new();
+    for (idx, p) in phases.iter().enumerate() {
+        if let Some(ref timing) = p.ped_timing {
+            ped_timing.insert(idx, timing.clone());
+        }
+    }
+    ped_timing
+}
+
+fn import_ped_timing(
+    phases: &mut Vec<Phase>,
+    ped_timing: BTreeMap<usize, PedestrianTiming>,
+    osm_node_id: i64,
+) -> Result<(), String> {
+    for (idx, timing) in ped_timing {
+        if let Some(p) = phases.get_mut(idx) {
+            p.ped_timing = Some(timing);
+        } else {
+            return Err(format!(
+                "Pedestrian timing for {} refers to missing phase {}",
+                osm_node_id, idx
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn import_phases(
+    raw: Vec<seattle_traffic_signals::Phase>,
+    osm_node_id: i64,
+    map: &Map,
+) -> Result<Vec<Phase>, String> {
+    let mut phases = Vec::new();
+    for p in raw {
+        let num_protected = p.protected_turns.len();
+        let num_permitted = p.permitted_turns.len();
+        let protected_groups = p
+            .protected_turns
+            .into_iter()
+            .filter_map(|t| import_turn_group(t, map))
+            .collect::<BTreeSet<_>>();
+        let yield_groups = p
+            .permitted_turns
+            .into_iter()
+            .filter_map(|t| import_turn_group(t, map))
+            .collect::<BTreeSet<_>>();
+        if protected_groups.len() == num_protected && yield_groups.len() == num_permitted {
+            phases.push(Phase {
+                protected_groups,
+                yield_groups,
+                phase_type: match p.phase_type {
+                    seattle_traffic_signals::PhaseType::Fixed(d) => {
+                        PhaseType::Fixed(Duration::seconds(d as f64))
+                    }
+                    seattle_traffic_signals::PhaseType::Adaptive(d) => {
+                        PhaseType::Adaptive(Duration::seconds(d as f64))
+                    }
+                },
+                ped_timing: None,
+            });
+        } else {
+            return Err(format!(
+                "Failed to import some of the turn groups for {}",
+                osm_node_id
+            ));
+        }
+    }
+    Ok(phases)
+}
+
 fn export_turn_group(id: &TurnGroupID, map: &Map) -> seattle_traffic_signals::Turn {
     let from = map.get_r(id.from.id).orig_id;
     let to = map.get_r(id.to.id).orig_id;
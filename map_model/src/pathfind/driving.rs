@@ -1,10 +1,15 @@
 use crate::pathfind::node_map::{deserialize_nodemap, NodeMap};
 use crate::pathfind::uber_turns::{IntersectionCluster, UberTurn};
-use crate::{Lane, LaneID, Map, Path, PathConstraints, PathRequest, PathStep, Turn, TurnID};
+use crate::{
+    Lane, LaneID, Map, Path, PathConstraints, PathRequest, PathStep, Traversable, Turn, TurnID,
+};
 use abstutil::MultiMap;
 use fast_paths::{deserialize_32, serialize_32, FastGraph, InputGraph, PathCalculator};
+use geom::Duration;
 use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
 use thread_local::ThreadLocal;
 
 #[derive(Serialize, Deserialize)]
@@ -15,6 +20,9 @@ pub struct VehiclePathfinder {
     nodes: NodeMap<Node>,
     uber_turns: Vec<UberTurn>,
     constraints: PathConstraints,
+    // Measured travel times that override the free-flow estimates. Only used for cars.
+    #[serde(skip_serializing, skip_deserializing)]
+    travel_times: BTreeMap<Traversable, Duration>,
 
     #[serde(skip_serializing, skip_deserializing)]
     path_calc: ThreadLocal<RefCell<PathCalculator>>,
@@ -49,7 +57,8 @@ impl VehiclePathfinder {
             }
         }
 
-        let input_graph = make_input_graph(map, &nodes, &uber_turns, constraints);
+        let travel_times = BTreeMap::new();
+        let input_graph = make_input_graph(map, &nodes, &uber_turns, constraints, &travel_times);
 
         // All VehiclePathfinders have the same nodes (lanes), so if we're not the first being
         // built, seed from the node ordering.
@@ -65,6 +74,7 @@ impl VehiclePathfinder {
             nodes,
             uber_turns,
             constraints,
+            travel_times,
             path_calc: ThreadLocal::new(),
         }
     }
@@ -121,10 +131,124 @@ impl VehiclePathfinder {
         // the node ordering.
         // TODO Make sure the result of this is deterministic and equivalent to computing from
         // scratch.
-        let input_graph = make_input_graph(map, &self.nodes, &self.uber_turns, self.constraints);
+        let input_graph = make_input_graph(
+            map,
+            &self.nodes,
+            &self.uber_turns,
+            self.constraints,
+            &self.travel_times,
+        );
         let node_ordering = self.graph.get_node_ordering();
         self.graph = fast_paths::prepare_with_order(&input_graph, &node_ordering).unwrap();
     }
+
+    // Only the edge weights change, so this is just like applying edits.
+    pub fn set_travel_times(&mut self, map: &Map, travel_times: BTreeMap<Traversable, Duration>) {
+        self.travel_times = travel_times;
+        self.apply_edits(map);
+    }
+
+    // Plain Dijkstra's over lanes, without the contraction hierarchy, so that the caller can add
+    // extra delay to any lane. Slower than pathfind. Like the contraction hierarchy, paths only go
+    // through an intersection cluster using one of its uber-turns. Also returns the cost of the
+    // path, not counting the last lane.
+    pub fn pathfind_with_delays(
+        &self,
+        req: &PathRequest,
+        delays: &BTreeMap<LaneID, Duration>,
+        map: &Map,
+    ) -> Option<(Path, usize)> {
+        let mut uber_turn_entrances: MultiMap<LaneID, usize> = MultiMap::new();
+        for (idx, ut) in self.uber_turns.iter().enumerate() {
+            if ut
+                .path
+                .iter()
+                .all(|t| req.constraints.can_use(map.get_l(t.dst), map))
+            {
+                uber_turn_entrances.insert(ut.entry(), idx);
+            }
+        }
+
+        let start = req.start.lane();
+        let end = req.end.lane();
+        let mut best: HashMap<LaneID, usize> = HashMap::new();
+        // How each lane was reached: a turn, or all the turns of an uber-turn
+        let mut backrefs: HashMap<LaneID, (Vec<TurnID>, Option<usize>)> = HashMap::new();
+        let mut queue: BinaryHeap<(Reverse<usize>, LaneID)> = BinaryHeap::new();
+        best.insert(start, 0);
+        queue.push((Reverse(0), start));
+
+        while let Some((Reverse(so_far), l)) = queue.pop() {
+            if l == end {
+                let mut steps = vec![PathStep::Lane(end)];
+                let mut uber_turns = Vec::new();
+                let mut current = end;
+                while current != start {
+                    let (turns, maybe_ut) = &backrefs[&current];
+                    for t in turns.iter().rev() {
+                        steps.push(PathStep::Turn(*t));
+                        steps.push(PathStep::Lane(t.src));
+                    }
+                    if let Some(idx) = maybe_ut {
+                        uber_turns.push(self.uber_turns[*idx].clone());
+                    }
+                    current = turns[0].src;
+                }
+                steps.reverse();
+                uber_turns.reverse();
+                return Some((
+                    Path::new(map, steps, req.end.dist_along(), uber_turns),
+                    so_far,
+                ));
+            }
+            if so_far > best[&l] {
+                continue;
+            }
+            let lane = map.get_l(l);
+            // Like the contraction hierarchy, don't cut through private zones.
+            if l != start
+                && !map
+                    .get_r(lane.parent)
+                    .allow_through_traffic
+                    .contains(req.constraints)
+            {
+                continue;
+            }
+
+            let mut next_steps: Vec<(LaneID, usize, Vec<TurnID>, Option<usize>)> = Vec::new();
+            let indices = uber_turn_entrances.get(l);
+            if indices.is_empty() {
+                for turn in map.get_turns_for(l, req.constraints) {
+                    next_steps.push((
+                        turn.id.dst,
+                        cost(lane, turn, req.constraints, map) + delay(delays, l),
+                        vec![turn.id],
+                        None,
+                    ));
+                }
+            } else {
+                for idx in indices {
+                    let ut = &self.uber_turns[*idx];
+                    let mut sum_cost = 0;
+                    for t in &ut.path {
+                        sum_cost += cost(map.get_l(t.src), map.get_t(*t), req.constraints, map)
+                            + delay(delays, t.src);
+                    }
+                    next_steps.push((ut.exit(), sum_cost, ut.path.clone(), Some(*idx)));
+                }
+            }
+
+            for (next, step_cost, turns, maybe_ut) in next_steps {
+                let next_cost = so_far + step_cost;
+                if best.get(&next).map(|c| next_cost < *c).unwrap_or(true) {
+                    best.insert(next, next_cost);
+                    backrefs.insert(next, (turns, maybe_ut));
+                    queue.push((Reverse(next_cost), next));
+                }
+            }
+        }
+        None
+    }
 }
 
 fn make_input_graph(
@@ -132,6 +256,7 @@ fn make_input_graph(
     nodes: &NodeMap<Node>,
     uber_turns: &Vec<UberTurn>,
     constraints: PathConstraints,
+    travel_times: &BTreeMap<Traversable, Duration>,
 ) -> InputGraph {
     let mut input_graph = InputGraph::new();
 
@@ -178,7 +303,7 @@ fn make_input_graph(
                         from,
                         nodes.get(Node::Lane(turn.id.dst)),
                         // Round up! 0 cost edges are ignored
-                        cost(l, turn, constraints, map).max(1),
+                        measured_cost(l, turn, constraints, map, travel_times).max(1),
                     );
                 }
             } else {
@@ -188,7 +313,13 @@ fn make_input_graph(
 
                     let mut sum_cost = 0;
                     for t in &ut.path {
-                        sum_cost += cost(map.get_l(t.src), map.get_t(*t), constraints, map);
+                        sum_cost += measured_cost(
+                            map.get_l(t.src),
+                            map.get_t(*t),
+                            constraints,
+                            map,
+                            travel_times,
+                        );
                     }
                     input_graph.add_edge(from, nodes.get(Node::UberTurn(*idx)), sum_cost.max(1));
                     input_graph.add_edge(
@@ -213,11 +344,60 @@ fn make_input_graph(
     input_graph
 }
 
+// Same units as pathfind_with_delays. None if some turn or lane can't be used anymore.
+pub fn steps_cost<'a>(
+    steps: impl Iterator<Item = &'a PathStep> + Clone,
+    constraints: PathConstraints,
+    delays: &BTreeMap<LaneID, Duration>,
+    map: &Map,
+) -> Option<usize> {
+    let mut total = 0;
+    for (step1, step2) in steps.clone().zip(steps.skip(1)) {
+        if let (PathStep::Lane(l), PathStep::Turn(t)) = (step1, step2) {
+            let turn = map.maybe_get_t(*t)?;
+            if !constraints.can_use(map.get_l(t.dst), map) {
+                return None;
+            }
+            total += cost(map.get_l(*l), turn, constraints, map) + delay(delays, *l);
+        }
+    }
+    Some(total)
+}
+
+fn delay(delays: &BTreeMap<LaneID, Duration>, l: LaneID) -> usize {
+    delays
+        .get(&l)
+        .map(|dt| dt.inner_seconds().round() as usize)
+        .unwrap_or(0)
+}
+
+// Like cost, but for cars, prefer the measured time to cross the lane or turn, when there is one.
+fn measured_cost(
+    lane: &Lane,
+    turn: &Turn,
+    constraints: PathConstraints,
+    map: &Map,
+    travel_times: &BTreeMap<Traversable, Duration>,
+) -> usize {
+    if constraints != PathConstraints::Car || travel_times.is_empty() {
+        return cost(lane, turn, constraints, map);
+    }
+    let t1 = travel_times
+        .get(&Traversable::Lane(lane.id))
+        .cloned()
+        .unwrap_or_else(|| lane.length() / map.get_r(lane.parent).speed_limit);
+    let t2 = travel_times
+        .get(&Traversable::Turn(turn.id))
+        .cloned()
+        .unwrap_or_else(|| turn.geom.length() / map.get_parent(turn.id.dst).speed_limit);
+    (t1 + t2).inner_seconds().round() as usize
+}
+
 pub fn cost(lane: &Lane, turn: &Turn, constraints: PathConstraints, map: &Map) -> usize {
     // TODO Could cost turns differently.
 
     match constraints {
-        PathConstraints::Car | PathConstraints::Train => {
+        PathConstraints::Car | PathConstraints::Train | PathConstraints::Truck => {
             // Prefer slightly longer route on faster roads
             let t1 = lane.length() / map.get_r(lane.parent).speed_limit;
             let t2 = turn.geom.length() / map.get_parent(turn.id.dst).speed_limit;
@@ -5,7 +5,7 @@ pub mod uber_turns;
 mod walking;
 
 pub use self::driving::cost;
-use self::driving::VehiclePathfinder;
+use self::driving::{steps_cost, VehiclePathfinder};
 use self::walking::{one_step_walking_path, walking_path_to_steps, SidewalkPathfinder};
 pub use self::walking::{walking_cost, WalkingNode};
 use crate::{
@@ -14,9 +14,9 @@ use crate::{
 };
 use abstutil::Timer;
 use enumset::EnumSetType;
-use geom::{Distance, PolyLine, EPSILON_DIST};
+use geom::{Distance, Duration, PolyLine, EPSILON_DIST};
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
 use std::error::Error;
 use std::fmt;
 
@@ -291,6 +291,48 @@ impl Path {
         }
     }
 
+    // Looks for a faster way from the current lane to the same destination, given extra delay on
+    // some lanes, like from current congestion. Switches if that saves at least min_savings, or if
+    // the remaining steps can't be used anymore. Progress along the original path is kept. Returns
+    // true if the path changed.
+    pub fn reroute_with_delays(
+        &mut self,
+        constraints: PathConstraints,
+        delays: &BTreeMap<LaneID, Duration>,
+        min_savings: Duration,
+        map: &Map,
+    ) -> bool {
+        assert!(self.currently_inside_ut.is_none());
+        let current_cost = steps_cost(self.steps.iter(), constraints, delays, map);
+        let req = PathRequest {
+            start: Position::start(self.current_step().as_lane()),
+            end: Position::new(self.last_step().as_lane(), self.end_dist),
+            constraints,
+        };
+        let (new_path, new_cost) = match map.pathfind_with_delays(&req, delays) {
+            Some(pair) => pair,
+            None => {
+                return false;
+            }
+        };
+        if let Some(current_cost) = current_cost {
+            if new_cost + (min_savings.inner_seconds() as usize) > current_cost {
+                return false;
+            }
+        }
+
+        for step in self.steps.iter().skip(1) {
+            self.total_length -= step.as_traversable().length(map);
+        }
+        for step in new_path.steps.iter().skip(1) {
+            self.total_length += step.as_traversable().length(map);
+        }
+        self.total_lanes = self.lanes_crossed_so_far() + new_path.total_lanes;
+        self.steps = new_path.steps;
+        self.uber_turns = new_path.uber_turns;
+        true
+    }
+
     pub fn current_step(&self) -> PathStep {
         self.steps[0]
     }
@@ -434,8 +476,12 @@ pub enum PathConstraints {
     Bike,
     Bus,
     Train,
+    Truck,
 }
 
+// Delivery trucks are assumed to weigh this much, in tonnes, when checking OSM maxweight tags.
+const TRUCK_WEIGHT: f64 = 7.5;
+
 impl PathConstraints {
     // Not bijective, but this is the best guess of user intent
     pub fn from_lt(lt: LaneType) -> PathConstraints {
@@ -468,6 +514,25 @@ impl PathConstraints {
             }
             PathConstraints::Bus => l.is_driving() || l.is_bus(),
             PathConstraints::Train => l.is_light_rail(),
+            PathConstraints::Truck => {
+                if !l.is_driving() {
+                    return false;
+                }
+                let road = map.get_r(l.parent);
+                if road.osm_tags.get(osm::HGV) == Some(&"no".to_string()) {
+                    return false;
+                }
+                // Values like "3.5" or "3.5 t". Ignore anything in other units.
+                if let Some(max) = road.osm_tags.get(osm::MAXWEIGHT) {
+                    let parts: Vec<&str> = max.split_whitespace().collect();
+                    if parts.len() == 1 || (parts.len() == 2 && parts[1] == "t") {
+                        if let Ok(tonnes) = parts[0].parse::<f64>() {
+                            return tonnes >= TRUCK_WEIGHT;
+                        }
+                    }
+                }
+                true
+            }
         }
     }
 
@@ -579,6 +644,7 @@ pub struct Pathfinder {
     bike_graph: VehiclePathfinder,
     bus_graph: VehiclePathfinder,
     train_graph: VehiclePathfinder,
+    truck_graph: VehiclePathfinder,
     walking_graph: SidewalkPathfinder,
     // TODO Option just during initialization! Ewww.
     walking_with_transit_graph: Option<SidewalkPathfinder>,
@@ -604,6 +670,10 @@ impl Pathfinder {
         let train_graph = VehiclePathfinder::new(map, PathConstraints::Train, None);
         timer.stop("prepare pathfinding for trains");
 
+        timer.start("prepare pathfinding for trucks");
+        let truck_graph = VehiclePathfinder::new(map, PathConstraints::Truck, Some(&car_graph));
+        timer.stop("prepare pathfinding for trucks");
+
         timer.start("prepare pathfinding for pedestrians");
         let walking_graph = SidewalkPathfinder::new(map, false, &bus_graph, &train_graph);
         timer.stop("prepare pathfinding for pedestrians");
@@ -613,6 +683,7 @@ impl Pathfinder {
             bike_graph,
             bus_graph,
             train_graph,
+            truck_graph,
             walking_graph,
             walking_with_transit_graph: None,
         }
@@ -696,6 +767,25 @@ impl Pathfinder {
             PathConstraints::Bike => self.bike_graph.pathfind(&req, map).map(|(p, _)| p),
             PathConstraints::Bus => self.bus_graph.pathfind(&req, map).map(|(p, _)| p),
             PathConstraints::Train => self.train_graph.pathfind(&req, map).map(|(p, _)| p),
+            PathConstraints::Truck => self.truck_graph.pathfind(&req, map).map(|(p, _)| p),
+        }
+    }
+
+    // Doesn't handle private zones specially, so only use this from somewhere already in the main
+    // map.
+    pub fn pathfind_with_delays(
+        &self,
+        req: &PathRequest,
+        delays: &BTreeMap<LaneID, Duration>,
+        map: &Map,
+    ) -> Option<(Path, usize)> {
+        match req.constraints {
+            PathConstraints::Pedestrian => None,
+            PathConstraints::Car => self.car_graph.pathfind_with_delays(req, delays, map),
+            PathConstraints::Bike => self.bike_graph.pathfind_with_delays(req, delays, map),
+            PathConstraints::Bus => self.bus_graph.pathfind_with_delays(req, delays, map),
+            PathConstraints::Train => self.train_graph.pathfind_with_delays(req, delays, map),
+            PathConstraints::Truck => self.truck_graph.pathfind_with_delays(req, delays, map),
         }
     }
 
@@ -778,6 +868,7 @@ impl Pathfinder {
             PathConstraints::Bike => self.bike_graph.pathfind(&req, map).map(|(p, _)| p),
             PathConstraints::Bus => self.bus_graph.pathfind(&req, map).map(|(p, _)| p),
             PathConstraints::Train => self.train_graph.pathfind(&req, map).map(|(p, _)| p),
+            PathConstraints::Truck => self.truck_graph.pathfind(&req, map).map(|(p, _)| p),
         }?;
         interior_path.append(main_path, map);
         Some(interior_path)
@@ -863,6 +954,7 @@ impl Pathfinder {
             PathConstraints::Bike => self.bike_graph.pathfind(&req, map).map(|(p, _)| p),
             PathConstraints::Bus => self.bus_graph.pathfind(&req, map).map(|(p, _)| p),
             PathConstraints::Train => self.train_graph.pathfind(&req, map).map(|(p, _)| p),
+            PathConstraints::Truck => self.truck_graph.pathfind(&req, map).map(|(p, _)| p),
         }?;
         main_path.append(interior_path, map);
         main_path.end_dist = orig_end_dist;
@@ -874,13 +966,21 @@ impl Pathfinder {
         map: &Map,
         start: Position,
         end: Position,
-    ) -> Option<(BusStopID, BusStopID, BusRouteID)> {
+    ) -> Option<Vec<(BusStopID, BusStopID, BusRouteID)>> {
         self.walking_with_transit_graph
             .as_ref()
             .unwrap()
             .should_use_transit(map, start, end)
     }
 
+    pub fn set_car_travel_times(
+        &mut self,
+        map: &Map,
+        travel_times: BTreeMap<Traversable, Duration>,
+    ) {
+        self.car_graph.set_travel_times(map, travel_times);
+    }
+
     pub fn apply_edits(&mut self, map: &Map, timer: &mut Timer) {
         timer.start("apply edits to car pathfinding");
         self.car_graph.apply_edits(map);
@@ -894,6 +994,10 @@ impl Pathfinder {
         self.bus_graph.apply_edits(map);
         timer.stop("apply edits to bus pathfinding");
 
+        timer.start("apply edits to truck pathfinding");
+        self.truck_graph.apply_edits(map);
+        timer.stop("apply edits to truck pathfinding");
+
         // Can't edit anything related to trains
 
         timer.start("apply edits to pedestrian pathfinding");
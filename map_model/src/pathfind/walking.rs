@@ -1,7 +1,8 @@
 use crate::pathfind::driving::VehiclePathfinder;
 use crate::pathfind::node_map::{deserialize_nodemap, NodeMap};
 use crate::{
-    BusRouteID, BusStopID, LaneID, Map, Path, PathConstraints, PathRequest, PathStep, Position,
+    BusRoute, BusRouteID, BusStopID, LaneID, Map, Path, PathConstraints, PathRequest, PathStep,
+    Position,
 };
 use fast_paths::{deserialize_32, serialize_32, FastGraph, InputGraph, PathCalculator};
 use geom::{Distance, Speed};
@@ -101,41 +102,81 @@ impl SidewalkPathfinder {
         Some(self.nodes.translate(&raw_path))
     }
 
-    // Attempt the pathfinding and see if we should ride a bus.
+    // Attempt the pathfinding and see if we should ride transit. If so, returns each ride in order
+    // as (board at, alight at, route). Between rides, people transfer at the same stop or walk to
+    // another one.
     pub fn should_use_transit(
         &self,
         map: &Map,
         start: Position,
         end: Position,
-    ) -> Option<(BusStopID, BusStopID, BusRouteID)> {
+    ) -> Option<Vec<(BusStopID, BusStopID, BusRouteID)>> {
         let raw_path = fast_paths::calc_path(
             &self.graph,
             self.nodes.get(WalkingNode::closest(start, map)),
             self.nodes.get(WalkingNode::closest(end, map)),
         )?;
 
-        let mut nodes = self.nodes.translate(&raw_path);
-        let mut first_stop = None;
-        for n in &nodes {
+        // Each run of stops without walking in between might still involve a few routes.
+        let mut rides = Vec::new();
+        let mut stops = Vec::new();
+        for n in self.nodes.translate(&raw_path) {
             if let WalkingNode::RideBus(stop) = n {
-                first_stop = Some(*stop);
-                break;
+                stops.push(stop);
+            } else {
+                if stops.len() > 1 {
+                    rides.extend(split_into_routes(&stops, map)?);
+                }
+                stops.clear();
             }
         }
-        let first_stop = first_stop?;
-        let possible_routes = map.get_routes_serving_stop(first_stop);
-
-        nodes.reverse();
-        for n in nodes {
-            if let WalkingNode::RideBus(stop2) = n {
-                if let Some(route) = possible_routes.iter().find(|r| r.stops.contains(&stop2)) {
-                    assert_ne!(first_stop, stop2);
-                    return Some((first_stop, stop2, route.id));
+        if rides.is_empty() {
+            None
+        } else {
+            Some(rides)
+        }
+    }
+}
+
+// Splits a sequence of stops into as few rides as possible, greedily staying on whichever route
+// goes the furthest.
+fn split_into_routes(
+    stops: &[BusStopID],
+    map: &Map,
+) -> Option<Vec<(BusStopID, BusStopID, BusRouteID)>> {
+    split_using_routes(stops, |bs| map.get_routes_serving_stop(bs))
+}
+
+fn split_using_routes<'a, F: Fn(BusStopID) -> Vec<&'a BusRoute>>(
+    stops: &[BusStopID],
+    routes_serving_stop: F,
+) -> Option<Vec<(BusStopID, BusStopID, BusRouteID)>> {
+    let mut rides = Vec::new();
+    let mut idx = 0;
+    while idx < stops.len() - 1 {
+        let mut best: Option<(usize, BusRouteID)> = None;
+        for route in routes_serving_stop(stops[idx]) {
+            // Some routes loop around and serve a stop twice
+            for (pos, stop) in route.stops.iter().enumerate() {
+                if *stop != stops[idx] {
+                    continue;
+                }
+                let mut end = idx;
+                while end + 1 < stops.len()
+                    && route.stops.get(pos + end + 1 - idx) == Some(&stops[end + 1])
+                {
+                    end += 1;
+                }
+                if end > idx && best.map(|(e, _)| end > e).unwrap_or(true) {
+                    best = Some((end, route.id));
                 }
             }
         }
-        unreachable!()
+        let (end, route) = best?;
+        rides.push((stops[idx], stops[end], route));
+        idx = end;
     }
+    Some(rides)
 }
 
 fn make_input_graph(
@@ -327,3 +368,86 @@ pub fn one_step_walking_path(req: &PathRequest, map: &Map) -> Path {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stop(idx: usize) -> BusStopID {
+        BusStopID {
+            sidewalk: LaneID(idx),
+            idx: 0,
+        }
+    }
+
+    fn route(id: usize, stops: Vec<usize>) -> BusRoute {
+        BusRoute {
+            id: BusRouteID(id),
+            full_name: format!("route {}", id),
+            short_name: format!("{}", id),
+            stops: stops.into_iter().map(stop).collect(),
+            start_border: None,
+            end_border: None,
+            route_type: PathConstraints::Bus,
+            spawn_times: Vec::new(),
+            timepoints: Vec::new(),
+        }
+    }
+
+    fn split(
+        routes: &[BusRoute],
+        stops: Vec<usize>,
+    ) -> Option<Vec<(BusStopID, BusStopID, BusRouteID)>> {
+        let stops: Vec<BusStopID> = stops.into_iter().map(stop).collect();
+        split_using_routes(&stops, |bs| {
+            routes.iter().filter(|r| r.stops.contains(&bs)).collect()
+        })
+    }
+
+    #[test]
+    fn test_one_route() {
+        let routes = vec![route(0, vec![1, 2, 3, 4]), route(1, vec![2, 3])];
+        // Stay on the route that goes the furthest, even when another serves the first few stops
+        assert_eq!(
+            split(&routes, vec![2, 3, 4]),
+            Some(vec![(stop(2), stop(4), BusRouteID(0))])
+        );
+    }
+
+    #[test]
+    fn test_transfer() {
+        let routes = vec![route(0, vec![1, 2, 3]), route(1, vec![3, 4, 5])];
+        assert_eq!(
+            split(&routes, vec![1, 2, 3, 4, 5]),
+            Some(vec![
+                (stop(1), stop(3), BusRouteID(0)),
+                (stop(3), stop(5), BusRouteID(1)),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_loop() {
+        // The route passes stop 2 twice; boarding the second time reaches stop 5
+        let routes = vec![route(0, vec![1, 2, 3, 4, 2, 5])];
+        assert_eq!(
+            split(&routes, vec![2, 5]),
+            Some(vec![(stop(2), stop(5), BusRouteID(0))])
+        );
+        assert_eq!(
+            split(&routes, vec![3, 4, 2, 5]),
+            Some(vec![(stop(3), stop(5), BusRouteID(0))])
+        );
+    }
+
+    #[test]
+    fn test_unsplittable() {
+        let routes = vec![route(0, vec![1, 2, 3]), route(1, vec![4, 5])];
+        // No route goes from 3 to 4
+        assert_eq!(split(&routes, vec![1, 2, 3, 4, 5]), None);
+        // Going backwards along a route doesn't work either
+        assert_eq!(split(&routes, vec![3, 2]), None);
+        // Skipping a stop along the route isn't allowed
+        assert_eq!(split(&routes, vec![1, 3]), None);
+    }
+}
@@ -1,7 +1,7 @@
 use crate::make::initial::lane_specs::get_lane_types;
 use crate::{osm, AreaType, IntersectionType, MapConfig, RoadSpec};
 use abstutil::{deserialize_btreemap, serialize_btreemap, Timer};
-use geom::{Angle, Distance, GPSBounds, Line, PolyLine, Polygon, Pt2D};
+use geom::{Angle, Distance, Duration, GPSBounds, Line, PolyLine, Polygon, Pt2D, Time};
 use petgraph::graphmap::DiGraphMap;
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet};
@@ -462,6 +462,8 @@ pub struct RawBusRoute {
     pub full_name: String,
     pub short_name: String,
     pub osm_rel_id: i64,
+    // Set instead of osm_rel_id when the route comes from a GTFS feed
+    pub gtfs_route_id: Option<String>,
     // If not, light rail
     pub is_bus: bool,
     pub stops: Vec<RawBusStop>,
@@ -469,9 +471,15 @@ pub struct RawBusRoute {
     pub border_end: Option<OriginalIntersection>,
     // Temporarily plumbed along
     pub all_pts: Vec<OriginalIntersection>,
+    // When vehicles depart from the first stop, sorted. Only GTFS routes have this.
+    pub spawn_times: Vec<Time>,
+    // Parallel to spawn_times, since each trip may be scheduled differently. For each departure,
+    // parallel to stops: at timepoints, how long after departing the first stop should the vehicle
+    // depart this one?
+    pub timepoints: Vec<Vec<Option<Duration>>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct RawBusStop {
     pub name: String,
     pub vehicle_pos: Pt2D,
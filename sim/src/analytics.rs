@@ -1,9 +1,12 @@
-use crate::{AgentType, AlertLocation, CarID, Event, ParkingSpot, TripID, TripMode, TripPhaseType};
-use abstutil::Counter;
-use geom::{Distance, Duration, Time};
+use crate::{
+    AgentID, AgentType, AlertLocation, CarID, Emissions, Event, ParkingSpot, PersonID, TripID,
+    TripMode, TripPhaseType, VehicleType,
+};
+use abstutil::{deserialize_btreemap, serialize_btreemap, Counter};
+use geom::{Distance, Duration, Histogram, Time};
 use map_model::{
-    BusRouteID, BusStopID, IntersectionID, LaneID, Map, ParkingLotID, Path, PathRequest, RoadID,
-    Traversable, TurnGroupID,
+    BuildingID, BusRouteID, BusStopID, IntersectionID, LaneID, Map, ParkingLotID, Path,
+    PathRequest, RoadID, Traversable, TurnGroupID,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, VecDeque};
@@ -21,8 +24,22 @@ pub struct Analytics {
     // For each passenger boarding, how long did they wait at the stop?
     pub passengers_boarding: BTreeMap<BusStopID, Vec<(Time, BusRouteID, Duration)>>,
     pub passengers_alighting: BTreeMap<BusStopID, Vec<(Time, BusRouteID)>>,
+    // At timepoints, how late was each arrival compared to the schedule? Negative is early.
+    pub bus_schedule_deviations: Vec<(Time, CarID, BusRouteID, BusStopID, Duration)>,
+    // How many people couldn't board because the vehicle was full?
+    pub passengers_left_behind: BTreeMap<BusStopID, Vec<(Time, BusRouteID, usize)>>,
+
+    // For each ride-hail pickup, how long did the passenger wait, and how far did the vehicle drive
+    // empty to get there?
+    pub ride_hail_pickups: Vec<(Time, CarID, Duration, Distance)>,
+    // For each ride-hail dropoff, how long and how far was the ride?
+    pub ride_hail_dropoffs: Vec<(Time, CarID, Duration, Distance)>,
+
+    // Each delivery a truck made, and the lane it blocked if it double-parked
+    pub delivery_stops: Vec<(Time, CarID, BuildingID, Option<LaneID>)>,
 
     pub started_trips: BTreeMap<TripID, Time>,
+    pub trip_to_person: BTreeMap<TripID, PersonID>,
     // TODO Hack: No TripMode means aborted
     // Finish time, ID, mode (or None as aborted), trip duration
     pub finished_trips: Vec<(Time, TripID, Option<TripMode>, Duration)>,
@@ -36,6 +53,31 @@ pub struct Analytics {
     pub parking_lane_changes: BTreeMap<LaneID, Vec<(Time, bool)>>,
     pub parking_lot_changes: BTreeMap<ParkingLotID, Vec<(Time, bool)>>,
 
+    // For cars, the total time spent crossing each lane and turn, and how many crossings. The time
+    // on a lane includes waiting at the end of it.
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    pub car_crossings: BTreeMap<Traversable, (Duration, usize)>,
+    // When each car entered its current lane or turn
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    car_entered: BTreeMap<CarID, (Traversable, Time)>,
+    record_car_crossings: bool,
+
+    // Everything vehicles have emitted so far, per road, intersection, trip, and vehicle
+    pub road_emissions: BTreeMap<RoadID, Emissions>,
+    pub intersection_emissions: BTreeMap<IntersectionID, Emissions>,
+    pub trip_emissions: BTreeMap<TripID, Emissions>,
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    pub vehicle_emissions: BTreeMap<CarID, Emissions>,
+
     pub(crate) alerts: Vec<(Time, AlertLocation, String)>,
 
     // After we restore from a savestate, don't record anything. This is only going to make sense
@@ -53,17 +95,38 @@ impl Analytics {
             bus_arrivals: Vec::new(),
             passengers_boarding: BTreeMap::new(),
             passengers_alighting: BTreeMap::new(),
+            bus_schedule_deviations: Vec::new(),
+            passengers_left_behind: BTreeMap::new(),
+            ride_hail_pickups: Vec::new(),
+            ride_hail_dropoffs: Vec::new(),
+            delivery_stops: Vec::new(),
             started_trips: BTreeMap::new(),
+            trip_to_person: BTreeMap::new(),
             finished_trips: Vec::new(),
             trip_log: Vec::new(),
             intersection_delays: BTreeMap::new(),
             parking_lane_changes: BTreeMap::new(),
             parking_lot_changes: BTreeMap::new(),
+            car_crossings: BTreeMap::new(),
+            car_entered: BTreeMap::new(),
+            record_car_crossings: false,
+            road_emissions: BTreeMap::new(),
+            intersection_emissions: BTreeMap::new(),
+            trip_emissions: BTreeMap::new(),
+            vehicle_emissions: BTreeMap::new(),
             alerts: Vec::new(),
             record_anything: true,
         }
     }
 
+    // Also record how long cars take to cross each lane and turn. This is expensive, so only
+    // traffic assignment turns it on.
+    pub fn new_with_car_crossings(record_car_crossings: bool) -> Analytics {
+        let mut a = Analytics::new();
+        a.record_car_crossings = record_car_crossings;
+        a
+    }
+
     pub fn event(&mut self, ev: Event, time: Time, map: &Map) {
         if !self.record_anything {
             return;
@@ -102,6 +165,30 @@ impl Analytics {
                 }
             };
         }
+        // Travel times for cars. Trips start and end in the middle of lanes, so only count crossings
+        // from the start of one lane or turn to the start of the next.
+        if let Event::AgentEntersTraversable(AgentID::Car(car), to, _) = ev {
+            if self.record_car_crossings && car.1 == VehicleType::Car {
+                let prev = self.car_entered.remove(&car);
+                let connected = match (prev, to) {
+                    (Some((Traversable::Lane(l), _)), Traversable::Turn(t)) => t.src == l,
+                    (Some((Traversable::Turn(t), _)), Traversable::Lane(l)) => t.dst == l,
+                    _ => false,
+                };
+                if connected {
+                    let (from, entered) = prev.unwrap();
+                    let entry = self
+                        .car_crossings
+                        .entry(from)
+                        .or_insert((Duration::ZERO, 0));
+                    entry.0 += time - entered;
+                    entry.1 += 1;
+                }
+                if connected || to.maybe_turn().is_some() {
+                    self.car_entered.insert(car, (to, time));
+                }
+            }
+        }
         match ev {
             Event::PersonLeavesMap(_, maybe_a, i, _) => {
                 // Ignore aborted trips
@@ -119,6 +206,10 @@ impl Analytics {
         if let Event::BusArrivedAtStop(bus, route, stop) = ev {
             self.bus_arrivals.push((time, bus, route, stop));
         }
+        if let Event::BusArrivedAtTimepoint(bus, route, stop, late) = ev {
+            self.bus_schedule_deviations
+                .push((time, bus, route, stop, late));
+        }
 
         // Passengers boarding/alighting
         if let Event::PassengerBoardsTransit(_, _, route, stop, waiting) = ev {
@@ -133,10 +224,52 @@ impl Analytics {
                 .or_insert_with(Vec::new)
                 .push((time, route));
         }
+        if let Event::PassengersLeftBehind(_, route, stop, count) = ev {
+            self.passengers_left_behind
+                .entry(stop)
+                .or_insert_with(Vec::new)
+                .push((time, route, count));
+        }
+
+        // Ride-hail
+        if let Event::RideHailPickup(_, car, waiting, empty) = ev {
+            self.ride_hail_pickups.push((time, car, waiting, empty));
+        }
+        if let Event::RideHailDropoff(_, car, duration, dist) = ev {
+            self.ride_hail_dropoffs.push((time, car, duration, dist));
+        }
+
+        // Deliveries
+        if let Event::DeliveryStop(car, b, blocked) = ev {
+            self.delivery_stops.push((time, car, b, blocked));
+        }
+
+        // Emissions
+        if let Event::VehicleEmissions(car, maybe_trip, on, amount) = ev {
+            match on {
+                Traversable::Lane(l) => {
+                    *self
+                        .road_emissions
+                        .entry(map.get_l(l).parent)
+                        .or_insert(Emissions::ZERO) += amount;
+                }
+                Traversable::Turn(t) => {
+                    *self
+                        .intersection_emissions
+                        .entry(t.parent)
+                        .or_insert(Emissions::ZERO) += amount;
+                }
+            }
+            if let Some(trip) = maybe_trip {
+                *self.trip_emissions.entry(trip).or_insert(Emissions::ZERO) += amount;
+            }
+            *self.vehicle_emissions.entry(car).or_insert(Emissions::ZERO) += amount;
+        }
 
         // Started trips
-        if let Event::TripPhaseStarting(id, _, _, _) = ev {
+        if let Event::TripPhaseStarting(id, person, _, _) = ev {
             self.started_trips.entry(id).or_insert(time);
+            self.trip_to_person.insert(id, person);
         }
 
         // Finished trips
@@ -212,6 +345,58 @@ impl Analytics {
         }
     }
 
+    // For checking determinism. Describes the first difference found, if any. Alerts and demand
+    // are ignored.
+    pub(crate) fn find_divergence(&self, other: &Analytics) -> Option<String> {
+        first_difference(
+            "finished trips",
+            &self.finished_trips,
+            &other.finished_trips,
+        )
+        .or_else(|| first_difference("trip log", &self.trip_log, &other.trip_log))
+        .or_else(|| first_difference("bus arrivals", &self.bus_arrivals, &other.bus_arrivals))
+        .or_else(|| {
+            if self.started_trips != other.started_trips {
+                return Some("started trips differ".to_string());
+            }
+            if self.intersection_delays != other.intersection_delays {
+                return Some("intersection delays differ".to_string());
+            }
+            if self.passengers_boarding != other.passengers_boarding
+                || self.passengers_alighting != other.passengers_alighting
+                || self.passengers_left_behind != other.passengers_left_behind
+                || self.bus_schedule_deviations != other.bus_schedule_deviations
+            {
+                return Some("transit passengers differ".to_string());
+            }
+            if self.ride_hail_pickups != other.ride_hail_pickups
+                || self.ride_hail_dropoffs != other.ride_hail_dropoffs
+            {
+                return Some("ride-hail trips differ".to_string());
+            }
+            if self.delivery_stops != other.delivery_stops {
+                return Some("deliveries differ".to_string());
+            }
+            if self.parking_lane_changes != other.parking_lane_changes
+                || self.parking_lot_changes != other.parking_lot_changes
+            {
+                return Some("parking changes differ".to_string());
+            }
+            if self.road_thruput.counts != other.road_thruput.counts
+                || self.intersection_thruput.counts != other.intersection_thruput.counts
+            {
+                return Some("throughput differs".to_string());
+            }
+            if self.car_crossings != other.car_crossings {
+                return Some("car travel times differ".to_string());
+            }
+            if self.vehicle_emissions != other.vehicle_emissions {
+                return Some("emissions differ".to_string());
+            }
+            None
+        })
+    }
+
     pub fn record_demand(&mut self, path: &Path, map: &Map) {
         for step in path.get_steps() {
             if let Traversable::Turn(t) = step.as_traversable() {
@@ -222,6 +407,22 @@ impl Analytics {
         }
     }
 
+    // The average time cars took to cross each lane and turn
+    pub fn car_travel_times(&self) -> BTreeMap<Traversable, Duration> {
+        self.car_crossings
+            .iter()
+            .map(|(t, (total, count))| (*t, *total / (*count as f64)))
+            .collect()
+    }
+
+    pub fn total_emissions(&self) -> Emissions {
+        let mut total = Emissions::ZERO;
+        for amount in self.vehicle_emissions.values() {
+            total += *amount;
+        }
+        total
+    }
+
     // TODO If these ever need to be speeded up, just cache the histogram and index in the events
     // list.
 
@@ -246,6 +447,18 @@ impl Analytics {
         now: Time,
         before: &Analytics,
     ) -> Vec<(Duration, Duration, TripMode)> {
+        self.both_finished_trips_by_id(now, before)
+            .into_iter()
+            .map(|(_, dt1, dt2, mode)| (dt1, dt2, mode))
+            .collect()
+    }
+
+    // Like both_finished_trips, but also says which trip. (trip, before, after, mode)
+    pub fn both_finished_trips_by_id(
+        &self,
+        now: Time,
+        before: &Analytics,
+    ) -> Vec<(TripID, Duration, Duration, TripMode)> {
         let mut a = BTreeMap::new();
         for (t, id, maybe_mode, dt) in &self.finished_trips {
             if *t > now {
@@ -263,13 +476,122 @@ impl Analytics {
             }
             if let Some(mode) = maybe_mode {
                 if let Some(dt1) = a.remove(id) {
-                    results.push((*dt, dt1, *mode));
+                    results.push((*id, *dt, dt1, *mode));
                 }
             }
         }
         results
     }
 
+    // Counts a route's arrivals at timepoints that were (early, on time, late). Like many transit
+    // agencies, on time means no more than 1 minute early or 5 minutes late.
+    pub fn on_time_performance(&self, route: BusRouteID, now: Time) -> (usize, usize, usize) {
+        let mut early = 0;
+        let mut on_time = 0;
+        let mut late = 0;
+        for (t, _, r, _, dt) in &self.bus_schedule_deviations {
+            if *t > now {
+                break;
+            }
+            if *r != route {
+                continue;
+            }
+            if *dt < Duration::seconds(-60.0) {
+                early += 1;
+            } else if *dt > Duration::minutes(5) {
+                late += 1;
+            } else {
+                on_time += 1;
+            }
+        }
+        (early, on_time, late)
+    }
+
+    // For every arrival of a route's vehicle at a stop (besides the first), how long since the
+    // previous vehicle arrived there?
+    pub fn bus_headways(&self, route: BusRouteID, now: Time) -> Vec<(Time, BusStopID, Duration)> {
+        let mut last_arrival: BTreeMap<BusStopID, Time> = BTreeMap::new();
+        let mut results = Vec::new();
+        for (t, _, r, stop) in &self.bus_arrivals {
+            if *t > now {
+                break;
+            }
+            if *r != route {
+                continue;
+            }
+            if let Some(prev) = last_arrival.insert(*stop, *t) {
+                results.push((*t, *stop, *t - prev));
+            }
+        }
+        results
+    }
+
+    // Returns (bunched, total) headways for a route. A vehicle is bunched with the previous one if
+    // it arrives at a stop less than a quarter of the scheduled headway later. Routes without a
+    // schedule are compared to their average observed headway instead.
+    pub fn bus_bunching(&self, route: BusRouteID, now: Time, map: &Map) -> (usize, usize) {
+        let headways = self.bus_headways(route, now);
+        if headways.is_empty() {
+            return (0, 0);
+        }
+        let expected = map.get_br(route).scheduled_headway().unwrap_or_else(|| {
+            headways.iter().map(|(_, _, dt)| *dt).sum::<Duration>() / (headways.len() as f64)
+        });
+        let bunched = headways
+            .iter()
+            .filter(|(_, _, dt)| *dt < expected / 4.0)
+            .count();
+        (bunched, headways.len())
+    }
+
+    // Returns how long ride-hail passengers waited to be picked up, how far vehicles drove empty
+    // to reach them, and the fraction of the fleet's time spent carrying passengers. Rides still in
+    // progress don't count yet.
+    pub fn ride_hail_performance(
+        &self,
+        fleet_size: usize,
+        now: Time,
+    ) -> (Histogram<Duration>, Distance, f64) {
+        let mut waits = Histogram::new();
+        let mut empty_dist = Distance::ZERO;
+        for (t, _, waiting, empty) in &self.ride_hail_pickups {
+            if *t > now {
+                break;
+            }
+            waits.add(*waiting);
+            empty_dist += *empty;
+        }
+
+        let mut occupied = Duration::ZERO;
+        for (t, _, duration, _) in &self.ride_hail_dropoffs {
+            if *t > now {
+                break;
+            }
+            occupied += *duration;
+        }
+        let available = (now - Time::START_OF_DAY) * (fleet_size as f64);
+        let utilization = if available == Duration::ZERO {
+            0.0
+        } else {
+            occupied / available
+        };
+        (waits, empty_dist, utilization)
+    }
+
+    // How many times trucks double-parked on each road to make a delivery
+    pub fn double_parking_per_road(&self, now: Time, map: &Map) -> Counter<RoadID> {
+        let mut cnt = Counter::new();
+        for (t, _, _, blocked) in &self.delivery_stops {
+            if *t > now {
+                break;
+            }
+            if let Some(l) = blocked {
+                cnt.inc(map.get_l(*l).parent);
+            }
+        }
+        cnt
+    }
+
     // Find intersections where the cumulative sum of delay has changed. Negative means faster.
     pub fn compare_delay(&self, now: Time, before: &Analytics) -> Vec<(IntersectionID, Duration)> {
         let mut results = Vec::new();
@@ -465,6 +787,27 @@ impl Default for Analytics {
     }
 }
 
+fn first_difference<T: PartialEq + std::fmt::Debug>(
+    name: &str,
+    list1: &[T],
+    list2: &[T],
+) -> Option<String> {
+    for (idx, (x1, x2)) in list1.iter().zip(list2.iter()).enumerate() {
+        if x1 != x2 {
+            return Some(format!("{} #{} differs: {:?} vs {:?}", name, idx, x1, x2));
+        }
+    }
+    if list1.len() != list2.len() {
+        return Some(format!(
+            "{} has {} entries vs {}",
+            name,
+            list1.len(),
+            list2.len()
+        ));
+    }
+    None
+}
+
 #[derive(Debug)]
 pub struct TripPhase {
     pub start_time: Time,
@@ -0,0 +1,215 @@
+use crate::mechanics::SpeedProfile;
+use crate::VehicleType;
+use geom::{Distance, Duration, Speed};
+use serde::{Deserialize, Serialize};
+use std::ops::{Add, AddAssign, Mul};
+
+// Tailpipe emissions, in grams
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub struct Emissions {
+    pub co2: f64,
+    pub nox: f64,
+    pub pm: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Pollutant {
+    CO2,
+    NOx,
+    PM,
+}
+
+impl Pollutant {
+    pub fn all() -> Vec<Pollutant> {
+        vec![Pollutant::CO2, Pollutant::NOx, Pollutant::PM]
+    }
+
+    pub fn describe(self) -> &'static str {
+        match self {
+            Pollutant::CO2 => "CO2",
+            Pollutant::NOx => "NOx",
+            Pollutant::PM => "particulate matter",
+        }
+    }
+}
+
+impl Emissions {
+    pub const ZERO: Emissions = Emissions {
+        co2: 0.0,
+        nox: 0.0,
+        pm: 0.0,
+    };
+
+    pub fn get(&self, p: Pollutant) -> f64 {
+        match p {
+            Pollutant::CO2 => self.co2,
+            Pollutant::NOx => self.nox,
+            Pollutant::PM => self.pm,
+        }
+    }
+
+    // How much a vehicle emits while moving through the first `elapsed` of a Crossing state. With
+    // acceleration profiles, this follows the speed profile. Otherwise the vehicle covers `dist`
+    // at a constant speed over `duration`.
+    pub(crate) fn for_crossing(
+        vehicle_type: VehicleType,
+        dist: Distance,
+        duration: Duration,
+        elapsed: Duration,
+        profile: Option<&SpeedProfile>,
+    ) -> Emissions {
+        let model = if let Some(m) = Model::new(vehicle_type) {
+            m
+        } else {
+            return Emissions::ZERO;
+        };
+        let elapsed = elapsed.min(duration).inner_seconds();
+        if elapsed <= 0.0 {
+            return Emissions::ZERO;
+        }
+
+        if let Some(profile) = profile {
+            // Sample the profile, estimating acceleration from the change in speed
+            let mut total = Emissions::ZERO;
+            let mut t1 = 0.0;
+            while t1 < elapsed {
+                let t2 = (t1 + PROFILE_STEP).min(elapsed);
+                let v1 = profile
+                    .speed_at(Duration::seconds(t1))
+                    .inner_meters_per_second();
+                let v2 = profile
+                    .speed_at(Duration::seconds(t2))
+                    .inner_meters_per_second();
+                let dt = t2 - t1;
+                total += model.rate((v1 + v2) / 2.0, (v2 - v1) / dt) * dt;
+                t1 = t2;
+            }
+            total
+        } else {
+            let v = dist.inner_meters() / duration.inner_seconds();
+            model.rate(v, 0.0) * elapsed
+        }
+    }
+
+    // How much a vehicle emits while stopped. Without acceleration profiles, a vehicle that stops
+    // then moves again jumps straight back to `resume_speed`, so count the extra cost of speeding
+    // up here.
+    pub(crate) fn for_idling(
+        vehicle_type: VehicleType,
+        time: Duration,
+        resume_speed: Option<Speed>,
+    ) -> Emissions {
+        let model = if let Some(m) = Model::new(vehicle_type) {
+            m
+        } else {
+            return Emissions::ZERO;
+        };
+        let mut total = model.rate(0.0, 0.0) * time.inner_seconds();
+        // Ignore tiny pauses from rounding
+        if let Some(v) = resume_speed {
+            if time > Duration::seconds(1.0) && v > Speed::ZERO {
+                total += model.extra_to_accelerate(v.inner_meters_per_second());
+            }
+        }
+        total
+    }
+}
+
+impl Add for Emissions {
+    type Output = Emissions;
+
+    fn add(self, other: Emissions) -> Emissions {
+        Emissions {
+            co2: self.co2 + other.co2,
+            nox: self.nox + other.nox,
+            pm: self.pm + other.pm,
+        }
+    }
+}
+
+impl AddAssign for Emissions {
+    fn add_assign(&mut self, other: Emissions) {
+        *self = *self + other;
+    }
+}
+
+impl Mul<f64> for Emissions {
+    type Output = Emissions;
+
+    fn mul(self, factor: f64) -> Emissions {
+        Emissions {
+            co2: self.co2 * factor,
+            nox: self.nox * factor,
+            pm: self.pm * factor,
+        }
+    }
+}
+
+// How often to sample a speed profile, in seconds
+const PROFILE_STEP: f64 = 0.5;
+
+// The instantaneous emission model from Panis, Broekx, and Liu, "Modelling instantaneous traffic
+// emission and the influence of traffic speed limits" (2006). Each pollutant is emitted at
+// f1 + f2*v + f3*v^2 + f4*a + f5*a^2 + f6*v*a grams per second, for speed v in m/s and
+// acceleration a in m/s^2.
+//
+// Cars use the paper's petrol car coefficients for CO2 and NOx, and diesel cars for PM. Trucks
+// and buses are roughly scaled up from that. Bikes and (electric) trains have no tailpipe.
+const CO2: [f64; 6] = [5.53e-1, 1.61e-1, -2.89e-3, 2.66e-1, 5.11e-1, 1.83e-1];
+const NOX: [f64; 6] = [6.19e-4, 8.0e-5, -4.03e-6, -4.13e-4, 3.80e-4, 1.77e-4];
+const PM: [f64; 6] = [0.0, 1.57e-5, -9.21e-7, 0.0, 3.75e-5, 1.89e-5];
+
+struct Model {
+    // Relative to a car
+    scale: Emissions,
+    // How quickly the vehicle speeds up after stopping, in m/s^2
+    accel: f64,
+}
+
+impl Model {
+    fn new(vehicle_type: VehicleType) -> Option<Model> {
+        let (co2, nox, pm, accel) = match vehicle_type {
+            VehicleType::Car => (1.0, 1.0, 1.0, 1.5),
+            VehicleType::Truck => (2.5, 10.0, 5.0, 0.8),
+            VehicleType::Bus => (3.0, 12.0, 6.0, 1.0),
+            VehicleType::Bike | VehicleType::Train => {
+                return None;
+            }
+        };
+        Some(Model {
+            scale: Emissions { co2, nox, pm },
+            accel,
+        })
+    }
+
+    // Grams per second
+    fn rate(&self, v: f64, a: f64) -> Emissions {
+        let f = |c: [f64; 6]| {
+            (c[0] + c[1] * v + c[2] * v * v + c[3] * a + c[4] * a * a + c[5] * v * a).max(0.0)
+        };
+        Emissions {
+            co2: self.scale.co2 * f(CO2),
+            nox: self.scale.nox * f(NOX),
+            pm: self.scale.pm * f(PM),
+        }
+    }
+
+    // Speeding up from a stop to v emits more than cruising over the same distance.
+    fn extra_to_accelerate(&self, v: f64) -> Emissions {
+        let steps = 10;
+        let dt = v / self.accel / (steps as f64);
+        let mut total = Emissions::ZERO;
+        for i in 0..steps {
+            let speed = self.accel * dt * (i as f64 + 0.5);
+            total += self.rate(speed, self.accel) * dt;
+        }
+        // The distance covered while accelerating was already counted as cruising
+        let dist = v * v / (2.0 * self.accel);
+        let cruising = self.rate(v, 0.0) * (dist / v);
+        Emissions {
+            co2: (total.co2 - cruising.co2).max(0.0),
+            nox: (total.nox - cruising.nox).max(0.0),
+            pm: (total.pm - cruising.pm).max(0.0),
+        }
+    }
+}
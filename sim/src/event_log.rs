@@ -0,0 +1,205 @@
+use crate::{Analytics, Event};
+use geom::Time;
+use map_model::Map;
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Write};
+
+// Appends every Event the sim produces to a file, so raw trip phases, boardings, parking changes,
+// etc can be post-processed without re-running the sim. If the path ends in .bin, records are
+// written with bincode, one after another. Otherwise, each line is a JSON object (NDJSON) with
+// "time" and "event".
+pub struct EventLog {
+    path: String,
+    writer: Writer,
+    // How many times this log has been cloned, to give each clone its own file
+    num_clones: Cell<usize>,
+}
+
+enum Writer {
+    Json(BufWriter<File>),
+    Binary(BufWriter<File>),
+}
+
+#[derive(Serialize)]
+struct LoggedEventRef<'a> {
+    time: Time,
+    event: &'a Event,
+}
+
+#[derive(Deserialize)]
+struct LoggedEvent {
+    time: Time,
+    event: Event,
+}
+
+impl EventLog {
+    pub fn new(path: String) -> EventLog {
+        std::fs::create_dir_all(std::path::Path::new(&path).parent().unwrap())
+            .expect("Creating parent dir failed");
+        let file = match File::create(&path) {
+            Ok(f) => BufWriter::new(f),
+            Err(err) => panic!("Can't create event log {}: {}", path, err),
+        };
+        let writer = if path.ends_with(".bin") {
+            Writer::Binary(file)
+        } else {
+            Writer::Json(file)
+        };
+        EventLog {
+            path,
+            writer,
+            num_clones: Cell::new(0),
+        }
+    }
+
+    pub(crate) fn record(&mut self, time: Time, event: &Event) {
+        let record = LoggedEventRef { time, event };
+        let result = match self.writer {
+            Writer::Json(ref mut f) => serde_json::to_writer(&mut *f, &record)
+                .map_err(|err| Error::new(ErrorKind::Other, err))
+                .and_then(|_| writeln!(f)),
+            Writer::Binary(ref mut f) => {
+                bincode::serialize_into(f, &record).map_err(|err| Error::new(ErrorKind::Other, err))
+            }
+        };
+        if let Err(err) = result {
+            panic!("Can't write to event log {}: {}", self.path, err);
+        }
+    }
+
+    pub(crate) fn flush(&mut self) {
+        let result = match self.writer {
+            Writer::Json(ref mut f) | Writer::Binary(ref mut f) => f.flush(),
+        };
+        if let Err(err) = result {
+            panic!("Can't flush event log {}: {}", self.path, err);
+        }
+    }
+
+    // Reads a log written by any sim and feeds every event into a fresh Analytics. The map must
+    // be the same (with the same edits) as the one used to produce the log.
+    pub fn replay(path: &str, map: &Map) -> Result<Analytics, Error> {
+        let mut analytics = Analytics::new();
+        let mut reader = BufReader::new(File::open(path)?);
+        if path.ends_with(".bin") {
+            loop {
+                match bincode::deserialize_from::<_, LoggedEvent>(&mut reader) {
+                    Ok(record) => {
+                        analytics.event(record.event, record.time, map);
+                    }
+                    Err(err) => {
+                        if let bincode::ErrorKind::Io(ref io) = *err {
+                            if io.kind() == ErrorKind::UnexpectedEof {
+                                break;
+                            }
+                        }
+                        return Err(Error::new(ErrorKind::Other, err));
+                    }
+                }
+            }
+        } else {
+            let mut line = String::new();
+            while reader.read_line(&mut line)? > 0 {
+                if !line.trim().is_empty() {
+                    let record: LoggedEvent = serde_json::from_str(&line)
+                        .map_err(|err| Error::new(ErrorKind::Other, err))?;
+                    analytics.event(record.event, record.time, map);
+                }
+                line.clear();
+            }
+        }
+        Ok(analytics)
+    }
+}
+
+// A cloned Sim shouldn't write interleaved events to the same file, so each clone logs to a new
+// file next to the original, like events.clone1.bin. The clone's log starts at the time of the
+// clone; earlier events are only in the original.
+impl Clone for EventLog {
+    fn clone(&self) -> EventLog {
+        self.num_clones.set(self.num_clones.get() + 1);
+        let path = std::path::Path::new(&self.path);
+        let clone_name = format!(
+            "{}.clone{}",
+            path.file_stem().unwrap().to_string_lossy(),
+            self.num_clones.get()
+        );
+        let clone_path = match path.extension() {
+            Some(ext) => path.with_file_name(format!("{}.{}", clone_name, ext.to_string_lossy())),
+            None => path.with_file_name(clone_name),
+        };
+        let log = EventLog::new(clone_path.to_string_lossy().to_string());
+        println!("Cloned the sim; logging its events to {}", log.path);
+        log
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AgentID, CarID, PersonID, TripID, TripMode, TripPhaseType, VehicleType};
+    use geom::Duration;
+    use map_model::IntersectionID;
+
+    fn round_trip(extension: &str) {
+        let path = std::env::temp_dir()
+            .join(format!("abst_event_log_test.{}", extension))
+            .to_string_lossy()
+            .to_string();
+        // None of these events need anything from the map
+        let map = Map::blank();
+        let car = CarID(3, VehicleType::Car);
+        let events = vec![
+            (
+                Time::START_OF_DAY + Duration::seconds(10.0),
+                Event::TripPhaseStarting(TripID(0), PersonID(7), None, TripPhaseType::Walking),
+            ),
+            (
+                Time::START_OF_DAY + Duration::seconds(42.5),
+                Event::IntersectionDelayMeasured(
+                    IntersectionID(2),
+                    Duration::seconds(3.0),
+                    AgentID::Car(car),
+                ),
+            ),
+            (
+                Time::START_OF_DAY + Duration::minutes(5),
+                Event::TripFinished {
+                    trip: TripID(0),
+                    mode: TripMode::Drive,
+                    total_time: Duration::seconds(290.0),
+                    blocked_time: Duration::seconds(3.0),
+                },
+            ),
+            (
+                Time::START_OF_DAY + Duration::minutes(6),
+                Event::TripAborted(TripID(1)),
+            ),
+        ];
+
+        let mut expected = Analytics::new();
+        let mut log = EventLog::new(path.clone());
+        for (time, ev) in &events {
+            log.record(*time, ev);
+            expected.event(ev.clone(), *time, &map);
+        }
+        log.flush();
+
+        let replayed = EventLog::replay(&path, &map).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(replayed.find_divergence(&expected), None);
+        assert_eq!(replayed.finished_trips.len(), 2);
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        round_trip("json");
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        round_trip("bin");
+    }
+}
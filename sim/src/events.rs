@@ -1,7 +1,8 @@
 use crate::{
-    AgentID, CarID, OffMapLocation, ParkingSpot, PedestrianID, PersonID, TripID, TripMode,
+    AgentID, CarID, Emissions, OffMapLocation, ParkingSpot, PedestrianID, PersonID, TripID,
+    TripMode,
 };
-use geom::Duration;
+use geom::{Distance, Duration};
 use map_model::{
     BuildingID, BusRouteID, BusStopID, IntersectionID, LaneID, Map, Path, PathRequest, Traversable,
 };
@@ -16,10 +17,22 @@ pub enum Event {
     CarLeftParkingSpot(CarID, ParkingSpot),
 
     BusArrivedAtStop(CarID, BusRouteID, BusStopID),
+    // How late compared to the schedule? Negative if early.
+    BusArrivedAtTimepoint(CarID, BusRouteID, BusStopID, Duration),
     BusDepartedFromStop(CarID, BusRouteID, BusStopID),
     // How long waiting at the stop?
     PassengerBoardsTransit(PersonID, CarID, BusRouteID, BusStopID, Duration),
     PassengerAlightsTransit(PersonID, CarID, BusRouteID, BusStopID),
+    // The vehicle was full, so this many people are still waiting
+    PassengersLeftBehind(CarID, BusRouteID, BusStopID, usize),
+
+    // How long did they wait, and how far did the vehicle drive empty to get there?
+    RideHailPickup(PersonID, CarID, Duration, Distance),
+    // How long and how far was the ride?
+    RideHailDropoff(PersonID, CarID, Duration, Distance),
+
+    // If the truck double-parked instead of using a loading zone, which lane did it block?
+    DeliveryStop(CarID, BuildingID, Option<LaneID>),
 
     PersonEntersBuilding(PersonID, BuildingID),
     PersonLeavesBuilding(PersonID, BuildingID),
@@ -42,6 +55,8 @@ pub enum Event {
     // board.
     AgentEntersTraversable(AgentID, Traversable, Option<usize>),
     IntersectionDelayMeasured(IntersectionID, Duration, AgentID),
+    // What a vehicle emitted while on a lane or turn
+    VehicleEmissions(CarID, Option<TripID>, Traversable, Emissions),
 
     TripFinished {
         trip: TripID,
@@ -76,6 +91,9 @@ pub enum TripPhaseType {
     WaitingForBus(BusRouteID, BusStopID),
     // What stop did they board at?
     RidingBus(BusRouteID, BusStopID, CarID),
+    WaitingForRideHail,
+    RidingRideHail(CarID),
+    Delivering,
     Aborted,
     Finished,
     DelayedStart,
@@ -93,6 +111,9 @@ impl TripPhaseType {
                 format!("waiting for bus {}", map.get_br(r).full_name)
             }
             TripPhaseType::RidingBus(r, _, _) => format!("riding bus {}", map.get_br(r).full_name),
+            TripPhaseType::WaitingForRideHail => "waiting for a ride-hail".to_string(),
+            TripPhaseType::RidingRideHail(_) => "riding in a ride-hail".to_string(),
+            TripPhaseType::Delivering => "making a delivery".to_string(),
             TripPhaseType::Aborted => "trip aborted due to some bug".to_string(),
             TripPhaseType::Finished => "trip finished".to_string(),
             TripPhaseType::DelayedStart => "delayed by previous trip taking too long".to_string(),
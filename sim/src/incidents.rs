@@ -0,0 +1,73 @@
+use geom::{Duration, Time};
+use map_model::{IntersectionID, LaneID, Map};
+use serde::{Deserialize, Serialize};
+
+// Something temporarily getting in the way of traffic, like a crash or a special event. Load a
+// timeline of these from a JSON file with --incidents.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Incident {
+    pub start: Time,
+    pub duration: Duration,
+    pub effect: IncidentEffect,
+}
+
+// Incidents only affect vehicles. Drivers whose route is affected look for another way around.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum IncidentEffect {
+    // Nobody can enter or leave this lane. Anybody already on it waits at the end.
+    BlockLane(LaneID),
+    // Vehicles entering this lane go at most this fraction of the speed limit
+    ReduceSpeed(LaneID, f64),
+    // Nobody can start a turn through this intersection
+    CloseIntersection(IntersectionID),
+}
+
+impl Incident {
+    // Incidents are written by hand, so make sure they refer to things that exist in this map.
+    pub fn check(&self, map: &Map) -> Result<(), String> {
+        match self.effect {
+            IncidentEffect::BlockLane(l) => check_lane(l, map),
+            IncidentEffect::ReduceSpeed(l, factor) => {
+                check_lane(l, map)?;
+                if factor > 0.0 && factor <= 1.0 {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "Incident slowing {} has a bad speed factor {}",
+                        l, factor
+                    ))
+                }
+            }
+            IncidentEffect::CloseIntersection(i) => {
+                if map.maybe_get_i(i).is_some() {
+                    Ok(())
+                } else {
+                    Err(format!("Incident closes {}, which doesn't exist", i))
+                }
+            }
+        }
+    }
+}
+
+fn check_lane(l: LaneID, map: &Map) -> Result<(), String> {
+    match map.maybe_get_l(l) {
+        Some(lane) if lane.lane_type.is_for_moving_vehicles() => Ok(()),
+        Some(_) => Err(format!(
+            "Incident affects {}, which vehicles don't drive on",
+            l
+        )),
+        None => Err(format!("Incident affects {}, which doesn't exist", l)),
+    }
+}
+
+impl IncidentEffect {
+    pub fn describe(&self) -> String {
+        match self {
+            IncidentEffect::BlockLane(l) => format!("{} blocked", l),
+            IncidentEffect::ReduceSpeed(l, pct) => {
+                format!("{} slowed to {}% speed", l, (pct * 100.0) as usize)
+            }
+            IncidentEffect::CloseIntersection(i) => format!("{} closed", i),
+        }
+    }
+}
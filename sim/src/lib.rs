@@ -1,9 +1,13 @@
 mod analytics;
+mod emissions;
+mod event_log;
 mod events;
+mod incidents;
 mod make;
 mod mechanics;
 mod pandemic;
 mod render;
+mod ridehail;
 mod router;
 mod scheduler;
 mod sim;
@@ -11,18 +15,27 @@ mod transit;
 mod trips;
 
 pub use self::analytics::{Analytics, TripPhase};
+pub use self::emissions::{Emissions, Pollutant};
+pub use self::event_log::EventLog;
 pub(crate) use self::events::Event;
 pub use self::events::{AlertLocation, TripPhaseType};
+pub use self::incidents::{Incident, IncidentEffect};
 pub use self::make::{
     BorderSpawnOverTime, IndividTrip, OffMapLocation, OriginDestination, PersonSpec, Scenario,
     ScenarioGenerator, ScenarioModifier, SimFlags, SpawnOverTime, SpawnTrip, TripSpawner, TripSpec,
 };
+pub use self::mechanics::CriticalGaps;
 pub(crate) use self::mechanics::{
     DrivingSimState, IntersectionSimState, ParkingSimState, WalkingSimState,
 };
 pub(crate) use self::pandemic::PandemicModel;
+pub use self::pandemic::{
+    Action, BuildingFilter, Exposure, ExposureLocation, Intervention, PandemicParams, SEIRCounts,
+    Trigger,
+};
+pub(crate) use self::ridehail::{Curb, RideHailSimState};
 pub(crate) use self::router::{ActionAtEnd, Router};
-pub(crate) use self::scheduler::{Command, Scheduler};
+pub(crate) use self::scheduler::{Command, CommandType, Scheduler};
 pub use self::sim::{AgentProperties, AlertHandler, Sim, SimCallback, SimOptions};
 pub(crate) use self::transit::TransitSimState;
 pub use self::trips::{Person, PersonState, TripInfo, TripResult};
@@ -46,6 +59,9 @@ pub const BIKE_LENGTH: Distance = Distance::const_meters(1.8);
 // These two must be < PARKING_SPOT_LENGTH
 pub const MIN_CAR_LENGTH: Distance = Distance::const_meters(4.5);
 pub const MAX_CAR_LENGTH: Distance = Distance::const_meters(6.5);
+// Delivery trucks and vans. Only the shortest fit in a parking spot.
+pub const MIN_TRUCK_LENGTH: Distance = Distance::const_meters(7.0);
+pub const MAX_TRUCK_LENGTH: Distance = Distance::const_meters(10.0);
 // Note this is more than MAX_CAR_LENGTH
 pub const BUS_LENGTH: Distance = Distance::const_meters(12.5);
 pub const LIGHT_RAIL_LENGTH: Distance = Distance::const_meters(60.0);
@@ -74,6 +90,7 @@ impl fmt::Display for CarID {
             VehicleType::Bus => write!(f, "Bus #{}", self.0),
             VehicleType::Train => write!(f, "Train #{}", self.0),
             VehicleType::Bike => write!(f, "Bike #{}", self.0),
+            VehicleType::Truck => write!(f, "Truck #{}", self.0),
         }
     }
 }
@@ -97,7 +114,7 @@ impl fmt::Display for PedestrianID {
 pub enum AgentID {
     Car(CarID),
     Pedestrian(PedestrianID),
-    // TODO Rename...
+    // TODO Rename... This also covers people riding in a ride-hail.
     BusPassenger(PersonID, CarID),
 }
 
@@ -112,7 +129,7 @@ impl AgentID {
     pub fn to_type(self) -> AgentType {
         match self {
             AgentID::Car(c) => match c.1 {
-                VehicleType::Car => AgentType::Car,
+                VehicleType::Car | VehicleType::Truck => AgentType::Car,
                 VehicleType::Bike => AgentType::Bike,
                 VehicleType::Bus => AgentType::Bus,
                 VehicleType::Train => AgentType::Train,
@@ -227,6 +244,7 @@ pub enum VehicleType {
     Bus,
     Train,
     Bike,
+    Truck,
 }
 
 impl fmt::Display for VehicleType {
@@ -236,6 +254,7 @@ impl fmt::Display for VehicleType {
             VehicleType::Bus => write!(f, "bus"),
             VehicleType::Train => write!(f, "train"),
             VehicleType::Bike => write!(f, "bike"),
+            VehicleType::Truck => write!(f, "truck"),
         }
     }
 }
@@ -247,6 +266,7 @@ impl VehicleType {
             VehicleType::Bus => PathConstraints::Bus,
             VehicleType::Train => PathConstraints::Train,
             VehicleType::Bike => PathConstraints::Bike,
+            VehicleType::Truck => PathConstraints::Truck,
         }
     }
 
@@ -256,6 +276,7 @@ impl VehicleType {
             VehicleType::Bus => true,
             VehicleType::Train => true,
             VehicleType::Bike => false,
+            VehicleType::Truck => false,
         }
     }
 }
@@ -310,6 +331,10 @@ pub struct ParkedCar {
 pub enum DrivingGoal {
     ParkNear(BuildingID),
     Border(IntersectionID, LaneID, Option<OffMapLocation>),
+    // Only for cars, as part of park-and-ride
+    ParkAtLot(ParkingLotID),
+    // Only for bikes, as part of bike-and-ride. Leave the bike at a rack on the stop's sidewalk.
+    BikeToStop(BusStopID),
 }
 
 impl DrivingGoal {
@@ -331,7 +356,9 @@ impl DrivingGoal {
     pub fn goal_pos(&self, constraints: PathConstraints, map: &Map) -> Position {
         match self {
             DrivingGoal::ParkNear(b) => match constraints {
-                PathConstraints::Car => Position::start(map.find_driving_lane_near_building(*b)),
+                PathConstraints::Car | PathConstraints::Truck => {
+                    Position::start(map.find_driving_lane_near_building(*b))
+                }
                 PathConstraints::Bike => {
                     let l = map.find_biking_lane_near_building(*b);
                     Position::new(l, map.get_l(l).length() / 2.0)
@@ -341,6 +368,13 @@ impl DrivingGoal {
                 }
             },
             DrivingGoal::Border(_, l, _) => Position::end(*l, map),
+            DrivingGoal::ParkAtLot(pl) => map.get_pl(*pl).driving_pos,
+            DrivingGoal::BikeToStop(bs) => {
+                let sidewalk = map.get_bs(*bs).sidewalk_pos.lane();
+                // Checked up-front by TripSpawner
+                let l = map.get_parent(sidewalk).sidewalk_to_bike(sidewalk).unwrap();
+                Position::new(l, map.get_l(l).length() / 2.0)
+            }
         }
     }
 
@@ -356,6 +390,11 @@ impl DrivingGoal {
                     Some(Router::park_near(path, *b))
                 }
             }
+            DrivingGoal::ParkAtLot(pl) => Some(Router::park_at_lot(path, *pl)),
+            DrivingGoal::BikeToStop(_) => {
+                let end = path.last_step().as_lane();
+                Router::bike_then_stop(path, map.get_l(end).length() / 2.0, map)
+            }
             DrivingGoal::Border(i, last_lane, _) => Some(Router::end_at_border(
                 path,
                 map.get_l(*last_lane).length(),
@@ -368,6 +407,8 @@ impl DrivingGoal {
         match self {
             DrivingGoal::ParkNear(b) => map.get_b(*b).polygon.center(),
             DrivingGoal::Border(i, _, _) => map.get_i(*i).polygon.center(),
+            DrivingGoal::ParkAtLot(pl) => map.get_pl(*pl).polygon.center(),
+            DrivingGoal::BikeToStop(bs) => map.get_bs(*bs).sidewalk_pos.pt(map),
         }
     }
 }
@@ -612,7 +653,8 @@ pub struct CreateCar {
     pub maybe_parked_car: Option<ParkedCar>,
     // None for buses
     pub trip_and_person: Option<(TripID, PersonID)>,
-    pub maybe_route: Option<BusRouteID>,
+    // For buses, the route and index into its spawn_times
+    pub maybe_route: Option<(BusRouteID, usize)>,
 }
 
 impl CreateCar {
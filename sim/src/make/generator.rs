@@ -221,7 +221,7 @@ impl SpawnOverTime {
             if rng.gen_bool(self.percent_use_transit) {
                 // TODO This throws away some work. It also sequentially does expensive
                 // work right here.
-                if let Some((stop1, stop2, route)) =
+                if let Some(rides) =
                     map.should_use_transit(start_spot.sidewalk_pos, goal.sidewalk_pos)
                 {
                     scenario.people.push(PersonSpec {
@@ -229,7 +229,7 @@ impl SpawnOverTime {
                         orig_id: None,
                         trips: vec![IndividTrip::new(
                             depart,
-                            SpawnTrip::UsingTransit(start_spot, goal, route, stop1, stop2),
+                            SpawnTrip::UsingTransit(start_spot, goal, rides),
                         )],
                     });
                     return;
@@ -282,7 +282,7 @@ impl BorderSpawnOverTime {
                 if rng.gen_bool(self.percent_use_transit) {
                     // TODO This throws away some work. It also sequentially does expensive
                     // work right here.
-                    if let Some((stop1, stop2, route)) =
+                    if let Some(rides) =
                         map.should_use_transit(start.sidewalk_pos, goal.sidewalk_pos)
                     {
                         scenario.people.push(PersonSpec {
@@ -290,7 +290,7 @@ impl BorderSpawnOverTime {
                             orig_id: None,
                             trips: vec![IndividTrip::new(
                                 depart,
-                                SpawnTrip::UsingTransit(start.clone(), goal, route, stop1, stop2),
+                                SpawnTrip::UsingTransit(start.clone(), goal, rides),
                             )],
                         });
                         continue;
@@ -1,5 +1,5 @@
-use crate::{AlertHandler, Scenario, Sim, SimOptions};
-use abstutil::CmdArgs;
+use crate::{AlertHandler, CriticalGaps, PandemicParams, Scenario, Sim, SimOptions};
+use abstutil::{CmdArgs, Timer};
 use map_model::{Map, MapEdits};
 use rand::SeedableRng;
 use rand_xorshift::XorShiftRng;
@@ -37,6 +37,10 @@ impl SimFlags {
                 } else {
                     None
                 },
+                pandemic_params: args
+                    .optional("--pandemic_params")
+                    .map(|path| abstutil::read_json(path, &mut Timer::throwaway()))
+                    .unwrap_or_else(PandemicParams::new),
                 alerts: args
                     .optional("--alerts")
                     .map(|x| match x.as_ref() {
@@ -47,6 +51,23 @@ impl SimFlags {
                     })
                     .unwrap_or(AlertHandler::Print),
                 pathfinding_upfront: args.enabled("--pathfinding_upfront"),
+                event_log: args.optional("--event_log"),
+                hold_transit_at_timepoints: !args.enabled("--disable_transit_holding"),
+                reroute_drivers: args.enabled("--reroute_drivers"),
+                ride_hail_fleet: args
+                    .optional_parse("--ride_hail_fleet", |s| s.parse())
+                    .unwrap_or(0),
+                acceleration_profiles: args.enabled("--acceleration_profiles"),
+                stop_sign_gaps: match args.optional("--stop_sign_gaps") {
+                    Some(path) => Some(abstutil::read_json(path, &mut Timer::throwaway())),
+                    None if args.enabled("--gap_acceptance") => Some(CriticalGaps::new()),
+                    None => None,
+                },
+                incidents: args
+                    .optional("--incidents")
+                    .map(|path| abstutil::read_json(path, &mut Timer::throwaway()))
+                    .unwrap_or_else(Vec::new),
+                record_car_crossings: args.enabled("--record_car_crossings"),
             },
         }
     }
@@ -68,8 +89,17 @@ impl SimFlags {
         XorShiftRng::from_seed([self.rng_seed; 16])
     }
 
+    // The incidents from --incidents refer to lanes and intersections by ID, so they can only be
+    // checked once the map is loaded.
+    pub fn check_incidents(&self, map: &Map) -> Result<(), String> {
+        for incident in &self.opts.incidents {
+            incident.check(map)?;
+        }
+        Ok(())
+    }
+
     // Convenience method to setup everything.
-    pub fn load(&self, timer: &mut abstutil::Timer) -> (Map, Sim, XorShiftRng) {
+    pub fn load(&self, timer: &mut Timer) -> Result<(Map, Sim, XorShiftRng), String> {
         let mut rng = self.make_rng();
 
         let mut opts = self.opts.clone();
@@ -84,9 +114,12 @@ impl SimFlags {
                 map.must_apply_edits(MapEdits::load(&map, &sim.edits_name, timer).unwrap(), timer);
                 map.recalculate_pathfinding_after_edits(timer);
             }
+            // The savestate keeps the incidents it started with, not the ones from --incidents.
+            // The map might've been regenerated since then, though.
+            sim.check_incidents(&map)?;
             sim.restore_paths(&map, timer);
 
-            (map, sim, rng)
+            Ok((map, sim, rng))
         } else if self.load.starts_with(&abstutil::path("system/scenarios/")) {
             timer.note(format!(
                 "Seeding the simulation from scenario {}",
@@ -96,6 +129,7 @@ impl SimFlags {
             let scenario: Scenario = abstutil::read_binary(self.load.clone(), timer);
 
             let map = Map::new(abstutil::path_map(&scenario.map_name), timer);
+            self.check_incidents(&map)?;
 
             if opts.run_name == "unnamed" {
                 opts.run_name = scenario.scenario_name.clone();
@@ -103,7 +137,7 @@ impl SimFlags {
             let mut sim = Sim::new(&map, opts, timer);
             scenario.instantiate(&mut sim, &map, &mut rng, timer);
 
-            (map, sim, rng)
+            Ok((map, sim, rng))
         } else if self.load.starts_with(&abstutil::path_all_raw_maps())
             || self.load.starts_with(&abstutil::path_all_synthetic_maps())
             || self.load.starts_with(&abstutil::path_all_maps())
@@ -111,12 +145,13 @@ impl SimFlags {
             timer.note(format!("Loading map {}", self.load));
 
             let map = Map::new(self.load.clone(), timer);
+            self.check_incidents(&map)?;
 
             timer.start("create sim");
             let sim = Sim::new(&map, opts, timer);
             timer.stop("create sim");
 
-            (map, sim, rng)
+            Ok((map, sim, rng))
         } else {
             panic!("Don't know how to load {}", self.load);
         }
@@ -1,11 +1,13 @@
-use crate::{IndividTrip, Scenario, SpawnTrip, TripMode};
+use crate::{DrivingGoal, IndividTrip, PersonID, PersonSpec, Scenario, SpawnTrip, TripMode};
 use geom::{Duration, Time};
-use map_model::Map;
+use map_model::{BuildingID, BuildingType, Map, PathConstraints};
+use rand::seq::SliceRandom;
 use rand::Rng;
 use rand_xorshift::XorShiftRng;
+use serde::{Deserialize, Serialize};
 use std::collections::BTreeSet;
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Serialize, Deserialize)]
 pub enum ScenarioModifier {
     RepeatDays(usize),
     CancelPeople(usize),
@@ -15,6 +17,7 @@ pub enum ScenarioModifier {
         departure_filter: (Time, Time),
         from_modes: BTreeSet<TripMode>,
     },
+    AddDeliveryTours(usize),
 }
 
 impl ScenarioModifier {
@@ -24,6 +27,7 @@ impl ScenarioModifier {
         match self {
             ScenarioModifier::RepeatDays(n) => repeat_days(s, *n),
             ScenarioModifier::CancelPeople(pct) => cancel_people(s, *pct, rng),
+            ScenarioModifier::AddDeliveryTours(n) => add_delivery_tours(s, *n, map, rng),
             ScenarioModifier::ChangeMode {
                 to_mode,
                 pct_ppl,
@@ -61,6 +65,7 @@ impl ScenarioModifier {
             ScenarioModifier::CancelPeople(pct) => {
                 format!("cancel all trips for {}% of people", pct)
             }
+            ScenarioModifier::AddDeliveryTours(n) => format!("add {} delivery tours", n),
             ScenarioModifier::ChangeMode {
                 pct_ppl,
                 to_mode,
@@ -118,3 +123,70 @@ fn cancel_people(mut s: Scenario, pct: usize, rng: &mut XorShiftRng) -> Scenario
     }
     s
 }
+
+// Each truck enters from some border during business hours, stops at a few commercial buildings,
+// and leaves through another border.
+fn add_delivery_tours(
+    mut s: Scenario,
+    num_trucks: usize,
+    map: &Map,
+    rng: &mut XorShiftRng,
+) -> Scenario {
+    let mut stops: Vec<BuildingID> = map
+        .all_buildings()
+        .iter()
+        .filter(|b| match b.bldg_type {
+            BuildingType::Commercial | BuildingType::ResidentialCommercial(_) => true,
+            _ => !b.amenities.is_empty(),
+        })
+        .map(|b| b.id)
+        .collect();
+    if stops.is_empty() {
+        stops = map.all_buildings().iter().map(|b| b.id).collect();
+    }
+    let starts: Vec<_> = map
+        .all_incoming_borders()
+        .into_iter()
+        .filter_map(|i| i.some_outgoing_road(map))
+        .collect();
+    let goals: Vec<DrivingGoal> = map
+        .all_outgoing_borders()
+        .into_iter()
+        .filter_map(|i| {
+            DrivingGoal::end_at_border(
+                i.some_incoming_road(map)?,
+                PathConstraints::Truck,
+                None,
+                map,
+            )
+        })
+        .collect();
+    if stops.is_empty() || starts.is_empty() || goals.is_empty() {
+        println!("WARNING: Nowhere for delivery tours to go");
+        return s;
+    }
+
+    s.scenario_name = format!("{} (with {} delivery tours)", s.scenario_name, num_trucks);
+    for _ in 0..num_trucks {
+        let num_stops = rng.gen_range(2, 6);
+        let depart = Time::START_OF_DAY
+            + Duration::hours(7)
+            + Duration::seconds(rng.gen_range(0.0, Duration::hours(11).inner_seconds()));
+        let mut trip = IndividTrip::new(
+            depart,
+            SpawnTrip::DeliveryTour {
+                dr: *starts.choose(rng).unwrap(),
+                stops: stops.choose_multiple(rng, num_stops).cloned().collect(),
+                goal: goals.choose(rng).unwrap().clone(),
+                origin: None,
+            },
+        );
+        trip.modified = true;
+        s.people.push(PersonSpec {
+            id: PersonID(s.people.len()),
+            orig_id: None,
+            trips: vec![trip],
+        });
+    }
+    s
+}
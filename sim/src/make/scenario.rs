@@ -1,12 +1,13 @@
 use crate::{
     CarID, DrivingGoal, OrigPersonID, ParkingSpot, PersonID, SidewalkPOI, SidewalkSpot, Sim,
     TripEndpoint, TripMode, TripSpec, Vehicle, VehicleSpec, VehicleType, BIKE_LENGTH,
-    MAX_CAR_LENGTH, MIN_CAR_LENGTH,
+    MAX_CAR_LENGTH, MAX_TRUCK_LENGTH, MIN_CAR_LENGTH, MIN_TRUCK_LENGTH,
 };
 use abstutil::{prettyprint_usize, Counter, Timer};
 use geom::{Distance, Duration, LonLat, Speed, Time};
 use map_model::{
-    BuildingID, BusRouteID, BusStopID, DirectedRoadID, Map, PathConstraints, Position, RoadID,
+    BuildingID, BusRouteID, BusStopID, DirectedRoadID, Map, ParkingLotID, PathConstraints,
+    Position, RoadID,
 };
 use rand::seq::SliceRandom;
 use rand::{Rng, SeedableRng};
@@ -71,7 +72,9 @@ pub enum SpawnTrip {
     UsingParkedCar(BuildingID, DrivingGoal),
     UsingBike(BuildingID, DrivingGoal),
     JustWalking(SidewalkSpot, SidewalkSpot),
-    UsingTransit(SidewalkSpot, SidewalkSpot, BusRouteID, BusStopID, BusStopID),
+    // A single ride, as written by scenarios from before transfers were possible. New trips use
+    // UsingTransit.
+    UsingOneTransitRoute(SidewalkSpot, SidewalkSpot, BusRouteID, BusStopID, BusStopID),
     // Completely off-map trip. Don't really simulate much of it.
     Remote {
         from: OffMapLocation,
@@ -79,6 +82,40 @@ pub enum SpawnTrip {
         trip_time: Duration,
         mode: TripMode,
     },
+    // Scenarios are encoded with bincode, which identifies variants by position. Add new variants
+    // below this, so older scenario files still load.
+
+    // Each ride in order: (board at, alight at, route)
+    UsingTransit(
+        SidewalkSpot,
+        SidewalkSpot,
+        Vec<(BusStopID, BusStopID, BusRouteID)>,
+    ),
+    // Drive from home to a parking lot and ride transit from there, leaving the car in the lot. If
+    // there's no lot, bike to the first stop instead.
+    ParkAndRide {
+        start: BuildingID,
+        lot: Option<ParkingLotID>,
+        goal: SidewalkSpot,
+        rides: Vec<(BusStopID, BusStopID, BusRouteID)>,
+    },
+    // The way back: ride transit, then drive home from the lot where the car was left earlier. If
+    // there's no lot, bike home from the last stop.
+    ReturnFromParkAndRide {
+        start: SidewalkSpot,
+        rides: Vec<(BusStopID, BusStopID, BusRouteID)>,
+        lot: Option<ParkingLotID>,
+        goal: BuildingID,
+    },
+    // Hail a vehicle from the fleet, from one building to another
+    UsingRideHail(BuildingID, BuildingID),
+    // A truck enters from a border, delivers to each building in order, and leaves.
+    DeliveryTour {
+        dr: DirectedRoadID,
+        stops: Vec<BuildingID>,
+        goal: DrivingGoal,
+        origin: Option<OffMapLocation>,
+    },
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
@@ -196,6 +233,21 @@ impl Scenario {
         }
     }
 
+    // Slower than cars, and too long for most parking spots
+    pub fn rand_truck(rng: &mut XorShiftRng) -> VehicleSpec {
+        let length = Scenario::rand_dist(rng, MIN_TRUCK_LENGTH, MAX_TRUCK_LENGTH);
+        let max_speed = Some(Scenario::rand_speed(
+            rng,
+            Speed::miles_per_hour(45.0),
+            Speed::miles_per_hour(55.0),
+        ));
+        VehicleSpec {
+            vehicle_type: VehicleType::Truck,
+            length,
+            max_speed,
+        }
+    }
+
     pub fn rand_dist(rng: &mut XorShiftRng, low: Distance, high: Distance) -> Distance {
         assert!(high > low);
         Distance::meters(rng.gen_range(low.inner_meters(), high.inner_meters()))
@@ -393,8 +445,17 @@ impl SpawnTrip {
                         format!("{} has no lanes to spawn a {:?}", dr.id, constraints).into()
                     })
                     // TODO We could be more precise and say exactly what vehicle will be used here
-                    .and_then(|l| TripSpec::spawn_vehicle_at(Position::start(*l), is_bike, map))
-                {
+                    .and_then(|l| {
+                        TripSpec::spawn_vehicle_at(
+                            Position::start(*l),
+                            if is_bike {
+                                VehicleType::Bike
+                            } else {
+                                VehicleType::Car
+                            },
+                            map,
+                        )
+                    }) {
                     Ok(start_pos) => TripSpec::VehicleAppearing {
                         start_pos,
                         goal,
@@ -422,13 +483,70 @@ impl SpawnTrip {
                 goal,
             },
             SpawnTrip::JustWalking(start, goal) => TripSpec::JustWalking { start, goal },
-            SpawnTrip::UsingTransit(start, goal, route, stop1, stop2) => TripSpec::UsingTransit {
+            SpawnTrip::UsingOneTransitRoute(start, goal, route, stop1, stop2) => {
+                TripSpec::UsingTransit {
+                    start,
+                    goal,
+                    rides: vec![(stop1, stop2, route)],
+                }
+            }
+            SpawnTrip::UsingTransit(start, goal, rides) => {
+                TripSpec::UsingTransit { start, goal, rides }
+            }
+            SpawnTrip::ParkAndRide {
                 start,
+                lot,
                 goal,
-                route,
-                stop1,
-                stop2,
+                rides,
+            } => TripSpec::ParkAndRide {
+                vehicle: use_vehicle.unwrap(),
+                start_bldg: start,
+                park: match lot {
+                    Some(pl) => DrivingGoal::ParkAtLot(pl),
+                    None => DrivingGoal::BikeToStop(rides[0].0),
+                },
+                goal,
+                rides,
+            },
+            SpawnTrip::ReturnFromParkAndRide {
+                start, rides, goal, ..
+            } => TripSpec::ReturnFromParkAndRide {
+                vehicle: use_vehicle.unwrap(),
+                start,
+                rides,
+                goal: DrivingGoal::ParkNear(goal),
             },
+            SpawnTrip::UsingRideHail(start, goal) => TripSpec::UsingRideHail { start, goal },
+            SpawnTrip::DeliveryTour {
+                dr,
+                stops,
+                goal,
+                origin,
+            } => {
+                match dr
+                    .lanes(PathConstraints::Truck, map)
+                    .choose(rng)
+                    .ok_or_else(|| format!("{} has no lanes to spawn a truck", dr.id).into())
+                    .and_then(|l| {
+                        TripSpec::spawn_vehicle_at(Position::start(*l), VehicleType::Truck, map)
+                    }) {
+                    Ok(start_pos) => TripSpec::DeliveryTour {
+                        start_pos,
+                        stops,
+                        goal,
+                        use_vehicle: use_vehicle.unwrap(),
+                        retry_if_no_room: true,
+                        origin,
+                    },
+                    Err(err) => TripSpec::NoRoomToSpawn {
+                        i: dr.src_i(map),
+                        goal,
+                        use_vehicle: use_vehicle.unwrap(),
+                        origin,
+                        error: err.to_string(),
+                    },
+                }
+            }
             SpawnTrip::Remote {
                 from,
                 to,
@@ -463,7 +581,14 @@ impl SpawnTrip {
             SpawnTrip::UsingParkedCar(_, _) => TripMode::Drive,
             SpawnTrip::UsingBike(_, _) => TripMode::Bike,
             SpawnTrip::JustWalking(_, _) => TripMode::Walk,
-            SpawnTrip::UsingTransit(_, _, _, _, _) => TripMode::Transit,
+            SpawnTrip::UsingOneTransitRoute(_, _, _, _, _) | SpawnTrip::UsingTransit(_, _, _) => {
+                TripMode::Transit
+            }
+            SpawnTrip::ParkAndRide { .. } | SpawnTrip::ReturnFromParkAndRide { .. } => {
+                TripMode::ParkAndRide
+            }
+            SpawnTrip::UsingRideHail(_, _) => TripMode::RideHail,
+            SpawnTrip::DeliveryTour { .. } => TripMode::Drive,
             // TODO Uh...
             SpawnTrip::Remote { .. } => TripMode::Drive,
         }
@@ -474,21 +599,27 @@ impl SpawnTrip {
             SpawnTrip::VehicleAppearing { ref start, .. } => {
                 TripEndpoint::Border(map.get_l(start.lane()).src_i, None)
             }
-            SpawnTrip::FromBorder { dr, ref origin, .. } => {
+            SpawnTrip::FromBorder { dr, ref origin, .. }
+            | SpawnTrip::DeliveryTour { dr, ref origin, .. } => {
                 TripEndpoint::Border(dr.src_i(map), origin.clone())
             }
             SpawnTrip::UsingParkedCar(b, _) => TripEndpoint::Bldg(*b),
             SpawnTrip::UsingBike(b, _) => TripEndpoint::Bldg(*b),
-            SpawnTrip::JustWalking(ref spot, _) | SpawnTrip::UsingTransit(ref spot, _, _, _, _) => {
-                match spot.connection {
-                    SidewalkPOI::Building(b) => TripEndpoint::Bldg(b),
-                    SidewalkPOI::Border(i, ref loc) => TripEndpoint::Border(i, loc.clone()),
-                    SidewalkPOI::SuddenlyAppear => {
-                        TripEndpoint::Border(map.get_l(spot.sidewalk_pos.lane()).src_i, None)
-                    }
-                    _ => unreachable!(),
+            SpawnTrip::ParkAndRide { start, .. } => TripEndpoint::Bldg(*start),
+            SpawnTrip::UsingRideHail(b, _) => TripEndpoint::Bldg(*b),
+            SpawnTrip::JustWalking(ref spot, _)
+            | SpawnTrip::UsingOneTransitRoute(ref spot, _, _, _, _)
+            | SpawnTrip::UsingTransit(ref spot, _, _)
+            | SpawnTrip::ReturnFromParkAndRide {
+                start: ref spot, ..
+            } => match spot.connection {
+                SidewalkPOI::Building(b) => TripEndpoint::Bldg(b),
+                SidewalkPOI::Border(i, ref loc) => TripEndpoint::Border(i, loc.clone()),
+                SidewalkPOI::SuddenlyAppear => {
+                    TripEndpoint::Border(map.get_l(spot.sidewalk_pos.lane()).src_i, None)
                 }
-            }
+                _ => unreachable!(),
+            },
             // Pick an arbitrary border
             SpawnTrip::Remote { ref from, .. } => {
                 TripEndpoint::Border(map.all_outgoing_borders()[0].id, Some(from.clone()))
@@ -501,17 +632,22 @@ impl SpawnTrip {
             SpawnTrip::VehicleAppearing { ref goal, .. }
             | SpawnTrip::FromBorder { ref goal, .. }
             | SpawnTrip::UsingParkedCar(_, ref goal)
-            | SpawnTrip::UsingBike(_, ref goal) => match goal {
+            | SpawnTrip::UsingBike(_, ref goal)
+            | SpawnTrip::DeliveryTour { ref goal, .. } => match goal {
                 DrivingGoal::ParkNear(b) => TripEndpoint::Bldg(*b),
                 DrivingGoal::Border(i, _, ref loc) => TripEndpoint::Border(*i, loc.clone()),
+                DrivingGoal::ParkAtLot(_) | DrivingGoal::BikeToStop(_) => unreachable!(),
+            },
+            SpawnTrip::ReturnFromParkAndRide { goal, .. } => TripEndpoint::Bldg(*goal),
+            SpawnTrip::UsingRideHail(_, b) => TripEndpoint::Bldg(*b),
+            SpawnTrip::JustWalking(_, ref spot)
+            | SpawnTrip::UsingOneTransitRoute(_, ref spot, _, _, _)
+            | SpawnTrip::UsingTransit(_, ref spot, _)
+            | SpawnTrip::ParkAndRide { goal: ref spot, .. } => match spot.connection {
+                SidewalkPOI::Building(b) => TripEndpoint::Bldg(b),
+                SidewalkPOI::Border(i, ref loc) => TripEndpoint::Border(i, loc.clone()),
+                _ => unreachable!(),
             },
-            SpawnTrip::JustWalking(_, ref spot) | SpawnTrip::UsingTransit(_, ref spot, _, _, _) => {
-                match spot.connection {
-                    SidewalkPOI::Building(b) => TripEndpoint::Bldg(b),
-                    SidewalkPOI::Border(i, ref loc) => TripEndpoint::Border(i, loc.clone()),
-                    _ => unreachable!(),
-                }
-            }
             // Pick an arbitrary border
             SpawnTrip::Remote { ref to, .. } => {
                 TripEndpoint::Border(map.all_incoming_borders()[0].id, Some(to.clone()))
@@ -554,18 +690,75 @@ impl SpawnTrip {
             TripMode::Transit => {
                 let start = from.start_sidewalk_spot(map)?;
                 let goal = to.end_sidewalk_spot(map)?;
-                if let Some((stop1, stop2, route)) =
-                    map.should_use_transit(start.sidewalk_pos, goal.sidewalk_pos)
-                {
-                    SpawnTrip::UsingTransit(start, goal, route, stop1, stop2)
+                if let Some(rides) = map.should_use_transit(start.sidewalk_pos, goal.sidewalk_pos) {
+                    SpawnTrip::UsingTransit(start, goal, rides)
                 } else {
                     //timer.warn(format!("{:?} not actually using transit, because pathfinding
                     // didn't find any useful route", trip));
                     SpawnTrip::JustWalking(start, goal)
                 }
             }
+            // The fleet only picks up and drops off at buildings
+            TripMode::RideHail => match (from, to) {
+                (TripEndpoint::Bldg(b1), TripEndpoint::Bldg(b2)) => {
+                    SpawnTrip::UsingRideHail(b1, b2)
+                }
+                _ => {
+                    return None;
+                }
+            },
+            // The vehicle waits at the lot, so these only make sense as a pair of trips. Use
+            // park_and_ride and return_from_park_and_ride.
+            TripMode::ParkAndRide => {
+                return None;
+            }
         })
     }
+
+    // Drive from home to a lot (or with no lot, bike to a stop near home), then take transit to
+    // the destination. None if transit isn't useful from there.
+    pub fn park_and_ride(
+        home: BuildingID,
+        lot: Option<ParkingLotID>,
+        destination: TripEndpoint,
+        map: &Map,
+    ) -> Option<SpawnTrip> {
+        let goal = destination.end_sidewalk_spot(map)?;
+        let rides =
+            map.should_use_transit(SpawnTrip::transfer_pos(home, lot, map), goal.sidewalk_pos)?;
+        Some(SpawnTrip::ParkAndRide {
+            start: home,
+            lot,
+            goal,
+            rides,
+        })
+    }
+
+    // The way back from park_and_ride. The person has to take that trip first, so the vehicle is
+    // waiting.
+    pub fn return_from_park_and_ride(
+        origin: TripEndpoint,
+        lot: Option<ParkingLotID>,
+        home: BuildingID,
+        map: &Map,
+    ) -> Option<SpawnTrip> {
+        let start = origin.start_sidewalk_spot(map)?;
+        let rides =
+            map.should_use_transit(start.sidewalk_pos, SpawnTrip::transfer_pos(home, lot, map))?;
+        Some(SpawnTrip::ReturnFromParkAndRide {
+            start,
+            rides,
+            lot,
+            goal: home,
+        })
+    }
+
+    fn transfer_pos(home: BuildingID, lot: Option<ParkingLotID>, map: &Map) -> Position {
+        match lot {
+            Some(pl) => map.get_pl(pl).sidewalk_pos,
+            None => map.get_b(home).front_path.sidewalk,
+        }
+    }
 }
 
 impl PersonSpec {
@@ -608,6 +801,27 @@ impl PersonSpec {
                 }
             }
         }
+
+        // People can only drive back from a lot where they parked earlier.
+        let mut cars_in_lots = Vec::new();
+        for trip in &self.trips {
+            match trip.trip {
+                SpawnTrip::ParkAndRide { lot: Some(pl), .. } => {
+                    cars_in_lots.push(pl);
+                }
+                SpawnTrip::ReturnFromParkAndRide { lot: Some(pl), .. } => {
+                    if let Some(idx) = cars_in_lots.iter().position(|x| *x == pl) {
+                        cars_in_lots.remove(idx);
+                    } else {
+                        return Err(format!(
+                            "At {}, {} {:?} returns to a car in {}, but never parked there",
+                            trip.depart, self.id, self.orig_id, pl
+                        ));
+                    }
+                }
+                _ => {}
+            }
+        }
         Ok(())
     }
 
@@ -624,8 +838,12 @@ impl PersonSpec {
         let mut vehicle_foreach_trip = Vec::new();
 
         let mut bike_idx = None;
+        // Trucks always start and end off-map, so one is enough
+        let mut truck_idx = None;
         // For each indexed car, is it parked somewhere, or off-map?
         let mut car_locations: Vec<(usize, Option<BuildingID>)> = Vec::new();
+        // Cars left at a lot during park-and-ride
+        let mut cars_in_lots: Vec<(usize, ParkingLotID)> = Vec::new();
 
         // TODO If the trip is cancelled, this should be affected...
         for trip in &self.trips {
@@ -666,6 +884,9 @@ impl PersonSpec {
                             DrivingGoal::Border(_, _, _) => {
                                 car_locations.push((idx, None));
                             }
+                            DrivingGoal::ParkAtLot(_) | DrivingGoal::BikeToStop(_) => {
+                                unreachable!()
+                            }
                         }
 
                         Some(idx)
@@ -696,18 +917,64 @@ impl PersonSpec {
                         DrivingGoal::Border(_, _, _) => {
                             car_locations.push((idx, None));
                         }
+                        DrivingGoal::ParkAtLot(_) | DrivingGoal::BikeToStop(_) => unreachable!(),
                     }
 
                     Some(idx)
                 }
-                SpawnTrip::UsingBike(_, _) => {
+                SpawnTrip::ParkAndRide {
+                    start: b,
+                    lot: Some(pl),
+                    ..
+                } => {
+                    // Just like UsingParkedCar
+                    let idx = if let Some(idx) = car_locations
+                        .iter()
+                        .find(|(_, parked_at)| *parked_at == Some(b))
+                        .map(|(idx, _)| *idx)
+                    {
+                        idx
+                    } else {
+                        let idx = vehicle_specs.len();
+                        vehicle_specs.push(Scenario::rand_car(rng));
+                        cars_initially_parked_at.push((idx, b));
+                        idx
+                    };
+                    car_locations.retain(|(i, _)| idx != *i);
+                    cars_in_lots.push((idx, pl));
+                    Some(idx)
+                }
+                SpawnTrip::ReturnFromParkAndRide {
+                    lot: Some(pl),
+                    goal,
+                    ..
+                } => {
+                    // check_schedule guarantees the car was left here
+                    let pos = cars_in_lots.iter().position(|(_, at)| *at == pl).unwrap();
+                    let idx = cars_in_lots.remove(pos).0;
+                    car_locations.push((idx, Some(goal)));
+                    Some(idx)
+                }
+                SpawnTrip::UsingBike(_, _)
+                | SpawnTrip::ParkAndRide { lot: None, .. }
+                | SpawnTrip::ReturnFromParkAndRide { lot: None, .. } => {
                     if bike_idx.is_none() {
                         bike_idx = Some(vehicle_specs.len());
                         vehicle_specs.push(Scenario::rand_bike(rng));
                     }
                     bike_idx
                 }
-                SpawnTrip::JustWalking(_, _) | SpawnTrip::UsingTransit(_, _, _, _, _) => None,
+                SpawnTrip::DeliveryTour { .. } => {
+                    if truck_idx.is_none() {
+                        truck_idx = Some(vehicle_specs.len());
+                        vehicle_specs.push(Scenario::rand_truck(rng));
+                    }
+                    truck_idx
+                }
+                SpawnTrip::JustWalking(_, _)
+                | SpawnTrip::UsingOneTransitRoute(_, _, _, _, _)
+                | SpawnTrip::UsingTransit(_, _, _)
+                | SpawnTrip::UsingRideHail(_, _) => None,
                 SpawnTrip::Remote { .. } => None,
             };
             vehicle_foreach_trip.push(use_for_trip);
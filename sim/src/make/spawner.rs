@@ -1,6 +1,7 @@
 use crate::{
-    CarID, Command, DrivingGoal, OffMapLocation, Person, PersonID, Scheduler, SidewalkSpot,
+    CarID, Command, Curb, DrivingGoal, OffMapLocation, Person, PersonID, Scheduler, SidewalkSpot,
     TripEndpoint, TripLeg, TripManager, TripMode, VehicleType, BIKE_LENGTH, MAX_CAR_LENGTH,
+    MAX_TRUCK_LENGTH,
 };
 use abstutil::Timer;
 use geom::{Duration, Time, EPSILON_DIST};
@@ -49,9 +50,42 @@ pub enum TripSpec {
     UsingTransit {
         start: SidewalkSpot,
         goal: SidewalkSpot,
-        route: BusRouteID,
-        stop1: BusStopID,
-        stop2: BusStopID,
+        // Each ride in order: (board at, alight at, route). Between rides, walk to the next stop,
+        // which might be the same one.
+        rides: Vec<(BusStopID, BusStopID, BusRouteID)>,
+    },
+    // Drive to a parking lot or bike to the first stop, then ride transit.
+    ParkAndRide {
+        // A currently parked car or a bike owned by the person
+        vehicle: CarID,
+        start_bldg: BuildingID,
+        // ParkAtLot for a car, BikeToStop for a bike
+        park: DrivingGoal,
+        goal: SidewalkSpot,
+        rides: Vec<(BusStopID, BusStopID, BusRouteID)>,
+    },
+    // The reverse of ParkAndRide. After riding transit, walk to the car parked in a lot or to a
+    // bike rack near the last stop, then drive or bike to the goal.
+    ReturnFromParkAndRide {
+        vehicle: CarID,
+        start: SidewalkSpot,
+        rides: Vec<(BusStopID, BusStopID, BusRouteID)>,
+        goal: DrivingGoal,
+    },
+    // Wait at the curb for a vehicle from the ride-hail fleet
+    UsingRideHail {
+        start: BuildingID,
+        goal: BuildingID,
+    },
+    // Like VehicleAppearing, but stop at each building along the way.
+    DeliveryTour {
+        start_pos: Position,
+        // A truck owned by the person
+        use_vehicle: CarID,
+        stops: Vec<BuildingID>,
+        goal: DrivingGoal,
+        retry_if_no_room: bool,
+        origin: Option<OffMapLocation>,
     },
     // Completely off-map trip. Don't really simulate much of it.
     Remote {
@@ -117,7 +151,9 @@ impl TripSpawner {
                             );
                         }
                     }
-                    DrivingGoal::ParkNear(_) => {}
+                    DrivingGoal::ParkNear(_)
+                    | DrivingGoal::ParkAtLot(_)
+                    | DrivingGoal::BikeToStop(_) => {}
                 }
             }
             TripSpec::NoRoomToSpawn { .. } => {}
@@ -145,6 +181,7 @@ impl TripSpawner {
                             }
                         })
                     }
+                    DrivingGoal::ParkAtLot(_) | DrivingGoal::BikeToStop(_) => unreachable!(),
                 };
 
                 if SidewalkSpot::bike_from_bike_rack(map.get_b(*start).sidewalk(), map).is_none() {
@@ -186,6 +223,124 @@ impl TripSpawner {
                 }
             }
             TripSpec::UsingTransit { .. } => {}
+            TripSpec::ParkAndRide {
+                vehicle,
+                start_bldg,
+                park,
+                goal,
+                rides,
+            } => {
+                if vehicle.1 == VehicleType::Bike {
+                    let stop_sidewalk = map.get_bs(rides[0].0).sidewalk_pos.lane();
+                    if SidewalkSpot::bike_from_bike_rack(map.get_b(*start_bldg).sidewalk(), map)
+                        .is_none()
+                        || map
+                            .get_parent(stop_sidewalk)
+                            .sidewalk_to_bike(stop_sidewalk)
+                            .is_none()
+                    {
+                        println!(
+                            "Can't bike from {} to {:?}; no biking or driving lane nearby? \
+                             Walking to transit instead",
+                            start_bldg, park
+                        );
+                        spec = TripSpec::UsingTransit {
+                            start: SidewalkSpot::building(*start_bldg, map),
+                            goal: goal.clone(),
+                            rides: rides.clone(),
+                        };
+                    }
+                }
+            }
+            TripSpec::ReturnFromParkAndRide {
+                vehicle,
+                start,
+                rides,
+                goal,
+            } => {
+                if vehicle.1 == VehicleType::Bike {
+                    let stop_sidewalk = map.get_bs(rides.last().unwrap().1).sidewalk_pos.lane();
+                    if SidewalkSpot::bike_from_bike_rack(stop_sidewalk, map).is_none() {
+                        let walk_to = match goal {
+                            DrivingGoal::ParkNear(b) => Some(SidewalkSpot::building(*b, map)),
+                            DrivingGoal::Border(i, _, off_map) => {
+                                SidewalkSpot::end_at_border(*i, off_map.clone(), map)
+                            }
+                            DrivingGoal::ParkAtLot(_) | DrivingGoal::BikeToStop(_) => {
+                                unreachable!()
+                            }
+                        };
+                        if let Some(walk_to) = walk_to {
+                            println!(
+                                "Can't start biking from {}; no biking or driving lane nearby? \
+                                 Walking from transit instead",
+                                stop_sidewalk
+                            );
+                            spec = TripSpec::UsingTransit {
+                                start: start.clone(),
+                                goal: walk_to,
+                                rides: rides.clone(),
+                            };
+                        } else {
+                            panic!(
+                                "Can't start biking from {}; no biking or driving lane nearby?",
+                                stop_sidewalk
+                            );
+                        }
+                    }
+                }
+            }
+            TripSpec::UsingRideHail { start, goal } => {
+                if Curb::near_bldg(*start, map).is_none() || Curb::near_bldg(*goal, map).is_none() {
+                    println!(
+                        "Can't take a ride-hail from {} to {}; nowhere to stop. Walking instead",
+                        start, goal
+                    );
+                    spec = TripSpec::JustWalking {
+                        start: SidewalkSpot::building(*start, map),
+                        goal: SidewalkSpot::building(*goal, map),
+                    };
+                }
+            }
+            TripSpec::DeliveryTour {
+                start_pos,
+                use_vehicle,
+                stops,
+                goal,
+                retry_if_no_room,
+                origin,
+            } => {
+                let stops: Vec<BuildingID> = stops
+                    .iter()
+                    .filter(|b| {
+                        if Curb::near_bldg(**b, map).is_some() {
+                            true
+                        } else {
+                            println!("Can't deliver to {}; nowhere to stop. Skipping it", b);
+                            false
+                        }
+                    })
+                    .cloned()
+                    .collect();
+                spec = if stops.is_empty() {
+                    TripSpec::VehicleAppearing {
+                        start_pos: *start_pos,
+                        goal: goal.clone(),
+                        use_vehicle: *use_vehicle,
+                        retry_if_no_room: *retry_if_no_room,
+                        origin: origin.clone(),
+                    }
+                } else {
+                    TripSpec::DeliveryTour {
+                        start_pos: *start_pos,
+                        use_vehicle: *use_vehicle,
+                        stops,
+                        goal: goal.clone(),
+                        retry_if_no_room: *retry_if_no_room,
+                        origin: origin.clone(),
+                    }
+                };
+            }
             TripSpec::Remote { .. } => {}
         };
 
@@ -288,6 +443,7 @@ impl TripSpawner {
                             legs.push(TripLeg::Walk(SidewalkSpot::building(b, map)));
                         }
                         DrivingGoal::Border(_, _, _) => {}
+                        DrivingGoal::ParkAtLot(_) | DrivingGoal::BikeToStop(_) => unreachable!(),
                     }
                     trips.new_trip(
                         person.id,
@@ -321,6 +477,7 @@ impl TripSpawner {
                             legs.push(TripLeg::Walk(SidewalkSpot::building(b, map)));
                         }
                         DrivingGoal::Border(_, _, _) => {}
+                        DrivingGoal::ParkAtLot(_) | DrivingGoal::BikeToStop(_) => unreachable!(),
                     };
                     trips.new_trip(
                         person.id,
@@ -332,25 +489,118 @@ impl TripSpawner {
                         map,
                     )
                 }
-                TripSpec::UsingTransit {
-                    route,
-                    stop1,
-                    stop2,
+                TripSpec::UsingTransit { rides, goal, .. } => {
+                    let mut legs = Vec::new();
+                    for (stop1, stop2, route) in rides {
+                        legs.push(TripLeg::Walk(SidewalkSpot::bus_stop(stop1, map)));
+                        legs.push(TripLeg::RideBus(route, stop2));
+                    }
+                    legs.push(TripLeg::Walk(goal));
+                    trips.new_trip(
+                        person.id,
+                        start_time,
+                        trip_start,
+                        TripMode::Transit,
+                        modified,
+                        legs,
+                        map,
+                    )
+                }
+                TripSpec::ParkAndRide {
+                    vehicle,
+                    start_bldg,
+                    park,
+                    goal,
+                    rides,
+                } => {
+                    let walk_to = if vehicle.1 == VehicleType::Bike {
+                        SidewalkSpot::bike_from_bike_rack(map.get_b(start_bldg).sidewalk(), map)
+                            .unwrap()
+                    } else {
+                        SidewalkSpot::deferred_parking_spot()
+                    };
+                    let mut legs = vec![TripLeg::Walk(walk_to), TripLeg::Drive(vehicle, park)];
+                    for (stop1, stop2, route) in rides {
+                        legs.push(TripLeg::Walk(SidewalkSpot::bus_stop(stop1, map)));
+                        legs.push(TripLeg::RideBus(route, stop2));
+                    }
+                    legs.push(TripLeg::Walk(goal));
+                    trips.new_trip(
+                        person.id,
+                        start_time,
+                        trip_start,
+                        TripMode::ParkAndRide,
+                        modified,
+                        legs,
+                        map,
+                    )
+                }
+                TripSpec::ReturnFromParkAndRide {
+                    vehicle,
+                    rides,
                     goal,
                     ..
                 } => {
-                    let walk_to = SidewalkSpot::bus_stop(stop1, map);
+                    let mut legs = Vec::new();
+                    let last_stop = rides.last().unwrap().1;
+                    for (stop1, stop2, route) in rides {
+                        legs.push(TripLeg::Walk(SidewalkSpot::bus_stop(stop1, map)));
+                        legs.push(TripLeg::RideBus(route, stop2));
+                    }
+                    let walk_to = if vehicle.1 == VehicleType::Bike {
+                        SidewalkSpot::bike_from_bike_rack(
+                            map.get_bs(last_stop).sidewalk_pos.lane(),
+                            map,
+                        )
+                        .unwrap()
+                    } else {
+                        SidewalkSpot::deferred_parking_spot()
+                    };
+                    legs.push(TripLeg::Walk(walk_to));
+                    legs.push(TripLeg::Drive(vehicle, goal.clone()));
+                    if let DrivingGoal::ParkNear(b) = goal {
+                        legs.push(TripLeg::Walk(SidewalkSpot::building(b, map)));
+                    }
                     trips.new_trip(
                         person.id,
                         start_time,
                         trip_start,
-                        TripMode::Transit,
+                        TripMode::ParkAndRide,
+                        modified,
+                        legs,
+                        map,
+                    )
+                }
+                TripSpec::UsingRideHail { goal, .. } => trips.new_trip(
+                    person.id,
+                    start_time,
+                    trip_start,
+                    TripMode::RideHail,
+                    modified,
+                    vec![
+                        TripLeg::RideHail(goal),
+                        TripLeg::Walk(SidewalkSpot::building(goal, map)),
+                    ],
+                    map,
+                ),
+                TripSpec::DeliveryTour {
+                    use_vehicle,
+                    stops,
+                    goal,
+                    ..
+                } => {
+                    let mut legs: Vec<TripLeg> = stops
+                        .into_iter()
+                        .map(|b| TripLeg::Deliver(use_vehicle, b))
+                        .collect();
+                    legs.push(TripLeg::Drive(use_vehicle, goal));
+                    trips.new_trip(
+                        person.id,
+                        start_time,
+                        trip_start,
+                        TripMode::Drive,
                         modified,
-                        vec![
-                            TripLeg::Walk(walk_to.clone()),
-                            TripLeg::RideBus(route, stop2),
-                            TripLeg::Walk(goal),
-                        ],
+                        legs,
                         map,
                     )
                 }
@@ -381,11 +631,15 @@ impl TripSpec {
     // If possible, fixes problems that schedule_trip would hit.
     pub fn spawn_vehicle_at(
         pos: Position,
-        is_bike: bool,
+        vehicle_type: VehicleType,
         map: &Map,
     ) -> Result<Position, Box<dyn Error>> {
         let lane_len = map.get_l(pos.lane()).length();
-        let vehicle_len = if is_bike { BIKE_LENGTH } else { MAX_CAR_LENGTH };
+        let vehicle_len = match vehicle_type {
+            VehicleType::Bike => BIKE_LENGTH,
+            VehicleType::Truck => MAX_TRUCK_LENGTH,
+            _ => MAX_CAR_LENGTH,
+        };
         // There's no hope.
         if lane_len <= vehicle_len {
             return Err(format!(
@@ -413,11 +667,7 @@ impl TripSpec {
                 use_vehicle,
                 ..
             } => {
-                let constraints = if use_vehicle.1 == VehicleType::Bike {
-                    PathConstraints::Bike
-                } else {
-                    PathConstraints::Car
-                };
+                let constraints = use_vehicle.1.to_constraints();
                 Some(PathRequest {
                     start: *start_pos,
                     end: goal.goal_pos(constraints, map),
@@ -439,12 +689,57 @@ impl TripSpec {
                     .sidewalk_pos,
                 constraints: PathConstraints::Pedestrian,
             }),
-            TripSpec::UsingTransit { start, stop1, .. } => Some(PathRequest {
+            TripSpec::UsingTransit { start, rides, .. } => Some(PathRequest {
                 start: start.sidewalk_pos,
-                end: SidewalkSpot::bus_stop(*stop1, map).sidewalk_pos,
+                end: SidewalkSpot::bus_stop(rides[0].0, map).sidewalk_pos,
                 constraints: PathConstraints::Pedestrian,
             }),
+            TripSpec::ParkAndRide { .. } | TripSpec::ReturnFromParkAndRide { .. } => {
+                self.clone().first_mode(map).get_pathfinding_request(map)
+            }
+            // The fleet figures out the route once a vehicle is dispatched
+            TripSpec::UsingRideHail { .. } => None,
+            TripSpec::DeliveryTour {
+                start_pos, stops, ..
+            } => Some(PathRequest {
+                start: *start_pos,
+                end: Curb::near_bldg(stops[0], map).unwrap().driving_pos,
+                constraints: PathConstraints::Truck,
+            }),
             TripSpec::Remote { .. } => None,
         }
     }
+
+    // Combined trips start out just like driving, biking, or riding transit somewhere. The rest of
+    // their legs take care of the remainder.
+    pub(crate) fn first_mode(self, map: &Map) -> TripSpec {
+        match self {
+            TripSpec::ParkAndRide {
+                vehicle,
+                start_bldg,
+                park,
+                ..
+            } => {
+                if vehicle.1 == VehicleType::Bike {
+                    TripSpec::UsingBike {
+                        bike: vehicle,
+                        start: start_bldg,
+                        goal: park,
+                    }
+                } else {
+                    TripSpec::UsingParkedCar {
+                        car: vehicle,
+                        start_bldg,
+                        goal: park,
+                    }
+                }
+            }
+            TripSpec::ReturnFromParkAndRide { start, rides, .. } => TripSpec::UsingTransit {
+                start,
+                goal: SidewalkSpot::bus_stop(rides.last().unwrap().1, map),
+                rides,
+            },
+            spec => spec,
+        }
+    }
 }
@@ -1,11 +1,22 @@
 use crate::{
-    CarStatus, DistanceInterval, DrawCarInput, ParkingSpot, PersonID, Router, TimeInterval,
-    TransitSimState, TripID, Vehicle, VehicleType,
+    CarStatus, DistanceInterval, DrawCarInput, Emissions, ParkingSpot, PersonID, Router,
+    TimeInterval, TransitSimState, TripID, Vehicle, VehicleType, FOLLOWING_DISTANCE,
 };
-use geom::{Distance, Duration, PolyLine, Time};
-use map_model::{Map, Traversable};
+use geom::{Distance, Duration, PolyLine, Speed, Time, EPSILON_DIST};
+use map_model::{LaneID, Map, Traversable, TurnPriority};
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
+
+// With acceleration profiles, how long a driver takes to start moving after the vehicle in front
+// of them does. This is what makes a queue discharge with some start-up lost time.
+const REACTION_TIME: Duration = Duration::const_seconds(1.0);
+// With acceleration profiles, a follower stays FOLLOWING_DISTANCE plus this much travel time behind
+// their leader, at the slower of their two speeds. Stopped vehicles are just FOLLOWING_DISTANCE
+// apart. This matches REACTION_TIME, so a discharging queue doesn't have to slow down to spread
+// out.
+const TIME_HEADWAY: Duration = Duration::const_seconds(1.0);
+// How many points along a follower's profile to check against their leader
+const HEADWAY_SAMPLES: usize = 20;
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct Car {
@@ -17,6 +28,13 @@ pub struct Car {
     pub trip_and_person: Option<(TripID, PersonID)>,
     pub started_at: Time,
     pub total_blocked_time: Duration,
+    // When the driver last considered switching routes
+    pub last_reroute_check: Time,
+    // What the car has emitted on its current lane or turn so far
+    pub emissions_on_head: EmissionsOnHead,
+    // How fast the car was going when it last finished crossing something. Only used with
+    // acceleration profiles.
+    pub last_speed: Speed,
 
     // In reverse order -- most recently left is first. The sum length of these must be >=
     // vehicle.length.
@@ -24,8 +42,16 @@ pub struct Car {
 }
 
 impl Car {
-    // Assumes the current head of the path is the thing to cross.
-    pub fn crossing_state(&self, start_dist: Distance, start_time: Time, map: &Map) -> CarState {
+    // Assumes the current head of the path is the thing to cross. This must be called before
+    // changing the car's state, since that determines how fast the car starts.
+    pub fn crossing_state(
+        &self,
+        start_dist: Distance,
+        start_time: Time,
+        map: &Map,
+        accelerate: bool,
+        speed_factors: &BTreeMap<LaneID, f64>,
+    ) -> CarState {
         let dist_int = DistanceInterval::new_driving(
             start_dist,
             if self.router.last_step() {
@@ -34,7 +60,27 @@ impl Car {
                 self.router.head().length(map)
             },
         );
-        self.crossing_state_with_end_dist(dist_int, start_time, map)
+        self.crossing_state_with_end_dist(dist_int, start_time, map, accelerate, speed_factors)
+    }
+
+    // Like crossing_state with acceleration profiles, but also plans around the vehicle in front.
+    pub fn following_state(
+        &self,
+        start_dist: Distance,
+        start_time: Time,
+        map: &Map,
+        speed_factors: &BTreeMap<LaneID, f64>,
+        leader: Option<&Leader>,
+    ) -> CarState {
+        let dist_int = DistanceInterval::new_driving(
+            start_dist,
+            if self.router.last_step() {
+                self.router.get_end_dist()
+            } else {
+                self.router.head().length(map)
+            },
+        );
+        self.plan_crossing(dist_int, start_time, map, true, speed_factors, leader)
     }
 
     pub fn crossing_state_with_end_dist(
@@ -42,14 +88,129 @@ impl Car {
         dist_int: DistanceInterval,
         start_time: Time,
         map: &Map,
+        accelerate: bool,
+        speed_factors: &BTreeMap<LaneID, f64>,
+    ) -> CarState {
+        self.plan_crossing(dist_int, start_time, map, accelerate, speed_factors, None)
+    }
+
+    fn plan_crossing(
+        &self,
+        mut dist_int: DistanceInterval,
+        start_time: Time,
+        map: &Map,
+        accelerate: bool,
+        speed_factors: &BTreeMap<LaneID, f64>,
+        leader: Option<&Leader>,
     ) -> CarState {
         let on = self.router.head();
         let mut speed = on.speed_limit(map);
+        // Slowed down by an incident
+        if let Traversable::Lane(l) = on {
+            if let Some(factor) = speed_factors.get(&l) {
+                speed = speed * *factor;
+            }
+        }
         if let Some(s) = self.vehicle.max_speed {
             speed = speed.min(s);
         }
-        let dt = (dist_int.end - dist_int.start) / speed;
-        CarState::Crossing(TimeInterval::new(start_time, start_time + dt), dist_int)
+        if !accelerate {
+            let dt = (dist_int.end - dist_int.start) / speed;
+            return CarState::Crossing(
+                TimeInterval::new(start_time, start_time + dt),
+                dist_int,
+                None,
+            );
+        }
+
+        let (start_speed, mut reaction) = self.current_speed(start_time);
+        let mut end_speed = self.target_end_speed(map);
+        if let Some(leader) = leader {
+            if reaction > Duration::ZERO {
+                if let Some(t) = leader.starts_moving() {
+                    reaction = reaction.max(t + REACTION_TIME - start_time);
+                }
+            }
+            // Pull up behind the leader if they're going to stop on this lane or turn
+            if let Some(back) = leader.stops_at() {
+                let stop = (back - FOLLOWING_DISTANCE).max(dist_int.start);
+                if stop < dist_int.end {
+                    dist_int = DistanceInterval::new_driving(dist_int.start, stop);
+                    end_speed = Speed::ZERO;
+                }
+            }
+        }
+        let make = |max_speed| {
+            SpeedProfile::new(
+                dist_int.end - dist_int.start,
+                start_speed,
+                max_speed,
+                end_speed,
+                self.vehicle.vehicle_type,
+                reaction,
+            )
+        };
+        let profile = match leader {
+            Some(leader) => {
+                SpeedProfile::fastest_behind(leader, dist_int.start, start_time, speed, make)
+            }
+            None => make(speed),
+        };
+        CarState::Crossing(
+            TimeInterval::new(start_time, start_time + profile.total_time()),
+            dist_int,
+            Some(profile),
+        )
+    }
+
+    // How fast is the car going right now, and does the driver need a moment to react before
+    // speeding up? Only meaningful with acceleration profiles.
+    pub fn current_speed(&self, now: Time) -> (Speed, Duration) {
+        match self.state {
+            CarState::Crossing(ref time_int, _, Some(ref profile)) => (
+                profile.speed_at(now - time_int.start),
+                profile.reaction_left(now - time_int.start),
+            ),
+            CarState::Queued { blocked_since } | CarState::WaitingToAdvance { blocked_since } => {
+                if blocked_since == now {
+                    // Didn't actually have to stop
+                    return (self.last_speed, Duration::ZERO);
+                }
+                // Brake while blocked. Only a driver who came to a stop needs a moment to react.
+                let decel = accel_and_decel(self.vehicle.vehicle_type).1;
+                let speed = self.last_speed.inner_meters_per_second()
+                    - decel * (now - blocked_since).inner_seconds();
+                if speed > 0.0 {
+                    (Speed::meters_per_second(speed), Duration::ZERO)
+                } else {
+                    (Speed::ZERO, REACTION_TIME)
+                }
+            }
+            // Unparking and leaving a stop already take a while
+            _ => (Speed::ZERO, Duration::ZERO),
+        }
+    }
+
+    // How fast should the car be going by the end of its current lane or turn?
+    fn target_end_speed(&self, map: &Map) -> Speed {
+        if self.router.last_step() {
+            return Speed::ZERO;
+        }
+        let next = self.router.next();
+        if let Traversable::Turn(t) = next {
+            if let Some(ss) = map.maybe_get_stop_sign(t.parent) {
+                if ss.get_priority(t, map) == TurnPriority::Yield {
+                    return Speed::ZERO;
+                }
+            }
+            // TODO We don't know if a traffic signal will be green by the time we get there, so
+            // optimistically assume it will be. If not, the car stops instantly.
+        }
+        let mut speed = next.speed_limit(map);
+        if let Some(s) = self.vehicle.max_speed {
+            speed = speed.min(s);
+        }
+        speed
     }
 
     pub fn get_draw_car(
@@ -209,7 +370,7 @@ impl Car {
             status: match self.state {
                 CarState::Queued { .. } => CarStatus::Moving,
                 CarState::WaitingToAdvance { .. } => CarStatus::Moving,
-                CarState::Crossing(_, _) => CarStatus::Moving,
+                CarState::Crossing(_, _, _) => CarStatus::Moving,
                 // Eh they're technically moving, but this is a bit easier to spot
                 CarState::Unparking(_, _, _) => CarStatus::Parked,
                 CarState::Parking(_, _, _) => CarStatus::Parked,
@@ -233,6 +394,41 @@ impl Car {
         }
     }
 
+    // Count what the car emitted during its current Crossing state, up to now. This must be called
+    // before the Crossing state is replaced.
+    pub fn finish_crossing(&mut self, now: Time) {
+        if let CarState::Crossing(ref time_int, ref dist_int, ref profile) = self.state {
+            let duration = time_int.end - time_int.start;
+            let elapsed = (now - time_int.start).min(duration);
+            let head = &mut self.emissions_on_head;
+            head.moving += elapsed;
+            head.emissions += Emissions::for_crossing(
+                self.vehicle.vehicle_type,
+                dist_int.end - dist_int.start,
+                duration,
+                elapsed,
+                profile.as_ref(),
+            );
+            head.constant_speed = if profile.is_none() && duration > Duration::ZERO {
+                Some(Speed::meters_per_second(
+                    (dist_int.end - dist_int.start).inner_meters() / duration.inner_seconds(),
+                ))
+            } else {
+                None
+            };
+        }
+    }
+
+    // Everything the car emitted on its current lane or turn, including idling. Starts counting
+    // over from now.
+    pub fn leave_head(&mut self, now: Time) -> Emissions {
+        self.finish_crossing(now);
+        let head = std::mem::replace(&mut self.emissions_on_head, EmissionsOnHead::new(now));
+        let idling = (now - head.entered - head.moving).max(Duration::ZERO);
+        head.emissions
+            + Emissions::for_idling(self.vehicle.vehicle_type, idling, head.constant_speed)
+    }
+
     pub fn is_parking(&self) -> bool {
         if let CarState::Parking(_, _, _) = self.state {
             return true;
@@ -241,9 +437,32 @@ impl Car {
     }
 }
 
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct EmissionsOnHead {
+    entered: Time,
+    // Any other time on the lane or turn was spent idling
+    moving: Duration,
+    emissions: Emissions,
+    // Without acceleration profiles, the speed of the last crossing
+    constant_speed: Option<Speed>,
+}
+
+impl EmissionsOnHead {
+    pub fn new(now: Time) -> EmissionsOnHead {
+        EmissionsOnHead {
+            entered: now,
+            moving: Duration::ZERO,
+            emissions: Emissions::ZERO,
+            constant_speed: None,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub enum CarState {
-    Crossing(TimeInterval, DistanceInterval),
+    // Only has a SpeedProfile with acceleration profiles enabled; otherwise the car moves at a constant
+    // speed.
+    Crossing(TimeInterval, DistanceInterval, Option<SpeedProfile>),
     Queued { blocked_since: Time },
     WaitingToAdvance { blocked_since: Time },
     // Where's the front of the car while this is happening?
@@ -255,7 +474,7 @@ pub enum CarState {
 impl CarState {
     pub fn get_end_time(&self) -> Time {
         match self {
-            CarState::Crossing(ref time_int, _) => time_int.end,
+            CarState::Crossing(ref time_int, _, _) => time_int.end,
             CarState::Queued { .. } => unreachable!(),
             CarState::WaitingToAdvance { .. } => unreachable!(),
             CarState::Unparking(_, _, ref time_int) => time_int.end,
@@ -264,3 +483,465 @@ impl CarState {
         }
     }
 }
+
+// With acceleration profiles, where the back of the vehicle in front of a follower is going to be.
+// Distances are along the follower's lane or turn.
+pub struct Leader {
+    // When the leader's current profile started, and where their back was then
+    start_time: Time,
+    start_back: Distance,
+    // None if the leader isn't moving right now
+    profile: Option<SpeedProfile>,
+    // The leader's front is already past the end of the follower's lane or turn. If they stop,
+    // the queue holds the follower back.
+    leaving: bool,
+}
+
+impl Leader {
+    // `front` is where the leader's front is right now.
+    pub fn new(car: &Car, front: Distance, now: Time, leaving: bool) -> Leader {
+        match car.state {
+            CarState::Crossing(ref time_int, _, Some(ref profile)) => Leader {
+                start_time: time_int.start,
+                start_back: front - car.vehicle.length - profile.dist_at(now - time_int.start),
+                profile: Some(profile.clone()),
+                leaving,
+            },
+            _ => Leader {
+                start_time: now,
+                start_back: front - car.vehicle.length,
+                profile: None,
+                leaving,
+            },
+        }
+    }
+
+    pub fn is_moving(&self) -> bool {
+        self.profile.is_some()
+    }
+
+    fn starts_moving(&self) -> Option<Time> {
+        self.profile
+            .as_ref()
+            .map(|p| self.start_time + Duration::seconds(p.reaction))
+    }
+
+    // Where the leader's back will come to rest, if that's before they leave the follower's lane
+    // or turn
+    fn stops_at(&self) -> Option<Distance> {
+        if self.leaving {
+            return None;
+        }
+        match self.profile {
+            Some(ref p) if p.end_speed > 0.0 => None,
+            Some(ref p) => Some(self.start_back + Distance::meters(p.dist)),
+            None => Some(self.start_back),
+        }
+    }
+
+    // After their current profile, assume the leader keeps going at the same speed.
+    fn back_at(&self, t: Time) -> Distance {
+        match self.profile {
+            Some(ref p) => {
+                let elapsed = t - self.start_time;
+                let after = (elapsed - p.total_time()).max(Duration::ZERO);
+                self.start_back + p.dist_at(elapsed) + Speed::meters_per_second(p.end_speed) * after
+            }
+            None => self.start_back,
+        }
+    }
+
+    fn speed_at(&self, t: Time) -> Speed {
+        match self.profile {
+            Some(ref p) => p.speed_at(t - self.start_time),
+            None => Speed::ZERO,
+        }
+    }
+}
+
+// Vehicles speed up to a cruising speed, then slow down to the speed they want to be going at the
+// end of the lane or turn -- zero if they're about to park or stop at a stop sign. A follower
+// cruises no faster than it takes to stay TIME_HEADWAY behind their leader, and stops behind them
+// if they're stopping. Internally, speeds are in m/s, rates in m/s^2, and times in seconds.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct SpeedProfile {
+    // The driver waits this long before moving at all
+    reaction: f64,
+    start_speed: f64,
+    cruise_speed: f64,
+    end_speed: f64,
+    accel: f64,
+    decel: f64,
+    accel_time: f64,
+    cruise_time: f64,
+    decel_time: f64,
+    dist: f64,
+}
+
+impl SpeedProfile {
+    fn new(
+        dist: Distance,
+        start_speed: Speed,
+        max_speed: Speed,
+        end_speed: Speed,
+        vehicle_type: VehicleType,
+        reaction: Duration,
+    ) -> SpeedProfile {
+        let (a, b) = accel_and_decel(vehicle_type);
+        let d = dist.inner_meters();
+        let vmax = max_speed.inner_meters_per_second();
+        // If the speed limit drops, just slow down instantly.
+        let v0 = start_speed.inner_meters_per_second().min(vmax);
+        let ve = end_speed.inner_meters_per_second().min(vmax);
+
+        // The fastest speed reachable if the vehicle speeds up, then immediately slows down
+        let peak_sq = (2.0 * a * b * d + b * v0 * v0 + a * ve * ve) / (a + b);
+        let (vc, ve) = if peak_sq >= vmax * vmax {
+            (vmax, ve)
+        } else if peak_sq < v0 * v0 {
+            // Not enough room to slow down all the way
+            (v0, (v0 * v0 - 2.0 * b * d).max(0.0).sqrt())
+        } else if peak_sq < ve * ve {
+            // Not enough room to speed up all the way
+            let v = (v0 * v0 + 2.0 * a * d).sqrt();
+            (v, v)
+        } else {
+            (peak_sq.sqrt(), ve)
+        };
+
+        let accel_time = (vc - v0) / a;
+        let decel_time = (vc - ve) / b;
+        let cruise_dist = d - (v0 + vc) / 2.0 * accel_time - (vc + ve) / 2.0 * decel_time;
+        let cruise_time = if vc > 0.0 {
+            (cruise_dist / vc).max(0.0)
+        } else {
+            0.0
+        };
+
+        SpeedProfile {
+            reaction: reaction.inner_seconds(),
+            start_speed: v0,
+            cruise_speed: vc,
+            end_speed: ve,
+            accel: a,
+            decel: b,
+            accel_time,
+            cruise_time,
+            decel_time,
+            dist: d,
+        }
+    }
+
+    // The fastest profile that stays a safe distance behind the leader. `make` builds the profile
+    // for a given cruising speed, and this one starts `start` along the lane or turn at
+    // `start_time`. If even crawling is too close, the queue holds the follower back.
+    fn fastest_behind<F: Fn(Speed) -> SpeedProfile>(
+        leader: &Leader,
+        start: Distance,
+        start_time: Time,
+        max_speed: Speed,
+        make: F,
+    ) -> SpeedProfile {
+        let fastest = make(max_speed);
+        if fastest.keeps_distance(leader, start, start_time) {
+            return fastest;
+        }
+        // Binary search for the cruising speed, down to a crawl
+        let mut slow = 1.0_f64.min(max_speed.inner_meters_per_second());
+        let mut fast = max_speed.inner_meters_per_second();
+        let mut best = make(Speed::meters_per_second(slow));
+        for _ in 0..10 {
+            let mid = (slow + fast) / 2.0;
+            let p = make(Speed::meters_per_second(mid));
+            if p.keeps_distance(leader, start, start_time) {
+                slow = mid;
+                best = p;
+            } else {
+                fast = mid;
+            }
+        }
+        best
+    }
+
+    fn keeps_distance(&self, leader: &Leader, start: Distance, start_time: Time) -> bool {
+        let total = self.total_time();
+        (1..=HEADWAY_SAMPLES).all(|i| {
+            let elapsed = total * (i as f64 / HEADWAY_SAMPLES as f64);
+            let t = start_time + elapsed;
+            let speed = self.speed_at(elapsed).min(leader.speed_at(t));
+            start + self.dist_at(elapsed) + FOLLOWING_DISTANCE + speed * TIME_HEADWAY
+                <= leader.back_at(t) + EPSILON_DIST
+        })
+    }
+
+    pub fn total_time(&self) -> Duration {
+        Duration::seconds(self.reaction + self.accel_time + self.cruise_time + self.decel_time)
+    }
+
+    // How much longer the driver waits before moving
+    fn reaction_left(&self, elapsed: Duration) -> Duration {
+        (Duration::seconds(self.reaction) - elapsed).max(Duration::ZERO)
+    }
+
+    // How far the vehicle has gone since starting
+    pub fn dist_at(&self, elapsed: Duration) -> Distance {
+        if elapsed >= self.total_time() {
+            return Distance::meters(self.dist);
+        }
+        let mut t = (elapsed.inner_seconds() - self.reaction).max(0.0);
+        let t1 = t.min(self.accel_time);
+        let mut d = self.start_speed * t1 + 0.5 * self.accel * t1 * t1;
+        t -= t1;
+        let t2 = t.min(self.cruise_time);
+        d += self.cruise_speed * t2;
+        t -= t2;
+        let t3 = t.min(self.decel_time);
+        d += self.cruise_speed * t3 - 0.5 * self.decel * t3 * t3;
+        Distance::meters(d.min(self.dist))
+    }
+
+    pub fn speed_at(&self, elapsed: Duration) -> Speed {
+        let mut t = elapsed.inner_seconds() - self.reaction;
+        if t < 0.0 {
+            return Speed::ZERO;
+        }
+        if t < self.accel_time {
+            return Speed::meters_per_second(self.start_speed + self.accel * t);
+        }
+        t -= self.accel_time + self.cruise_time;
+        if t < 0.0 {
+            return Speed::meters_per_second(self.cruise_speed);
+        }
+        if t < self.decel_time {
+            return Speed::meters_per_second(self.cruise_speed - self.decel * t);
+        }
+        Speed::meters_per_second(self.end_speed)
+    }
+}
+
+// How quickly a vehicle speeds up, and how quickly it comfortably slows down, in m/s^2
+fn accel_and_decel(vehicle_type: VehicleType) -> (f64, f64) {
+    match vehicle_type {
+        VehicleType::Car => (1.5, 2.0),
+        VehicleType::Truck => (0.8, 1.5),
+        VehicleType::Bus => (1.0, 1.5),
+        VehicleType::Train => (1.0, 1.0),
+        VehicleType::Bike => (1.0, 1.5),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 0.01,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    fn profile(dist: f64, start: f64, max: f64, end: f64, reaction: f64) -> SpeedProfile {
+        SpeedProfile::new(
+            Distance::meters(dist),
+            Speed::meters_per_second(start),
+            Speed::meters_per_second(max),
+            Speed::meters_per_second(end),
+            VehicleType::Car,
+            Duration::seconds(reaction),
+        )
+    }
+
+    // Distance never goes backwards, and matches the speed over every small step
+    fn check_consistent(p: &SpeedProfile) {
+        let dt = 0.1;
+        let mut t = 0.0;
+        while t + dt <= p.total_time().inner_seconds() {
+            let d1 = p.dist_at(Duration::seconds(t)).inner_meters();
+            let d2 = p.dist_at(Duration::seconds(t + dt)).inner_meters();
+            assert!(d2 >= d1);
+            let v = p.speed_at(Duration::seconds(t + dt / 2.0));
+            assert!(((d2 - d1) / dt - v.inner_meters_per_second()).abs() < 0.2);
+            t += dt;
+        }
+    }
+
+    #[test]
+    fn test_reaches_cruising_speed() {
+        // Cars speed up at 1.5m/s^2 and slow down at 2m/s^2
+        let p = profile(200.0, 0.0, 10.0, 0.0, 0.0);
+        // 6.67s to speed up over 33.3m, 5s to slow down over 25m, and 14.17s to cruise the rest
+        assert_close(p.total_time().inner_seconds(), 25.833);
+        assert_close(p.speed_at(Duration::ZERO).inner_meters_per_second(), 0.0);
+        assert_close(
+            p.speed_at(Duration::seconds(3.0)).inner_meters_per_second(),
+            4.5,
+        );
+        assert_close(
+            p.speed_at(Duration::seconds(10.0))
+                .inner_meters_per_second(),
+            10.0,
+        );
+        assert_close(p.speed_at(p.total_time()).inner_meters_per_second(), 0.0);
+        assert_close(
+            p.dist_at(Duration::seconds(20.0 / 3.0)).inner_meters(),
+            33.333,
+        );
+        assert_close(p.dist_at(p.total_time()).inner_meters(), 200.0);
+        assert_close(
+            p.dist_at(p.total_time() + Duration::seconds(5.0))
+                .inner_meters(),
+            200.0,
+        );
+        check_consistent(&p);
+    }
+
+    #[test]
+    fn test_too_short_to_cruise() {
+        // Speeds up until it has to start slowing down, never reaching 20m/s
+        let p = profile(10.0, 0.0, 20.0, 0.0, 0.0);
+        let peak = (2.0 * 1.5 * 2.0 * 10.0 / 3.5_f64).sqrt();
+        assert_close(
+            p.speed_at(Duration::seconds(peak / 1.5))
+                .inner_meters_per_second(),
+            peak,
+        );
+        assert_close(p.total_time().inner_seconds(), peak / 1.5 + peak / 2.0);
+        assert_close(p.dist_at(p.total_time()).inner_meters(), 10.0);
+        check_consistent(&p);
+    }
+
+    #[test]
+    fn test_cant_stop_in_time() {
+        // Only 10m to slow down from 10m/s, so it finishes still moving
+        let p = profile(10.0, 10.0, 10.0, 0.0, 0.0);
+        let end_speed = (100.0 - 2.0 * 2.0 * 10.0_f64).sqrt();
+        assert_close(
+            p.speed_at(p.total_time()).inner_meters_per_second(),
+            end_speed,
+        );
+        assert_close(p.total_time().inner_seconds(), (10.0 - end_speed) / 2.0);
+        assert_close(p.dist_at(p.total_time()).inner_meters(), 10.0);
+        check_consistent(&p);
+    }
+
+    #[test]
+    fn test_constant_speed() {
+        let p = profile(50.0, 10.0, 10.0, 10.0, 0.0);
+        assert_close(p.total_time().inner_seconds(), 5.0);
+        assert_close(p.dist_at(Duration::seconds(2.0)).inner_meters(), 20.0);
+        assert_close(
+            p.speed_at(Duration::seconds(2.0)).inner_meters_per_second(),
+            10.0,
+        );
+        check_consistent(&p);
+    }
+
+    #[test]
+    fn test_reaction_time() {
+        let without = profile(200.0, 0.0, 10.0, 0.0, 0.0);
+        let p = profile(200.0, 0.0, 10.0, 0.0, 1.0);
+        assert_close(
+            p.total_time().inner_seconds(),
+            without.total_time().inner_seconds() + 1.0,
+        );
+        assert_close(p.dist_at(Duration::seconds(0.5)).inner_meters(), 0.0);
+        assert_close(
+            p.speed_at(Duration::seconds(0.5)).inner_meters_per_second(),
+            0.0,
+        );
+        assert_close(
+            p.dist_at(Duration::seconds(4.0)).inner_meters(),
+            without.dist_at(Duration::seconds(3.0)).inner_meters(),
+        );
+        check_consistent(&p);
+    }
+
+    fn leader(back: f64, profile: Option<SpeedProfile>) -> Leader {
+        Leader {
+            start_time: Time::START_OF_DAY,
+            start_back: Distance::meters(back),
+            profile,
+            leaving: false,
+        }
+    }
+
+    // Plan to cross 200m from a stop, behind the leader
+    fn follow(leader: &Leader) -> SpeedProfile {
+        SpeedProfile::fastest_behind(
+            leader,
+            Distance::ZERO,
+            Time::START_OF_DAY,
+            Speed::meters_per_second(10.0),
+            |max| {
+                SpeedProfile::new(
+                    Distance::meters(200.0),
+                    Speed::ZERO,
+                    max,
+                    Speed::meters_per_second(10.0),
+                    VehicleType::Car,
+                    Duration::ZERO,
+                )
+            },
+        )
+    }
+
+    #[test]
+    fn test_follow_slower_leader() {
+        // The leader's back is 20m ahead, and they're going 5m/s the whole way
+        let slow = leader(20.0, Some(profile(500.0, 5.0, 5.0, 5.0, 0.0)));
+        // Alone, the follower would catch up to them
+        let alone = profile(200.0, 0.0, 10.0, 10.0, 0.0);
+        assert!(!alone.keeps_distance(&slow, Distance::ZERO, Time::START_OF_DAY));
+
+        let p = follow(&slow);
+        assert!(p.keeps_distance(&slow, Distance::ZERO, Time::START_OF_DAY));
+        // It can go a little faster than the leader, since it reaches the end of the lane before
+        // catching up.
+        assert!(p.cruise_speed > 5.0 && p.cruise_speed < 6.0);
+        check_consistent(&p);
+
+        // The gap reflects speed: once both are moving, it's at least FOLLOWING_DISTANCE plus a
+        // second of travel at the leader's 5m/s.
+        for secs in vec![10.0, 20.0, 30.0] {
+            let t = Duration::seconds(secs);
+            let gap = slow.back_at(Time::START_OF_DAY + t) - p.dist_at(t);
+            assert!(gap >= FOLLOWING_DISTANCE + Distance::meters(5.0) - EPSILON_DIST);
+        }
+    }
+
+    #[test]
+    fn test_follow_faster_leader() {
+        // Nothing to slow down for
+        let fast = leader(20.0, Some(profile(500.0, 10.0, 10.0, 10.0, 0.0)));
+        let p = follow(&fast);
+        assert_eq!(p, profile(200.0, 0.0, 10.0, 10.0, 0.0));
+    }
+
+    #[test]
+    fn test_leader_stops_at() {
+        // The leader is stopping 50m along, so a follower pulls up behind there
+        let stopping = leader(30.0, Some(profile(20.0, 5.0, 10.0, 0.0, 0.0)));
+        assert_close(stopping.stops_at().unwrap().inner_meters(), 50.0);
+        // A stopped leader doesn't move at all
+        let stopped = leader(30.0, None);
+        assert_close(stopped.stops_at().unwrap().inner_meters(), 30.0);
+        assert!(!stopped.is_moving());
+        // And one driving past the end of the lane doesn't stop on it
+        let mut leaving = leader(30.0, Some(profile(20.0, 5.0, 10.0, 0.0, 0.0)));
+        leaving.leaving = true;
+        assert!(leaving.stops_at().is_none());
+    }
+
+    #[test]
+    fn test_leader_starts_moving() {
+        // The leader waits a second before moving. A stopped follower reacts to that.
+        let starting = leader(10.0, Some(profile(200.0, 0.0, 10.0, 10.0, 1.0)));
+        assert_eq!(
+            starting.starts_moving(),
+            Some(Time::START_OF_DAY + Duration::seconds(1.0))
+        );
+    }
+}
@@ -1,27 +1,34 @@
-use crate::mechanics::car::{Car, CarState};
+use crate::mechanics::car::{Car, CarState, EmissionsOnHead, Leader};
 use crate::mechanics::Queue;
 use crate::{
     ActionAtEnd, AgentID, AgentProperties, CarID, Command, CreateCar, DistanceInterval,
-    DrawCarInput, Event, IntersectionSimState, ParkedCar, ParkingSimState, ParkingSpot, PersonID,
-    Scheduler, TimeInterval, TransitSimState, TripManager, UnzoomedAgent, Vehicle, WalkingSimState,
-    FOLLOWING_DISTANCE,
+    DrawCarInput, Emissions, Event, IncidentEffect, IntersectionSimState, ParkedCar,
+    ParkingSimState, ParkingSpot, PersonID, RideHailSimState, Scheduler, TimeInterval,
+    TransitSimState, TripManager, TripPhaseType, UnzoomedAgent, Vehicle, VehicleType,
+    WalkingSimState, FOLLOWING_DISTANCE,
 };
 use abstutil::{deserialize_btreemap, serialize_btreemap};
-use geom::{Distance, Duration, PolyLine, Time};
-use map_model::{LaneID, Map, Path, PathStep, Traversable};
+use geom::{Distance, Duration, PolyLine, Speed, Time};
+use map_model::{IntersectionID, LaneID, Map, Path, PathStep, Position, Traversable, TurnID};
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
 
 const TIME_TO_UNPARK_ONSTRET: Duration = Duration::const_seconds(10.0);
 const TIME_TO_PARK_ONSTREET: Duration = Duration::const_seconds(15.0);
 const TIME_TO_UNPARK_OFFSTREET: Duration = Duration::const_seconds(5.0);
 const TIME_TO_PARK_OFFSTREET: Duration = Duration::const_seconds(5.0);
-const TIME_TO_WAIT_AT_STOP: Duration = Duration::const_seconds(10.0);
 
 // TODO Do something else.
 pub(crate) const BLIND_RETRY_TO_CREEP_FORWARDS: Duration = Duration::const_seconds(0.1);
 pub(crate) const BLIND_RETRY_TO_REACH_END_DIST: Duration = Duration::const_seconds(5.0);
 
+// How often drivers reconsider their route, when they're waiting to turn
+const REROUTE_INTERVAL: Duration = Duration::const_seconds(120.0);
+// When rerouting, how much to avoid lanes blocked by an incident
+const INCIDENT_DELAY: Duration = Duration::const_seconds(3600.0);
+// Roughly how long each car queued on a lane adds to the time to get through it
+const DELAY_PER_QUEUED_CAR: Duration = Duration::const_seconds(2.0);
+
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
 pub struct DrivingSimState {
     #[serde(
@@ -37,15 +44,51 @@ pub struct DrivingSimState {
     events: Vec<Event>,
 
     recalc_lanechanging: bool,
+    reroute_drivers: bool,
+    acceleration_profiles: bool,
+
+    // From the incidents happening right now
+    blocked_lanes: BTreeSet<LaneID>,
+    closed_intersections: BTreeSet<IntersectionID>,
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    speed_factors: BTreeMap<LaneID, f64>,
+    // Extra time that drivers expect to lose on each lane because of incidents
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    incident_delays: BTreeMap<LaneID, Duration>,
+    // incident_delays plus the delay from cars queued on each lane, for rerouting. Kept up-to-date
+    // as cars enter and leave lanes.
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    reroute_delays: BTreeMap<LaneID, Duration>,
 }
 
 impl DrivingSimState {
-    pub fn new(map: &Map, recalc_lanechanging: bool) -> DrivingSimState {
+    pub fn new(
+        map: &Map,
+        recalc_lanechanging: bool,
+        reroute_drivers: bool,
+        acceleration_profiles: bool,
+    ) -> DrivingSimState {
         let mut sim = DrivingSimState {
             cars: BTreeMap::new(),
             queues: BTreeMap::new(),
             events: Vec::new(),
             recalc_lanechanging,
+            reroute_drivers,
+            acceleration_profiles,
+            blocked_lanes: BTreeSet::new(),
+            closed_intersections: BTreeSet::new(),
+            speed_factors: BTreeMap::new(),
+            incident_delays: BTreeMap::new(),
+            reroute_delays: BTreeMap::new(),
         };
 
         for l in map.all_lanes() {
@@ -94,6 +137,9 @@ impl DrivingSimState {
                 last_steps: VecDeque::new(),
                 started_at: now,
                 total_blocked_time: Duration::ZERO,
+                last_reroute_check: now,
+                emissions_on_head: EmissionsOnHead::new(now),
+                last_speed: Speed::ZERO,
                 trip_and_person: params.trip_and_person,
             };
             if let Some(p) = params.maybe_parked_car {
@@ -139,7 +185,13 @@ impl DrivingSimState {
                     }
                 }
 
-                car.state = car.crossing_state(params.start_dist, now, map);
+                car.state = car.crossing_state(
+                    params.start_dist,
+                    now,
+                    map,
+                    self.acceleration_profiles,
+                    &self.speed_factors,
+                );
             }
             scheduler.push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
             {
@@ -149,7 +201,9 @@ impl DrivingSimState {
                 // get_idx_to_insert_car does a more detailed check of the current space usage.
                 queue.reserved_length += car.vehicle.length + FOLLOWING_DISTANCE;
             }
+            self.update_reroute_delay(Traversable::Lane(first_lane));
             self.cars.insert(car.vehicle.id, car);
+            self.replan_followers(Traversable::Lane(first_lane), idx, now, map, scheduler);
             return true;
         }
         false
@@ -165,6 +219,7 @@ impl DrivingSimState {
         trips: &mut TripManager,
         scheduler: &mut Scheduler,
         transit: &mut TransitSimState,
+        ridehail: &mut RideHailSimState,
         walking: &mut WalkingSimState,
     ) {
         // State transitions for this car:
@@ -184,6 +239,14 @@ impl DrivingSimState {
         // Why is it safe to process cars in any order, rather than making sure to follow the order
         // of queues? Because of the invariant that distances should never suddenly jump when a car
         // has entered/exiting a queue.
+
+        // With acceleration profiles, anybody following this car replans if it starts or stops
+        // moving.
+        let before = {
+            let car = &self.cars[&id];
+            (car.router.head(), std::mem::discriminant(&car.state))
+        };
+
         // This car might have reached the router's end distance, but maybe not -- might
         // actually be stuck behind other cars. We have to calculate the distances right now to
         // be sure.
@@ -208,6 +271,7 @@ impl DrivingSimState {
                 parking,
                 intersections,
                 transit,
+                ridehail,
                 scheduler,
             );
             self.cars.insert(id, car);
@@ -227,13 +291,111 @@ impl DrivingSimState {
             let mut car = self.cars.remove(&id).unwrap();
             // Responsibility of update_car_with_distances to manage scheduling stuff!
             if self.update_car_with_distances(
-                &mut car, &dists, idx, now, map, parking, trips, scheduler, transit, walking,
+                &mut car, &dists, idx, now, map, parking, trips, scheduler, transit, ridehail,
+                walking,
             ) {
                 self.cars.insert(id, car);
             } else {
                 self.delete_car(&mut car, dists, idx, now, map, scheduler, intersections);
             }
         }
+
+        if let Some(car) = self.cars.get(&id) {
+            let (from, state) = before;
+            let on = car.router.head();
+            let changed = on != from || std::mem::discriminant(&car.state) != state;
+            if on != from {
+                // Whoever was behind us follows us out
+                self.replan_followers(from, 0, now, map, scheduler);
+            }
+            if changed {
+                let idx = self.queues[&on].cars.iter().position(|c| *c == id).unwrap();
+                self.replan_followers(on, idx, now, map, scheduler);
+            }
+        }
+    }
+
+    // With acceleration profiles, drivers plan their speed around the vehicle in front of them.
+    // Replan everybody on this queue from the car at idx back, stopping at the first follower who
+    // isn't moving or waiting to.
+    fn replan_followers(
+        &mut self,
+        on: Traversable,
+        start_idx: usize,
+        now: Time,
+        map: &Map,
+        scheduler: &mut Scheduler,
+    ) {
+        if !self.acceleration_profiles {
+            return;
+        }
+        let dists = self.queues[&on].get_car_positions(now, &self.cars, &self.queues);
+        for idx in start_idx..dists.len() {
+            let (id, dist) = dists[idx];
+            let leader = if idx == 0 {
+                self.queues[&on].laggy_head.map(|l| {
+                    Leader::new(&self.cars[&l], self.laggy_head_front(on, l, now), now, true)
+                })
+            } else {
+                let (l, front) = dists[idx - 1];
+                Some(Leader::new(&self.cars[&l], front, now, false))
+            };
+            let car = &self.cars[&id];
+            let state = match car.state {
+                CarState::Crossing(_, _, Some(_)) => {
+                    Some(car.following_state(dist, now, map, &self.speed_factors, leader.as_ref()))
+                }
+                // Start moving once the leader does
+                CarState::Queued { .. }
+                    if leader.as_ref().map(|l| l.is_moving()).unwrap_or(true) =>
+                {
+                    let state =
+                        car.following_state(dist, now, map, &self.speed_factors, leader.as_ref());
+                    // If there's nowhere to go yet, stay queued instead of finishing right away
+                    // and waking up again.
+                    if state.get_end_time() > now {
+                        Some(state)
+                    } else {
+                        None
+                    }
+                }
+                _ => None,
+            };
+            let state = match state {
+                Some(state) => state,
+                None => {
+                    if idx == start_idx {
+                        continue;
+                    }
+                    break;
+                }
+            };
+
+            let car = self.cars.get_mut(&id).unwrap();
+            if let CarState::Queued { blocked_since } = car.state {
+                car.total_blocked_time += now - blocked_since;
+            }
+            car.finish_crossing(now);
+            car.state = state;
+            scheduler.update(car.state.get_end_time(), Command::UpdateCar(id));
+        }
+    }
+
+    // Where a laggy head's front is, measured along a queue they're leaving
+    fn laggy_head_front(&self, on: Traversable, id: CarID, now: Time) -> Distance {
+        let car = &self.cars[&id];
+        let (_, mut dist) = self.queues[&car.router.head()]
+            .get_car_positions(now, &self.cars, &self.queues)
+            .into_iter()
+            .find(|(c, _)| *c == id)
+            .unwrap();
+        for step in &car.last_steps {
+            if *step == on {
+                break;
+            }
+            dist += self.queues[step].geom_len;
+        }
+        self.queues[&on].geom_len + dist
     }
 
     // If this returns true, we need to immediately run update_car_with_distances. If we don't,
@@ -247,10 +409,13 @@ impl DrivingSimState {
         parking: &mut ParkingSimState,
         intersections: &mut IntersectionSimState,
         transit: &mut TransitSimState,
+        ridehail: &mut RideHailSimState,
         scheduler: &mut Scheduler,
     ) -> bool {
         match car.state {
-            CarState::Crossing(_, _) => {
+            CarState::Crossing(_, _, _) => {
+                car.last_speed = car.current_speed(now).0;
+                car.finish_crossing(now);
                 car.state = CarState::Queued { blocked_since: now };
                 if car.router.last_step() {
                     // Immediately run update_car_with_distances.
@@ -260,6 +425,15 @@ impl DrivingSimState {
                 if queue.cars[0] == car.vehicle.id && queue.laggy_head.is_none() {
                     // Want to re-run, but no urgency about it happening immediately.
                     car.state = CarState::WaitingToAdvance { blocked_since: now };
+                    if self.reroute_drivers && now - car.last_reroute_check >= REROUTE_INTERVAL {
+                        car.last_reroute_check = now;
+                        car.router.maybe_reroute(
+                            &car.vehicle,
+                            &self.reroute_delays,
+                            map,
+                            &mut self.events,
+                        );
+                    }
                     if self.recalc_lanechanging {
                         car.router.opportunistically_lanechange(&self.queues, map);
                     }
@@ -281,14 +455,41 @@ impl DrivingSimState {
                         &mut self.events,
                     );
                 }
-                car.state = car.crossing_state(front, now, map);
+                car.state = car.crossing_state(
+                    front,
+                    now,
+                    map,
+                    self.acceleration_profiles,
+                    &self.speed_factors,
+                );
                 scheduler.push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
             }
             CarState::IdlingAtStop(dist, _) => {
-                car.router = transit.bus_departed_from_stop(car.vehicle.id, map);
+                if car.vehicle.vehicle_type == VehicleType::Truck {
+                    // The route to the next stop was already set after double-parking
+                    let (trip, person) = car.trip_and_person.unwrap();
+                    self.events.push(Event::TripPhaseStarting(
+                        trip,
+                        person,
+                        None,
+                        TripPhaseType::Driving,
+                    ));
+                } else {
+                    car.router = if car.vehicle.vehicle_type.is_transit() {
+                        transit.bus_departed_from_stop(car.vehicle.id, map)
+                    } else {
+                        ridehail.vehicle_departed_curb(car.vehicle.id, map)
+                    };
+                }
                 self.events
                     .push(Event::PathAmended(car.router.get_path().clone()));
-                car.state = car.crossing_state(dist, now, map);
+                car.state = car.crossing_state(
+                    dist,
+                    now,
+                    map,
+                    self.acceleration_profiles,
+                    &self.speed_factors,
+                );
                 scheduler.push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
 
                 // Update our follower, so they know we stopped idling.
@@ -311,6 +512,8 @@ impl DrivingSimState {
                                     dist - car.vehicle.length - FOLLOWING_DISTANCE,
                                     now,
                                     map,
+                                    self.acceleration_profiles,
+                                    &self.speed_factors,
                                 );
                                 scheduler.update(
                                     follower.state.get_end_time(),
@@ -322,7 +525,7 @@ impl DrivingSimState {
                         // They weren't blocked. Note that there's no way the Crossing state could
                         // jump forwards here; the leader is still in front
                         // of them.
-                        CarState::Crossing(_, _)
+                        CarState::Crossing(_, _, _)
                         | CarState::Unparking(_, _, _)
                         | CarState::Parking(_, _, _)
                         | CarState::IdlingAtStop(_, _) => {}
@@ -337,6 +540,30 @@ impl DrivingSimState {
                 assert!(from != goto);
 
                 if let Traversable::Turn(t) = goto {
+                    // Check an entire uber-turn before starting it. Once inside, finish it rather
+                    // than stopping in the middle of the intersection cluster.
+                    let blocked = if car.router.get_path().currently_inside_ut().is_some() {
+                        false
+                    } else if let Some(ut) = car.router.get_path().about_to_start_ut() {
+                        ut.path.iter().any(|t| self.blocked_by_incident(*t))
+                    } else {
+                        self.blocked_by_incident(t)
+                    };
+                    if blocked {
+                        intersections.cancel_request(AgentID::Car(car.vehicle.id), t);
+                        if car.router.maybe_reroute(
+                            &car.vehicle,
+                            &self.reroute_delays,
+                            map,
+                            &mut self.events,
+                        ) {
+                            // Try the new route
+                            scheduler.push(now, Command::UpdateCar(car.vehicle.id));
+                        }
+                        // Otherwise, we'll get woken up when the incident is cleared.
+                        return false;
+                    }
+
                     let mut speed = goto.speed_limit(map);
                     if let Some(s) = car.vehicle.max_speed {
                         speed = speed.min(s);
@@ -360,6 +587,8 @@ impl DrivingSimState {
                     assert_eq!(queue.cars.pop_front().unwrap(), car.vehicle.id);
                     queue.laggy_head = Some(car.vehicle.id);
                 }
+                self.update_reroute_delay(from);
+                self.record_emissions(car, now);
 
                 // We do NOT need to update the follower. If they were Queued, they'll remain that
                 // way, until laggy_head is None.
@@ -372,7 +601,13 @@ impl DrivingSimState {
                     &mut self.events,
                 );
                 car.total_blocked_time += now - blocked_since;
-                car.state = car.crossing_state(Distance::ZERO, now, map);
+                car.state = car.crossing_state(
+                    Distance::ZERO,
+                    now,
+                    map,
+                    self.acceleration_profiles,
+                    &self.speed_factors,
+                );
                 scheduler.push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
                 self.events.push(Event::AgentEntersTraversable(
                     AgentID::Car(car.vehicle.id),
@@ -399,6 +634,8 @@ impl DrivingSimState {
                         ),
                         now,
                         map,
+                        self.acceleration_profiles,
+                        &self.speed_factors,
                     )
                     .get_end_time(),
                     Command::UpdateLaggyHead(car.vehicle.id),
@@ -409,6 +646,7 @@ impl DrivingSimState {
                     .unwrap()
                     .cars
                     .push_back(car.vehicle.id);
+                self.update_reroute_delay(goto);
             }
             CarState::Parking(_, _, _) => unreachable!(),
         }
@@ -427,12 +665,13 @@ impl DrivingSimState {
         trips: &mut TripManager,
         scheduler: &mut Scheduler,
         transit: &mut TransitSimState,
+        ridehail: &mut RideHailSimState,
         walking: &mut WalkingSimState,
     ) -> bool {
         let our_dist = dists[idx].1;
 
         match car.state {
-            CarState::Crossing(_, _)
+            CarState::Crossing(_, _, _)
             | CarState::Unparking(_, _, _)
             | CarState::IdlingAtStop(_, _)
             | CarState::WaitingToAdvance { .. } => unreachable!(),
@@ -495,7 +734,13 @@ impl DrivingSimState {
                     }
                     Some(ActionAtEnd::GotoLaneEnd) => {
                         car.total_blocked_time += now - blocked_since;
-                        car.state = car.crossing_state(our_dist, now, map);
+                        car.state = car.crossing_state(
+                            our_dist,
+                            now,
+                            map,
+                            self.acceleration_profiles,
+                            &self.speed_factors,
+                        );
                         scheduler
                             .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
                         true
@@ -508,23 +753,25 @@ impl DrivingSimState {
                             bike_rack,
                             car.total_blocked_time,
                             map,
+                            parking,
                             scheduler,
                         );
                         false
                     }
                     Some(ActionAtEnd::BusAtStop) => {
                         car.total_blocked_time += now - blocked_since;
-                        if transit.bus_arrived_at_stop(
+                        if let Some(dwell) = transit.bus_arrived_at_stop(
                             now,
                             car.vehicle.id,
                             trips,
                             walking,
+                            parking,
                             scheduler,
                             map,
                         ) {
                             car.state = CarState::IdlingAtStop(
                                 our_dist,
-                                TimeInterval::new(now, now + TIME_TO_WAIT_AT_STOP),
+                                TimeInterval::new(now, now + dwell),
                             );
                             scheduler
                                 .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
@@ -534,6 +781,51 @@ impl DrivingSimState {
                             false
                         }
                     }
+                    Some(ActionAtEnd::RideHailAtCurb) => {
+                        car.total_blocked_time += now - blocked_since;
+                        if let Some(dwell) = ridehail.vehicle_at_curb(
+                            now,
+                            car.vehicle.id,
+                            trips,
+                            parking,
+                            scheduler,
+                            map,
+                        ) {
+                            car.state = CarState::IdlingAtStop(
+                                our_dist,
+                                TimeInterval::new(now, now + dwell),
+                            );
+                            scheduler
+                                .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
+                            true
+                        } else {
+                            // Pulling over after dropping off
+                            false
+                        }
+                    }
+                    Some(ActionAtEnd::DoubleParkToDeliver) => {
+                        car.total_blocked_time += now - blocked_since;
+                        if let Some((dwell, router)) = trips.truck_double_parked(
+                            now,
+                            car.vehicle.id,
+                            Position::new(car.router.head().as_lane(), our_dist),
+                            map,
+                            parking,
+                            scheduler,
+                        ) {
+                            car.router = router;
+                            car.state = CarState::IdlingAtStop(
+                                our_dist,
+                                TimeInterval::new(now, now + dwell),
+                            );
+                            scheduler
+                                .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
+                            true
+                        } else {
+                            // The trip was aborted
+                            false
+                        }
+                    }
                     None => {
                         scheduler.push(
                             now + BLIND_RETRY_TO_REACH_END_DIST,
@@ -623,6 +915,7 @@ impl DrivingSimState {
         scheduler: &mut Scheduler,
         intersections: &mut IntersectionSimState,
     ) {
+        self.record_emissions(car, now);
         {
             let queue = self.queues.get_mut(&car.router.head()).unwrap();
             assert_eq!(queue.cars.remove(idx).unwrap(), car.vehicle.id);
@@ -634,6 +927,7 @@ impl DrivingSimState {
             };
             intersections.space_freed(now, i, scheduler, map);
         }
+        self.update_reroute_delay(car.router.head());
 
         intersections.vehicle_gone(car.vehicle.id);
 
@@ -661,17 +955,30 @@ impl DrivingSimState {
                 CarState::Queued { blocked_since } => {
                     // Prevent them from jumping forwards.
                     follower.total_blocked_time += now - blocked_since;
-                    follower.state = follower.crossing_state(follower_dist, now, map);
+                    follower.state = follower.crossing_state(
+                        follower_dist,
+                        now,
+                        map,
+                        self.acceleration_profiles,
+                        &self.speed_factors,
+                    );
                     scheduler.update(
                         follower.state.get_end_time(),
                         Command::UpdateCar(follower_id),
                     );
                 }
-                CarState::Crossing(_, _) => {
+                CarState::Crossing(_, _, _) => {
                     // If the follower was still Crossing, they might not've been blocked
                     // by leader yet. In that case, recalculating their Crossing state is a
                     // no-op.
-                    follower.state = follower.crossing_state(follower_dist, now, map);
+                    follower.finish_crossing(now);
+                    follower.state = follower.crossing_state(
+                        follower_dist,
+                        now,
+                        map,
+                        self.acceleration_profiles,
+                        &self.speed_factors,
+                    );
                     scheduler.update(
                         follower.state.get_end_time(),
                         Command::UpdateCar(follower_id),
@@ -683,6 +990,21 @@ impl DrivingSimState {
                 | CarState::IdlingAtStop(_, _) => {}
                 CarState::WaitingToAdvance { .. } => unreachable!(),
             }
+            self.replan_followers(car.router.head(), idx, now, map, scheduler);
+        }
+    }
+
+    // Everything the car emitted on its current lane or turn
+    fn record_emissions(&mut self, car: &mut Car, now: Time) {
+        let on = car.router.head();
+        let emissions = car.leave_head(now);
+        if emissions != Emissions::ZERO {
+            self.events.push(Event::VehicleEmissions(
+                car.vehicle.id,
+                car.trip_and_person.map(|(t, _)| t),
+                on,
+                emissions,
+            ));
         }
     }
 
@@ -740,6 +1062,8 @@ impl DrivingSimState {
                     ),
                     now,
                     map,
+                    self.acceleration_profiles,
+                    &self.speed_factors,
                 )
                 .get_end_time();
             // Sometimes due to rounding, retry_at will be exactly time, but we really need to
@@ -805,6 +1129,17 @@ impl DrivingSimState {
                                 // gets out of the way. So
                                 // immediately promote them to WaitingToAdvance.
                                 follower.state = CarState::WaitingToAdvance { blocked_since };
+                                if self.reroute_drivers
+                                    && now - follower.last_reroute_check >= REROUTE_INTERVAL
+                                {
+                                    follower.last_reroute_check = now;
+                                    follower.router.maybe_reroute(
+                                        &follower.vehicle,
+                                        &self.reroute_delays,
+                                        map,
+                                        &mut self.events,
+                                    );
+                                }
                                 if self.recalc_lanechanging {
                                     follower
                                         .router
@@ -817,12 +1152,13 @@ impl DrivingSimState {
                         // They weren't blocked. Note that there's no way the Crossing state
                         // could jump forwards here; the leader
                         // vanished from the end of the traversable.
-                        CarState::Crossing(_, _)
+                        CarState::Crossing(_, _, _)
                         | CarState::Unparking(_, _, _)
                         | CarState::Parking(_, _, _)
                         | CarState::IdlingAtStop(_, _) => {}
                     }
                 }
+                self.replan_followers(on, 0, now, map, scheduler);
             } else {
                 // Only the last step we cleared could possibly have cars. Any intermediates,
                 // this car was previously completely blocking them.
@@ -863,6 +1199,26 @@ impl DrivingSimState {
         self.cars.contains_key(&id)
     }
 
+    // Like a loop detector covering the last stretch of a lane before the intersection. Is any
+    // vehicle's front currently in that zone?
+    pub fn vehicle_approaching(
+        &self,
+        now: Time,
+        lane: LaneID,
+        detection_zone: Distance,
+        map: &Map,
+    ) -> bool {
+        if let Some(queue) = self.queues.get(&Traversable::Lane(lane)) {
+            let start = map.get_l(lane).length() - detection_zone;
+            queue
+                .get_car_positions(now, &self.cars, &self.queues)
+                .into_iter()
+                .any(|(_, dist)| dist >= start)
+        } else {
+            false
+        }
+    }
+
     pub fn get_all_draw_cars(
         &self,
         now: Time,
@@ -1051,4 +1407,150 @@ impl DrivingSimState {
     pub fn collect_events(&mut self) -> Vec<Event> {
         std::mem::replace(&mut self.events, Vec::new())
     }
+
+    // Called whenever an incident starts or ends, with everything happening now. Drivers headed
+    // into trouble look for another way, and anybody stuck waiting gets to try again.
+    pub fn set_incidents(
+        &mut self,
+        active: Vec<&IncidentEffect>,
+        now: Time,
+        map: &Map,
+        scheduler: &mut Scheduler,
+    ) {
+        let mut affected_lanes = self.lanes_near_incidents(map);
+
+        self.blocked_lanes.clear();
+        self.closed_intersections.clear();
+        self.speed_factors.clear();
+        for effect in active {
+            match effect {
+                IncidentEffect::BlockLane(l) => {
+                    self.blocked_lanes.insert(*l);
+                }
+                IncidentEffect::ReduceSpeed(l, factor) => {
+                    let f = self.speed_factors.entry(*l).or_insert(1.0);
+                    *f = f.min(*factor);
+                }
+                IncidentEffect::CloseIntersection(i) => {
+                    self.closed_intersections.insert(*i);
+                }
+            }
+        }
+
+        let old_delays = std::mem::replace(&mut self.incident_delays, BTreeMap::new());
+        for (l, factor) in &self.speed_factors {
+            let lane = Traversable::Lane(*l);
+            let usual = lane.length(map) / lane.speed_limit(map);
+            self.incident_delays
+                .insert(*l, usual * (1.0 / factor - 1.0));
+        }
+        for l in &self.blocked_lanes {
+            self.incident_delays.insert(*l, INCIDENT_DELAY);
+        }
+        for i in &self.closed_intersections {
+            for l in &map.get_i(*i).incoming_lanes {
+                // Sidewalks don't have a queue to keep track of
+                if map.get_l(*l).lane_type.is_for_moving_vehicles() {
+                    self.incident_delays.insert(*l, INCIDENT_DELAY);
+                }
+            }
+        }
+        let mut changed: BTreeSet<LaneID> = old_delays.into_iter().map(|(l, _)| l).collect();
+        changed.extend(self.incident_delays.keys().cloned());
+        for l in changed {
+            self.update_reroute_delay(Traversable::Lane(l));
+        }
+        affected_lanes.extend(self.lanes_near_incidents(map));
+
+        let blocked_lanes = &self.blocked_lanes;
+        let closed_intersections = &self.closed_intersections;
+        for car in self.cars.values_mut() {
+            let on_affected_lane = match car.router.head() {
+                Traversable::Lane(l) => affected_lanes.contains(&l),
+                Traversable::Turn(_) => false,
+            };
+            match car.state {
+                // Maybe they're stuck, or maybe they can go a different way now
+                CarState::WaitingToAdvance { .. } => {
+                    // An incident anywhere along an uber-turn might affect them
+                    if on_affected_lane || car.router.get_path().about_to_start_ut().is_some() {
+                        scheduler.update(now, Command::UpdateCar(car.vehicle.id));
+                    }
+                }
+                // Rerouting here is safe; they haven't asked to start a turn yet. Cars in the
+                // middle of a turn will notice once they reach the next lane.
+                CarState::Crossing(_, _, _) | CarState::Queued { .. }
+                    if car.router.head().maybe_lane().is_some() =>
+                {
+                    let headed_into_trouble =
+                        car.router.get_path().get_steps().iter().any(|step| {
+                            match step.as_traversable() {
+                                Traversable::Lane(l) => blocked_lanes.contains(&l),
+                                Traversable::Turn(t) => closed_intersections.contains(&t.parent),
+                            }
+                        });
+                    if headed_into_trouble {
+                        car.router.maybe_reroute(
+                            &car.vehicle,
+                            &self.reroute_delays,
+                            map,
+                            &mut self.events,
+                        );
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn blocked_by_incident(&self, t: TurnID) -> bool {
+        self.closed_intersections.contains(&t.parent)
+            || self.blocked_lanes.contains(&t.src)
+            || self.blocked_lanes.contains(&t.dst)
+    }
+
+    // Cars can't appear on a blocked lane or pull out of a spot along one until the incident is
+    // cleared.
+    pub fn spawn_blocked_by_incident(&self, params: &CreateCar) -> bool {
+        if self.blocked_lanes.contains(&params.router.head().as_lane()) {
+            return true;
+        }
+        match params.maybe_parked_car.as_ref().map(|p| &p.spot) {
+            Some(ParkingSpot::Onstreet(l, _)) => self.blocked_lanes.contains(l),
+            _ => false,
+        }
+    }
+
+    // Where might a car be waiting to turn because of an incident?
+    fn lanes_near_incidents(&self, map: &Map) -> BTreeSet<LaneID> {
+        let mut lanes = BTreeSet::new();
+        for l in &self.blocked_lanes {
+            lanes.insert(*l);
+            lanes.extend(map.get_i(map.get_l(*l).src_i).incoming_lanes.clone());
+        }
+        for i in &self.closed_intersections {
+            lanes.extend(map.get_i(*i).incoming_lanes.clone());
+        }
+        lanes
+    }
+
+    // Call whenever the cars on a queue or the incident delays change.
+    fn update_reroute_delay(&mut self, on: Traversable) {
+        let l = match on {
+            Traversable::Lane(l) => l,
+            Traversable::Turn(_) => {
+                return;
+            }
+        };
+        let num_cars = self.queues[&on].cars.len();
+        let incident = self.incident_delays.get(&l).cloned();
+        if num_cars == 0 && incident.is_none() {
+            self.reroute_delays.remove(&l);
+        } else {
+            self.reroute_delays.insert(
+                l,
+                incident.unwrap_or(Duration::ZERO) + (num_cars as f64) * DELAY_PER_QUEUED_CAR,
+            );
+        }
+    }
 }
@@ -1,17 +1,23 @@
-use crate::mechanics::car::Car;
+use crate::mechanics::car::{Car, CarState};
 use crate::mechanics::Queue;
-use crate::{AgentID, AlertLocation, CarID, Command, Event, Scheduler, Speed};
+use crate::{AgentID, AlertLocation, CarID, Command, DrivingSimState, Event, Scheduler, Speed};
 use abstutil::{deserialize_btreemap, retain_btreeset, serialize_btreemap};
-use geom::{Duration, Time};
+use geom::{Distance, Duration, Time};
 use map_model::{
     ControlStopSign, ControlTrafficSignal, IntersectionID, LaneID, Map, PhaseType, RoadID,
-    Traversable, TurnID, TurnPriority, TurnType,
+    Roundabout, Traversable, TurnID, TurnPriority, TurnType,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet, HashSet};
 
 const WAIT_AT_STOP_SIGN: Duration = Duration::const_seconds(0.5);
 const WAIT_BEFORE_YIELD_AT_TRAFFIC_SIGNAL: Duration = Duration::const_seconds(0.2);
+// Vehicles entering a roundabout wait for at least this long of a gap in circulating traffic, no
+// matter the turn type. Roughly the critical headway at single-lane roundabouts from the Highway
+// Capacity Manual.
+const ROUNDABOUT_CRITICAL_GAP: Duration = Duration::const_seconds(5.2);
+// How far back from the stop line actuated signals detect approaching vehicles
+const ACTUATED_DETECTION_ZONE: Distance = Distance::const_meters(30.0);
 
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
 pub struct IntersectionSimState {
@@ -19,6 +25,8 @@ pub struct IntersectionSimState {
     use_freeform_policy_everywhere: bool,
     dont_block_the_box: bool,
     break_turn_conflict_cycles: bool,
+    // If None, yield turns at stop signs go as soon as nothing conflicting is accepted
+    critical_gaps: Option<CriticalGaps>,
     // (x, y) means x is blocked by y. It's a many-to-many relationship. TODO Better data
     // structure.
     blocked_by: BTreeSet<(CarID, CarID)>,
@@ -41,7 +49,10 @@ struct State {
     reserved: BTreeSet<Request>,
 
     // Only relevant for traffic signals
+    current_plan: usize,
     current_phase: usize,
+    phase_started_at: Time,
+    // For actuated phases, this is just the next time to check the detectors.
     phase_ends_at: Time,
 }
 
@@ -51,6 +62,36 @@ struct Request {
     turn: TurnID,
 }
 
+// At a stop sign, a vehicle making a yield turn only goes if the next vehicle on a conflicting
+// protected turn will arrive at least this long from now. The defaults are roughly the critical
+// headways for passenger cars at two-way stops from the Highway Capacity Manual.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CriticalGaps {
+    pub left: Duration,
+    pub straight: Duration,
+    pub right: Duration,
+}
+
+impl CriticalGaps {
+    pub fn new() -> CriticalGaps {
+        CriticalGaps {
+            left: Duration::seconds(7.1),
+            straight: Duration::seconds(6.5),
+            right: Duration::seconds(6.2),
+        }
+    }
+
+    fn get(&self, turn_type: TurnType) -> Duration {
+        match turn_type {
+            TurnType::Left => self.left,
+            TurnType::Right => self.right,
+            TurnType::Straight => self.straight,
+            // Pedestrians don't yield like this
+            TurnType::Crosswalk | TurnType::SharedSidewalkCorner => Duration::ZERO,
+        }
+    }
+}
+
 impl IntersectionSimState {
     pub fn new(
         map: &Map,
@@ -58,12 +99,14 @@ impl IntersectionSimState {
         use_freeform_policy_everywhere: bool,
         dont_block_the_box: bool,
         break_turn_conflict_cycles: bool,
+        critical_gaps: Option<CriticalGaps>,
     ) -> IntersectionSimState {
         let mut sim = IntersectionSimState {
             state: BTreeMap::new(),
             use_freeform_policy_everywhere,
             dont_block_the_box,
             break_turn_conflict_cycles,
+            critical_gaps,
             blocked_by: BTreeSet::new(),
             events: Vec::new(),
         };
@@ -75,12 +118,14 @@ impl IntersectionSimState {
                     accepted: BTreeSet::new(),
                     waiting: BTreeMap::new(),
                     reserved: BTreeSet::new(),
+                    current_plan: 0,
                     current_phase: 0,
+                    phase_started_at: Time::START_OF_DAY,
                     phase_ends_at: Time::START_OF_DAY,
                 },
             );
             if i.is_traffic_signal() && !use_freeform_policy_everywhere {
-                sim.update_intersection(Time::START_OF_DAY, i.id, map, scheduler);
+                sim.start_phase(Time::START_OF_DAY, i.id, map, scheduler);
             }
         }
         sim
@@ -166,7 +211,8 @@ impl IntersectionSimState {
                 protected.push(req);
             }
         } else if let Some(ref signal) = map.maybe_get_traffic_signal(i) {
-            let phase = &signal.phases[self.state[&i].current_phase];
+            let state = &self.state[&i];
+            let phase = &signal.plan_phases(state.current_plan)[state.current_phase];
             for (req, _) in all {
                 match phase.get_priority_of_turn(req.turn, signal) {
                     TurnPriority::Protected => {
@@ -188,6 +234,14 @@ impl IntersectionSimState {
                     yielding.push(req);
                 }
             }
+        } else if let Some(ref roundabout) = map.maybe_get_roundabout(i) {
+            for (req, _) in all {
+                if roundabout.get_priority(req.turn, map) == TurnPriority::Protected {
+                    protected.push(req);
+                } else {
+                    yielding.push(req);
+                }
+            }
         } else {
             assert!(map.get_i(i).is_border());
         };
@@ -214,45 +268,94 @@ impl IntersectionSimState {
         id: IntersectionID,
         map: &Map,
         scheduler: &mut Scheduler,
+        driving: &DrivingSimState,
     ) {
         let state = self.state.get_mut(&id).unwrap();
         let signal = map.get_traffic_signal(id);
 
         // Switch to a new phase?
-        if now != Time::START_OF_DAY {
-            assert_eq!(now, state.phase_ends_at);
-            let old_phase = &signal.phases[state.current_phase];
-            match old_phase.phase_type {
-                PhaseType::Fixed(_) => {
+        assert_eq!(now, state.phase_ends_at);
+        let old_phase = &signal.plan_phases(state.current_plan)[state.current_phase];
+        match old_phase.phase_type {
+            PhaseType::Fixed(_) => {
+                state.current_phase += 1;
+            }
+            PhaseType::Adaptive(_) => {
+                // TODO Make a better policy here. For now, if there's _anyone_ waiting to
+                // start a protected turn, repeat this phase for the full duration. Note that
+                // "waiting" is only defined as "at the end of the lane, ready to start the
+                // turn." If a vehicle/ped is a second away from the intersection, this won't
+                // detect that. Actuated phases use detectors on the Queues instead.
+                if state.waiting.keys().all(|req| {
+                    old_phase.get_priority_of_turn(req.turn, signal) != TurnPriority::Protected
+                }) {
                     state.current_phase += 1;
-                }
-                PhaseType::Adaptive(_) => {
-                    // TODO Make a better policy here. For now, if there's _anyone_ waiting to
-                    // start a protected turn, repeat this phase for the full duration. Note that
-                    // "waiting" is only defined as "at the end of the lane, ready to start the
-                    // turn." If a vehicle/ped is a second away from the intersection, this won't
-                    // detect that. We could pass in all of the Queues here and use that to count
-                    // all incoming agents, even ones a little farther away.
-                    if state.waiting.keys().all(|req| {
-                        old_phase.get_priority_of_turn(req.turn, signal) != TurnPriority::Protected
-                    }) {
-                        state.current_phase += 1;
-                        self.events.push(Event::Alert(
-                            AlertLocation::Intersection(id),
-                            "Repeating an adaptive phase".to_string(),
-                        ));
-                    }
+                    self.events.push(Event::Alert(
+                        AlertLocation::Intersection(id),
+                        "Repeating an adaptive phase".to_string(),
+                    ));
                 }
             }
-            if state.current_phase == signal.phases.len() {
-                state.current_phase = 0;
+            PhaseType::Actuated(ref timing) => {
+                // Stay green while vehicles keep arriving on the protected approaches, but not
+                // past max_green. Detectors are only checked every gap_out, so a vehicle has to
+                // show up in each window to keep extending the phase.
+                let max_ends_at = state.phase_started_at + timing.max_green;
+                if now < max_ends_at
+                    && old_phase
+                        .protected_groups
+                        .iter()
+                        .map(|g| &signal.turn_groups[g])
+                        .filter(|g| g.turn_type != TurnType::Crosswalk)
+                        .flat_map(|g| g.members.iter())
+                        .any(|t| {
+                            driving.vehicle_approaching(now, t.src, ACTUATED_DETECTION_ZONE, map)
+                        })
+                {
+                    state.phase_ends_at = (now + timing.gap_out).min(max_ends_at);
+                    scheduler.push(state.phase_ends_at, Command::UpdateIntersection(id));
+                    return;
+                }
+                state.current_phase += 1;
             }
         }
+        if state.current_phase == signal.plan_phases(state.current_plan).len() {
+            state.current_phase = 0;
+        }
 
-        state.phase_ends_at = now
-            + signal.phases[state.current_phase]
-                .phase_type
-                .simple_duration();
+        self.start_phase(now, id, map, scheduler);
+    }
+
+    fn start_phase(&mut self, now: Time, id: IntersectionID, map: &Map, scheduler: &mut Scheduler) {
+        let state = self.state.get_mut(&id).unwrap();
+        let signal = map.get_traffic_signal(id);
+
+        // Switch to a different timing plan? This only happens between phases. Like at the start
+        // of the day, the new plan picks up wherever its offset says it should be in the cycle.
+        let plan = signal.plan_at(now);
+        if now == Time::START_OF_DAY || plan != state.current_plan {
+            state.current_plan = plan;
+            let (phase, into_phase) = phase_at_offset(signal, plan, now);
+            let phases = signal.plan_phases(plan);
+            state.current_phase = phase;
+            // The real start might've been before midnight; it's only used for actuated phases and
+            // pedestrian timing.
+            state.phase_started_at = now.clamped_sub(into_phase);
+            // An actuated phase might already be past min_green. Then it's up to the detectors
+            // whether it keeps going, so check them right away.
+            let initial = initial_duration(&phases[phase].phase_type);
+            state.phase_ends_at = if into_phase < initial {
+                now + initial - into_phase
+            } else {
+                now
+            };
+        } else {
+            state.phase_started_at = now;
+            state.phase_ends_at = now
+                + initial_duration(
+                    &signal.plan_phases(state.current_plan)[state.current_phase].phase_type,
+                );
+        }
         scheduler.push(state.phase_ends_at, Command::UpdateIntersection(id));
         self.wakeup_waiting(now, id, scheduler, map);
     }
@@ -316,7 +419,9 @@ impl IntersectionSimState {
         } else if let Some(ref signal) = map.maybe_get_traffic_signal(turn.parent) {
             self.traffic_signal_policy(&req, map, signal, speed, now, Some(scheduler))
         } else if let Some(ref sign) = map.maybe_get_stop_sign(turn.parent) {
-            self.stop_sign_policy(&req, map, sign, now, scheduler)
+            self.stop_sign_policy(&req, map, sign, now, scheduler, readonly_pair)
+        } else if let Some(ref roundabout) = map.maybe_get_roundabout(turn.parent) {
+            self.roundabout_policy(&req, map, roundabout, now, scheduler, readonly_pair)
         } else {
             unreachable!()
         };
@@ -411,6 +516,8 @@ impl IntersectionSimState {
             println!("{}", abstutil::to_json(sign));
         } else if let Some(ref signal) = map.maybe_get_traffic_signal(id) {
             println!("{}", abstutil::to_json(signal));
+        } else if let Some(ref roundabout) = map.maybe_get_roundabout(id) {
+            println!("{}", abstutil::to_json(roundabout));
         } else {
             println!("Border");
         }
@@ -507,10 +614,19 @@ impl IntersectionSimState {
         (state.current_phase, state.phase_ends_at - now)
     }
 
+    // Which of the signal's timing plans is running. See ControlTrafficSignal::plan_at.
+    pub fn current_plan(&self, i: IntersectionID) -> usize {
+        self.state[&i].current_plan
+    }
+
     pub fn handle_live_edited_traffic_signals(&mut self, map: &Map) {
         for state in self.state.values_mut() {
             if let Some(ts) = map.maybe_get_traffic_signal(state.id) {
-                if state.current_phase >= ts.phases.len() {
+                if state.current_plan > ts.plans.len() {
+                    // The next phase change will switch to whatever plan should be running.
+                    state.current_plan = 0;
+                }
+                if state.current_phase >= ts.plan_phases(state.current_plan).len() {
                     // Just jump back to the first one. Shrug.
                     state.current_phase = 0;
                     println!(
@@ -532,6 +648,7 @@ impl IntersectionSimState {
         sign: &ControlStopSign,
         now: Time,
         scheduler: &mut Scheduler,
+        maybe_cars_and_queues: Option<(&BTreeMap<CarID, Car>, &BTreeMap<Traversable, Queue>)>,
     ) -> bool {
         let our_priority = sign.get_priority(req.turn, map);
         assert!(our_priority != TurnPriority::Banned);
@@ -560,9 +677,60 @@ impl IntersectionSimState {
         // If a case #1 could've started by now, then they would have. Since they didn't, they must
         // be blocked.
 
-        // TODO Make sure we can optimistically finish this turn before an approaching
-        // higher-priority vehicle wants to begin.
+        // Make sure there's a big enough gap before an approaching higher-priority vehicle wants
+        // to begin a conflicting turn.
+        if our_priority == TurnPriority::Yield {
+            if let (Some(gaps), Some((cars, queues))) = (&self.critical_gaps, maybe_cars_and_queues)
+            {
+                let critical_gap = gaps.get(map.get_t(req.turn).turn_type);
+                if !accept_gap(
+                    req,
+                    critical_gap,
+                    |t| sign.get_priority(t, map),
+                    map,
+                    now,
+                    scheduler,
+                    (cars, queues),
+                ) {
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
 
+    // Traffic already circulating always has priority. Entering vehicles don't have to stop, but
+    // they wait for a gap.
+    fn roundabout_policy(
+        &mut self,
+        req: &Request,
+        map: &Map,
+        roundabout: &Roundabout,
+        now: Time,
+        scheduler: &mut Scheduler,
+        maybe_cars_and_queues: Option<(&BTreeMap<CarID, Car>, &BTreeMap<Traversable, Queue>)>,
+    ) -> bool {
+        if !roundabout.is_entry(req.turn, map) {
+            return true;
+        }
+        if let Some((cars, queues)) = maybe_cars_and_queues {
+            return accept_gap(
+                req,
+                ROUNDABOUT_CRITICAL_GAP,
+                |t| {
+                    if roundabout.gives_way_to(req.turn, t, map) {
+                        TurnPriority::Protected
+                    } else {
+                        TurnPriority::Yield
+                    }
+                },
+                map,
+                now,
+                scheduler,
+                (cars, queues),
+            );
+        }
         true
     }
 
@@ -578,9 +746,8 @@ impl IntersectionSimState {
         let turn = map.get_t(req.turn);
 
         let state = &self.state[&req.turn.parent];
-        let phase = &signal.phases[state.current_phase];
+        let phase = &signal.plan_phases(state.current_plan)[state.current_phase];
         let full_phase_duration = phase.phase_type.simple_duration();
-        let remaining_phase_time = state.phase_ends_at - now;
         let our_time = state.waiting[req];
 
         // Can't go at all this phase.
@@ -589,6 +756,40 @@ impl IntersectionSimState {
             return false;
         }
 
+        let mut remaining_phase_time = match phase.phase_type {
+            // A vehicle about to make a protected turn is sitting on a detector, so the phase
+            // will keep going until max_green.
+            PhaseType::Actuated(ref timing)
+                if our_priority == TurnPriority::Protected
+                    && turn.turn_type != TurnType::Crosswalk =>
+            {
+                state.phase_started_at + timing.max_green - now
+            }
+            _ => state.phase_ends_at - now,
+        };
+
+        if let Some(ref ped) = phase.ped_timing {
+            let into_phase = now - state.phase_started_at;
+            if turn.turn_type == TurnType::Crosswalk {
+                // Nobody starts crossing during flashing don't walk. They'll be woken up when the
+                // next phase starts.
+                if into_phase >= ped.walk {
+                    return false;
+                }
+                remaining_phase_time = ped.walk + ped.clearance - into_phase;
+            } else if into_phase < ped.leading_interval {
+                // Since we have "ownership" of scheduling for req.agent, don't need to use
+                // scheduler.update.
+                if let Some(s) = scheduler {
+                    s.push(
+                        state.phase_started_at + ped.leading_interval,
+                        Command::update_agent(req.agent),
+                    );
+                }
+                return false;
+            }
+        }
+
         if our_priority == TurnPriority::Yield
             && now < our_time + WAIT_BEFORE_YIELD_AT_TRAFFIC_SIGNAL
         {
@@ -733,6 +934,114 @@ impl IntersectionSimState {
     }
 }
 
+// Actuated phases might end after min_green, so that's the only part that's scheduled upfront.
+fn initial_duration(phase_type: &PhaseType) -> Duration {
+    match phase_type {
+        PhaseType::Actuated(timing) => timing.min_green,
+        other => other.simple_duration(),
+    }
+}
+
+// The offset of a plan is when its first phase starts, relative to midnight. Returns the phase
+// that should be running now and how long ago it started, assuming every phase in the cycle lasts
+// as long as it does in ControlTrafficSignal::cycle_length.
+fn phase_at_offset(signal: &ControlTrafficSignal, plan: usize, now: Time) -> (usize, Duration) {
+    let phases = signal.plan_phases(plan);
+    let cycle_length = signal.cycle_length(plan);
+    if cycle_length == Duration::ZERO {
+        return (0, Duration::ZERO);
+    }
+    let mut into_cycle = Duration::seconds(
+        (now.inner_seconds() - signal.plan_offset(plan).inner_seconds())
+            .rem_euclid(cycle_length.inner_seconds()),
+    );
+    for (idx, p) in phases.iter().enumerate() {
+        let dt = p.phase_type.simple_duration();
+        if into_cycle < dt {
+            return (idx, into_cycle);
+        }
+        into_cycle -= dt;
+    }
+    // Only reachable due to floating point issues
+    (0, Duration::ZERO)
+}
+
+// Only start a yield turn if the next vehicle approaching on a conflicting protected turn will
+// arrive at least critical_gap from now. Otherwise, schedule a retry and return false. Only
+// vehicles currently moving count; anybody already stopped is either blocked or will be caught by
+// the accepted conflict checks.
+fn accept_gap<F: Fn(TurnID) -> TurnPriority>(
+    req: &Request,
+    critical_gap: Duration,
+    priority: F,
+    map: &Map,
+    now: Time,
+    scheduler: &mut Scheduler,
+    (cars, queues): (&BTreeMap<CarID, Car>, &BTreeMap<Traversable, Queue>),
+) -> bool {
+    if let Some(arrival) = next_conflicting_arrival(req, priority, map, now, cars, queues) {
+        if arrival < critical_gap {
+            // Check again once that vehicle should've reached the intersection. If it starts a
+            // conflicting turn, we'll get woken up when it finishes.
+            scheduler.push(
+                now + arrival.max(WAIT_AT_STOP_SIGN),
+                Command::update_agent(req.agent),
+            );
+            return false;
+        }
+    }
+    true
+}
+
+// How long until the next vehicle on a protected turn that conflicts with the request reaches the
+// intersection?
+fn next_conflicting_arrival<F: Fn(TurnID) -> TurnPriority>(
+    req: &Request,
+    priority: F,
+    map: &Map,
+    now: Time,
+    cars: &BTreeMap<CarID, Car>,
+    queues: &BTreeMap<Traversable, Queue>,
+) -> Option<Duration> {
+    let our_turn = map.get_t(req.turn);
+    let mut earliest: Option<Duration> = None;
+    for l in &map.get_i(req.turn.parent).incoming_lanes {
+        let queue = if let Some(q) = queues.get(&Traversable::Lane(*l)) {
+            q
+        } else {
+            continue;
+        };
+        for id in &queue.cars {
+            // The car making the request isn't in cars while it's being updated
+            let car = if let Some(car) = cars.get(id) {
+                car
+            } else {
+                continue;
+            };
+            let time_int = match car.state {
+                CarState::Crossing(ref time_int, _, _) => time_int,
+                _ => continue,
+            };
+            let t = match car.router.maybe_next() {
+                Some(Traversable::Turn(t)) => t,
+                _ => continue,
+            };
+            if priority(t) != TurnPriority::Protected || !our_turn.conflicts_with(map.get_t(t)) {
+                continue;
+            }
+            let arrival = if time_int.end > now {
+                time_int.end - now
+            } else {
+                Duration::ZERO
+            };
+            if earliest.map(|x| arrival < x).unwrap_or(true) {
+                earliest = Some(arrival);
+            }
+        }
+    }
+    earliest
+}
+
 // TODO Sometimes a traffic signal is surrounded by tiny lanes with almost no capacity. Workaround
 // for now.
 fn allow_block_the_box(osm_node_id: i64) -> bool {
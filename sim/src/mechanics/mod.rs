@@ -5,8 +5,9 @@ mod parking;
 mod queue;
 mod walking;
 
+pub use self::car::SpeedProfile;
 pub use self::driving::DrivingSimState;
-pub use self::intersection::IntersectionSimState;
+pub use self::intersection::{CriticalGaps, IntersectionSimState};
 pub use self::parking::ParkingSimState;
 pub use self::queue::Queue;
 pub use self::walking::WalkingSimState;
@@ -274,8 +274,8 @@ impl ParkingSimState {
         driving_pos: Position,
         vehicle: &Vehicle,
         // Either the building where a seeded car starts or the target of a trip. For filtering
-        // private spots.
-        target: BuildingID,
+        // private spots; if there's no building, only public spots are used.
+        target: Option<BuildingID>,
         map: &Map,
     ) -> Vec<(ParkingSpot, Position)> {
         let mut candidates = Vec::new();
@@ -295,7 +295,7 @@ impl ParkingSimState {
 
         for b in self.driving_to_offstreet.get(driving_pos.lane()) {
             let parking = map.get_b(*b).parking.as_ref().unwrap();
-            if parking.public_garage_name.is_none() && target != *b {
+            if parking.public_garage_name.is_none() && target != Some(*b) {
                 continue;
             }
             let bldg_dist = parking.driving_pos.dist_along();
@@ -402,7 +402,7 @@ impl ParkingSimState {
         &self,
         start: LaneID,
         vehicle: &Vehicle,
-        target: BuildingID,
+        target: Option<BuildingID>,
         map: &Map,
     ) -> Option<(Vec<PathStep>, ParkingSpot, Position)> {
         let mut backrefs: HashMap<LaneID, TurnID> = HashMap::new();
@@ -143,10 +143,18 @@ impl Queue {
                     assert_eq!(bound, self.geom_len);
                     self.geom_len
                 }
-                CarState::Crossing(ref time_int, ref dist_int) => {
+                CarState::Crossing(ref time_int, ref dist_int, ref profile) => {
                     // TODO Why percent_clamp_end? We process car updates in any order, so we might
                     // calculate this before moving this car from Crossing to another state.
-                    dist_int.lerp(time_int.percent_clamp_end(now)).min(bound)
+                    let front = if let Some(p) = profile {
+                        dist_int.start + p.dist_at(now - time_int.start)
+                    } else {
+                        dist_int.lerp(time_int.percent_clamp_end(now))
+                    };
+                    // A faster follower may catch up to their leader before finishing the Crossing.
+                    // With acceleration profiles, followers plan around their leader, but the
+                    // leader can still stop unexpectedly. Either way, they're held right behind.
+                    front.min(bound)
                 }
                 CarState::Unparking(front, _, _) => front,
                 CarState::Parking(front, _, _) => front,
@@ -256,7 +264,7 @@ fn dump_cars(
         let car = &cars[id];
         println!("- {} @ {} (length {})", id, dist, car.vehicle.length);
         match car.state {
-            CarState::Crossing(ref time_int, ref dist_int) => {
+            CarState::Crossing(ref time_int, ref dist_int, _) => {
                 println!(
                     "  Going {} .. {} during {} .. {}",
                     dist_int.start, dist_int.end, time_int.start, time_int.end
@@ -1,7 +1,9 @@
 mod pandemic;
+mod params;
 
 use geom::{Duration, Time};
-pub use pandemic::{Cmd, PandemicModel};
+pub use pandemic::{Cmd, Exposure, ExposureLocation, PandemicModel, SEIRCounts};
+pub use params::{Action, BuildingFilter, Intervention, PandemicParams, Trigger};
 use rand::Rng;
 use rand_distr::{Distribution, Exp, Normal};
 use rand_xorshift::XorShiftRng;
@@ -80,69 +82,43 @@ pub enum StateEvent {
 #[derive(Debug, Clone)]
 pub struct Event {
     s: StateEvent,
-    p_hosp: f64,  // probability of people being hospitalized after infection
-    p_death: f64, // probability of dying after hospitalizaion
     t: AnyTime,
 }
 
 impl Event {
-    fn next(&self, now: AnyTime, rng: &mut XorShiftRng) -> State {
+    fn next(&self, now: AnyTime, params: &PandemicParams, rng: &mut XorShiftRng) -> State {
+        let t_inf = params.t_inf.inner_seconds();
+        let t_inc = params.t_inc.inner_seconds();
         match self.s {
             StateEvent::Exposition => State::Exposed((
                 Event {
                     s: StateEvent::Incubation,
-                    p_hosp: self.p_hosp,
-                    p_death: self.p_death,
-                    t: now + State::get_time_normal(State::T_INC, State::T_INC / 2.0, rng),
+                    t: now + State::get_time_normal(t_inc, t_inc / 2.0, rng),
+                },
+                now.into(),
+            )),
+            StateEvent::Incubation => State::Infectious((
+                Event {
+                    s: if rng.gen_bool(params.p_hosp) {
+                        StateEvent::Hospitalization
+                    } else {
+                        StateEvent::Recovery
+                    },
+                    t: now + State::get_time_normal(t_inf, t_inf / 2.0, rng),
+                },
+                now.into(),
+            )),
+            StateEvent::Hospitalization => State::Hospitalized((
+                Event {
+                    s: if rng.gen_bool(params.p_death) {
+                        StateEvent::Death
+                    } else {
+                        StateEvent::Recovery
+                    },
+                    t: now + State::get_time_normal(t_inf, t_inf / 2.0, rng),
                 },
                 now.into(),
             )),
-            StateEvent::Incubation => {
-                if rng.gen_bool(self.p_death) {
-                    State::Infectious((
-                        Event {
-                            s: StateEvent::Recovery,
-                            p_hosp: self.p_hosp,
-                            p_death: self.p_death,
-                            t: now + State::get_time_normal(State::T_INF, State::T_INF / 2.0, rng),
-                        },
-                        now.into(),
-                    ))
-                } else {
-                    State::Infectious((
-                        Event {
-                            s: StateEvent::Hospitalization,
-                            p_hosp: self.p_hosp,
-                            p_death: self.p_death,
-                            t: now + State::get_time_normal(State::T_INF, State::T_INF / 2.0, rng),
-                        },
-                        now.into(),
-                    ))
-                }
-            }
-            StateEvent::Hospitalization => {
-                if rng.gen_bool(self.p_hosp) {
-                    State::Hospitalized((
-                        Event {
-                            s: StateEvent::Recovery,
-                            p_hosp: self.p_hosp,
-                            p_death: self.p_death,
-                            t: now + State::get_time_normal(State::T_INF, State::T_INF / 2.0, rng),
-                        },
-                        now.into(),
-                    ))
-                } else {
-                    State::Hospitalized((
-                        Event {
-                            s: StateEvent::Death,
-                            p_hosp: self.p_hosp,
-                            p_death: self.p_death,
-                            t: now + State::get_time_normal(State::T_INF, State::T_INF / 2.0, rng),
-                        },
-                        now.into(),
-                    ))
-                }
-            }
             StateEvent::Death => State::Dead(now.into()),
             StateEvent::Recovery => State::Recovered(now.into()),
         }
@@ -160,28 +136,10 @@ pub enum State {
 }
 
 impl State {
-    const T_INF: f64 = 360.0 * 10.0; // TODO dummy values
-    const T_INC: f64 = 3600.0; // TODO dummy values
-    const R_0: f64 = 2.5;
-    // const S_RATIO: f64 = 0.985;
-    const E_RATIO: f64 = 0.01;
-    const I_RATIO: f64 = 0.05;
-    // const R_RATIO: f64 = 0.0;
-
-    pub fn ini_infectious_ratio() -> f64 {
-        Self::I_RATIO
-    }
-
-    pub fn ini_exposed_ratio() -> f64 {
-        Self::E_RATIO
-    }
-
-    fn new(p_hosp: f64, p_death: f64) -> Self {
+    fn new() -> Self {
         Self::Sane((
             Event {
                 s: StateEvent::Exposition,
-                p_hosp,
-                p_death,
                 t: AnyTime::from(std::f64::INFINITY),
             },
             Time::START_OF_DAY,
@@ -258,13 +216,9 @@ impl State {
     //     match self {
     //         Self::Sane(Event {
     //             s,
-    //             p_hosp,
-    //             p_death,
     //             t: _,
     //         }) => Self::Sane(Event {
     //             s,
-    //             p_hosp,
-    //             p_death,
     //             t: new_time,
     //         }),
     //         _ => unreachable!(),
@@ -272,42 +226,52 @@ impl State {
     // }
 
     // TODO: not sure if we want an option here...
-    pub fn next_default(self, default: AnyTime, rng: &mut XorShiftRng) -> Option<Self> {
+    pub fn next_default(
+        self,
+        default: AnyTime,
+        params: &PandemicParams,
+        rng: &mut XorShiftRng,
+    ) -> Option<Self> {
         // TODO: when #![feature(bindings_after_at)] reaches stable
         // rewrite this part with it
         match self {
             Self::Sane((ev, _)) => Some(Self::Sane((ev, default.into()))),
-            Self::Exposed((ev, _)) => Some(ev.next(default, rng)),
-            Self::Infectious((ev, _)) => Some(ev.next(default, rng)),
-            Self::Hospitalized((ev, _)) => Some(ev.next(default, rng)),
+            Self::Exposed((ev, _)) => Some(ev.next(default, params, rng)),
+            Self::Infectious((ev, _)) => Some(ev.next(default, params, rng)),
+            Self::Hospitalized((ev, _)) => Some(ev.next(default, params, rng)),
             Self::Recovered(_) => Some(Self::Recovered(default.into())),
             Self::Dead(_) => Some(Self::Dead(default.into())),
         }
     }
 
     // TODO: not sure if we want an option here...
-    pub fn next(self, now: AnyTime, rng: &mut XorShiftRng) -> Option<Self> {
+    pub fn next(
+        self,
+        now: AnyTime,
+        params: &PandemicParams,
+        rng: &mut XorShiftRng,
+    ) -> Option<Self> {
         // TODO: when #![feature(bindings_after_at)] reaches stable
         // rewrite this part with it
         match self {
             Self::Sane((ev, t)) => Some(Self::Sane((ev, t))),
             Self::Exposed((ev, t)) => {
                 if ev.t <= now {
-                    Some(ev.next(now, rng))
+                    Some(ev.next(now, params, rng))
                 } else {
                     Some(Self::Exposed((ev, t)))
                 }
             }
             Self::Infectious((ev, t)) => {
                 if ev.t <= now {
-                    Some(ev.next(now, rng))
+                    Some(ev.next(now, params, rng))
                 } else {
                     Some(Self::Infectious((ev, t)))
                 }
             }
             Self::Hospitalized((ev, t)) => {
                 if ev.t <= now {
-                    Some(ev.next(now, rng))
+                    Some(ev.next(now, params, rng))
                 } else {
                     Some(Self::Hospitalized((ev, t)))
                 }
@@ -322,13 +286,14 @@ impl State {
         self,
         now: AnyTime,
         overlap: Duration,
+        params: &PandemicParams,
         rng: &mut XorShiftRng,
     ) -> Result<Self, String> {
         // rewrite this part with it
         match self {
             Self::Sane((ev, t)) => {
-                if overlap >= Self::get_time_exp(State::R_0 / State::T_INF, rng) {
-                    Ok(ev.next(now, rng))
+                if overlap >= Self::get_time_exp(params.r_0 / params.t_inf.inner_seconds(), rng) {
+                    Ok(ev.next(now, params, rng))
                 } else {
                     Ok(Self::Sane((ev, t)))
                 }
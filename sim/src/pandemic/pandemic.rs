@@ -1,11 +1,20 @@
-use crate::pandemic::{AnyTime, State};
-use crate::{CarID, Event, OffMapLocation, Person, PersonID, Scheduler, TripPhaseType};
+use crate::pandemic::params::is_home;
+use crate::pandemic::{Action, AnyTime, PandemicParams, State, Trigger};
+use crate::{
+    CarID, Command, Event, OffMapLocation, Person, PersonID, Scheduler, TransitSimState,
+    TripEndpoint, TripManager, TripPhaseType,
+};
 use geom::{Duration, Time};
-use map_model::{BuildingID, BusStopID};
+use map_model::{BuildingID, BusStopID, Map};
 use rand::Rng;
 use rand_xorshift::XorShiftRng;
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+
+// How often to check if interventions triggered by the number of infectious people should start
+const CHECK_THRESHOLDS_INTERVAL: Duration = Duration::const_seconds(30.0 * 60.0);
+// How often to record how many people are in each state
+const RECORD_COUNTS_INTERVAL: Duration = Duration::const_seconds(10.0 * 60.0);
 
 // TODO This does not model transmission by surfaces; only person-to-person.
 // TODO If two people are in the same shared space indefinitely and neither leaves, we don't model
@@ -21,6 +30,19 @@ pub struct PandemicModel {
     buses: SharedSpace<CarID>,
     person_to_bus: BTreeMap<PersonID, CarID>,
 
+    params: PandemicParams,
+    // When each intervention started, in the order they were started
+    interventions_started: Vec<(Time, usize)>,
+    // People wearing masks, and how much each mask reduces transmission
+    masked: BTreeSet<PersonID>,
+    mask_efficacy: f64,
+    // Once quarantine starts, how long after becoming infectious do people stay home?
+    quarantine_delay: Option<Duration>,
+    quarantined: BTreeSet<PersonID>,
+
+    counts_over_time: Vec<SEIRCounts>,
+    exposures: Vec<Exposure>,
+
     rng: XorShiftRng,
     initialized: bool,
 }
@@ -30,18 +52,44 @@ pub struct PandemicModel {
 pub enum Cmd {
     BecomeHospitalized(PersonID),
     BecomeQuarantined(PersonID),
+    // Indexes into PandemicParams::interventions
+    StartIntervention(usize),
+    CheckThresholds,
+    RecordCounts,
 }
 
-// TODO Pretend handle_event and handle_cmd also take in some object that lets you do things like:
-//
-// - replace_future_trips(PersonID, Vec<IndividTrip>)
-//
-// I'm not exactly sure how this should work yet. Any place you want to change the rest of the
-// simulation, just add a comment describing what you want to do exactly, and we'll figure it out
-// from there.
+// How many people are in each state at some time
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SEIRCounts {
+    pub time: Time,
+    pub sane: usize,
+    pub exposed: usize,
+    // Includes people in the hospital
+    pub infected: usize,
+    pub recovered: usize,
+    pub dead: usize,
+}
+
+// Somebody infectious exposed somebody else, after they shared a space.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Exposure {
+    // When the source or target left the space
+    pub time: Time,
+    pub source: PersonID,
+    pub target: PersonID,
+    pub location: ExposureLocation,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ExposureLocation {
+    Building(BuildingID),
+    RemoteBuilding(OffMapLocation),
+    BusStop(BusStopID),
+    Bus(CarID),
+}
 
 impl PandemicModel {
-    pub fn new(rng: XorShiftRng) -> PandemicModel {
+    pub fn new(params: PandemicParams, rng: XorShiftRng) -> PandemicModel {
         PandemicModel {
             pop: BTreeMap::new(),
 
@@ -51,6 +99,16 @@ impl PandemicModel {
             buses: SharedSpace::new(),
             person_to_bus: BTreeMap::new(),
 
+            params,
+            interventions_started: Vec::new(),
+            masked: BTreeSet::new(),
+            mask_efficacy: 0.0,
+            quarantine_delay: None,
+            quarantined: BTreeSet::new(),
+
+            counts_over_time: Vec::new(),
+            exposures: Vec::new(),
+
             rng,
             initialized: false,
         }
@@ -58,7 +116,7 @@ impl PandemicModel {
 
     // Sorry, initialization order of simulations is still a bit messy. This'll be called at
     // Time::START_OF_DAY after all of the people have been created from a Scenario.
-    pub fn initialize(&mut self, population: &Vec<Person>, _scheduler: &mut Scheduler) {
+    pub fn initialize(&mut self, population: &Vec<Person>, scheduler: &mut Scheduler) {
         assert!(!self.initialized);
         self.initialized = true;
 
@@ -66,18 +124,23 @@ impl PandemicModel {
         // TODO the intial time is not well set. it should start "before"
         // the beginning of the day. Also
         for p in population {
-            let state = State::new(0.5, 0.5);
-            let state = if self.rng.gen_bool(State::ini_exposed_ratio()) {
+            let state = State::new();
+            let state = if self.rng.gen_bool(self.params.ini_exposed_ratio) {
                 let next_state = state
                     .start(
                         AnyTime::from(Time::START_OF_DAY),
                         Duration::seconds(std::f64::MAX),
+                        &self.params,
                         &mut self.rng,
                     )
                     .unwrap();
-                let next_state = if self.rng.gen_bool(State::ini_infectious_ratio()) {
+                let next_state = if self.rng.gen_bool(self.params.ini_infectious_ratio) {
                     next_state
-                        .next_default(AnyTime::from(Time::START_OF_DAY), &mut self.rng)
+                        .next_default(
+                            AnyTime::from(Time::START_OF_DAY),
+                            &self.params,
+                            &mut self.rng,
+                        )
                         .unwrap()
                 } else {
                     next_state
@@ -88,6 +151,28 @@ impl PandemicModel {
             };
             self.pop.insert(p.id, state);
         }
+
+        let mut any_thresholds = false;
+        for (idx, intervention) in self.params.interventions.iter().enumerate() {
+            match intervention.trigger {
+                Trigger::At(t) => {
+                    scheduler.push(t, Command::Pandemic(Cmd::StartIntervention(idx)));
+                }
+                Trigger::InfectiousAbove(_) => {
+                    any_thresholds = true;
+                }
+            }
+        }
+        if any_thresholds {
+            scheduler.push(Time::START_OF_DAY, Command::Pandemic(Cmd::CheckThresholds));
+        }
+
+        self.counts_over_time
+            .push(self.current_counts(Time::START_OF_DAY));
+        scheduler.push(
+            Time::START_OF_DAY + RECORD_COUNTS_INTERVAL,
+            Command::Pandemic(Cmd::RecordCounts),
+        );
     }
 
     pub fn count_sane(&self) -> usize {
@@ -153,7 +238,32 @@ impl PandemicModel {
             + self.count_dead()
     }
 
-    pub fn handle_event(&mut self, now: Time, ev: &Event, scheduler: &mut Scheduler) {
+    fn current_counts(&self, now: Time) -> SEIRCounts {
+        SEIRCounts {
+            time: now,
+            sane: self.count_sane(),
+            exposed: self.count_exposed(),
+            infected: self.count_infected(),
+            recovered: self.count_recovered(),
+            dead: self.count_dead(),
+        }
+    }
+
+    // Recorded periodically since midnight, ending with the current counts
+    pub fn counts_over_time(&self, now: Time) -> Vec<SEIRCounts> {
+        let mut counts = self.counts_over_time.clone();
+        if counts.last().map(|c| c.time < now).unwrap_or(true) {
+            counts.push(self.current_counts(now));
+        }
+        counts
+    }
+
+    // Every time somebody has been exposed, in order
+    pub fn get_exposures(&self) -> &Vec<Exposure> {
+        &self.exposures
+    }
+
+    pub fn handle_event(&mut self, now: Time, ev: &Event, map: &Map, scheduler: &mut Scheduler) {
         assert!(self.initialized);
 
         match ev {
@@ -162,7 +272,15 @@ impl PandemicModel {
             }
             Event::PersonLeavesBuilding(person, bldg) => {
                 if let Some(others) = self.bldgs.person_leaves_space(now, *person, *bldg) {
-                    self.transmission(now, *person, others, scheduler);
+                    let masks = !is_home(map.get_b(*bldg));
+                    self.transmission(
+                        now,
+                        *person,
+                        others,
+                        ExposureLocation::Building(*bldg),
+                        masks,
+                        scheduler,
+                    );
                 } else {
                     panic!("{} left {}, but they weren't inside", person, bldg);
                 }
@@ -176,7 +294,15 @@ impl PandemicModel {
                     self.remote_bldgs
                         .person_leaves_space(now, *person, loc.clone())
                 {
-                    self.transmission(now, *person, others, scheduler);
+                    // We don't know what's off-map, so assume it's not somebody's home
+                    self.transmission(
+                        now,
+                        *person,
+                        others,
+                        ExposureLocation::RemoteBuilding(loc.clone()),
+                        true,
+                        scheduler,
+                    );
                 } else {
                     panic!("{} left {:?}, but they weren't inside", person, loc);
                 }
@@ -192,7 +318,14 @@ impl PandemicModel {
                             .bus_stops
                             .person_leaves_space(now, person, *stop)
                             .unwrap();
-                        self.transmission(now, person, others, scheduler);
+                        self.transmission(
+                            now,
+                            person,
+                            others,
+                            ExposureLocation::BusStop(*stop),
+                            true,
+                            scheduler,
+                        );
 
                         self.buses.person_enters_space(now, person, *bus);
                         self.person_to_bus.insert(person, *bus);
@@ -203,7 +336,14 @@ impl PandemicModel {
                         // of a bus ride.
                         if let Some(car) = self.person_to_bus.remove(&person) {
                             let others = self.buses.person_leaves_space(now, person, car).unwrap();
-                            self.transmission(now, person, others, scheduler);
+                            self.transmission(
+                                now,
+                                person,
+                                others,
+                                ExposureLocation::Bus(car),
+                                true,
+                                scheduler,
+                            );
                         }
                     }
                     _ => {
@@ -228,22 +368,126 @@ impl PandemicModel {
         }
     }
 
-    pub fn handle_cmd(&mut self, _now: Time, cmd: Cmd, _scheduler: &mut Scheduler) {
+    pub fn handle_cmd(
+        &mut self,
+        now: Time,
+        cmd: Cmd,
+        map: &Map,
+        trips: &mut TripManager,
+        transit: &mut TransitSimState,
+        scheduler: &mut Scheduler,
+    ) {
         assert!(self.initialized);
 
-        // TODO Here we might enforce policies. Like severe -> become hospitalized
-        // Symptomatic -> stay quaratined, and/or track contacts to quarantine them too (or test
-        // them)
+        // TODO Track contacts to quarantine them too (or test them)
         match cmd {
-            Cmd::BecomeHospitalized(_person) => {
-                // self.hospitalized.insert(person);
+            Cmd::BecomeHospitalized(person) => {
+                trips.cancel_unstarted_trips(person, scheduler, |_| true);
+            }
+            Cmd::BecomeQuarantined(person) => {
+                if self.quarantined.insert(person) {
+                    trips.cancel_unstarted_trips(person, scheduler, |_| true);
+                }
             }
-            Cmd::BecomeQuarantined(_person) => {
-                // self.quarantined.insert(person);
+            Cmd::StartIntervention(idx) => {
+                self.start_intervention(now, idx, map, trips, transit, scheduler);
+            }
+            Cmd::CheckThresholds => {
+                let infectious = (self.count_infected() as f64) / (self.pop.len() as f64);
+                let mut waiting = false;
+                for idx in 0..self.params.interventions.len() {
+                    if let Trigger::InfectiousAbove(threshold) =
+                        self.params.interventions[idx].trigger
+                    {
+                        if self.interventions_started.iter().any(|(_, i)| *i == idx) {
+                            continue;
+                        }
+                        if infectious > threshold {
+                            self.start_intervention(now, idx, map, trips, transit, scheduler);
+                        } else {
+                            waiting = true;
+                        }
+                    }
+                }
+                if waiting {
+                    scheduler.push(
+                        now + CHECK_THRESHOLDS_INTERVAL,
+                        Command::Pandemic(Cmd::CheckThresholds),
+                    );
+                }
+            }
+            Cmd::RecordCounts => {
+                self.counts_over_time.push(self.current_counts(now));
+                scheduler.push(
+                    now + RECORD_COUNTS_INTERVAL,
+                    Command::Pandemic(Cmd::RecordCounts),
+                );
+            }
+        }
+    }
+
+    fn start_intervention(
+        &mut self,
+        now: Time,
+        idx: usize,
+        map: &Map,
+        trips: &mut TripManager,
+        transit: &mut TransitSimState,
+        scheduler: &mut Scheduler,
+    ) {
+        self.interventions_started.push((now, idx));
+        match self.params.interventions[idx].action.clone() {
+            Action::CloseBuildings(filters) => {
+                let closed: BTreeSet<BuildingID> = map
+                    .all_buildings()
+                    .iter()
+                    .filter(|b| filters.iter().any(|f| f.matches(b)))
+                    .map(|b| b.id)
+                    .collect();
+                for person in self.pop.keys() {
+                    trips.cancel_unstarted_trips(*person, scheduler, |info| match info.end {
+                        TripEndpoint::Bldg(b) => closed.contains(&b),
+                        _ => false,
+                    });
+                }
+            }
+            Action::Masks {
+                compliance,
+                efficacy,
+            } => {
+                self.mask_efficacy = efficacy;
+                for person in self.pop.keys() {
+                    if self.rng.gen_bool(compliance) {
+                        self.masked.insert(*person);
+                    }
+                }
+            }
+            Action::Quarantine { delay } => {
+                self.quarantine_delay = Some(delay);
+                for (person, state) in &self.pop {
+                    if let State::Infectious((_, since)) = state {
+                        // update, in case somebody's quarantine was already scheduled
+                        scheduler.update(
+                            now.max(*since + delay),
+                            Command::Pandemic(Cmd::BecomeQuarantined(*person)),
+                        );
+                    }
+                }
+            }
+            Action::LimitTransitCapacity(pct) => {
+                transit.limit_capacity(pct);
             }
         }
     }
 
+    // Which interventions started when
+    pub fn interventions_started(&self) -> Vec<(Time, String)> {
+        self.interventions_started
+            .iter()
+            .map(|(t, idx)| (*t, self.params.interventions[*idx].action.describe()))
+            .collect()
+    }
+
     pub fn get_time(&self, person: PersonID) -> Option<Time> {
         match self.pop.get(&person) {
             Some(state) => state.get_time(),
@@ -300,21 +544,52 @@ impl PandemicModel {
         now: Time,
         person: PersonID,
         other_occupants: Vec<(PersonID, Duration)>,
+        location: ExposureLocation,
+        masks: bool,
         scheduler: &mut Scheduler,
     ) {
         // person has spent some duration in the same space as other people. Does transmission
         // occur?
-        for (other, overlap) in other_occupants {
+        for (other, mut overlap) in other_occupants {
             if let Some(pid) = self.infectious_contact(person, other) {
-                self.become_exposed(now, overlap, pid, scheduler);
+                if masks {
+                    for p in vec![person, other] {
+                        if self.masked.contains(&p) {
+                            overlap = overlap * (1.0 - self.mask_efficacy);
+                        }
+                    }
+                }
+                let source = if pid == person { other } else { person };
+                self.become_exposed(now, overlap, pid, source, location.clone(), scheduler);
             }
         }
     }
 
     // transition from a state to another without interaction with others
-    fn transition(&mut self, now: Time, person: PersonID, _scheduler: &mut Scheduler) {
+    fn transition(&mut self, now: Time, person: PersonID, scheduler: &mut Scheduler) {
         let state = self.pop.remove(&person).unwrap();
-        let state = state.next(AnyTime::from(now), &mut self.rng).unwrap();
+        let was_infectious = state.is_infectious();
+        let was_hospitalized = match state {
+            State::Hospitalized(_) => true,
+            _ => false,
+        };
+        let state = state
+            .next(AnyTime::from(now), &self.params, &mut self.rng)
+            .unwrap();
+        match state {
+            State::Infectious(_) if !was_infectious => {
+                if let Some(delay) = self.quarantine_delay {
+                    scheduler.update(
+                        now + delay,
+                        Command::Pandemic(Cmd::BecomeQuarantined(person)),
+                    );
+                }
+            }
+            State::Hospitalized(_) if !was_hospitalized => {
+                scheduler.push(now, Command::Pandemic(Cmd::BecomeHospitalized(person)));
+            }
+            _ => {}
+        }
         self.pop.insert(person, state);
 
         // if self.rng.gen_bool(0.1) {
@@ -330,6 +605,8 @@ impl PandemicModel {
         now: Time,
         overlap: Duration,
         person: PersonID,
+        source: PersonID,
+        location: ExposureLocation,
         _scheduler: &mut Scheduler,
     ) {
         // When poeple become expose
@@ -339,8 +616,16 @@ impl PandemicModel {
             std::f64::INFINITY
         );
         let state = state
-            .start(AnyTime::from(now), overlap, &mut self.rng)
+            .start(AnyTime::from(now), overlap, &self.params, &mut self.rng)
             .unwrap();
+        if state.is_exposed() {
+            self.exposures.push(Exposure {
+                time: now,
+                source,
+                target: person,
+                location,
+            });
+        }
         self.pop.insert(person, state);
 
         // if self.rng.gen_bool(0.1) {
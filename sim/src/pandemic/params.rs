@@ -0,0 +1,126 @@
+use geom::{Duration, Time};
+use map_model::{Building, BuildingType};
+use serde::{Deserialize, Serialize};
+
+// Everything about the disease and the response to it that isn't hardcoded. Load this from a JSON
+// file with --pandemic_params.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PandemicParams {
+    // How long somebody stays infectious, on average
+    pub t_inf: Duration,
+    // How long between exposure and becoming infectious, on average
+    pub t_inc: Duration,
+    // How many people one infectious person exposes over t_inf, if they were around sane people
+    // the whole time
+    pub r_0: f64,
+    // At the start of the day, what fraction of people are exposed? Some of those are already
+    // infectious.
+    pub ini_exposed_ratio: f64,
+    pub ini_infectious_ratio: f64,
+    // The probability of being hospitalized after becoming infectious
+    pub p_hosp: f64,
+    // The probability of dying after being hospitalized
+    pub p_death: f64,
+
+    pub interventions: Vec<Intervention>,
+}
+
+impl PandemicParams {
+    pub fn new() -> PandemicParams {
+        PandemicParams {
+            // TODO dummy values
+            t_inf: Duration::seconds(360.0 * 10.0),
+            t_inc: Duration::seconds(3600.0),
+            r_0: 2.5,
+            ini_exposed_ratio: 0.01,
+            ini_infectious_ratio: 0.05,
+            p_hosp: 0.5,
+            p_death: 0.5,
+            interventions: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Intervention {
+    pub trigger: Trigger,
+    pub action: Action,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Trigger {
+    At(Time),
+    // When this fraction of people are infectious or hospitalized at once
+    InfectiousAbove(f64),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Action {
+    // People cancel the rest of their day, starting with the first trip to one of these buildings
+    CloseBuildings(Vec<BuildingFilter>),
+    // This fraction of people wear masks in shared spaces, except for at home. Each mask reduces
+    // the chance of transmission between two people by efficacy.
+    Masks { compliance: f64, efficacy: f64 },
+    // People cancel the rest of their day once they're infectious for this long, or immediately
+    // if they already are
+    Quarantine { delay: Duration },
+    // Transit vehicles only fill up to this fraction of their usual capacity
+    LimitTransitCapacity(f64),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum BuildingFilter {
+    // Anything without residents
+    Commercial,
+    // Buildings with this type of amenity, like "school" or "restaurant"
+    Amenity(String),
+}
+
+impl BuildingFilter {
+    pub fn matches(&self, b: &Building) -> bool {
+        match self {
+            BuildingFilter::Commercial => !b.bldg_type.has_residents(),
+            BuildingFilter::Amenity(amenity) => b.amenities.iter().any(|(_, a)| a == amenity),
+        }
+    }
+}
+
+impl Action {
+    pub fn describe(&self) -> String {
+        match self {
+            Action::CloseBuildings(filters) => format!(
+                "close {}",
+                filters
+                    .iter()
+                    .map(|f| match f {
+                        BuildingFilter::Commercial => "commercial buildings".to_string(),
+                        BuildingFilter::Amenity(a) => format!("amenity={}", a),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Action::Masks {
+                compliance,
+                efficacy,
+            } => format!(
+                "{}% of people wear masks that are {}% effective",
+                (compliance * 100.0) as usize,
+                (efficacy * 100.0) as usize
+            ),
+            Action::Quarantine { delay } => {
+                format!("quarantine people infectious for {}", delay)
+            }
+            Action::LimitTransitCapacity(pct) => {
+                format!("limit transit to {}% of capacity", (pct * 100.0) as usize)
+            }
+        }
+    }
+}
+
+// Homes don't count as shared spaces for masks
+pub(crate) fn is_home(b: &Building) -> bool {
+    match b.bldg_type {
+        BuildingType::Residential(_) => true,
+        _ => false,
+    }
+}
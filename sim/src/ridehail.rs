@@ -0,0 +1,364 @@
+use crate::{
+    AlertLocation, CarID, Command, CreateCar, Event, ParkingSimState, PersonID, Router, Scheduler,
+    TripID, TripManager, TripPhaseType, Vehicle, VehicleSpec, VehicleType, MAX_CAR_LENGTH,
+    MIN_CAR_LENGTH,
+};
+use abstutil::{deserialize_btreemap, serialize_btreemap};
+use geom::{Distance, Duration, Time, EPSILON_DIST};
+use map_model::{BuildingID, LaneID, LaneType, Map, Path, PathConstraints, PathRequest, Position};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+
+// How long a vehicle blocks the curb while the passenger gets in
+const TIME_TO_BOARD: Duration = Duration::const_seconds(30.0);
+
+// Where a ride-hail vehicle stops near a building. Like most real drivers, it just stops in the
+// driving lane, blocking it.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+pub struct Curb {
+    pub driving_pos: Position,
+    pub sidewalk_pos: Position,
+}
+
+impl Curb {
+    // None if there's no driving lane on the building's road, or it's too short to stop on.
+    pub fn near_bldg(b: BuildingID, map: &Map) -> Option<Curb> {
+        let sidewalk_pos = map.get_b(b).front_path.sidewalk;
+        let lane = map
+            .find_closest_lane(sidewalk_pos.lane(), vec![LaneType::Driving])
+            .ok()?;
+        if !can_stop_on(lane, map) {
+            return None;
+        }
+        // Leave room behind the curb for a vehicle to start, so the dispatcher never has to
+        // circle the block.
+        let dist = sidewalk_pos
+            .equiv_pos(lane, Distance::ZERO, map)
+            .dist_along()
+            .max(2.0 * MAX_CAR_LENGTH)
+            .min(map.get_l(lane).length() - EPSILON_DIST);
+        Some(Curb {
+            driving_pos: Position::new(lane, dist),
+            sidewalk_pos,
+        })
+    }
+}
+
+// A vehicle stuck on a parking blackhole could only ever drive off the map.
+fn can_stop_on(l: LaneID, map: &Map) -> bool {
+    let lane = map.get_l(l);
+    lane.parking_blackhole.is_none() && lane.length() > 2.0 * MAX_CAR_LENGTH
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone)]
+struct Ride {
+    trip: TripID,
+    person: PersonID,
+    pickup: Curb,
+    dropoff: Curb,
+    requested_at: Time,
+    // From the pickup to the dropoff
+    req: PathRequest,
+    path: Path,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone)]
+struct FleetVehicle {
+    vehicle: Vehicle,
+    state: FleetState,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone)]
+enum FleetState {
+    // Pulled over somewhere, off the road
+    Idle(Position),
+    // Driving empty to the pickup. How far is that?
+    ToPickup(Ride, Distance),
+    // When did they get in?
+    Boarding(Ride, Time),
+    // When did they get in, and how far is the ride?
+    WithPassenger(Ride, Time, Distance),
+}
+
+// Like TransitSimState, this manages the transitions of people riding in vehicles that nobody on
+// the map owns. Each vehicle serves one passenger at a time.
+#[derive(Serialize, Deserialize, PartialEq, Clone)]
+pub struct RideHailSimState {
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    vehicles: BTreeMap<CarID, FleetVehicle>,
+    // Rides that no idle vehicle could serve yet, oldest first
+    waiting: VecDeque<Ride>,
+
+    events: Vec<Event>,
+}
+
+impl RideHailSimState {
+    // The fleet starts spread evenly over the map.
+    pub fn new(fleet_size: usize, trips: &mut TripManager, map: &Map) -> RideHailSimState {
+        let mut state = RideHailSimState {
+            vehicles: BTreeMap::new(),
+            waiting: VecDeque::new(),
+            events: Vec::new(),
+        };
+        if fleet_size == 0 {
+            return state;
+        }
+
+        let lanes: Vec<LaneID> = map
+            .all_lanes()
+            .iter()
+            .filter(|l| l.lane_type == LaneType::Driving && can_stop_on(l.id, map))
+            .map(|l| l.id)
+            .collect();
+        if lanes.is_empty() {
+            println!("WARNING: Nowhere to start a ride-hail fleet");
+            return state;
+        }
+        for idx in 0..fleet_size {
+            let l = lanes[idx * lanes.len() / fleet_size];
+            let id = CarID(trips.new_car_id(), VehicleType::Car);
+            state.vehicles.insert(
+                id,
+                FleetVehicle {
+                    vehicle: VehicleSpec {
+                        vehicle_type: VehicleType::Car,
+                        length: MIN_CAR_LENGTH,
+                        max_speed: None,
+                    }
+                    .make(id, None),
+                    state: FleetState::Idle(Position::new(l, map.get_l(l).length() / 2.0)),
+                },
+            );
+        }
+        state
+    }
+
+    pub fn request_ride(
+        &mut self,
+        now: Time,
+        trip: TripID,
+        person: PersonID,
+        from: BuildingID,
+        to: BuildingID,
+        trips: &mut TripManager,
+        parking: &mut ParkingSimState,
+        scheduler: &mut Scheduler,
+        map: &Map,
+    ) {
+        if self.vehicles.is_empty() {
+            self.events.push(Event::Alert(
+                AlertLocation::Person(person),
+                format!("{} wants a ride-hail, but there's no fleet", trip),
+            ));
+            trips.abort_trip(now, trip, None, parking, scheduler, map);
+            return;
+        }
+
+        // TripSpawner checked these exist
+        let pickup = Curb::near_bldg(from, map).unwrap();
+        let mut dropoff = Curb::near_bldg(to, map).unwrap();
+        // Rather than circling the block, let them out right away and walk back.
+        if dropoff.driving_pos.lane() == pickup.driving_pos.lane()
+            && dropoff.driving_pos.dist_along() < pickup.driving_pos.dist_along()
+        {
+            dropoff = pickup;
+        }
+        let req = PathRequest {
+            start: pickup.driving_pos,
+            end: dropoff.driving_pos,
+            constraints: PathConstraints::Car,
+        };
+        let path = if let Some(path) = map.pathfind(req.clone()) {
+            path
+        } else {
+            self.events.push(Event::Alert(
+                AlertLocation::Person(person),
+                format!(
+                    "Aborting {} because there's no ride-hail route {}",
+                    trip, req
+                ),
+            ));
+            trips.abort_trip(now, trip, None, parking, scheduler, map);
+            return;
+        };
+
+        let ride = Ride {
+            trip,
+            person,
+            pickup,
+            dropoff,
+            requested_at: now,
+            req,
+            path,
+        };
+        if let Some(ride) = self.dispatch(now, ride, scheduler, map) {
+            self.waiting.push_back(ride);
+        }
+    }
+
+    // Sends the closest idle vehicle that can reach the pickup. Returns the ride if there's none.
+    fn dispatch(
+        &mut self,
+        now: Time,
+        ride: Ride,
+        scheduler: &mut Scheduler,
+        map: &Map,
+    ) -> Option<Ride> {
+        let pickup = ride.pickup.driving_pos;
+        let pickup_pt = pickup.pt(map);
+        let mut idle: Vec<(CarID, Position)> = self
+            .vehicles
+            .iter()
+            .filter_map(|(id, v)| match v.state {
+                FleetState::Idle(pos) => Some((*id, pos)),
+                _ => None,
+            })
+            .collect();
+        idle.sort_by_key(|(id, pos)| (pos.pt(map).dist_to(pickup_pt), *id));
+
+        for (id, mut pos) in idle {
+            // Just back up a little, instead of circling the block
+            if pos.lane() == pickup.lane() && pos.dist_along() >= pickup.dist_along() {
+                pos = Position::new(pickup.lane(), pickup.dist_along() - MAX_CAR_LENGTH);
+            }
+            let req = PathRequest {
+                start: pos,
+                end: pickup,
+                constraints: PathConstraints::Car,
+            };
+            if let Some(path) = map.pathfind(req.clone()) {
+                let empty = driving_dist(&req, &path, map);
+                let vehicle = self.vehicles.get_mut(&id).unwrap();
+                scheduler.push(
+                    now,
+                    Command::SpawnCar(
+                        CreateCar {
+                            start_dist: pos.dist_along(),
+                            vehicle: vehicle.vehicle.clone(),
+                            router: Router::stop_at_curb(path, pickup.dist_along()),
+                            req,
+                            maybe_parked_car: None,
+                            trip_and_person: None,
+                            maybe_route: None,
+                        },
+                        true,
+                    ),
+                );
+                vehicle.state = FleetState::ToPickup(ride, empty);
+                return None;
+            }
+        }
+        Some(ride)
+    }
+
+    // Returns how long to stay at the curb, or None if the vehicle should leave the road.
+    pub fn vehicle_at_curb(
+        &mut self,
+        now: Time,
+        id: CarID,
+        trips: &mut TripManager,
+        parking: &mut ParkingSimState,
+        scheduler: &mut Scheduler,
+        map: &Map,
+    ) -> Option<Duration> {
+        let vehicle = self.vehicles.get_mut(&id).unwrap();
+        match vehicle.state.clone() {
+            FleetState::ToPickup(ride, empty) => {
+                trips.ride_hail_picked_up(ride.trip, id);
+                self.events.push(Event::RideHailPickup(
+                    ride.person,
+                    id,
+                    now - ride.requested_at,
+                    empty,
+                ));
+                self.events.push(Event::TripPhaseStarting(
+                    ride.trip,
+                    ride.person,
+                    Some(ride.req.clone()),
+                    TripPhaseType::RidingRideHail(id),
+                ));
+                vehicle.state = FleetState::Boarding(ride, now);
+                Some(TIME_TO_BOARD)
+            }
+            FleetState::WithPassenger(ride, picked_up, dist) => {
+                self.events.push(Event::RideHailDropoff(
+                    ride.person,
+                    id,
+                    now - picked_up,
+                    dist,
+                ));
+                trips.person_left_ride_hail(
+                    now,
+                    ride.person,
+                    id,
+                    ride.dropoff.sidewalk_pos,
+                    map,
+                    parking,
+                    scheduler,
+                );
+                vehicle.state = FleetState::Idle(ride.dropoff.driving_pos);
+
+                // Somebody might've been waiting for a while. The first ride this vehicle can
+                // reach gets it.
+                for idx in 0..self.waiting.len() {
+                    let ride = self.waiting.remove(idx).unwrap();
+                    if let Some(ride) = self.dispatch(now, ride, scheduler, map) {
+                        self.waiting.insert(idx, ride);
+                    } else {
+                        break;
+                    }
+                }
+                None
+            }
+            FleetState::Idle(_) | FleetState::Boarding(_, _) => unreachable!(),
+        }
+    }
+
+    pub fn vehicle_departed_curb(&mut self, id: CarID, map: &Map) -> Router {
+        let vehicle = self.vehicles.get_mut(&id).unwrap();
+        match vehicle.state.clone() {
+            FleetState::Boarding(ride, picked_up) => {
+                let router =
+                    Router::stop_at_curb(ride.path.clone(), ride.dropoff.driving_pos.dist_along());
+                let dist = driving_dist(&ride.req, &ride.path, map);
+                vehicle.state = FleetState::WithPassenger(ride, picked_up, dist);
+                router
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn collect_events(&mut self) -> Vec<Event> {
+        std::mem::replace(&mut self.events, Vec::new())
+    }
+
+    pub fn fleet_size(&self) -> usize {
+        self.vehicles.len()
+    }
+
+    // Vehicles that're on the road right now
+    pub fn active_vehicles(&self) -> usize {
+        self.vehicles
+            .values()
+            .filter(|v| match v.state {
+                FleetState::Idle(_) => false,
+                _ => true,
+            })
+            .count()
+    }
+
+    // How many people are waiting for a vehicle to be dispatched
+    pub fn num_waiting(&self) -> usize {
+        self.waiting.len()
+    }
+}
+
+// Paths count the first and last lanes completely.
+fn driving_dist(req: &PathRequest, path: &Path, map: &Map) -> Distance {
+    path.total_length()
+        - req.start.dist_along()
+        - (map.get_l(req.end.lane()).length() - req.end.dist_along())
+}
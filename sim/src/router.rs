@@ -1,15 +1,21 @@
 use crate::mechanics::Queue;
 use crate::{
-    Event, ParkingSimState, ParkingSpot, PersonID, SidewalkSpot, TripID, TripPhaseType, Vehicle,
+    AlertLocation, Event, ParkingSimState, ParkingSpot, PersonID, SidewalkSpot, TripID,
+    TripPhaseType, Vehicle,
 };
-use geom::Distance;
+use geom::{Distance, Duration};
 use map_model::{
-    BuildingID, IntersectionID, LaneID, Map, Path, PathConstraints, PathRequest, PathStep,
-    Position, Traversable, TurnID, TurnType,
+    BuildingID, IntersectionID, LaneID, Map, ParkingLotID, Path, PathConstraints, PathRequest,
+    PathStep, Position, Traversable, TurnID, TurnType, PARKING_SPOT_LENGTH,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
+// Only switch routes if it'd save at least this much time
+const MIN_REROUTE_SAVINGS: Duration = Duration::const_seconds(60.0);
+// Trucks only unload in a free parking spot this close to where they'd otherwise stop
+const MAX_LOADING_ZONE_DIST: Distance = Distance::const_meters(30.0);
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Router {
     // Front is always the current step
@@ -24,6 +30,8 @@ pub enum ActionAtEnd {
     GotoLaneEnd,
     StopBiking(SidewalkSpot),
     BusAtStop,
+    RideHailAtCurb,
+    DoubleParkToDeliver,
     GiveUpOnParking,
 }
 
@@ -38,6 +46,12 @@ enum Goal {
         stuck_end_dist: Option<Distance>,
         started_looking: bool,
     },
+    // When the lot is full, fall back to any public spot nearby.
+    ParkAtLot {
+        lot: ParkingLotID,
+        spot: Option<(ParkingSpot, Distance)>,
+        stuck_end_dist: Option<Distance>,
+    },
     EndAtBorder {
         end_dist: Distance,
         i: IntersectionID,
@@ -48,6 +62,17 @@ enum Goal {
     FollowBusRoute {
         end_dist: Distance,
     },
+    StopAtCurb {
+        end_dist: Distance,
+    },
+    // Use a free parking spot close to the building as a loading zone if there is one, otherwise
+    // double-park at end_dist.
+    Deliver {
+        target: BuildingID,
+        end_dist: Distance,
+        spot: Option<ParkingSpot>,
+        looked: bool,
+    },
 }
 
 impl Router {
@@ -80,6 +105,17 @@ impl Router {
         }
     }
 
+    pub fn park_at_lot(path: Path, lot: ParkingLotID) -> Router {
+        Router {
+            path,
+            goal: Goal::ParkAtLot {
+                lot,
+                spot: None,
+                stuck_end_dist: None,
+            },
+        }
+    }
+
     pub fn bike_then_stop(path: Path, end_dist: Distance, map: &Map) -> Option<Router> {
         let last_lane = path.get_steps().iter().last().unwrap().as_lane();
         if map
@@ -104,6 +140,25 @@ impl Router {
         }
     }
 
+    pub fn stop_at_curb(path: Path, end_dist: Distance) -> Router {
+        Router {
+            path,
+            goal: Goal::StopAtCurb { end_dist },
+        }
+    }
+
+    pub fn deliver(path: Path, end_dist: Distance, target: BuildingID) -> Router {
+        Router {
+            path,
+            goal: Goal::Deliver {
+                target,
+                end_dist,
+                spot: None,
+                looked: false,
+            },
+        }
+    }
+
     pub fn head(&self) -> Traversable {
         self.path.current_step().as_traversable()
     }
@@ -133,9 +188,16 @@ impl Router {
                 spot,
                 stuck_end_dist,
                 ..
+            }
+            | Goal::ParkAtLot {
+                spot,
+                stuck_end_dist,
+                ..
             } => stuck_end_dist.unwrap_or_else(|| spot.unwrap().1),
             Goal::BikeThenStop { end_dist } => end_dist,
             Goal::FollowBusRoute { end_dist } => end_dist,
+            Goal::StopAtCurb { end_dist } => end_dist,
+            Goal::Deliver { end_dist, .. } => end_dist,
         }
     }
 
@@ -223,7 +285,7 @@ impl Router {
                     let candidates = parking.get_all_free_spots(
                         Position::new(current_lane, front),
                         vehicle,
-                        target,
+                        Some(target),
                         map,
                     );
                     let best = if let Some(ref p) = map.get_b(target).parking {
@@ -260,8 +322,8 @@ impl Router {
                         }
                         *spot = Some((new_spot, new_pos.dist_along()));
                     } else {
-                        if let Some((new_path_steps, new_spot, new_pos)) =
-                            parking.path_to_free_parking_spot(current_lane, vehicle, target, map)
+                        if let Some((new_path_steps, new_spot, new_pos)) = parking
+                            .path_to_free_parking_spot(current_lane, vehicle, Some(target), map)
                         {
                             *spot = Some((new_spot, new_pos.dist_along()));
                             for step in new_path_steps {
@@ -299,6 +361,102 @@ impl Router {
                     None
                 }
             }
+            Goal::ParkAtLot {
+                lot,
+                ref mut spot,
+                ref mut stuck_end_dist,
+            } => {
+                if let Some(d) = stuck_end_dist {
+                    if *d == front {
+                        return Some(ActionAtEnd::GiveUpOnParking);
+                    } else {
+                        return None;
+                    }
+                }
+
+                let need_new_spot = match spot {
+                    Some((s, _)) => !parking.is_free(*s),
+                    None => true,
+                };
+                if need_new_spot {
+                    let current_lane = self.path.current_step().as_lane();
+                    // If the car already gave up on the lot and went looking elsewhere, don't
+                    // turn back.
+                    let at_lot = map.get_pl(lot).driving_pos.lane() == current_lane;
+                    let lot_spot = if at_lot {
+                        parking.get_free_lot_spots(lot).into_iter().next()
+                    } else {
+                        None
+                    };
+                    if let Some(new_spot) = lot_spot {
+                        let pos = parking.spot_to_driving_pos(new_spot, vehicle, map);
+                        *spot = Some((new_spot, pos.dist_along()));
+                    } else {
+                        if let (true, Some((_, p))) = (at_lot, trip_and_person) {
+                            events.push(Event::Alert(
+                                AlertLocation::Person(p),
+                                format!(
+                                    "{} is full, so {} is looking for parking nearby",
+                                    lot, vehicle.id
+                                ),
+                            ));
+                        }
+                        // Settle for any public spot nearby. The rest of the trip starts walking
+                        // from wherever the car winds up.
+                        let start = Position::new(current_lane, front);
+                        if let Some((new_spot, new_pos)) = parking
+                            .get_all_free_spots(start, vehicle, None, map)
+                            .into_iter()
+                            .min_by_key(|(_, pos)| pos.dist_along())
+                        {
+                            *spot = Some((new_spot, new_pos.dist_along()));
+                            if let Some((t, p)) = trip_and_person {
+                                events.push(Event::TripPhaseStarting(
+                                    t,
+                                    p,
+                                    Some(PathRequest {
+                                        start,
+                                        end: new_pos,
+                                        constraints: PathConstraints::Car,
+                                    }),
+                                    TripPhaseType::Parking,
+                                ));
+                            }
+                        } else {
+                            if let Some((new_path_steps, new_spot, new_pos)) =
+                                parking.path_to_free_parking_spot(current_lane, vehicle, None, map)
+                            {
+                                *spot = Some((new_spot, new_pos.dist_along()));
+                                for step in new_path_steps {
+                                    self.path.add(step, map);
+                                }
+                                events.push(Event::PathAmended(self.path.clone()));
+                                if let Some((t, p)) = trip_and_person {
+                                    events.push(Event::TripPhaseStarting(
+                                        t,
+                                        p,
+                                        Some(PathRequest {
+                                            start,
+                                            end: new_pos,
+                                            constraints: PathConstraints::Car,
+                                        }),
+                                        TripPhaseType::Parking,
+                                    ));
+                                }
+                            } else {
+                                *stuck_end_dist = Some(map.get_l(current_lane).length());
+                            }
+                            return Some(ActionAtEnd::GotoLaneEnd);
+                        }
+                    }
+                }
+
+                if spot.unwrap().1 == front {
+                    Some(ActionAtEnd::StartParking(spot.unwrap().0))
+                } else {
+                    None
+                }
+            }
             Goal::BikeThenStop { end_dist } => {
                 if end_dist == front {
                     // Checked up-front that this exists
@@ -321,6 +479,63 @@ impl Router {
                     None
                 }
             }
+            Goal::StopAtCurb { end_dist } => {
+                if end_dist == front {
+                    Some(ActionAtEnd::RideHailAtCurb)
+                } else {
+                    None
+                }
+            }
+            Goal::Deliver {
+                target,
+                ref mut end_dist,
+                ref mut spot,
+                ref mut looked,
+            } => {
+                if !*looked {
+                    *looked = true;
+                    let current_lane = self.path.current_step().as_lane();
+                    let best = parking
+                        .get_all_free_spots(
+                            Position::new(current_lane, front),
+                            vehicle,
+                            Some(target),
+                            map,
+                        )
+                        .into_iter()
+                        .filter(|(s, pos)| match s {
+                            ParkingSpot::Onstreet(_, _) => {
+                                vehicle.length <= PARKING_SPOT_LENGTH
+                                    && (pos.dist_along() - *end_dist).abs() <= MAX_LOADING_ZONE_DIST
+                            }
+                            ParkingSpot::Offstreet(b, _) => *b == target,
+                            ParkingSpot::Lot(_, _) => false,
+                        })
+                        .min_by_key(|(_, pos)| (pos.dist_along() - *end_dist).abs());
+                    if let Some((new_spot, new_pos)) = best {
+                        *spot = Some(new_spot);
+                        *end_dist = new_pos.dist_along();
+                        if *end_dist != front {
+                            return Some(ActionAtEnd::GotoLaneEnd);
+                        }
+                    }
+                }
+                // If somebody else took the spot first, just double-park there instead.
+                if let Some(s) = spot {
+                    if !parking.is_free(*s) {
+                        *spot = None;
+                    }
+                }
+
+                if *end_dist == front {
+                    Some(match spot {
+                        Some(s) => ActionAtEnd::StartParking(*s),
+                        None => ActionAtEnd::DoubleParkToDeliver,
+                    })
+                } else {
+                    None
+                }
+            }
         }
     }
 
@@ -401,6 +616,51 @@ impl Router {
         self.path.modify_step(3, PathStep::Turn(turn2), map);
     }
 
+    // Looks for a faster route to the same place, given the extra time expected on some lanes
+    // right now, from queued cars and incidents. Switches if the current route can't be followed
+    // anymore (like after a road is closed) or if it'd save a significant amount of time. Only for
+    // cars driving somewhere, not for buses or cars already looking for parking. Returns true if
+    // the route changed.
+    pub fn maybe_reroute(
+        &mut self,
+        vehicle: &Vehicle,
+        delays: &BTreeMap<LaneID, Duration>,
+        map: &Map,
+        events: &mut Vec<Event>,
+    ) -> bool {
+        match self.goal {
+            Goal::EndAtBorder { .. }
+            | Goal::ParkAtLot { spot: None, .. }
+            | Goal::StopAtCurb { .. }
+            | Goal::Deliver { .. } => {}
+            Goal::ParkNearBuilding {
+                started_looking: false,
+                ..
+            } => {}
+            _ => {
+                return false;
+            }
+        }
+        if self.path.is_last_step()
+            || self.path.about_to_start_ut().is_some()
+            || self.path.currently_inside_ut().is_some()
+        {
+            return false;
+        }
+
+        if self.path.reroute_with_delays(
+            vehicle.vehicle_type.to_constraints(),
+            delays,
+            MIN_REROUTE_SAVINGS,
+            map,
+        ) {
+            events.push(Event::PathAmended(self.path.clone()));
+            true
+        } else {
+            false
+        }
+    }
+
     pub fn replace_path_for_serialization(&mut self, path: Path) -> Path {
         std::mem::replace(&mut self.path, path)
     }
@@ -1,9 +1,9 @@
 use crate::{
-    pandemic, AgentID, CarID, CreateCar, CreatePedestrian, PedestrianID, TripID, TripSpec,
+    pandemic, AgentID, CarID, CreateCar, CreatePedestrian, PedestrianID, PersonID, TripID, TripSpec,
 };
 use derivative::Derivative;
 use geom::{Duration, Histogram, Time};
-use map_model::{BusRouteID, IntersectionID, Path, PathRequest};
+use map_model::{BuildingID, BusRouteID, IntersectionID, Path, PathRequest};
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 use std::collections::btree_map::Entry;
@@ -23,7 +23,13 @@ pub enum Command {
     Callback(Duration),
     Pandemic(pandemic::Cmd),
     FinishRemoteTrip(TripID),
-    SeedBus(BusRouteID),
+    // Which of the route's scheduled departures, if it has a schedule
+    SeedBus(BusRouteID, usize),
+    // From one building to another
+    RequestRide(TripID, PersonID, BuildingID, BuildingID),
+    // Indexes into the incidents from SimOptions
+    StartIncident(usize),
+    EndIncident(usize),
 }
 
 impl Command {
@@ -47,7 +53,10 @@ impl Command {
             Command::Callback(_) => CommandType::Callback,
             Command::Pandemic(ref p) => CommandType::Pandemic(p.clone()),
             Command::FinishRemoteTrip(t) => CommandType::FinishRemoteTrip(*t),
-            Command::SeedBus(r) => CommandType::SeedBus(*r),
+            Command::SeedBus(r, _) => CommandType::SeedBus(*r),
+            Command::RequestRide(t, _, _, _) => CommandType::RequestRide(*t),
+            Command::StartIncident(idx) => CommandType::StartIncident(*idx),
+            Command::EndIncident(idx) => CommandType::EndIncident(*idx),
         }
     }
 }
@@ -65,6 +74,9 @@ pub enum CommandType {
     Pandemic(pandemic::Cmd),
     FinishRemoteTrip(TripID),
     SeedBus(BusRouteID),
+    RequestRide(TripID),
+    StartIncident(usize),
+    EndIncident(usize),
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone)]
@@ -172,6 +184,11 @@ impl Scheduler {
         self.queued_commands.remove(&cmd.to_type());
     }
 
+    // It's fine if a command of this type hasn't actually been scheduled.
+    pub fn cancel_by_type(&mut self, cmd: CommandType) {
+        self.queued_commands.remove(&cmd);
+    }
+
     // TODO Should panic if a command of this type isn't scheduled. But currently failing
     // unexpectedly.
     pub fn must_cancel_by_type(&mut self, cmd: CommandType) {
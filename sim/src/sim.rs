@@ -1,12 +1,12 @@
 use crate::analytics::Window;
 use crate::{
-    AgentID, AgentType, AlertLocation, Analytics, CarID, Command, CreateCar, DrawCarInput,
-    DrawPedCrowdInput, DrawPedestrianInput, DrivingSimState, Event, GetDrawAgents,
-    IntersectionSimState, OrigPersonID, PandemicModel, ParkedCar, ParkingSimState, ParkingSpot,
-    PedestrianID, Person, PersonID, PersonState, Router, Scheduler, SidewalkPOI, SidewalkSpot,
-    TransitSimState, TripID, TripInfo, TripManager, TripPhaseType, TripResult, TripSpawner,
-    UnzoomedAgent, Vehicle, VehicleSpec, VehicleType, WalkingSimState, BUS_LENGTH,
-    LIGHT_RAIL_LENGTH, MIN_CAR_LENGTH,
+    AgentID, AgentType, AlertLocation, Analytics, CarID, Command, CreateCar, CriticalGaps,
+    DrawCarInput, DrawPedCrowdInput, DrawPedestrianInput, DrivingSimState, Event, EventLog,
+    GetDrawAgents, Incident, IntersectionSimState, OrigPersonID, PandemicModel, PandemicParams,
+    ParkedCar, ParkingSimState, ParkingSpot, PedestrianID, Person, PersonID, PersonState,
+    RideHailSimState, Router, Scheduler, SidewalkPOI, SidewalkSpot, TransitSimState, TripID,
+    TripInfo, TripManager, TripPhaseType, TripResult, TripSpawner, UnzoomedAgent, Vehicle,
+    VehicleSpec, VehicleType, WalkingSimState, BUS_LENGTH, LIGHT_RAIL_LENGTH, MIN_CAR_LENGTH,
 };
 use abstutil::Timer;
 use derivative::Derivative;
@@ -18,7 +18,7 @@ use map_model::{
 };
 use rand_xorshift::XorShiftRng;
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::panic;
 
 // TODO Do something else.
@@ -32,12 +32,16 @@ pub struct Sim {
     walking: WalkingSimState,
     intersections: IntersectionSimState,
     transit: TransitSimState,
+    ridehail: RideHailSimState,
     trips: TripManager,
     #[derivative(PartialEq = "ignore")]
     #[serde(skip_serializing, skip_deserializing)]
     pandemic: Option<PandemicModel>,
     scheduler: Scheduler,
     time: Time,
+    incidents: Vec<Incident>,
+    // Indexes into incidents
+    active_incidents: BTreeSet<usize>,
 
     // TODO Reconsider these
     pub(crate) map_name: String,
@@ -58,6 +62,10 @@ pub struct Sim {
     #[derivative(PartialEq = "ignore")]
     #[serde(skip_serializing, skip_deserializing)]
     alerts: AlertHandler,
+
+    #[derivative(PartialEq = "ignore")]
+    #[serde(skip_serializing, skip_deserializing)]
+    event_log: Option<EventLog>,
 }
 
 #[derive(Clone)]
@@ -68,8 +76,31 @@ pub struct SimOptions {
     pub recalc_lanechanging: bool,
     pub break_turn_conflict_cycles: bool,
     pub enable_pandemic_model: Option<XorShiftRng>,
+    // Only used if the pandemic model is enabled
+    pub pandemic_params: PandemicParams,
     pub alerts: AlertHandler,
     pub pathfinding_upfront: bool,
+    // Append every event to this file as the sim runs. See EventLog for the format.
+    pub event_log: Option<String>,
+    // Transit vehicles with a schedule wait at timepoints if they're early.
+    pub hold_transit_at_timepoints: bool,
+    // Drivers periodically look for a faster route, given current congestion.
+    pub reroute_drivers: bool,
+    // How many vehicles are in the ride-hail fleet
+    pub ride_hail_fleet: usize,
+    // Vehicles speed up and slow down gradually instead of instantly moving at the speed limit, and
+    // follow the vehicle in front of them: they plan their speed to stay a time headway behind it,
+    // pull up behind it when it stops, and start moving a moment after it does. So spacing grows
+    // with speed, and queues discharge with some start-up lost time.
+    pub acceleration_profiles: bool,
+    // Vehicles making yield turns at stop signs wait for a gap in conflicting traffic this big.
+    // If None, they go as soon as nothing conflicting has started.
+    pub stop_sign_gaps: Option<CriticalGaps>,
+    // A timeline of lane blockages, slowdowns, and intersection closures. SimFlags checks these
+    // against the map before they get here.
+    pub incidents: Vec<Incident>,
+    // Record how long cars take to cross every lane and turn. Only traffic assignment needs this.
+    pub record_car_crossings: bool,
 }
 
 #[derive(Clone)]
@@ -97,8 +128,17 @@ impl SimOptions {
             recalc_lanechanging: true,
             break_turn_conflict_cycles: true,
             enable_pandemic_model: None,
+            pandemic_params: PandemicParams::new(),
             alerts: AlertHandler::Print,
             pathfinding_upfront: false,
+            event_log: None,
+            hold_transit_at_timepoints: true,
+            reroute_drivers: false,
+            ride_hail_fleet: 0,
+            acceleration_profiles: false,
+            stop_sign_gaps: None,
+            incidents: Vec::new(),
+            record_car_crossings: false,
         }
     }
 }
@@ -107,8 +147,18 @@ impl SimOptions {
 impl Sim {
     pub fn new(map: &Map, opts: SimOptions, timer: &mut Timer) -> Sim {
         let mut scheduler = Scheduler::new();
+        let mut trips = TripManager::new(opts.pathfinding_upfront);
+        let ridehail = RideHailSimState::new(opts.ride_hail_fleet, &mut trips, map);
+        for (idx, incident) in opts.incidents.iter().enumerate() {
+            scheduler.push(incident.start, Command::StartIncident(idx));
+        }
         Sim {
-            driving: DrivingSimState::new(map, opts.recalc_lanechanging),
+            driving: DrivingSimState::new(
+                map,
+                opts.recalc_lanechanging,
+                opts.reroute_drivers,
+                opts.acceleration_profiles,
+            ),
             parking: ParkingSimState::new(map, timer),
             walking: WalkingSimState::new(),
             intersections: IntersectionSimState::new(
@@ -117,16 +167,20 @@ impl Sim {
                 opts.use_freeform_policy_everywhere,
                 opts.dont_block_the_box,
                 opts.break_turn_conflict_cycles,
+                opts.stop_sign_gaps.clone(),
             ),
-            transit: TransitSimState::new(),
-            trips: TripManager::new(opts.pathfinding_upfront),
+            transit: TransitSimState::new(opts.hold_transit_at_timepoints),
+            ridehail,
+            trips,
             pandemic: if let Some(rng) = opts.enable_pandemic_model {
-                Some(PandemicModel::new(rng))
+                Some(PandemicModel::new(opts.pandemic_params, rng))
             } else {
                 None
             },
             scheduler,
             time: Time::START_OF_DAY,
+            incidents: opts.incidents,
+            active_incidents: BTreeSet::new(),
 
             map_name: map.get_name().to_string(),
             // TODO
@@ -134,8 +188,9 @@ impl Sim {
             run_name: opts.run_name,
             step_count: 0,
             alerts: opts.alerts,
+            event_log: opts.event_log.map(EventLog::new),
 
-            analytics: Analytics::new(),
+            analytics: Analytics::new_with_car_crossings(opts.record_car_crossings),
         }
     }
 
@@ -189,14 +244,14 @@ impl Sim {
         // TODO Refactor the logic in router
         let spot = if let Some((spot, _)) = self
             .parking
-            .get_all_free_spots(Position::start(driving_lane), &vehicle, b, map)
+            .get_all_free_spots(Position::start(driving_lane), &vehicle, Some(b), map)
             .get(0)
         {
             spot.clone()
         } else {
             let (_, spot, _) =
                 self.parking
-                    .path_to_free_parking_spot(driving_lane, &vehicle, b, map)?;
+                    .path_to_free_parking_spot(driving_lane, &vehicle, Some(b), map)?;
             spot
         };
 
@@ -229,8 +284,48 @@ impl Sim {
     }
 
     pub(crate) fn seed_bus_route(&mut self, route: &BusRoute, map: &Map, timer: &mut Timer) {
+        self.transit.create_empty_route(route, map);
+        if route.spawn_times.is_empty() {
+            self.spawn_bus(route, 0, map, timer);
+        } else if let Some(idx) = route.spawn_times.iter().position(|t| *t >= self.time) {
+            self.schedule_bus(route, idx, map);
+        }
+    }
+
+    // Scheduled departures are from the first stop, so a vehicle starting at a border has to
+    // appear early enough to get there on time. Several vehicles may depart at the same time.
+    fn schedule_bus(&mut self, route: &BusRoute, idx: usize, map: &Map) {
+        let lead_time = if route.start_border.is_some() {
+            let (_, path) = self.transit.first_step(route.id);
+            path.get_steps()
+                .iter()
+                .map(|step| {
+                    let on = step.as_traversable();
+                    on.length(map) / on.speed_limit(map)
+                })
+                .sum::<Duration>()
+        } else {
+            Duration::ZERO
+        };
+        let t = route.spawn_times[idx].clamped_sub(lead_time).max(self.time);
+        self.scheduler.push(t, Command::SeedBus(route.id, idx));
+    }
+
+    // idx is into the route's spawn_times, if it has a schedule
+    fn spawn_bus(&mut self, route: &BusRoute, idx: usize, map: &Map, timer: &mut Timer) {
+        // Schedule the next one. Without a schedule from GTFS, just run every hour.
+        // TODO Let the player change the rate of spawning
+        if route.spawn_times.is_empty() {
+            self.scheduler.push(
+                self.time + Duration::hours(1),
+                Command::SeedBus(route.id, 0),
+            );
+        } else if idx + 1 < route.spawn_times.len() {
+            self.schedule_bus(route, idx + 1, map);
+        }
+
         // Spawn one bus for the first leg.
-        let (req, path) = self.transit.create_empty_route(route, map);
+        let (req, path) = self.transit.first_step(route.id);
 
         // For now, no desire for randomness. Caller can pass in list of specs if that ever
         // changes.
@@ -263,20 +358,22 @@ impl Sim {
                     req,
                     maybe_parked_car: None,
                     trip_and_person: None,
-                    maybe_route: Some(route.id),
+                    maybe_route: Some((route.id, idx)),
                 },
                 true,
             ),
         );
-
-        // TODO Change the rate of spawning based on a schedule from GTFS or player's choice
-        self.scheduler
-            .push(self.time + Duration::hours(1), Command::SeedBus(route.id));
     }
 
     pub fn set_name(&mut self, name: String) {
         self.run_name = name;
     }
+
+    // Instead of pathfinding when they start driving, these trips will follow the given path, as
+    // long as the request is the same. Used for iterative traffic assignment.
+    pub fn assign_paths(&mut self, paths: BTreeMap<TripID, (PathRequest, Path)>) {
+        self.trips.assign_paths(paths);
+    }
 }
 
 // Drawing
@@ -408,7 +505,13 @@ impl Sim {
                 );
             }
             Command::SpawnCar(create_car, retry_if_no_room) => {
-                if self.driving.start_car_on_lane(
+                if self.driving.spawn_blocked_by_incident(&create_car) {
+                    // Wait it out, even if we wouldn't retry when there's just no room
+                    self.scheduler.push(
+                        self.time + BLIND_RETRY_TO_SPAWN,
+                        Command::SpawnCar(create_car, retry_if_no_room),
+                    );
+                } else if self.driving.start_car_on_lane(
                     self.time,
                     create_car.clone(),
                     map,
@@ -423,10 +526,10 @@ impl Sim {
                             trip,
                             person,
                             Some(create_car.req.clone()),
-                            if create_car.vehicle.id.1 == VehicleType::Car {
-                                TripPhaseType::Driving
-                            } else {
+                            if create_car.vehicle.id.1 == VehicleType::Bike {
                                 TripPhaseType::Biking
+                            } else {
+                                TripPhaseType::Driving
                             },
                         ));
                     }
@@ -440,8 +543,9 @@ impl Sim {
                         }
                         self.parking.remove_parked_car(parked_car);
                     }
-                    if let Some(route) = create_car.maybe_route {
-                        self.transit.bus_created(create_car.vehicle.id, route);
+                    if let Some((route, idx)) = create_car.maybe_route {
+                        self.transit
+                            .bus_created(create_car.vehicle.id, route, idx, map);
                     }
                     self.analytics
                         .record_demand(create_car.router.get_path(), map);
@@ -517,6 +621,7 @@ impl Sim {
                     &mut self.trips,
                     &mut self.scheduler,
                     &mut self.transit,
+                    &mut self.ridehail,
                     &mut self.walking,
                 );
             }
@@ -542,8 +647,13 @@ impl Sim {
                 );
             }
             Command::UpdateIntersection(i) => {
-                self.intersections
-                    .update_intersection(self.time, i, map, &mut self.scheduler);
+                self.intersections.update_intersection(
+                    self.time,
+                    i,
+                    map,
+                    &mut self.scheduler,
+                    &self.driving,
+                );
             }
             Command::Callback(frequency) => {
                 self.scheduler
@@ -553,10 +663,14 @@ impl Sim {
                 }
             }
             Command::Pandemic(cmd) => {
-                self.pandemic
-                    .as_mut()
-                    .unwrap()
-                    .handle_cmd(self.time, cmd, &mut self.scheduler);
+                self.pandemic.as_mut().unwrap().handle_cmd(
+                    self.time,
+                    cmd,
+                    map,
+                    &mut self.trips,
+                    &mut self.transit,
+                    &mut self.scheduler,
+                );
             }
             Command::FinishRemoteTrip(trip) => {
                 self.trips.remote_trip_finished(
@@ -567,8 +681,43 @@ impl Sim {
                     &mut self.scheduler,
                 );
             }
-            Command::SeedBus(r) => {
-                self.seed_bus_route(map.get_br(r), map, &mut Timer::throwaway());
+            Command::SeedBus(r, idx) => {
+                self.spawn_bus(map.get_br(r), idx, map, &mut Timer::throwaway());
+            }
+            Command::RequestRide(trip, person, from, to) => {
+                self.ridehail.request_ride(
+                    self.time,
+                    trip,
+                    person,
+                    from,
+                    to,
+                    &mut self.trips,
+                    &mut self.parking,
+                    &mut self.scheduler,
+                    map,
+                );
+            }
+            Command::StartIncident(idx) => {
+                let incident = &self.incidents[idx];
+                self.active_incidents.insert(idx);
+                self.scheduler
+                    .push(self.time + incident.duration, Command::EndIncident(idx));
+                events.push(Event::Alert(
+                    AlertLocation::Nil,
+                    format!("Incident started: {}", incident.effect.describe()),
+                ));
+                self.update_incidents(map);
+            }
+            Command::EndIncident(idx) => {
+                self.active_incidents.remove(&idx);
+                events.push(Event::Alert(
+                    AlertLocation::Nil,
+                    format!(
+                        "Incident cleared: {}",
+                        self.incidents[idx].effect.describe()
+                    ),
+                ));
+                self.update_incidents(map);
             }
         }
 
@@ -581,19 +730,34 @@ impl Sim {
     fn dispatch_events(&mut self, mut events: Vec<Event>, map: &Map) {
         events.extend(self.trips.collect_events());
         events.extend(self.transit.collect_events());
+        events.extend(self.ridehail.collect_events());
         events.extend(self.driving.collect_events());
         events.extend(self.walking.collect_events());
         events.extend(self.intersections.collect_events());
         events.extend(self.parking.collect_events());
         for ev in events {
             if let Some(ref mut m) = self.pandemic {
-                m.handle_event(self.time, &ev, &mut self.scheduler);
+                m.handle_event(self.time, &ev, map, &mut self.scheduler);
+            }
+            if let Some(ref mut log) = self.event_log {
+                log.record(self.time, &ev);
             }
 
             self.analytics.event(ev, self.time, map);
         }
     }
 
+    fn update_incidents(&mut self, map: &Map) {
+        let incidents = &self.incidents;
+        let active = self
+            .active_incidents
+            .iter()
+            .map(|idx| &incidents[*idx].effect)
+            .collect();
+        self.driving
+            .set_incidents(active, self.time, map, &mut self.scheduler);
+    }
+
     pub fn timed_step(
         &mut self,
         map: &Map,
@@ -638,6 +802,9 @@ impl Sim {
                 last_update = Instant::now();
             }
         }
+        if let Some(ref mut log) = self.event_log {
+            log.flush();
+        }
         timer.stop(format!("Advance sim to {}", end_time));
     }
     pub fn tiny_step(&mut self, map: &Map, maybe_cb: &mut Option<Box<dyn SimCallback>>) {
@@ -798,6 +965,10 @@ impl Sim {
                 "- transit: {} bytes",
                 abstutil::prettyprint_usize(abstutil::serialized_size_bytes(&self.transit))
             );
+            println!(
+                "- ridehail: {} bytes",
+                abstutil::prettyprint_usize(abstutil::serialized_size_bytes(&self.ridehail))
+            );
             println!(
                 "- trips: {} bytes",
                 abstutil::prettyprint_usize(abstutil::serialized_size_bytes(&self.trips))
@@ -834,6 +1005,13 @@ impl Sim {
         Ok(sim)
     }
 
+    pub(crate) fn check_incidents(&self, map: &Map) -> Result<(), String> {
+        for incident in &self.incidents {
+            incident.check(map)?;
+        }
+        Ok(())
+    }
+
     pub fn restore_paths(&mut self, map: &Map, timer: &mut Timer) {
         let paths = timer.parallelize(
             "calculate paths",
@@ -848,6 +1026,107 @@ impl Sim {
     }
 }
 
+// Determinism checks
+impl Sim {
+    // Resuming from a savestate should never change the future. This runs the sim until
+    // savestate_at, saves, and keeps running until compare_at. Then it loads the savestate, runs
+    // that until compare_at too, and compares the two. Returns the first difference found.
+    pub fn verify_savestate_determinism(
+        &mut self,
+        map: &Map,
+        savestate_at: Time,
+        compare_at: Time,
+        timer: &mut Timer,
+    ) -> Option<String> {
+        assert!(self.time <= savestate_at && savestate_at <= compare_at);
+        if self.pandemic.is_some() {
+            // The pandemic model isn't part of savestates, so it can't be resumed.
+            return Some("can't verify a sim with the pandemic model enabled".to_string());
+        }
+
+        self.timed_step(map, savestate_at - self.time, &mut None, timer);
+        let path = self.save();
+        // Analytics aren't part of savestates, so carry them over manually.
+        let analytics = self.analytics.clone();
+        self.timed_step(map, compare_at - self.time, &mut None, timer);
+
+        let loaded = Sim::load_savestate(path.clone(), map, timer);
+        // The savestate is only needed to resume from, so don't leave it in the player's saves
+        if let Err(err) = std::fs::remove_file(&path) {
+            println!("Couldn't clean up savestate {}: {}", path, err);
+        }
+        // Only succeeds if there are no other savestates from this run
+        let _ = std::fs::remove_dir(self.save_dir());
+        let mut resumed = match loaded {
+            Ok(sim) => sim,
+            Err(err) => {
+                return Some(format!("couldn't load savestate {}: {}", path, err));
+            }
+        };
+        resumed.analytics = analytics;
+        resumed.alerts = self.alerts.clone();
+        resumed.timed_step(map, compare_at - resumed.time, &mut None, timer);
+
+        self.find_divergence(&resumed, map)
+    }
+
+    fn find_divergence(&self, other: &Sim, map: &Map) -> Option<String> {
+        if self.time != other.time {
+            return Some(format!("time is {} vs {}", self.time, other.time));
+        }
+
+        // Agent positions are the most understandable difference, so check them first.
+        let agents1: BTreeSet<AgentID> = self.active_agents().into_iter().collect();
+        let agents2: BTreeSet<AgentID> = other.active_agents().into_iter().collect();
+        if let Some(a) = agents1.symmetric_difference(&agents2).next() {
+            return Some(format!(
+                "{} is active in only one sim ({} vs {} agents)",
+                a,
+                agents1.len(),
+                agents2.len()
+            ));
+        }
+        for a in agents1 {
+            let pt1 = self.canonical_pt_for_agent(a, map);
+            let pt2 = other.canonical_pt_for_agent(a, map);
+            if pt1 != pt2 {
+                return Some(format!("{} is at {:?} vs {:?}", a, pt1, pt2));
+            }
+        }
+
+        if let Some(diff) = self.trips.find_divergence(&other.trips) {
+            return Some(diff);
+        }
+        if let Some(diff) = self.analytics.find_divergence(&other.analytics) {
+            return Some(format!("Analytics: {}", diff));
+        }
+
+        // Fallback to just saying which piece is different.
+        if self.driving != other.driving {
+            return Some("driving state differs".to_string());
+        }
+        if self.parking != other.parking {
+            return Some("parking state differs".to_string());
+        }
+        if self.walking != other.walking {
+            return Some("walking state differs".to_string());
+        }
+        if self.intersections != other.intersections {
+            return Some("intersection state differs".to_string());
+        }
+        if self.transit != other.transit {
+            return Some("transit state differs".to_string());
+        }
+        if self.ridehail != other.ridehail {
+            return Some("ride-hail state differs".to_string());
+        }
+        if self.scheduler != other.scheduler {
+            return Some("scheduler differs".to_string());
+        }
+        None
+    }
+}
+
 // Queries of all sorts
 impl Sim {
     pub fn time(&self) -> Time {
@@ -867,7 +1146,7 @@ impl Sim {
         self.trips.num_trips()
     }
     pub fn num_agents(&self) -> BTreeMap<AgentType, usize> {
-        self.trips.num_agents(&self.transit)
+        self.trips.num_agents(&self.transit, &self.ridehail)
     }
     // (total number of people, just in buildings, just off map)
     pub fn num_ppl(&self) -> (usize, usize, usize) {
@@ -910,6 +1189,10 @@ impl Sim {
         }
     }
 
+    pub fn ride_hail_fleet_size(&self) -> usize {
+        self.ridehail.fleet_size()
+    }
+
     pub fn num_transit_passengers(&self, car: CarID) -> usize {
         self.transit.get_passengers(car).len()
     }
@@ -986,6 +1269,7 @@ impl Sim {
             VehicleType::Bike,
             VehicleType::Bus,
             VehicleType::Train,
+            VehicleType::Truck,
         ] {
             let id = CarID(idx, *vt);
             if self.driving.does_car_exist(id) {
@@ -993,10 +1277,12 @@ impl Sim {
             }
         }
 
-        let id = CarID(idx, VehicleType::Car);
-        // Only cars can be parked.
-        if self.parking.lookup_parked_car(id).is_some() {
-            return Some(id);
+        // Only cars can be parked, and trucks stopping at loading zones.
+        for vt in &[VehicleType::Car, VehicleType::Truck] {
+            let id = CarID(idx, *vt);
+            if self.parking.lookup_parked_car(id).is_some() {
+                return Some(id);
+            }
         }
 
         None
@@ -1131,6 +1417,11 @@ impl Sim {
             .current_phase_and_remaining_time(self.time, i)
     }
 
+    // Index into the signal's timing plans; see ControlTrafficSignal::plan_phases
+    pub fn current_signal_plan(&self, i: IntersectionID) -> usize {
+        self.intersections.current_plan(i)
+    }
+
     // TODO This is an awkward copy of raw_throughput
     // TODO And it does NOT count buses/trains spawning
     pub fn all_arrivals_at_border(
@@ -1233,3 +1524,460 @@ pub struct AgentProperties {
     pub lanes_crossed: usize,
     pub total_lanes: usize,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        DrivingGoal, IncidentEffect, IndividTrip, PersonSpec, Scenario, SimFlags, SpawnTrip,
+    };
+    use geom::{GPSBounds, LonLat, Polygon};
+    use map_model::raw::{OriginalIntersection, OriginalRoad, RawIntersection, RawMap, RawRoad};
+    use map_model::{osm, EditCmd, EditIntersection, IntersectionType, LaneType, RoadSpec};
+
+    // A 2x2 grid of intersections, one with a traffic signal, with a border sticking out of each
+    // side. Roads are 200m long.
+    fn grid_map() -> Map {
+        let mut raw = RawMap::blank("test", "grid");
+        let spacing = 200.0;
+        let id = |x: usize, y: usize| OriginalIntersection {
+            osm_node_id: (10 * x + y) as i64,
+        };
+        let is_border = |x: usize, y: usize| x == 0 || x == 3 || y == 0 || y == 3;
+        for x in 0..4 {
+            for y in 0..4 {
+                // No corners
+                if is_border(x, y) && (x == 0 || x == 3) && (y == 0 || y == 3) {
+                    continue;
+                }
+                raw.intersections.insert(
+                    id(x, y),
+                    RawIntersection {
+                        point: Pt2D::new(spacing * (x as f64), spacing * (y as f64)),
+                        intersection_type: if is_border(x, y) {
+                            IntersectionType::Border
+                        } else if x == 1 && y == 1 {
+                            IntersectionType::TrafficSignal
+                        } else {
+                            IntersectionType::StopSign
+                        },
+                        elevation: Distance::ZERO,
+                    },
+                );
+            }
+        }
+
+        let mut roads = Vec::new();
+        for a in 1..3 {
+            for b in 0..3 {
+                // Horizontal and vertical
+                roads.push((id(b, a), id(b + 1, a)));
+                roads.push((id(a, b), id(a, b + 1)));
+            }
+        }
+        for (i1, i2) in roads {
+            insert_road(
+                &mut raw,
+                i1,
+                i2,
+                RoadSpec {
+                    fwd: vec![LaneType::Driving, LaneType::Parking, LaneType::Sidewalk],
+                    back: vec![LaneType::Driving, LaneType::Parking, LaneType::Sidewalk],
+                },
+                false,
+            );
+        }
+
+        finish_map(raw, 3.0 * spacing)
+    }
+
+    // Four intersections around a one-way ring of roads tagged junction=roundabout, with a road
+    // to a border sticking out of each. Roads are 200m long.
+    fn roundabout_map() -> Map {
+        let mut raw = RawMap::blank("test", "roundabout");
+        let id = |n: usize| OriginalIntersection {
+            osm_node_id: n as i64,
+        };
+        // The ring, then the borders
+        let points = vec![
+            (200.0, 200.0),
+            (400.0, 200.0),
+            (400.0, 400.0),
+            (200.0, 400.0),
+            (200.0, 0.0),
+            (600.0, 200.0),
+            (400.0, 600.0),
+            (0.0, 400.0),
+        ];
+        for (n, (x, y)) in points.into_iter().enumerate() {
+            raw.intersections.insert(
+                id(n),
+                RawIntersection {
+                    point: Pt2D::new(x, y),
+                    intersection_type: if n < 4 {
+                        IntersectionType::StopSign
+                    } else {
+                        IntersectionType::Border
+                    },
+                    elevation: Distance::ZERO,
+                },
+            );
+        }
+        for n in 0..4 {
+            insert_road(
+                &mut raw,
+                id(n),
+                id((n + 1) % 4),
+                RoadSpec {
+                    fwd: vec![LaneType::Driving],
+                    back: Vec::new(),
+                },
+                true,
+            );
+            insert_road(
+                &mut raw,
+                id(n + 4),
+                id(n),
+                RoadSpec {
+                    fwd: vec![LaneType::Driving],
+                    back: vec![LaneType::Driving],
+                },
+                false,
+            );
+        }
+
+        finish_map(raw, 600.0)
+    }
+
+    fn insert_road(
+        raw: &mut RawMap,
+        i1: OriginalIntersection,
+        i2: OriginalIntersection,
+        spec: RoadSpec,
+        roundabout: bool,
+    ) {
+        let idx = raw.roads.len();
+        let road = OriginalRoad {
+            osm_way_id: idx as i64,
+            i1,
+            i2,
+        };
+        let mut osm_tags = BTreeMap::new();
+        osm_tags.insert(osm::SYNTHETIC.to_string(), "true".to_string());
+        osm_tags.insert(osm::SYNTHETIC_LANES.to_string(), spec.to_string());
+        osm_tags.insert(osm::ENDPT_FWD.to_string(), "true".to_string());
+        osm_tags.insert(osm::ENDPT_BACK.to_string(), "true".to_string());
+        osm_tags.insert(osm::OSM_WAY_ID.to_string(), idx.to_string());
+        osm_tags.insert(osm::MAXSPEED.to_string(), "25 mph".to_string());
+        if roundabout {
+            osm_tags.insert("junction".to_string(), "roundabout".to_string());
+        }
+        raw.roads.insert(
+            road,
+            RawRoad {
+                center_points: vec![raw.intersections[&i1].point, raw.intersections[&i2].point],
+                osm_tags,
+                turn_restrictions: Vec::new(),
+                complicated_turn_restrictions: Vec::new(),
+            },
+        );
+    }
+
+    // Like map_editor does for synthetic maps
+    fn finish_map(mut raw: RawMap, max: f64) -> Map {
+        raw.boundary_polygon = Polygon::rectangle(max, max);
+        let mut seattle_bounds = GPSBounds::new();
+        seattle_bounds.update(LonLat::new(-122.453224, 47.723277));
+        seattle_bounds.update(LonLat::new(-122.240505, 47.495342));
+        raw.gps_bounds = GPSBounds::new();
+        raw.gps_bounds
+            .update(Pt2D::new(0.0, 0.0).to_gps(&seattle_bounds));
+        raw.gps_bounds
+            .update(Pt2D::new(max, max).to_gps(&seattle_bounds));
+
+        Map::create_from_raw(raw, true, &mut Timer::throwaway())
+    }
+
+    // Everybody drives or walks between every pair of borders, starting over a few minutes.
+    fn border_to_border(map: &Map) -> Scenario {
+        let borders: Vec<IntersectionID> = map
+            .all_intersections()
+            .iter()
+            .filter(|i| i.is_border())
+            .map(|i| i.id)
+            .collect();
+        let mut people = Vec::new();
+        for from in &borders {
+            for to in &borders {
+                if from == to {
+                    continue;
+                }
+                let depart = Time::START_OF_DAY + Duration::seconds(5.0 * (people.len() as f64));
+                let trip = if people.len() % 4 == 0 {
+                    SpawnTrip::JustWalking(
+                        SidewalkSpot::start_at_border(*from, None, map).unwrap(),
+                        SidewalkSpot::end_at_border(*to, None, map).unwrap(),
+                    )
+                } else {
+                    SpawnTrip::FromBorder {
+                        dr: map.get_i(*from).some_outgoing_road(map).unwrap(),
+                        goal: DrivingGoal::end_at_border(
+                            map.get_i(*to).some_incoming_road(map).unwrap(),
+                            PathConstraints::Car,
+                            None,
+                            map,
+                        )
+                        .unwrap(),
+                        is_bike: false,
+                        origin: None,
+                    }
+                };
+                people.push(PersonSpec {
+                    id: PersonID(people.len()),
+                    orig_id: None,
+                    trips: vec![IndividTrip::new(depart, trip)],
+                });
+            }
+        }
+        Scenario {
+            scenario_name: "border_to_border".to_string(),
+            map_name: map.get_name().to_string(),
+            people,
+            only_seed_buses: None,
+        }
+    }
+
+    #[test]
+    fn test_savestate_determinism() {
+        let map = grid_map();
+        let scenario = border_to_border(&map);
+        for acceleration_profiles in vec![false, true] {
+            let mut flags = SimFlags::synthetic_test("grid", "test_savestate_determinism");
+            flags.opts.acceleration_profiles = acceleration_profiles;
+            let mut rng = flags.make_rng();
+            let mut timer = Timer::throwaway();
+            let mut sim = Sim::new(&map, flags.opts, &mut timer);
+            scenario.instantiate(&mut sim, &map, &mut rng, &mut timer);
+
+            // Save in the middle of everybody starting, with plenty of agents moving around
+            let savestate_at = Time::START_OF_DAY + Duration::minutes(2);
+            let compare_at = Time::START_OF_DAY + Duration::minutes(15);
+            if let Some(diff) =
+                sim.verify_savestate_determinism(&map, savestate_at, compare_at, &mut timer)
+            {
+                panic!(
+                    "Resuming from a savestate at {} diverged by {}: {}",
+                    savestate_at, compare_at, diff
+                );
+            }
+            assert!(!sim.get_analytics().finished_trips.is_empty());
+        }
+    }
+
+    // Drive between two borders, identified by OSM node ID.
+    fn drive(map: &Map, from: i64, to: i64, depart: Time) -> IndividTrip {
+        let from = map.get_i(map.find_i_by_osm_id(from).unwrap());
+        let to = map.get_i(map.find_i_by_osm_id(to).unwrap());
+        IndividTrip::new(
+            depart,
+            SpawnTrip::FromBorder {
+                dr: from.some_outgoing_road(map).unwrap(),
+                goal: DrivingGoal::end_at_border(
+                    to.some_incoming_road(map).unwrap(),
+                    PathConstraints::Car,
+                    None,
+                    map,
+                )
+                .unwrap(),
+                is_bike: false,
+                origin: None,
+            },
+        )
+    }
+
+    // When does each trip start its turn through an intersection?
+    fn entering_times(map: &Map, i: i64, trips: Vec<IndividTrip>, opts: SimOptions) -> Vec<Time> {
+        let i = map.find_i_by_osm_id(i).unwrap();
+        let num_trips = trips.len();
+        let scenario = Scenario {
+            scenario_name: "entering_times".to_string(),
+            map_name: map.get_name().to_string(),
+            people: trips
+                .into_iter()
+                .enumerate()
+                .map(|(idx, trip)| PersonSpec {
+                    id: PersonID(idx),
+                    orig_id: None,
+                    trips: vec![trip],
+                })
+                .collect(),
+            only_seed_buses: None,
+        };
+        let flags = SimFlags::synthetic_test(map.get_name(), "entering_times");
+        let mut rng = flags.make_rng();
+        let mut timer = Timer::throwaway();
+        let mut sim = Sim::new(map, opts, &mut timer);
+        scenario.instantiate(&mut sim, map, &mut rng, &mut timer);
+
+        let mut times: BTreeMap<PersonID, Time> = BTreeMap::new();
+        while times.len() < num_trips {
+            assert!(
+                sim.time() < Time::START_OF_DAY + Duration::minutes(10),
+                "Only {} of {} trips went through {} by {}",
+                times.len(),
+                num_trips,
+                i,
+                sim.time()
+            );
+            sim.tiny_step(map, &mut None);
+            for a in sim.get_accepted_agents(i) {
+                if let Some(p) = sim.agent_to_person(a) {
+                    times.entry(p).or_insert_with(|| sim.time());
+                }
+            }
+        }
+        times.values().cloned().collect()
+    }
+
+    // The first car passes through the intersection without entering there. Time the second car to
+    // reach the intersection just before the first, and make sure it waits.
+    fn check_entry_yields(map: &Map, i: i64, first: (i64, i64), second: (i64, i64)) {
+        let start = Time::START_OF_DAY;
+        let opts = || SimOptions::new("check_entry_yields");
+        let first_alone =
+            entering_times(map, i, vec![drive(map, first.0, first.1, start)], opts())[0];
+        let second_alone =
+            entering_times(map, i, vec![drive(map, second.0, second.1, start)], opts())[0];
+        // Delay whichever car needs it so that the second would get there 2s before the first
+        let lead = Duration::seconds(2.0);
+        let (first_depart, second_depart) = if first_alone >= second_alone + lead {
+            (start, start + (first_alone - second_alone - lead))
+        } else {
+            (start + (second_alone + lead - first_alone), start)
+        };
+
+        let both = entering_times(
+            map,
+            i,
+            vec![
+                drive(map, first.0, first.1, first_depart),
+                drive(map, second.0, second.1, second_depart),
+            ],
+            opts(),
+        );
+        assert!(
+            both[1] > both[0],
+            "Entering car went at {}, before the circulating car at {}",
+            both[1],
+            both[0]
+        );
+    }
+
+    #[test]
+    fn test_roundabout_entry_yields() {
+        let map = roundabout_map();
+        // From the north, one car enters the ring and circulates past the east approach. Another
+        // enters from the east.
+        check_entry_yields(&map, 1, (4, 6), (5, 6));
+    }
+
+    #[test]
+    fn test_mini_roundabout_entry_yields() {
+        let mut map = grid_map();
+        let i = map.find_i_by_osm_id(22).unwrap();
+        let mut edits = map.get_edits().clone();
+        edits.commands.push(EditCmd::ChangeIntersection {
+            i,
+            old: map.get_i_edit(i),
+            new: EditIntersection::Roundabout,
+        });
+        let mut timer = Timer::throwaway();
+        map.must_apply_edits(edits, &mut timer);
+        map.recalculate_pathfinding_after_edits(&mut timer);
+
+        // Going north from the south border passes in front of the east approach
+        check_entry_yields(&map, 22, (23, 20), (32, 20));
+    }
+
+    // Cars queue behind an intersection that's closed for a while, then go through it once it
+    // reopens.
+    fn queue_discharge(map: &Map, acceleration_profiles: bool) -> Vec<Duration> {
+        let mut opts = SimOptions::new("queue_discharge");
+        opts.acceleration_profiles = acceleration_profiles;
+        opts.incidents = vec![Incident {
+            start: Time::START_OF_DAY,
+            duration: Duration::seconds(90.0),
+            effect: IncidentEffect::CloseIntersection(map.find_i_by_osm_id(11).unwrap()),
+        }];
+        let trips = (0..6)
+            .map(|_| drive(map, 10, 31, Time::START_OF_DAY))
+            .collect();
+        let mut times = entering_times(map, 11, trips, opts);
+        times.sort();
+        assert!(times[0] >= Time::START_OF_DAY + Duration::seconds(90.0));
+        times.windows(2).map(|pair| pair[1] - pair[0]).collect()
+    }
+
+    #[test]
+    fn test_queue_discharge() {
+        let map = grid_map();
+        // Without acceleration profiles, each car follows right behind the one in front.
+        for gap in queue_discharge(&map, false) {
+            assert!(
+                gap < Duration::seconds(1.5),
+                "Queue discharged {} apart",
+                gap
+            );
+        }
+        // With them, each driver waits a moment after the one in front starts moving, then has to
+        // speed up from a stop.
+        for gap in queue_discharge(&map, true) {
+            assert!(
+                gap >= Duration::seconds(2.0),
+                "Queue discharged {} apart",
+                gap
+            );
+        }
+    }
+
+    #[test]
+    fn test_check_incidents() {
+        let map = grid_map();
+        let mut flags = SimFlags::synthetic_test("grid", "test_check_incidents");
+        let incident = |effect| Incident {
+            start: Time::START_OF_DAY,
+            duration: Duration::minutes(5),
+            effect,
+        };
+        let lane = |lt| {
+            map.all_lanes()
+                .iter()
+                .find(|l| l.lane_type == lt)
+                .unwrap()
+                .id
+        };
+        let driving = lane(LaneType::Driving);
+        flags.opts.incidents = vec![
+            incident(IncidentEffect::BlockLane(driving)),
+            incident(IncidentEffect::ReduceSpeed(driving, 0.5)),
+            incident(IncidentEffect::CloseIntersection(IntersectionID(0))),
+        ];
+        assert!(flags.check_incidents(&map).is_ok());
+
+        for effect in vec![
+            IncidentEffect::BlockLane(LaneID(map.all_lanes().len())),
+            IncidentEffect::BlockLane(lane(LaneType::Sidewalk)),
+            IncidentEffect::ReduceSpeed(lane(LaneType::Parking), 0.5),
+            IncidentEffect::ReduceSpeed(driving, 0.0),
+            IncidentEffect::ReduceSpeed(driving, 1.5),
+            IncidentEffect::CloseIntersection(IntersectionID(map.all_intersections().len())),
+        ] {
+            flags.opts.incidents = vec![incident(effect.clone())];
+            assert!(
+                flags.check_incidents(&map).is_err(),
+                "{} should be rejected",
+                effect.describe()
+            );
+        }
+    }
+}
@@ -1,13 +1,25 @@
 use crate::{
-    CarID, Event, PedestrianID, PersonID, Router, Scheduler, TripID, TripManager, TripPhaseType,
-    VehicleType, WalkingSimState,
+    CarID, Event, ParkingSimState, PedestrianID, PersonID, Router, Scheduler, TripID, TripManager,
+    TripPhaseType, VehicleType, WalkingSimState,
 };
 use abstutil::{deserialize_btreemap, serialize_btreemap};
-use geom::Time;
+use geom::{Duration, Time};
 use map_model::{BusRoute, BusRouteID, BusStopID, Map, Path, PathRequest, Position};
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet};
 
+// How long a vehicle spends at a stop: a fixed cost to pull in and open the doors, plus time for
+// each passenger. Trains have more doors, so passengers get on and off faster.
+const DWELL_BASE: Duration = Duration::const_seconds(5.0);
+const BUS_TIME_PER_BOARDING: Duration = Duration::const_seconds(3.0);
+const BUS_TIME_PER_ALIGHTING: Duration = Duration::const_seconds(1.5);
+const TRAIN_TIME_PER_BOARDING: Duration = Duration::const_seconds(0.5);
+const TRAIN_TIME_PER_ALIGHTING: Duration = Duration::const_seconds(0.5);
+
+// Including standing room
+const BUS_CAPACITY: usize = 80;
+const TRAIN_CAPACITY: usize = 400;
+
 // These index stops along a route, not stops along a single sidewalk.
 type StopIdx = usize;
 
@@ -33,6 +45,9 @@ struct Bus {
     // Where does each passenger want to deboard?
     passengers: Vec<(PersonID, BusStopID)>,
     state: BusState,
+    // Which of the route's scheduled departures this vehicle is running, if the route has a
+    // schedule
+    schedule: Option<usize>,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
@@ -62,22 +77,32 @@ pub struct TransitSimState {
         deserialize_with = "deserialize_btreemap"
     )]
     peds_waiting: BTreeMap<BusStopID, Vec<(PedestrianID, BusRouteID, BusStopID, Time)>>,
+    // If a vehicle reaches a timepoint early, wait until the scheduled time to depart
+    hold_at_timepoints: bool,
+    // Only fill up vehicles to this fraction of their capacity
+    capacity_pct: f64,
 
     events: Vec<Event>,
 }
 
 impl TransitSimState {
-    pub fn new() -> TransitSimState {
+    pub fn new(hold_at_timepoints: bool) -> TransitSimState {
         TransitSimState {
             buses: BTreeMap::new(),
             routes: BTreeMap::new(),
             peds_waiting: BTreeMap::new(),
+            hold_at_timepoints,
+            capacity_pct: 1.0,
             events: Vec::new(),
         }
     }
 
-    // Returns the path for the first leg.
-    pub fn create_empty_route(&mut self, bus_route: &BusRoute, map: &Map) -> (PathRequest, Path) {
+    // Passengers already aboard stay on, but nobody else boards a full vehicle.
+    pub fn limit_capacity(&mut self, pct: f64) {
+        self.capacity_pct = pct;
+    }
+
+    pub fn create_empty_route(&mut self, bus_route: &BusRoute, map: &Map) {
         assert!(bus_route.stops.len() > 1);
 
         let mut stops = Vec::new();
@@ -136,10 +161,6 @@ impl TransitSimState {
             None
         };
 
-        let first_step = start_from_border
-            .clone()
-            .or(stops[0].next_stop.clone())
-            .unwrap();
         self.routes.insert(
             bus_route.id,
             Route {
@@ -149,10 +170,20 @@ impl TransitSimState {
                 end_at_border,
             },
         );
-        first_step
     }
 
-    pub fn bus_created(&mut self, bus: CarID, r: BusRouteID) {
+    // The path for the first leg of a new vehicle on the route
+    pub fn first_step(&self, r: BusRouteID) -> (PathRequest, Path) {
+        let route = &self.routes[&r];
+        route
+            .start_from_border
+            .clone()
+            .or_else(|| route.stops[0].next_stop.clone())
+            .unwrap()
+    }
+
+    // idx is into the route's spawn_times, if it has a schedule
+    pub fn bus_created(&mut self, bus: CarID, r: BusRouteID, idx: usize, map: &Map) {
         let route = self.routes.get_mut(&r).unwrap();
         route.active_vehicles.insert(bus);
         self.buses.insert(
@@ -166,21 +197,27 @@ impl TransitSimState {
                 } else {
                     BusState::DrivingToStop(1)
                 },
+                schedule: if map.get_br(r).spawn_times.is_empty() {
+                    None
+                } else {
+                    Some(idx)
+                },
             },
         );
     }
 
-    // If true, the bus is idling. If false, the bus actually arrived at a border and should now
-    // vanish.
+    // If the bus is idling, returns how long it'll wait at the stop. If None, the bus actually
+    // arrived at a border and should now vanish.
     pub fn bus_arrived_at_stop(
         &mut self,
         now: Time,
         id: CarID,
         trips: &mut TripManager,
         walking: &mut WalkingSimState,
+        parking: &ParkingSimState,
         scheduler: &mut Scheduler,
         map: &Map,
-    ) -> bool {
+    ) -> Option<Duration> {
         let mut bus = self.buses.get_mut(&id).unwrap();
         match bus.state {
             BusState::DrivingToStop(stop_idx) => {
@@ -188,15 +225,21 @@ impl TransitSimState {
                 let stop1 = self.routes[&bus.route].stops[stop_idx].id;
                 self.events
                     .push(Event::BusArrivedAtStop(id, bus.route, stop1));
+                let (time_per_boarding, time_per_alighting) = match bus.car.1 {
+                    VehicleType::Train => (TRAIN_TIME_PER_BOARDING, TRAIN_TIME_PER_ALIGHTING),
+                    _ => (BUS_TIME_PER_BOARDING, BUS_TIME_PER_ALIGHTING),
+                };
+                let mut dwell = DWELL_BASE;
 
                 // Deboard existing passengers.
                 let mut still_riding = Vec::new();
                 for (person, stop2) in bus.passengers.drain(..) {
                     if stop1 == stop2 {
-                        trips.person_left_bus(now, person, bus.car, map, scheduler);
+                        trips.person_left_bus(now, person, bus.car, map, parking, scheduler);
                         self.events.push(Event::PassengerAlightsTransit(
                             person, bus.car, bus.route, stop1,
                         ));
+                        dwell += time_per_alighting;
                     } else {
                         still_riding.push((person, stop2));
                     }
@@ -205,10 +248,16 @@ impl TransitSimState {
 
                 // Board new passengers.
                 let mut still_waiting = Vec::new();
+                let mut left_behind = 0;
                 for (ped, route, stop2, started_waiting) in
                     self.peds_waiting.remove(&stop1).unwrap_or_else(Vec::new)
                 {
-                    if bus.route == route {
+                    if bus.route == route
+                        && bus.passengers.len() >= capacity(bus.car, self.capacity_pct)
+                    {
+                        left_behind += 1;
+                        still_waiting.push((ped, route, stop2, started_waiting));
+                    } else if bus.route == route {
                         let (trip, person) = trips.ped_boarded_bus(
                             now,
                             ped,
@@ -234,12 +283,37 @@ impl TransitSimState {
                             TripPhaseType::RidingBus(route, stop1, bus.car),
                         ));
                         bus.passengers.push((person, stop2));
+                        dwell += time_per_boarding;
                     } else {
                         still_waiting.push((ped, route, stop2, started_waiting));
                     }
                 }
                 self.peds_waiting.insert(stop1, still_waiting);
-                true
+                if left_behind > 0 {
+                    self.events.push(Event::PassengersLeftBehind(
+                        bus.car,
+                        bus.route,
+                        stop1,
+                        left_behind,
+                    ));
+                }
+
+                // Compare to the schedule, and maybe wait for it to catch up. If there wasn't room
+                // to spawn the vehicle right away, it's already running a bit late.
+                if let Some(sched) = bus.schedule {
+                    let route = map.get_br(bus.route);
+                    if let Some(offset) = route.timepoints[sched].get(stop_idx).cloned().flatten() {
+                        let late = now - (route.spawn_times[sched] + offset);
+                        self.events.push(Event::BusArrivedAtTimepoint(
+                            bus.car, bus.route, stop1, late,
+                        ));
+                        if self.hold_at_timepoints && late + dwell < Duration::ZERO {
+                            dwell = Duration::ZERO - late;
+                        }
+                    }
+                }
+
+                Some(dwell)
             }
             BusState::DrivingOffMap => {
                 self.routes
@@ -248,7 +322,7 @@ impl TransitSimState {
                     .active_vehicles
                     .remove(&id);
                 bus.state = BusState::Done;
-                false
+                None
             }
             BusState::AtStop(_) | BusState::Done => unreachable!(),
         }
@@ -297,12 +371,22 @@ impl TransitSimState {
         if let Some(route) = self.routes.get(&route_id) {
             for bus in &route.active_vehicles {
                 if let BusState::AtStop(idx) = self.buses[bus].state {
-                    if route.stops[idx].id == stop1 {
+                    if route.stops[idx].id == stop1
+                        && self.buses[bus].passengers.len() < capacity(*bus, self.capacity_pct)
+                    {
                         self.buses
                             .get_mut(bus)
                             .unwrap()
                             .passengers
                             .push((person, stop2));
+                        // Each leg of a trip records its own wait, even if there wasn't one
+                        self.events.push(Event::PassengerBoardsTransit(
+                            person,
+                            *bus,
+                            route_id,
+                            stop1,
+                            Duration::ZERO,
+                        ));
                         self.events.push(Event::TripPhaseStarting(
                             trip,
                             person,
@@ -386,3 +470,12 @@ impl TransitSimState {
         (buses, trains)
     }
 }
+
+fn capacity(vehicle: CarID, pct: f64) -> usize {
+    let full = match vehicle.1 {
+        VehicleType::Train => TRAIN_CAPACITY,
+        _ => BUS_CAPACITY,
+    };
+    // Always let at least one person on
+    ((full as f64) * pct).max(1.0) as usize
+}
@@ -1,18 +1,22 @@
 use crate::{
-    AgentID, AgentType, AlertLocation, CarID, Command, CreateCar, CreatePedestrian, DrivingGoal,
-    Event, OffMapLocation, OrigPersonID, ParkedCar, ParkingSimState, ParkingSpot, PedestrianID,
-    PersonID, Scheduler, SidewalkPOI, SidewalkSpot, TransitSimState, TripID, TripPhaseType,
-    TripSpec, Vehicle, VehicleSpec, VehicleType, WalkingSimState,
+    AgentID, AgentType, AlertLocation, CarID, Command, CommandType, CreateCar, CreatePedestrian,
+    Curb, DrivingGoal, Event, OffMapLocation, OrigPersonID, ParkedCar, ParkingSimState,
+    ParkingSpot, PedestrianID, PersonID, RideHailSimState, Router, Scheduler, SidewalkPOI,
+    SidewalkSpot, TransitSimState, TripID, TripPhaseType, TripSpec, Vehicle, VehicleSpec,
+    VehicleType, WalkingSimState,
 };
 use abstutil::{deserialize_btreemap, serialize_btreemap, Counter};
 use geom::{Duration, Speed, Time};
 use map_model::{
-    BuildingID, BusRouteID, BusStopID, IntersectionID, Map, Path, PathConstraints, PathRequest,
-    Position,
+    BuildingID, BusRouteID, BusStopID, IntersectionID, LaneID, Map, Path, PathConstraints,
+    PathRequest, Position,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, VecDeque};
 
+// How long a truck stays at each stop on a delivery tour
+const TIME_TO_DELIVER: Duration = Duration::const_seconds(300.0);
+
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct TripManager {
     trips: Vec<Trip>,
@@ -25,6 +29,8 @@ pub struct TripManager {
     active_trip_mode: BTreeMap<AgentID, TripID>,
     unfinished_trips: usize,
     pub pathfinding_upfront: bool,
+    // Driving trips follow these paths instead of pathfinding, as long as their request matches.
+    assigned_paths: BTreeMap<TripID, (PathRequest, Path)>,
 
     car_id_counter: usize,
 
@@ -41,9 +47,14 @@ impl TripManager {
             car_id_counter: 0,
             events: Vec::new(),
             pathfinding_upfront,
+            assigned_paths: BTreeMap::new(),
         }
     }
 
+    pub fn assign_paths(&mut self, paths: BTreeMap<TripID, (PathRequest, Path)>) {
+        self.assigned_paths = paths;
+    }
+
     // TODO assert the specs are correct yo
     pub fn new_person(
         &mut self,
@@ -108,6 +119,7 @@ impl TripManager {
             Some(TripLeg::Drive(_, ref goal)) => match goal {
                 DrivingGoal::ParkNear(b) => TripEndpoint::Bldg(*b),
                 DrivingGoal::Border(i, _, loc) => TripEndpoint::Border(*i, loc.clone()),
+                DrivingGoal::ParkAtLot(_) | DrivingGoal::BikeToStop(_) => unreachable!(),
             },
             Some(TripLeg::Remote(ref to)) => {
                 TripEndpoint::Border(map.all_incoming_borders()[0].id, Some(to.clone()))
@@ -184,9 +196,24 @@ impl TripManager {
         trip.total_blocked_time += blocked_time;
 
         match trip.legs.pop_front() {
-            Some(TripLeg::Drive(c, DrivingGoal::ParkNear(_))) => {
+            Some(TripLeg::Drive(c, DrivingGoal::ParkNear(_)))
+            | Some(TripLeg::Drive(c, DrivingGoal::ParkAtLot(_))) => {
                 assert_eq!(car, c);
             }
+            // A truck unloading at a loading zone
+            Some(TripLeg::Deliver(c, b)) => {
+                assert_eq!(car, c);
+                let id = trip.id;
+                self.events.push(Event::DeliveryStop(car, b, None));
+                self.events.push(Event::TripPhaseStarting(
+                    id,
+                    trip.person,
+                    None,
+                    TripPhaseType::Delivering,
+                ));
+                self.truck_leaves_loading_zone(now, id, spot, map, parking, scheduler);
+                return;
+            }
             _ => unreachable!(),
         };
 
@@ -220,6 +247,7 @@ impl TripManager {
             SidewalkSpot::parking_spot(spot, map, parking),
             &self.people[trip.person.0],
             map,
+            parking,
             scheduler,
             &mut self.events,
         ) {
@@ -227,6 +255,137 @@ impl TripManager {
         }
     }
 
+    fn truck_leaves_loading_zone(
+        &mut self,
+        now: Time,
+        id: TripID,
+        spot: ParkingSpot,
+        map: &Map,
+        parking: &mut ParkingSimState,
+        scheduler: &mut Scheduler,
+    ) {
+        let parked_car = parking.get_car_at_spot(spot).unwrap().clone();
+        let mut start = parking.spot_to_driving_pos(spot, &parked_car.vehicle, map);
+        match spot {
+            ParkingSpot::Onstreet(_, _) => {}
+            ParkingSpot::Offstreet(b, _) => {
+                self.events
+                    .push(Event::PersonEntersBuilding(self.trips[id.0].person, b));
+                start = Position::new(start.lane(), start.dist_along() + parked_car.vehicle.length);
+            }
+            ParkingSpot::Lot(_, _) => {
+                start = Position::new(start.lane(), start.dist_along() + parked_car.vehicle.length);
+            }
+        }
+
+        if let Some((req, router)) = self.next_delivery_leg(id, start, None, map) {
+            scheduler.push(
+                now + TIME_TO_DELIVER,
+                Command::SpawnCar(
+                    CreateCar::for_parked_car(
+                        parked_car,
+                        router,
+                        req,
+                        start.dist_along(),
+                        id,
+                        self.trips[id.0].person,
+                    ),
+                    true,
+                ),
+            );
+        } else {
+            parking.remove_parked_car(parked_car.clone());
+            self.abort_trip(now, id, Some(parked_car.vehicle), parking, scheduler, map);
+        }
+    }
+
+    // Returns how long to stay double-parked and the route to follow afterwards, or None if the
+    // trip was aborted.
+    pub fn truck_double_parked(
+        &mut self,
+        now: Time,
+        car: CarID,
+        pos: Position,
+        map: &Map,
+        parking: &mut ParkingSimState,
+        scheduler: &mut Scheduler,
+    ) -> Option<(Duration, Router)> {
+        let id = self.active_trip_mode[&AgentID::Car(car)];
+        let trip = &mut self.trips[id.0];
+        match trip.legs.pop_front() {
+            Some(TripLeg::Deliver(c, b)) => {
+                assert_eq!(car, c);
+                self.events
+                    .push(Event::DeliveryStop(car, b, Some(pos.lane())));
+            }
+            _ => unreachable!(),
+        };
+        self.events.push(Event::TripPhaseStarting(
+            id,
+            trip.person,
+            None,
+            TripPhaseType::Delivering,
+        ));
+
+        if let Some((_, router)) = self.next_delivery_leg(id, pos, Some(pos.lane()), map) {
+            Some((TIME_TO_DELIVER, router))
+        } else {
+            self.active_trip_mode.remove(&AgentID::Car(car));
+            self.abort_trip(now, id, None, parking, scheduler, map);
+            None
+        }
+    }
+
+    // Routes a truck from a stop to the next one, or off the map after the last. Stops behind the
+    // truck on the same lane are delivered to right away, instead of circling the block.
+    fn next_delivery_leg(
+        &mut self,
+        id: TripID,
+        start: Position,
+        double_parked: Option<LaneID>,
+        map: &Map,
+    ) -> Option<(PathRequest, Router)> {
+        let trip = &mut self.trips[id.0];
+        loop {
+            let leg = trip.legs[0].clone();
+            let end = match leg {
+                TripLeg::Deliver(car, b) => {
+                    let end = Curb::near_bldg(b, map).unwrap().driving_pos;
+                    if end.lane() == start.lane() && end.dist_along() <= start.dist_along() {
+                        trip.legs.pop_front();
+                        self.events.push(Event::DeliveryStop(car, b, double_parked));
+                        continue;
+                    }
+                    end
+                }
+                TripLeg::Drive(_, ref goal) => goal.goal_pos(PathConstraints::Truck, map),
+                _ => unreachable!(),
+            };
+            let req = PathRequest {
+                start,
+                end,
+                constraints: PathConstraints::Truck,
+            };
+            let path = if let Some(p) = assigned_path(&mut self.assigned_paths, id, &req)
+                .or_else(|| map.pathfind(req.clone()))
+            {
+                p
+            } else {
+                self.events.push(Event::Alert(
+                    AlertLocation::Person(trip.person),
+                    format!("Aborting {} because no path for the truck: {}", id, req),
+                ));
+                return None;
+            };
+            let router = match leg {
+                TripLeg::Deliver(_, b) => Router::deliver(path, end.dist_along(), b),
+                TripLeg::Drive(_, goal) => goal.make_router(path, map, VehicleType::Truck)?,
+                _ => unreachable!(),
+            };
+            return Some((req, router));
+        }
+    }
+
     pub fn ped_reached_parking_spot(
         &mut self,
         now: Time,
@@ -274,7 +433,9 @@ impl TripManager {
             end,
             constraints: PathConstraints::Car,
         };
-        let path = if let Some(p) = map.pathfind(req.clone()) {
+        let path = if let Some(p) = assigned_path(&mut self.assigned_paths, trip.id, &req)
+            .or_else(|| map.pathfind(req.clone()))
+        {
             p
         } else {
             self.events.push(Event::Alert(
@@ -382,6 +543,7 @@ impl TripManager {
         bike_rack: SidewalkSpot,
         blocked_time: Duration,
         map: &Map,
+        parking: &ParkingSimState,
         scheduler: &mut Scheduler,
     ) {
         self.events.push(Event::BikeStoppedAtSidewalk(
@@ -392,7 +554,8 @@ impl TripManager {
         trip.total_blocked_time += blocked_time;
 
         match trip.legs.pop_front() {
-            Some(TripLeg::Drive(c, DrivingGoal::ParkNear(_))) => {
+            Some(TripLeg::Drive(c, DrivingGoal::ParkNear(_)))
+            | Some(TripLeg::Drive(c, DrivingGoal::BikeToStop(_))) => {
                 assert_eq!(c, bike);
             }
             _ => unreachable!(),
@@ -403,6 +566,7 @@ impl TripManager {
             bike_rack,
             &self.people[trip.person.0],
             map,
+            parking,
             scheduler,
             &mut self.events,
         ) {
@@ -520,6 +684,47 @@ impl TripManager {
         (trip.id, trip.person)
     }
 
+    pub fn ride_hail_picked_up(&mut self, trip: TripID, car: CarID) {
+        let person = self.trips[trip.0].person;
+        self.active_trip_mode
+            .insert(AgentID::BusPassenger(person, car), trip);
+        self.people[person.0].on_bus = Some(car);
+    }
+
+    pub fn person_left_ride_hail(
+        &mut self,
+        now: Time,
+        person: PersonID,
+        car: CarID,
+        dropoff: Position,
+        map: &Map,
+        parking: &ParkingSimState,
+        scheduler: &mut Scheduler,
+    ) {
+        let trip = &mut self.trips[self
+            .active_trip_mode
+            .remove(&AgentID::BusPassenger(person, car))
+            .unwrap()
+            .0];
+        match trip.legs.pop_front().unwrap() {
+            TripLeg::RideHail(_) => {}
+            _ => unreachable!(),
+        }
+        self.people[person.0].on_bus.take().unwrap();
+
+        if !trip.spawn_ped(
+            now,
+            SidewalkSpot::suddenly_appear(dropoff.lane(), dropoff.dist_along(), map),
+            &self.people[trip.person.0],
+            map,
+            parking,
+            scheduler,
+            &mut self.events,
+        ) {
+            self.unfinished_trips -= 1;
+        }
+    }
+
     // TODO Need to characterize delay the bus experienced
     pub fn person_left_bus(
         &mut self,
@@ -527,6 +732,7 @@ impl TripManager {
         person: PersonID,
         bus: CarID,
         map: &Map,
+        parking: &ParkingSimState,
         scheduler: &mut Scheduler,
     ) {
         let trip = &mut self.trips[self
@@ -545,6 +751,7 @@ impl TripManager {
             start,
             &self.people[trip.person.0],
             map,
+            parking,
             scheduler,
             &mut self.events,
         ) {
@@ -682,6 +889,43 @@ impl TripManager {
         self.events.push(Event::TripAborted(trip.id));
     }
 
+    // Cancel the person's remaining trips, starting with the first that hasn't started yet and
+    // matches. Returns how many were cancelled.
+    pub fn cancel_unstarted_trips<F: Fn(&TripInfo) -> bool>(
+        &mut self,
+        person: PersonID,
+        scheduler: &mut Scheduler,
+        matches: F,
+    ) -> usize {
+        let remaining: Vec<TripID> = self.people[person.0]
+            .trips
+            .iter()
+            .filter(|t| {
+                let trip = &self.trips[t.0];
+                !trip.started && !trip.aborted && !trip.cancelled
+            })
+            .cloned()
+            .collect();
+        let first = match remaining
+            .iter()
+            .position(|t| matches(&self.trips[t.0].info))
+        {
+            Some(idx) => idx,
+            None => {
+                return 0;
+            }
+        };
+        for t in &remaining[first..] {
+            self.cancel_trip(*t);
+            scheduler.cancel_by_type(CommandType::StartTrip(*t));
+        }
+        let cancelled = &remaining[first..];
+        self.people[person.0]
+            .delayed_trips
+            .retain(|(t, _, _, _)| !cancelled.contains(t));
+        cancelled.len()
+    }
+
     pub fn abort_trip(
         &mut self,
         now: Time,
@@ -722,13 +966,13 @@ impl TripManager {
                 if let TripEndpoint::Bldg(b) = trip.info.end {
                     let driving_lane = map.find_driving_lane_near_building(b);
                     if let Some(spot) = parking
-                        .get_all_free_spots(Position::start(driving_lane), &vehicle, b, map)
+                        .get_all_free_spots(Position::start(driving_lane), &vehicle, Some(b), map)
                         // TODO Could pick something closer, but meh, aborted trips are bugs anyway
                         .get(0)
                         .map(|(spot, _)| spot.clone())
                         .or_else(|| {
                             parking
-                                .path_to_free_parking_spot(driving_lane, &vehicle, b, map)
+                                .path_to_free_parking_spot(driving_lane, &vehicle, Some(b), map)
                                 .map(|(_, spot, _)| spot)
                         })
                     {
@@ -755,10 +999,13 @@ impl TripManager {
         } else {
             // If the trip was aborted because we'e totally out of parking, don't forget to clean
             // this up.
-            if let TripLeg::Drive(c, _) = &trip.legs[0] {
-                if let Some(t) = self.active_trip_mode.remove(&AgentID::Car(*c)) {
-                    assert_eq!(t, trip.id);
+            match &trip.legs[0] {
+                TripLeg::Drive(c, _) | TripLeg::Deliver(c, _) => {
+                    if let Some(t) = self.active_trip_mode.remove(&AgentID::Car(*c)) {
+                        assert_eq!(t, trip.id);
+                    }
                 }
+                _ => {}
             }
         }
 
@@ -795,8 +1042,16 @@ impl TripManager {
         let person = &self.people[trip.person.0];
         let a = match &trip.legs[0] {
             TripLeg::Walk(_) => AgentID::Pedestrian(person.ped),
-            TripLeg::Drive(c, _) => AgentID::Car(*c),
+            TripLeg::Drive(c, _) | TripLeg::Deliver(c, _) => AgentID::Car(*c),
             TripLeg::RideBus(_, _) => AgentID::BusPassenger(person.id, person.on_bus.unwrap()),
+            TripLeg::RideHail(_) => {
+                if let Some(car) = person.on_bus {
+                    AgentID::BusPassenger(person.id, car)
+                } else {
+                    // Still waiting for the vehicle
+                    return TripResult::ModeChange;
+                }
+            }
             TripLeg::Remote(_) => {
                 return TripResult::RemoteTrip;
             }
@@ -829,7 +1084,11 @@ impl TripManager {
             self.unfinished_trips,
         )
     }
-    pub fn num_agents(&self, transit: &TransitSimState) -> BTreeMap<AgentType, usize> {
+    pub fn num_agents(
+        &self,
+        transit: &TransitSimState,
+        ridehail: &RideHailSimState,
+    ) -> BTreeMap<AgentType, usize> {
         let mut cnt = Counter::new();
         for a in self.active_trip_mode.keys() {
             cnt.inc(a.to_type());
@@ -837,6 +1096,7 @@ impl TripManager {
         let (buses, trains) = transit.active_vehicles();
         cnt.add(AgentType::Bus, buses);
         cnt.add(AgentType::Train, trains);
+        cnt.add(AgentType::Car, ridehail.active_vehicles());
         AgentType::all()
             .into_iter()
             .map(|k| (k, cnt.get(k)))
@@ -870,6 +1130,31 @@ impl TripManager {
     pub fn trip_info(&self, id: TripID) -> TripInfo {
         self.trips[id.0].info.clone()
     }
+    // For checking determinism. Describes the first difference found, if any.
+    pub(crate) fn find_divergence(&self, other: &TripManager) -> Option<String> {
+        if self.trips.len() != other.trips.len() {
+            return Some(format!(
+                "{} trips vs {} trips",
+                self.trips.len(),
+                other.trips.len()
+            ));
+        }
+        for (t1, t2) in self.trips.iter().zip(other.trips.iter()) {
+            if t1 != t2 {
+                return Some(format!("{} differs: {:?} vs {:?}", t1.id, t1, t2));
+            }
+        }
+        for (p1, p2) in self.people.iter().zip(other.people.iter()) {
+            if p1 != p2 {
+                return Some(format!("{} differs: {:?} vs {:?}", p1.id, p1, p2));
+            }
+        }
+        if self != other {
+            return Some("TripManager differs in active agents or counters".to_string());
+        }
+        None
+    }
+
     pub fn all_trip_info(&self) -> Vec<(TripID, TripInfo)> {
         self.trips.iter().map(|t| (t.id, t.info.clone())).collect()
     }
@@ -937,6 +1222,12 @@ impl TripManager {
     ) {
         assert!(!self.trips[trip.0].cancelled);
         assert!(!self.trips[trip.0].aborted);
+        let spec = spec.first_mode(map);
+        if let Some(ref req) = maybe_req {
+            if let Some(path) = assigned_path(&mut self.assigned_paths, trip, req) {
+                maybe_path = Some(path);
+            }
+        }
         if !self.pathfinding_upfront && maybe_path.is_none() && maybe_req.is_some() {
             maybe_path = map.pathfind(maybe_req.clone().unwrap());
         }
@@ -1164,7 +1455,7 @@ impl TripManager {
                     self.abort_trip(now, trip, None, parking, scheduler, map);
                 }
             }
-            TripSpec::UsingTransit { start, stop1, .. } => {
+            TripSpec::UsingTransit { start, rides, .. } => {
                 assert_eq!(
                     person.state,
                     match start.connection {
@@ -1194,7 +1485,7 @@ impl TripManager {
                 );
                 person.state = PersonState::Trip(trip);
 
-                let walk_to = SidewalkSpot::bus_stop(stop1, map);
+                let walk_to = SidewalkSpot::bus_stop(rides[0].0, map);
                 let req = maybe_req.unwrap();
                 if let Some(path) = maybe_path {
                     scheduler.push(
@@ -1233,6 +1524,61 @@ impl TripManager {
                     TripPhaseType::Remote,
                 ));
             }
+            TripSpec::UsingRideHail { start, goal } => {
+                assert_eq!(person.state, PersonState::Inside(start));
+                person.state = PersonState::Trip(trip);
+                // They wait at the curb
+                self.events
+                    .push(Event::PersonLeavesBuilding(person.id, start));
+                self.events.push(Event::TripPhaseStarting(
+                    trip,
+                    person.id,
+                    None,
+                    TripPhaseType::WaitingForRideHail,
+                ));
+                scheduler.push(now, Command::RequestRide(trip, person.id, start, goal));
+            }
+            TripSpec::DeliveryTour {
+                start_pos,
+                use_vehicle,
+                stops,
+                retry_if_no_room,
+                origin,
+                ..
+            } => {
+                assert_eq!(person.state, PersonState::OffMap);
+                self.events.push(Event::PersonEntersMap(
+                    person.id,
+                    AgentID::Car(use_vehicle),
+                    map.get_l(start_pos.lane()).src_i,
+                    origin,
+                ));
+                person.state = PersonState::Trip(trip);
+
+                let vehicle = person.get_vehicle(use_vehicle);
+                let req = maybe_req.unwrap();
+                if let Some(path) = maybe_path {
+                    let router = Router::deliver(path, req.end.dist_along(), stops[0]);
+                    scheduler.push(
+                        now,
+                        Command::SpawnCar(
+                            CreateCar::for_appearing(
+                                vehicle, start_pos, router, req, trip, person.id,
+                            ),
+                            retry_if_no_room,
+                        ),
+                    );
+                } else {
+                    self.events.push(Event::Alert(
+                        AlertLocation::Person(person.id),
+                        format!("DeliveryTour trip couldn't find the first path: {}", req),
+                    ));
+                    self.abort_trip(now, trip, Some(vehicle), parking, scheduler, map);
+                }
+            }
+            TripSpec::ParkAndRide { .. } | TripSpec::ReturnFromParkAndRide { .. } => {
+                unreachable!()
+            }
         }
     }
 
@@ -1248,10 +1594,12 @@ impl TripManager {
                     let agent_type = match t.info.mode {
                         TripMode::Walk => AgentType::Pedestrian,
                         TripMode::Bike => AgentType::Bike,
-                        TripMode::Drive => AgentType::Car,
+                        TripMode::Drive | TripMode::RideHail => AgentType::Car,
                         // TODO Not true for long. People will be able to spawn at borders already
                         // on a bus.
                         TripMode::Transit => AgentType::Pedestrian,
+                        // Only the way back can start at a border, walking to transit
+                        TripMode::ParkAndRide => AgentType::Pedestrian,
                     };
                     times.push((t.info.departure, agent_type));
                 }
@@ -1262,6 +1610,20 @@ impl TripManager {
     }
 }
 
+// If the trip was assigned a path for exactly this request, use it.
+fn assigned_path(
+    assigned_paths: &mut BTreeMap<TripID, (PathRequest, Path)>,
+    trip: TripID,
+    req: &PathRequest,
+) -> Option<Path> {
+    let (assigned_req, path) = assigned_paths.remove(&trip)?;
+    if &assigned_req == req {
+        Some(path)
+    } else {
+        None
+    }
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 struct Trip {
     id: TripID,
@@ -1294,13 +1656,30 @@ impl Trip {
         start: SidewalkSpot,
         person: &Person,
         map: &Map,
+        parking: &ParkingSimState,
         scheduler: &mut Scheduler,
         events: &mut Vec<Event>,
     ) -> bool {
-        let walk_to = match self.legs[0] {
+        let mut walk_to = match self.legs[0] {
             TripLeg::Walk(ref to) => to.clone(),
             _ => unreachable!(),
         };
+        // Going back to a car parked earlier, like after park-and-ride
+        if walk_to.connection == SidewalkPOI::DeferredParkingSpot {
+            let car = match self.legs[1] {
+                TripLeg::Drive(car, _) => car,
+                _ => unreachable!(),
+            };
+            if let Some(parked_car) = parking.lookup_parked_car(car) {
+                walk_to = SidewalkSpot::parking_spot(parked_car.spot, map, parking);
+            } else {
+                events.push(Event::Alert(
+                    AlertLocation::Person(self.person),
+                    format!("Aborting {} because {} isn't parked anywhere", self.id, car),
+                ));
+                return false;
+            }
+        }
 
         let req = PathRequest {
             start: start.sidewalk_pos,
@@ -1354,6 +1733,10 @@ pub enum TripLeg {
     // A person may own many vehicles, so specify which they use
     Drive(CarID, DrivingGoal),
     RideBus(BusRouteID, BusStopID),
+    // Where are they going?
+    RideHail(BuildingID),
+    // A truck making a delivery to a building
+    Deliver(CarID, BuildingID),
     Remote(OffMapLocation),
 }
 
@@ -1363,6 +1746,9 @@ pub enum TripMode {
     Bike,
     Transit,
     Drive,
+    RideHail,
+    // Driving (or biking) to transit, then riding it, or the reverse on the way back
+    ParkAndRide,
 }
 
 impl TripMode {
@@ -1372,6 +1758,8 @@ impl TripMode {
             TripMode::Bike,
             TripMode::Transit,
             TripMode::Drive,
+            TripMode::RideHail,
+            TripMode::ParkAndRide,
         ]
     }
 
@@ -1381,6 +1769,8 @@ impl TripMode {
             TripMode::Bike => "bike",
             TripMode::Transit => "use transit",
             TripMode::Drive => "drive",
+            TripMode::RideHail => "take a ride-hail",
+            TripMode::ParkAndRide => "park and ride",
         }
     }
 
@@ -1391,6 +1781,8 @@ impl TripMode {
             TripMode::Bike => "biking",
             TripMode::Transit => "using transit",
             TripMode::Drive => "driving",
+            TripMode::RideHail => "riding in a ride-hail",
+            TripMode::ParkAndRide => "parking and riding",
         }
     }
 
@@ -1400,6 +1792,8 @@ impl TripMode {
             TripMode::Bike => "Bike",
             TripMode::Transit => "Bus",
             TripMode::Drive => "Car",
+            TripMode::RideHail => "Ride-hail",
+            TripMode::ParkAndRide => "Park-and-ride",
         }
     }
 
@@ -1409,7 +1803,7 @@ impl TripMode {
             TripMode::Bike => PathConstraints::Bike,
             // TODO WRONG
             TripMode::Transit => PathConstraints::Bus,
-            TripMode::Drive => PathConstraints::Car,
+            TripMode::Drive | TripMode::RideHail | TripMode::ParkAndRide => PathConstraints::Car,
         }
     }
 
@@ -1419,7 +1813,7 @@ impl TripMode {
             PathConstraints::Bike => TripMode::Bike,
             // TODO The bijection breaks down... transit rider vs train vs bus...
             PathConstraints::Bus | PathConstraints::Train => TripMode::Transit,
-            PathConstraints::Car => TripMode::Drive,
+            PathConstraints::Car | PathConstraints::Truck => TripMode::Drive,
         }
     }
 }